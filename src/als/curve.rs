@@ -0,0 +1,188 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::convert::TryFrom;
+
+use crate::error::Error;
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Mapping from ambient light in lux to a brightness percentage.
+///
+/// The curve is given as `(lux, percent)` points. Between two points the
+/// percentage is interpolated on a logarithmic lux axis, since perceived
+/// brightness follows the logarithm of illuminance; outside of the points
+/// it stays at the first or last percentage.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::als::LuxCurve;
+///
+/// let curve = LuxCurve::new(&[(0.0, 10.0), (99.0, 50.0), (9999.0, 100.0)]).unwrap();
+/// assert_eq!(curve.percent(0.0), 10.0);
+/// assert_eq!(curve.percent(99.0), 50.0);
+/// assert_eq!(curve.percent(999.0).round(), 75.0);
+/// assert_eq!(curve.percent(50000.0), 100.0);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "dbus",
+    serde(try_from = "Vec<(f64, f64)>", into = "Vec<(f64, f64)>")
+)]
+pub struct LuxCurve {
+    points: Vec<(f64, f64)>,
+}
+
+impl LuxCurve {
+    /// Create a curve from `(lux, percent)` points sorted by lux.
+    ///
+    /// Fails with [`Error::InvalidLuxCurve`] if there are no points, lux
+    /// values are negative or not increasing, or a percentage is outside
+    /// of `0.0..=100.0`.
+    pub fn new(points: &[(f64, f64)]) -> Result<LuxCurve, Error> {
+        let invalid = |reason: String| Err(Error::InvalidLuxCurve { reason });
+        if points.is_empty() {
+            return invalid(String::from("no points"));
+        }
+        for (i, &(lux, percent)) in points.iter().enumerate() {
+            if !lux.is_finite() || lux < 0.0 {
+                return invalid(format!("{} lux is not a valid illuminance", lux));
+            }
+            if !(0.0..=100.0).contains(&percent) {
+                return invalid(format!("{}% is not between 0 and 100", percent));
+            }
+            if i > 0 && lux <= points[i - 1].0 {
+                return invalid(format!("{} lux does not increase", lux));
+            }
+        }
+        Ok(LuxCurve {
+            points: points.to_vec(),
+        })
+    }
+
+    /// Curve for keyboard backlights: fully on in the dark and off from
+    /// an ordinarily lit room.
+    pub fn keyboard() -> LuxCurve {
+        LuxCurve {
+            points: vec![(0.0, 100.0), (10.0, 100.0), (80.0, 0.0)],
+        }
+    }
+
+    /// Get the `(lux, percent)` points of the curve.
+    pub fn get_points(&self) -> &[(f64, f64)] {
+        &self.points
+    }
+
+    /// Brightness percentage for `lux`.
+    pub fn percent(&self, lux: f64) -> f64 {
+        let first = self.points[0];
+        let last = self.points[self.points.len() - 1];
+        if lux.is_nan() || lux <= first.0 {
+            return first.1;
+        }
+        if lux >= last.0 {
+            return last.1;
+        }
+        let i = self.points.iter().position(|&(x, _)| x > lux).unwrap_or(0);
+        let ((x0, y0), (x1, y1)) = (self.points[i - 1], self.points[i]);
+        let t = (log_lux(lux) - log_lux(x0)) / (log_lux(x1) - log_lux(x0));
+        y0 + t * (y1 - y0)
+    }
+}
+
+impl Default for LuxCurve {
+    /// Curve for screens: dim in the dark, half brightness in an office and
+    /// full brightness in daylight.
+    fn default() -> LuxCurve {
+        LuxCurve {
+            points: vec![
+                (0.0, 5.0),
+                (10.0, 20.0),
+                (100.0, 40.0),
+                (500.0, 60.0),
+                (2000.0, 80.0),
+                (10000.0, 100.0),
+            ],
+        }
+    }
+}
+
+impl TryFrom<Vec<(f64, f64)>> for LuxCurve {
+    type Error = Error;
+
+    /// Check the points like [`LuxCurve::new`].
+    fn try_from(points: Vec<(f64, f64)>) -> Result<LuxCurve, Error> {
+        LuxCurve::new(&points)
+    }
+}
+
+impl From<LuxCurve> for Vec<(f64, f64)> {
+    fn from(val: LuxCurve) -> Self {
+        val.points
+    }
+}
+
+/// `log10(1 + lux)`, defined at 0 lux.
+fn log_lux(lux: f64) -> f64 {
+    lux.ln_1p() / std::f64::consts::LN_10
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_curves() {
+        let bad: [&[(f64, f64)]; 5] = [
+            &[],
+            &[(-1.0, 10.0)],
+            &[(0.0, 10.0), (f64::INFINITY, 20.0)],
+            &[(0.0, 10.0), (0.0, 20.0)],
+            &[(0.0, 101.0)],
+        ];
+        for points in bad.iter() {
+            assert!(
+                matches!(LuxCurve::new(points), Err(Error::InvalidLuxCurve { .. })),
+                "{:?}",
+                points
+            );
+        }
+    }
+
+    #[test]
+    fn from_points() {
+        let points = vec![(0.0, 10.0), (100.0, 60.0)];
+        let curve = LuxCurve::try_from(points.clone()).unwrap();
+        assert_eq!(Vec::from(curve), points);
+        assert!(LuxCurve::try_from(Vec::new()).is_err());
+        assert!(LuxCurve::try_from(vec![(100.0, 60.0), (0.0, 10.0)]).is_err());
+    }
+
+    #[test]
+    fn interpolation() {
+        let curve = LuxCurve::default();
+        assert_eq!(curve.percent(f64::NAN), 5.0);
+        assert_eq!(curve.percent(-3.0), 5.0);
+        assert_eq!(curve.percent(100.0), 40.0);
+        let mut last = 0.0;
+        for lux in (0..20000).step_by(50) {
+            let percent = curve.percent(f64::from(lux));
+            assert!(percent >= last && percent <= 100.0, "{} lux", lux);
+            last = percent;
+        }
+
+        let keyboard = LuxCurve::keyboard();
+        assert_eq!(keyboard.percent(5.0), 100.0);
+        assert!(keyboard.percent(30.0) > 0.0 && keyboard.percent(30.0) < 100.0);
+        assert_eq!(keyboard.percent(300.0), 0.0);
+    }
+}
@@ -0,0 +1,214 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Exponential moving average of lux readings, so a passing shadow or a
+/// flickering lamp does not move the brightness.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::als::LuxFilter;
+///
+/// let mut filter = LuxFilter::new(0.5);
+/// assert_eq!(filter.update(100.0), 100.0);
+/// assert_eq!(filter.update(0.0), 50.0);
+/// assert_eq!(filter.update(0.0), 25.0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct LuxFilter {
+    smoothing: f64,
+    smoothed: Option<f64>,
+}
+
+impl LuxFilter {
+    /// Create a filter keeping `smoothing` (`0.0..1.0`) of the previous
+    /// value on every reading, `0.0` disables smoothing.
+    pub fn new(smoothing: f64) -> LuxFilter {
+        let smoothing = if smoothing.is_nan() {
+            0.0
+        } else {
+            smoothing.clamp(0.0, 0.99)
+        };
+        LuxFilter {
+            smoothing,
+            smoothed: None,
+        }
+    }
+
+    /// Add a reading and get the smoothed lux, the first reading is taken as is.
+    pub fn update(&mut self, lux: f64) -> f64 {
+        let smoothed = match self.smoothed {
+            Some(previous) if lux.is_finite() => {
+                self.smoothing * previous + (1.0 - self.smoothing) * lux.max(0.0)
+            }
+            Some(previous) => previous,
+            None => lux.max(0.0),
+        };
+        self.smoothed = Some(smoothed);
+        smoothed
+    }
+
+    /// Get the smoothed lux, `None` before the first reading.
+    pub fn get(&self) -> Option<f64> {
+        self.smoothed
+    }
+
+    /// Forget previous readings.
+    pub fn reset(&mut self) {
+        self.smoothed = None;
+    }
+}
+
+impl Default for LuxFilter {
+    fn default() -> LuxFilter {
+        LuxFilter::new(0.7)
+    }
+}
+
+/// Ignores changes of a brightness percentage smaller than a threshold, so
+/// the brightness does not hunt around a reading that sits between two
+/// levels.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::als::Hysteresis;
+///
+/// let mut hysteresis = Hysteresis::new(5.0);
+/// assert_eq!(hysteresis.update(40.0), Some(40.0));
+/// assert_eq!(hysteresis.update(43.0), None);
+/// assert_eq!(hysteresis.update(36.0), None);
+/// assert_eq!(hysteresis.update(46.0), Some(46.0));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct Hysteresis {
+    threshold: f64,
+    current: Option<f64>,
+}
+
+impl Hysteresis {
+    /// Create a hysteresis ignoring changes smaller than `threshold`
+    /// percentage points.
+    pub fn new(threshold: f64) -> Hysteresis {
+        Hysteresis {
+            threshold: if threshold.is_nan() {
+                0.0
+            } else {
+                threshold.max(0.0)
+            },
+            current: None,
+        }
+    }
+
+    /// Offer a new target and get it back if it should be applied,
+    /// recording it as applied.
+    ///
+    /// Reaching `0.0` or `100.0` is always applied, so the ends of a curve
+    /// are never missed by less than the threshold.
+    pub fn update(&mut self, target: f64) -> Option<f64> {
+        let target = self.check(target)?;
+        self.record(target);
+        Some(target)
+    }
+
+    /// Offer a new target and get it back if it should be applied, without
+    /// recording it, so a target that failed to apply is offered again.
+    pub fn check(&self, target: f64) -> Option<f64> {
+        let apply = match self.current {
+            None => true,
+            Some(current) => {
+                let delta = (target - current).abs();
+                delta >= self.threshold || (delta > 0.0 && (target <= 0.0 || target >= 100.0))
+            }
+        };
+        if apply {
+            Some(target)
+        } else {
+            None
+        }
+    }
+
+    /// Record `target` as applied, e.g. once a target from
+    /// [`check`](Hysteresis::check) was written.
+    pub fn record(&mut self, target: f64) {
+        self.current = Some(target);
+    }
+
+    /// Get the last applied percentage, `None` before the first update.
+    pub fn get(&self) -> Option<f64> {
+        self.current
+    }
+
+    /// Forget the last applied percentage, e.g. after the brightness was
+    /// changed by hand.
+    pub fn reset(&mut self) {
+        self.current = None;
+    }
+}
+
+impl Default for Hysteresis {
+    fn default() -> Hysteresis {
+        Hysteresis::new(5.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter() {
+        let mut filter = LuxFilter::new(0.0);
+        assert_eq!(filter.get(), None);
+        assert_eq!(filter.update(10.0), 10.0);
+        assert_eq!(filter.update(300.0), 300.0);
+
+        let mut filter = LuxFilter::new(0.9);
+        filter.update(1000.0);
+        assert_eq!(filter.update(f64::NAN), 1000.0);
+        let mut lux = 0.0;
+        for _ in 0..100 {
+            lux = filter.update(0.0);
+        }
+        assert!(lux < 1.0);
+        filter.reset();
+        assert_eq!(filter.update(-5.0), 0.0);
+
+        let mut filter = LuxFilter::new(0.5);
+        filter.update(10.0);
+        assert_eq!(filter.update(-10.0), 5.0);
+    }
+
+    #[test]
+    fn hysteresis_reaches_ends() {
+        let mut hysteresis = Hysteresis::new(10.0);
+        assert_eq!(hysteresis.update(95.0), Some(95.0));
+        assert_eq!(hysteresis.update(100.0), Some(100.0));
+        assert_eq!(hysteresis.update(100.0), None);
+        assert_eq!(hysteresis.update(3.0), Some(3.0));
+        assert_eq!(hysteresis.update(0.0), Some(0.0));
+        hysteresis.reset();
+        assert_eq!(hysteresis.get(), None);
+    }
+
+    #[test]
+    fn hysteresis_check_does_not_record() {
+        let mut hysteresis = Hysteresis::new(10.0);
+        hysteresis.record(50.0);
+        assert_eq!(hysteresis.check(70.0), Some(70.0));
+        assert_eq!(hysteresis.get(), Some(50.0));
+        assert_eq!(hysteresis.check(70.0), Some(70.0));
+        assert_eq!(hysteresis.check(55.0), None);
+    }
+}
@@ -0,0 +1,525 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use super::{Hysteresis, LuxCurve, LuxFilter};
+use crate::{
+    curve::Curve,
+    error::Error,
+    misc::LedDevice,
+    monitor::MonitorDevice,
+    sysfs::{scan_dir, Scan, Sysfs},
+    utils::device_error,
+};
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Channel prefixes used by illuminance sensors, most drivers use the first.
+const CHANNELS: [&str; 2] = ["in_illuminance", "in_illuminance0"];
+
+/// An ambient light sensor.
+///
+/// Sensors are extracted from the `/sys/bus/iio/devices/` directory. Lux is
+/// read from `in_illuminance_input` if the driver provides it, otherwise
+/// computed as `(in_illuminance_raw + in_illuminance_offset) *
+/// in_illuminance_scale`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::als::LightSensor;
+///
+/// for sensor in LightSensor::get_all_sensors().unwrap() {
+///     println!("{}: {} lux", sensor.get_device_name(), sensor.read_lux().unwrap());
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct LightSensor {
+    /// Name of the sensor in `/sys/bus/iio/devices/`, e.g. `iio:device0`.
+    pub device: String,
+    /// Value taken from `/sys/bus/iio/devices/<sensor>/name`, e.g. `als` or
+    /// `acpi-als`.
+    pub name: Option<String>,
+    /// Value taken from `<channel>_scale`, 1 if missing.
+    pub scale: f64,
+    /// Value taken from `<channel>_offset`, 0 if missing.
+    pub offset: f64,
+    /// Attribute lux is read from.
+    path: PathBuf,
+    /// Whether `path` is already in lux.
+    processed: bool,
+}
+
+impl LightSensor {
+    /// Get sensor by device name, e.g. `iio:device0`.
+    pub fn get_sensor(device: &str) -> Result<LightSensor, Error> {
+        LightSensor::get_sensor_in(&Sysfs::default(), device)
+    }
+
+    /// Get sensor by device name from the given sysfs tree.
+    ///
+    /// Fails with [`Error::InvalidDeviceName`] if the device is not an
+    /// illuminance sensor.
+    pub fn get_sensor_in(sysfs: &Sysfs, device: &str) -> Result<LightSensor, Error> {
+        let dir = sysfs.iio_dir().join(device);
+        for channel in CHANNELS.iter() {
+            let input = dir.join(format!("{}_input", channel));
+            let raw = dir.join(format!("{}_raw", channel));
+            let (path, processed) = if input.exists() {
+                (input, true)
+            } else if raw.exists() {
+                (raw, false)
+            } else {
+                continue;
+            };
+            let (scale, offset) = if processed {
+                (1.0, 0.0)
+            } else {
+                (
+                    read_f64(&dir.join(format!("{}_scale", channel)))?.unwrap_or(1.0),
+                    read_f64(&dir.join(format!("{}_offset", channel)))?.unwrap_or(0.0),
+                )
+            };
+            return Ok(LightSensor {
+                device: device.to_string(),
+                name: fs::read_to_string(dir.join("name"))
+                    .ok()
+                    .map(|name| name.trim().to_string()),
+                scale,
+                offset,
+                path,
+                processed,
+            });
+        }
+        Err(Error::InvalidDeviceName {
+            device: device.to_string(),
+        })
+    }
+
+    /// Get all ambient light sensors.
+    pub fn get_all_sensors() -> Result<Vec<LightSensor>, Error> {
+        LightSensor::get_all_sensors_in(&Sysfs::default())
+    }
+
+    /// Get all ambient light sensors from the given sysfs tree.
+    ///
+    /// Sensors that cannot be read are skipped, use
+    /// [`scan_sensors_in`](LightSensor::scan_sensors_in) to find out why.
+    pub fn get_all_sensors_in(sysfs: &Sysfs) -> Result<Vec<LightSensor>, Error> {
+        Ok(LightSensor::scan_sensors_in(sysfs)?.devices)
+    }
+
+    /// Get all ambient light sensors from the given sysfs tree along with
+    /// the errors for those that could not be read.
+    ///
+    /// Industrial I/O devices that are not illuminance sensors, e.g.
+    /// accelerometers, are left out.
+    pub fn scan_sensors_in(sysfs: &Sysfs) -> Result<Scan<LightSensor>, Error> {
+        scan_dir(
+            &sysfs.iio_dir(),
+            |device| match LightSensor::get_sensor_in(sysfs, &device) {
+                Err(Error::InvalidDeviceName { .. }) => None,
+                res => Some(res),
+            },
+        )
+    }
+
+    /// Get device name of sensor.
+    pub fn get_device_name(&self) -> &str {
+        &self.device
+    }
+
+    /// Read the current illuminance in lux.
+    pub fn read_lux(&self) -> Result<f64, Error> {
+        let value = read_f64(&self.path)?.ok_or_else(|| Error::DeviceGone {
+            device: self.device.clone(),
+        })?;
+        Ok(if self.processed {
+            value
+        } else {
+            (value + self.offset) * self.scale
+        })
+    }
+}
+
+/// Read a number from `path`, `None` if the attribute does not exist.
+fn read_f64(path: &Path) -> Result<Option<f64>, Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let s = fs::read_to_string(path).map_err(|e| device_error(path, e))?;
+    s.trim().parse::<f64>().map(Some).map_err(|_| {
+        Error::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: not a number: {:?}", path.display(), s.trim()),
+        ))
+    })
+}
+
+/// What one [`AutoBrightness::update`] did.
+#[derive(Debug)]
+pub struct AutoUpdate {
+    /// Smoothed illuminance the update was based on.
+    pub lux: f64,
+    /// Percentage the monitor was set to, `None` if it was left alone.
+    pub monitor: Option<f64>,
+    /// Percentage the keyboards were set to, `None` if they were left alone.
+    pub keyboard: Option<f64>,
+    /// Keyboards that could not be set and why, they are retried on the
+    /// next update.
+    pub keyboard_errors: Vec<(String, Error)>,
+}
+
+/// Adjusts a monitor, and optionally keyboard backlights, to the ambient
+/// light.
+///
+/// Every update reads the sensor, smooths the reading with a
+/// [`LuxFilter`], maps it to a percentage with a [`LuxCurve`] and applies it
+/// unless the [`Hysteresis`] says the change is too small to bother.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use bulbb::{
+///     als::{AutoBrightness, LightSensor, LuxCurve},
+///     misc::LedDevice,
+///     monitor::MonitorDevice,
+/// };
+///
+/// let sensor = LightSensor::get_all_sensors().unwrap().remove(0);
+/// let monitor = MonitorDevice::preferred().unwrap().unwrap();
+/// let mut auto = AutoBrightness::new(sensor, monitor)
+///     .with_curve(LuxCurve::new(&[(0.0, 10.0), (300.0, 50.0), (5000.0, 100.0)]).unwrap())
+///     .with_keyboards(LedDevice::get_all_keyboard_devices().unwrap())
+///     .with_interval(Duration::from_secs(2));
+/// auto.run().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct AutoBrightness {
+    sensor: LightSensor,
+    monitor: MonitorDevice,
+    keyboards: Vec<LedDevice>,
+    curve: LuxCurve,
+    keyboard_curve: LuxCurve,
+    brightness_curve: Curve,
+    filter: LuxFilter,
+    hysteresis: Hysteresis,
+    keyboard_hysteresis: Hysteresis,
+    interval: Duration,
+}
+
+impl AutoBrightness {
+    /// Drive `monitor` from `sensor` with the default curve, smoothing
+    /// and hysteresis, updating every second.
+    pub fn new(sensor: LightSensor, monitor: MonitorDevice) -> AutoBrightness {
+        AutoBrightness {
+            sensor,
+            monitor,
+            keyboards: Vec::new(),
+            curve: LuxCurve::default(),
+            keyboard_curve: LuxCurve::keyboard(),
+            brightness_curve: Curve::Cie1931,
+            filter: LuxFilter::default(),
+            hysteresis: Hysteresis::default(),
+            keyboard_hysteresis: Hysteresis::default(),
+            interval: Duration::from_secs(1),
+        }
+    }
+
+    /// Map lux to the monitor's percentage with `curve`.
+    pub fn with_curve(mut self, curve: LuxCurve) -> AutoBrightness {
+        self.curve = curve;
+        self
+    }
+
+    /// Map the monitor's percentage to raw levels with `curve`, defaults
+    /// to [`Curve::Cie1931`].
+    pub fn with_brightness_curve(mut self, curve: Curve) -> AutoBrightness {
+        self.brightness_curve = curve;
+        self
+    }
+
+    /// Keep `smoothing` (`0.0..1.0`) of the previous lux on every reading.
+    pub fn with_smoothing(mut self, smoothing: f64) -> AutoBrightness {
+        self.filter = LuxFilter::new(smoothing);
+        self
+    }
+
+    /// Ignore changes smaller than `threshold` percentage points.
+    pub fn with_hysteresis(mut self, threshold: f64) -> AutoBrightness {
+        self.hysteresis = Hysteresis::new(threshold);
+        self.keyboard_hysteresis = Hysteresis::new(threshold);
+        self
+    }
+
+    /// Also drive `keyboards`, e.g. from
+    /// [`LedDevice::get_all_keyboard_devices`].
+    pub fn with_keyboards(mut self, keyboards: Vec<LedDevice>) -> AutoBrightness {
+        self.keyboards = keyboards;
+        self
+    }
+
+    /// Map lux to the keyboards' percentage with `curve`, defaults to
+    /// [`LuxCurve::keyboard`].
+    pub fn with_keyboard_curve(mut self, curve: LuxCurve) -> AutoBrightness {
+        self.keyboard_curve = curve;
+        self
+    }
+
+    /// Wait `interval` between updates in [`run`](AutoBrightness::run).
+    pub fn with_interval(mut self, interval: Duration) -> AutoBrightness {
+        self.interval = interval;
+        self
+    }
+
+    /// Get the monitor being driven.
+    pub fn get_monitor(&self) -> &MonitorDevice {
+        &self.monitor
+    }
+
+    /// Get the sensor being read.
+    pub fn get_sensor(&self) -> &LightSensor {
+        &self.sensor
+    }
+
+    /// Read the sensor once and adjust brightness.
+    pub fn update(&mut self) -> Result<AutoUpdate, Error> {
+        let lux = self.sensor.read_lux()?;
+        self.apply(lux)
+    }
+
+    /// Adjust brightness to a lux reading.
+    ///
+    /// Every keyboard is tried even if some fail, those that do are listed
+    /// in [`AutoUpdate::keyboard_errors`]. A level that could not be set is
+    /// not taken as applied, so the next update tries it again.
+    pub fn apply(&mut self, lux: f64) -> Result<AutoUpdate, Error> {
+        let lux = self.filter.update(lux);
+
+        let monitor = self.hysteresis.check(self.curve.percent(lux));
+        if let Some(percent) = monitor {
+            self.monitor.set_percent(percent, self.brightness_curve)?;
+            self.hysteresis.record(percent);
+        }
+
+        let keyboard = if self.keyboards.is_empty() {
+            None
+        } else {
+            self.keyboard_hysteresis
+                .check(self.keyboard_curve.percent(lux))
+        };
+        let mut keyboard_errors = Vec::new();
+        if let Some(percent) = keyboard {
+            for led in &self.keyboards {
+                if let Err(e) = led.set_percent(percent, Curve::Linear) {
+                    keyboard_errors.push((led.get_device_name().to_string(), e));
+                }
+            }
+            if keyboard_errors.is_empty() {
+                self.keyboard_hysteresis.record(percent);
+            }
+        }
+
+        Ok(AutoUpdate {
+            lux,
+            monitor,
+            keyboard,
+            keyboard_errors,
+        })
+    }
+
+    /// Forget previous readings and levels, e.g. after the user changed
+    /// the brightness by hand or the lid was opened.
+    pub fn reset(&mut self) {
+        self.filter.reset();
+        self.hysteresis.reset();
+        self.keyboard_hysteresis.reset();
+    }
+
+    /// Update forever, blocking the current thread.
+    ///
+    /// Only returns if reading the sensor or changing the brightness of the
+    /// monitor fails, keyboards that cannot be changed are retried on the
+    /// next update.
+    pub fn run(&mut self) -> Result<(), Error> {
+        loop {
+            self.update()?;
+            thread::sleep(self.interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{FakeBacklight, FakeLed, FakeLightSensor, FakeSysfs};
+
+    #[test]
+    fn sensors() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_light_sensor(
+            &FakeLightSensor::new("iio:device0")
+                .raw(1200)
+                .scale("0.25")
+                .offset("-200"),
+        )
+        .unwrap();
+        fake.add_light_sensor(&FakeLightSensor::new("iio:device1").input("42.5"))
+            .unwrap();
+        fake.add_light_sensor(
+            &FakeLightSensor::new("iio:device2")
+                .without("in_illuminance_raw")
+                .attribute("in_accel_x_raw", "3"),
+        )
+        .unwrap();
+
+        let sensors = LightSensor::get_all_sensors_in(&fake.sysfs()).unwrap();
+        assert_eq!(sensors.len(), 2);
+        assert_eq!(sensors[0].name.as_deref(), Some("als"));
+        assert_eq!(sensors[0].read_lux().unwrap(), 250.0);
+        assert_eq!(sensors[1].read_lux().unwrap(), 42.5);
+
+        fake.write_light_sensor("iio:device0", "in_illuminance_raw", "600")
+            .unwrap();
+        assert_eq!(sensors[0].read_lux().unwrap(), 100.0);
+        assert!(matches!(
+            LightSensor::get_sensor_in(&fake.sysfs(), "iio:device2"),
+            Err(Error::InvalidDeviceName { .. })
+        ));
+    }
+
+    #[test]
+    fn auto_brightness() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_light_sensor(&FakeLightSensor::new("iio:device0").raw(0))
+            .unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight").max_brightness(1000))
+            .unwrap();
+        fake.add_led(&FakeLed::new("asus::kbd_backlight").max_brightness(3))
+            .unwrap();
+        let sysfs = fake.sysfs();
+
+        let mut auto = AutoBrightness::new(
+            LightSensor::get_sensor_in(&sysfs, "iio:device0").unwrap(),
+            MonitorDevice::get_monitor_device_in(&sysfs, String::from("intel_backlight")).unwrap(),
+        )
+        .with_curve(LuxCurve::new(&[(0.0, 10.0), (99.0, 50.0), (9999.0, 100.0)]).unwrap())
+        .with_brightness_curve(Curve::Linear)
+        .with_smoothing(0.5)
+        .with_keyboards(LedDevice::get_all_keyboard_devices_in(&sysfs).unwrap());
+
+        // dark room: dim screen, keyboard on
+        let update = auto.update().unwrap();
+        assert_eq!(update.monitor, Some(10.0));
+        assert_eq!(update.keyboard, Some(100.0));
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "brightness")
+                .unwrap(),
+            "100"
+        );
+        assert_eq!(
+            fake.read_led("asus::kbd_backlight", "brightness").unwrap(),
+            "3"
+        );
+
+        // a short flash is smoothed and then ignored by the hysteresis
+        fake.write_light_sensor("iio:device0", "in_illuminance_raw", "1")
+            .unwrap();
+        let update = auto.update().unwrap();
+        assert_eq!(update.lux, 0.5);
+        assert_eq!(update.monitor, None);
+
+        // daylight
+        fake.write_light_sensor("iio:device0", "in_illuminance_raw", "20000")
+            .unwrap();
+        for _ in 0..5 {
+            auto.update().unwrap();
+        }
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "brightness")
+                .unwrap(),
+            "1000"
+        );
+        assert_eq!(
+            fake.read_led("asus::kbd_backlight", "brightness").unwrap(),
+            "0"
+        );
+    }
+
+    #[test]
+    fn failed_writes_are_retried() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_light_sensor(&FakeLightSensor::new("iio:device0").raw(0))
+            .unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight").max_brightness(100))
+            .unwrap();
+        fake.add_led(&FakeLed::new("asus::kbd_backlight").max_brightness(3))
+            .unwrap();
+        fake.add_led(&FakeLed::new("tpacpi::kbd_backlight").max_brightness(2))
+            .unwrap();
+        let sysfs = fake.sysfs();
+        let mut auto = AutoBrightness::new(
+            LightSensor::get_sensor_in(&sysfs, "iio:device0").unwrap(),
+            MonitorDevice::get_monitor_device_in(&sysfs, String::from("intel_backlight")).unwrap(),
+        )
+        .with_curve(LuxCurve::new(&[(0.0, 10.0), (1000.0, 100.0)]).unwrap())
+        .with_brightness_curve(Curve::Linear)
+        .with_smoothing(0.0)
+        .with_keyboards(LedDevice::get_all_keyboard_devices_in(&sysfs).unwrap());
+
+        let brightness = |dir: std::path::PathBuf| dir.join("brightness");
+        fs::remove_file(brightness(sysfs.backlight_device("intel_backlight"))).unwrap();
+        fs::remove_file(brightness(sysfs.led_device("asus::kbd_backlight"))).unwrap();
+
+        // the monitor fails and is not taken as set
+        assert!(auto.apply(0.0).is_err());
+        fake.write_backlight("intel_backlight", "brightness", "0")
+            .unwrap();
+
+        // the first keyboard fails, the second is set all the same
+        let update = auto.apply(0.0).unwrap();
+        assert_eq!(update.monitor, Some(10.0));
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "brightness")
+                .unwrap(),
+            "10"
+        );
+        assert_eq!(update.keyboard_errors.len(), 1);
+        assert_eq!(update.keyboard_errors[0].0, "asus::kbd_backlight");
+        assert_eq!(
+            fake.read_led("tpacpi::kbd_backlight", "brightness")
+                .unwrap(),
+            "2"
+        );
+
+        // the keyboard level is tried again
+        fake.write_led("asus::kbd_backlight", "brightness", "0")
+            .unwrap();
+        let update = auto.apply(0.0).unwrap();
+        assert_eq!(update.monitor, None);
+        assert_eq!(update.keyboard, Some(100.0));
+        assert!(update.keyboard_errors.is_empty());
+        assert_eq!(
+            fake.read_led("asus::kbd_backlight", "brightness").unwrap(),
+            "3"
+        );
+        assert_eq!(auto.apply(0.0).unwrap().keyboard, None);
+    }
+}
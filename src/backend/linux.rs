@@ -0,0 +1,567 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    collections::HashMap,
+    fmt, io,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    error::Error,
+    misc::LedTrigger,
+    monitor::BacklightPower,
+    sysfs::{DeviceClass, Sysfs},
+    utils::write_sys,
+};
+
+#[cfg(feature = "dbus")]
+use zbus::Connection;
+
+/// A way of changing the brightness of a device.
+///
+/// Every [`MonitorDevice`](crate::monitor::MonitorDevice) and
+/// [`LedDevice`](crate::misc::LedDevice) carries a backend, which can be
+/// replaced per device or bypassed per call.
+pub trait BrightnessBackend: fmt::Debug + Send + Sync {
+    /// Set brightness of `device` in `class` to `level`.
+    ///
+    /// `level` has already been checked against the device's `max_brightness`.
+    fn set_brightness(
+        &self,
+        sysfs: &Sysfs,
+        class: DeviceClass,
+        device: &str,
+        level: u32,
+    ) -> Result<(), Error>;
+
+    /// Set `bl_power` of the backlight `device`.
+    ///
+    /// Only sysfs can change power, logind has no equivalent. Backends that
+    /// cannot change power return [`Error::Unsupported`].
+    fn set_power(&self, sysfs: &Sysfs, device: &str, power: BacklightPower) -> Result<(), Error> {
+        let _ = (sysfs, device, power);
+        Err(Error::Unsupported {
+            operation: "set_power",
+        })
+    }
+
+    /// Whether [`set_power`](BrightnessBackend::set_power) is implemented.
+    fn can_set_power(&self) -> bool {
+        false
+    }
+
+    /// Set the trigger of the LED `device`.
+    ///
+    /// Backends that cannot change triggers return [`Error::Unsupported`].
+    fn set_trigger(&self, sysfs: &Sysfs, device: &str, trigger: &LedTrigger) -> Result<(), Error> {
+        let _ = (sysfs, device, trigger);
+        Err(Error::Unsupported {
+            operation: "set_trigger",
+        })
+    }
+
+    /// Set `multi_intensity` of the multicolor LED `device`, one value per channel.
+    ///
+    /// `intensities` have already been checked against the device's channels
+    /// and `max_brightness`. Backends that cannot change intensities return
+    /// [`Error::Unsupported`].
+    fn set_intensities(
+        &self,
+        sysfs: &Sysfs,
+        device: &str,
+        intensities: &[u32],
+    ) -> Result<(), Error> {
+        let _ = (sysfs, device, intensities);
+        Err(Error::Unsupported {
+            operation: "set_intensities",
+        })
+    }
+}
+
+/// Writes directly to `/sys/class/<class>/<device>/brightness`.
+///
+/// ### NOTE
+///
+/// This will fail if user is not root (even when executed with sudo).
+/// It is recommended to create a udev rule to allow user of a certain
+/// group to write to the file. The example below will allow all users in
+/// the `video` group to change the brightness of all devices in `/sys/class/backlight/`.
+///
+/// ```ignore
+/// ACTION=="add", SUBSYSTEM=="backlight", RUN+="/bin/chgrp video /sys/class/backlight/%k/brightness"
+/// ACTION=="add", SUBSYSTEM=="backlight", RUN+="/bin/chmod g+w /sys/class/backlight/%k/brightness"
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SysfsBackend;
+
+impl BrightnessBackend for SysfsBackend {
+    fn set_brightness(
+        &self,
+        sysfs: &Sysfs,
+        class: DeviceClass,
+        device: &str,
+        level: u32,
+    ) -> Result<(), Error> {
+        write_sys(
+            &sysfs.device_dir(class, device).join("brightness"),
+            &level.to_string(),
+        )
+    }
+
+    fn set_power(&self, sysfs: &Sysfs, device: &str, power: BacklightPower) -> Result<(), Error> {
+        write_sys(
+            &sysfs.backlight_device(device).join("bl_power"),
+            &power.id().to_string(),
+        )
+    }
+
+    fn can_set_power(&self) -> bool {
+        true
+    }
+
+    fn set_trigger(&self, sysfs: &Sysfs, device: &str, trigger: &LedTrigger) -> Result<(), Error> {
+        write_sys(&sysfs.led_device(device).join("trigger"), trigger.id())
+    }
+
+    fn set_intensities(
+        &self,
+        sysfs: &Sysfs,
+        device: &str,
+        intensities: &[u32],
+    ) -> Result<(), Error> {
+        let intensities: Vec<String> = intensities.iter().map(u32::to_string).collect();
+        write_sys(
+            &sysfs.led_device(device).join("multi_intensity"),
+            &intensities.join(" "),
+        )
+    }
+}
+
+/// Calls `SetBrightness` on the caller's systemd-logind session over D-Bus.
+///
+/// logind always acts on the real `/sys`, so the device's [`Sysfs`] root is ignored.
+/// logind has no equivalent for `bl_power`, LED triggers or multicolor
+/// intensities, so only [`set_brightness`](BrightnessBackend::set_brightness)
+/// is supported.
+///
+/// The system bus connection is opened on first use and shared by clones.
+#[cfg(feature = "dbus")]
+#[derive(Debug, Clone, Default)]
+pub struct LogindBackend {
+    bus: Arc<Mutex<Option<Connection>>>,
+}
+
+#[cfg(feature = "dbus")]
+impl LogindBackend {
+    /// Create a backend that connects to the system bus on first use.
+    pub fn new() -> LogindBackend {
+        LogindBackend::default()
+    }
+
+    fn connection(&self) -> Result<Connection, Error> {
+        let mut bus = self.bus.lock().unwrap_or_else(|e| e.into_inner());
+        match &*bus {
+            Some(connection) => Ok(connection.clone()),
+            None => {
+                let connection = Connection::new_system()?;
+                *bus = Some(connection.clone());
+                Ok(connection)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "dbus")]
+impl BrightnessBackend for LogindBackend {
+    fn set_brightness(
+        &self,
+        _sysfs: &Sysfs,
+        class: DeviceClass,
+        device: &str,
+        level: u32,
+    ) -> Result<(), Error> {
+        let sd_bus = self.connection()?;
+        match sd_bus.call_method(
+            Some("org.freedesktop.login1"),
+            "/org/freedesktop/login1/session/auto",
+            Some("org.freedesktop.login1.Session"),
+            "SetBrightness",
+            &(<&str>::from(class), device, level),
+        ) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                // reconnect next time if the connection broke
+                if let zbus::Error::Io(_) = e {
+                    *self.bus.lock().unwrap_or_else(|e| e.into_inner()) = None;
+                }
+                Err(Error::SetBrightnessDBusError(e))
+            }
+        }
+    }
+}
+
+/// Tries each backend in turn, moving on to the next one only when the
+/// previous one was denied permission (`EACCES`/`EPERM`) or does not support
+/// the operation.
+///
+/// # Examples
+///
+/// ```
+/// use std::{io, sync::Arc};
+/// use bulbb::{
+///     backend::{BrightnessBackend, FallbackBackend, MockBackend},
+///     sysfs::{DeviceClass, Sysfs},
+/// };
+///
+/// let denied = Arc::new(MockBackend::failing(io::ErrorKind::PermissionDenied));
+/// let mock = Arc::new(MockBackend::new());
+/// let backend = FallbackBackend::new(vec![denied.clone(), mock.clone()]);
+/// backend
+///     .set_brightness(&Sysfs::default(), DeviceClass::Backlight, "acpi_video0", 3)
+///     .unwrap();
+/// assert_eq!(denied.get_brightness(DeviceClass::Backlight, "acpi_video0"), None);
+/// assert_eq!(mock.get_brightness(DeviceClass::Backlight, "acpi_video0"), Some(3));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FallbackBackend {
+    backends: Vec<Arc<dyn BrightnessBackend>>,
+}
+
+impl FallbackBackend {
+    /// Create a fallback chain, tried in order.
+    pub fn new(backends: Vec<Arc<dyn BrightnessBackend>>) -> FallbackBackend {
+        FallbackBackend { backends }
+    }
+}
+
+impl FallbackBackend {
+    fn try_each<F>(&self, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&dyn BrightnessBackend) -> Result<(), Error>,
+    {
+        let mut first_err = None;
+        for backend in &self.backends {
+            match f(backend.as_ref()) {
+                Err(Error::Io(e)) if e.kind() == io::ErrorKind::PermissionDenied => {
+                    first_err.get_or_insert(Error::Io(e));
+                }
+                Err(Error::Unsupported { operation }) => {
+                    first_err.get_or_insert(Error::Unsupported { operation });
+                }
+                res => return res,
+            }
+        }
+        Err(first_err.unwrap_or_else(|| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "no brightness backend available",
+            ))
+        }))
+    }
+}
+
+impl BrightnessBackend for FallbackBackend {
+    fn set_brightness(
+        &self,
+        sysfs: &Sysfs,
+        class: DeviceClass,
+        device: &str,
+        level: u32,
+    ) -> Result<(), Error> {
+        self.try_each(|backend| backend.set_brightness(sysfs, class, device, level))
+    }
+
+    fn set_power(&self, sysfs: &Sysfs, device: &str, power: BacklightPower) -> Result<(), Error> {
+        self.try_each(|backend| backend.set_power(sysfs, device, power))
+    }
+
+    fn can_set_power(&self) -> bool {
+        self.backends.iter().any(|backend| backend.can_set_power())
+    }
+
+    fn set_trigger(&self, sysfs: &Sysfs, device: &str, trigger: &LedTrigger) -> Result<(), Error> {
+        self.try_each(|backend| backend.set_trigger(sysfs, device, trigger))
+    }
+
+    fn set_intensities(
+        &self,
+        sysfs: &Sysfs,
+        device: &str,
+        intensities: &[u32],
+    ) -> Result<(), Error> {
+        self.try_each(|backend| backend.set_intensities(sysfs, device, intensities))
+    }
+}
+
+/// Keeps brightness levels in memory instead of touching any device.
+///
+/// Useful for tests and dry runs.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::{backend::{BrightnessBackend, MockBackend}, sysfs::{DeviceClass, Sysfs}};
+///
+/// let mock = MockBackend::new();
+/// mock.set_brightness(&Sysfs::default(), DeviceClass::Backlight, "acpi_video0", 3).unwrap();
+/// assert_eq!(mock.get_brightness(DeviceClass::Backlight, "acpi_video0"), Some(3));
+/// ```
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    levels: Mutex<HashMap<(DeviceClass, String), u32>>,
+    power: Mutex<HashMap<String, BacklightPower>>,
+    triggers: Mutex<HashMap<String, LedTrigger>>,
+    intensities: Mutex<HashMap<String, Vec<u32>>>,
+    error: Option<io::ErrorKind>,
+}
+
+impl MockBackend {
+    /// Create a mock that accepts every write.
+    pub fn new() -> MockBackend {
+        MockBackend::default()
+    }
+
+    /// Create a mock that fails every write with an I/O error of `kind`.
+    pub fn failing(kind: io::ErrorKind) -> MockBackend {
+        MockBackend {
+            error: Some(kind),
+            ..MockBackend::default()
+        }
+    }
+
+    /// Last level written to `device` in `class`.
+    pub fn get_brightness(&self, class: DeviceClass, device: &str) -> Option<u32> {
+        self.levels
+            .lock()
+            .unwrap()
+            .get(&(class, device.to_string()))
+            .copied()
+    }
+
+    /// Last power state written to the backlight `device`.
+    pub fn get_power(&self, device: &str) -> Option<BacklightPower> {
+        self.power.lock().unwrap().get(device).copied()
+    }
+
+    /// Last trigger written to the LED `device`.
+    pub fn get_trigger(&self, device: &str) -> Option<LedTrigger> {
+        self.triggers.lock().unwrap().get(device).cloned()
+    }
+
+    /// Last intensities written to the multicolor LED `device`.
+    pub fn get_intensities(&self, device: &str) -> Option<Vec<u32>> {
+        self.intensities.lock().unwrap().get(device).cloned()
+    }
+}
+
+impl BrightnessBackend for MockBackend {
+    fn set_brightness(
+        &self,
+        _sysfs: &Sysfs,
+        class: DeviceClass,
+        device: &str,
+        level: u32,
+    ) -> Result<(), Error> {
+        if let Some(kind) = self.error {
+            return Err(Error::Io(io::Error::from(kind)));
+        }
+        self.levels
+            .lock()
+            .unwrap()
+            .insert((class, device.to_string()), level);
+        Ok(())
+    }
+
+    fn set_power(&self, _sysfs: &Sysfs, device: &str, power: BacklightPower) -> Result<(), Error> {
+        if let Some(kind) = self.error {
+            return Err(Error::Io(io::Error::from(kind)));
+        }
+        self.power.lock().unwrap().insert(device.to_string(), power);
+        Ok(())
+    }
+
+    fn can_set_power(&self) -> bool {
+        true
+    }
+
+    fn set_trigger(&self, _sysfs: &Sysfs, device: &str, trigger: &LedTrigger) -> Result<(), Error> {
+        if let Some(kind) = self.error {
+            return Err(Error::Io(io::Error::from(kind)));
+        }
+        self.triggers
+            .lock()
+            .unwrap()
+            .insert(device.to_string(), trigger.clone());
+        Ok(())
+    }
+
+    fn set_intensities(
+        &self,
+        _sysfs: &Sysfs,
+        device: &str,
+        intensities: &[u32],
+    ) -> Result<(), Error> {
+        if let Some(kind) = self.error {
+            return Err(Error::Io(io::Error::from(kind)));
+        }
+        self.intensities
+            .lock()
+            .unwrap()
+            .insert(device.to_string(), intensities.to_vec());
+        Ok(())
+    }
+}
+
+/// The backend devices use unless told otherwise.
+///
+/// This is [`SysfsBackend`], falling back to `LogindBackend` when the
+/// `dbus` feature is enabled.
+pub fn default_backend() -> Arc<dyn BrightnessBackend> {
+    #[cfg(feature = "dbus")]
+    {
+        Arc::new(FallbackBackend::new(vec![
+            Arc::new(SysfsBackend),
+            Arc::new(LogindBackend::new()),
+        ]))
+    }
+    #[cfg(not(feature = "dbus"))]
+    {
+        Arc::new(SysfsBackend)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{FakeBacklight, FakeLed, FakeSysfs};
+
+    #[test]
+    fn sysfs_backend_writes_brightness() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight"))
+            .unwrap();
+        SysfsBackend
+            .set_brightness(&fake.sysfs(), DeviceClass::Backlight, "intel_backlight", 12)
+            .unwrap();
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "brightness")
+                .unwrap(),
+            "12"
+        );
+    }
+
+    #[test]
+    fn fallback_on_permission_denied() {
+        let sysfs = Sysfs::default();
+        let mock = Arc::new(MockBackend::new());
+        let backend = FallbackBackend::new(vec![
+            Arc::new(MockBackend::failing(io::ErrorKind::PermissionDenied)),
+            mock.clone(),
+        ]);
+        backend
+            .set_brightness(&sysfs, DeviceClass::Leds, "input2::capslock", 1)
+            .unwrap();
+        assert_eq!(
+            mock.get_brightness(DeviceClass::Leds, "input2::capslock"),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn fallback_stops_on_other_errors() {
+        let sysfs = Sysfs::default();
+        let mock = Arc::new(MockBackend::new());
+        let backend = FallbackBackend::new(vec![
+            Arc::new(MockBackend::failing(io::ErrorKind::NotFound)),
+            mock.clone(),
+        ]);
+        match backend.set_brightness(&sysfs, DeviceClass::Leds, "input2::capslock", 1) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert_eq!(
+            mock.get_brightness(DeviceClass::Leds, "input2::capslock"),
+            None
+        );
+    }
+
+    #[derive(Debug)]
+    struct BrightnessOnly;
+
+    impl BrightnessBackend for BrightnessOnly {
+        fn set_brightness(&self, _: &Sysfs, _: DeviceClass, _: &str, _: u32) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sysfs_backend_writes_power() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight"))
+            .unwrap();
+        SysfsBackend
+            .set_power(&fake.sysfs(), "intel_backlight", BacklightPower::Powerdown)
+            .unwrap();
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "bl_power").unwrap(),
+            "4"
+        );
+    }
+
+    #[test]
+    fn fallback_power_skips_unsupported() {
+        let mock = Arc::new(MockBackend::new());
+        let backend = FallbackBackend::new(vec![Arc::new(BrightnessOnly), mock.clone()]);
+        backend
+            .set_power(&Sysfs::default(), "acpi_video0", BacklightPower::Normal)
+            .unwrap();
+        assert_eq!(mock.get_power("acpi_video0"), Some(BacklightPower::Normal));
+        assert!(backend.can_set_power());
+        assert!(!BrightnessOnly.can_set_power());
+
+        let backend = FallbackBackend::new(vec![
+            Arc::new(MockBackend::failing(io::ErrorKind::PermissionDenied)),
+            Arc::new(BrightnessOnly),
+        ]);
+        match backend.set_power(&Sysfs::default(), "acpi_video0", BacklightPower::Normal) {
+            Err(Error::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::PermissionDenied),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn sysfs_backend_writes_trigger() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_led(&FakeLed::new("input2::capslock").trigger("[none] kbd-capslock"))
+            .unwrap();
+        SysfsBackend
+            .set_trigger(
+                &fake.sysfs(),
+                "input2::capslock",
+                &LedTrigger::from_id("kbd-capslock"),
+            )
+            .unwrap();
+        assert_eq!(
+            fake.read_led("input2::capslock", "trigger").unwrap(),
+            "kbd-capslock"
+        );
+    }
+
+    #[test]
+    fn fallback_exhausted() {
+        let backend = FallbackBackend::new(vec![Arc::new(MockBackend::failing(
+            io::ErrorKind::PermissionDenied,
+        ))]);
+        assert!(backend
+            .set_brightness(&Sysfs::default(), DeviceClass::Backlight, "acpi_video0", 1)
+            .is_err());
+    }
+}
@@ -0,0 +1,672 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{fmt, str::FromStr};
+
+use crate::{curve::Curve, error::Error};
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// How a fractional level is turned into a raw level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum Rounding {
+    /// To the nearest level, halves away from zero.
+    #[default]
+    Nearest,
+    /// To the level below.
+    Down,
+    /// To the level above.
+    Up,
+}
+
+impl Rounding {
+    fn round(self, raw: f64) -> f64 {
+        match self {
+            Rounding::Nearest => raw.round(),
+            Rounding::Down => raw.floor(),
+            Rounding::Up => raw.ceil(),
+        }
+    }
+}
+
+/// An amount of brightness, in raw levels or in percent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum Level {
+    /// Raw levels, as written to the driver.
+    Raw(u32),
+    /// Percent of the maximum.
+    Percent(f64),
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            Level::Raw(raw) => write!(f, "{}", raw),
+            Level::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// A brightness expression as given to brightnessctl, e.g. `50%`, `+10%`,
+/// `5-`, `max`.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::brightness::{BrightnessExpr, Level};
+///
+/// let expr: BrightnessExpr = "+10%".parse().unwrap();
+/// assert_eq!(expr, BrightnessExpr::Up(Level::Percent(10.0)));
+/// assert_eq!("3-".parse::<BrightnessExpr>().unwrap(), BrightnessExpr::Down(Level::Raw(3)));
+/// assert_eq!(
+///     "max".parse::<BrightnessExpr>().unwrap(),
+///     BrightnessExpr::Set(Level::Percent(100.0))
+/// );
+/// assert!("50%%".parse::<BrightnessExpr>().is_err());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum BrightnessExpr {
+    /// Set the level, `50%` or `500`.
+    Set(Level),
+    /// Raise the level, `+10%`, `10%+`, `+3` or `3+`.
+    Up(Level),
+    /// Lower the level, `-10%`, `10%-`, `-3` or `3-`.
+    Down(Level),
+}
+
+impl BrightnessExpr {
+    /// Parse a brightnessctl-style expression.
+    ///
+    /// Accepts `max` and `min`, an absolute level (`500`, `50%`) and a
+    /// relative one with the sign before or after it (`+10%`, `10%-`).
+    /// Raw levels must be whole numbers and absolute percentages within
+    /// `0..=100`; anything else fails with
+    /// [`Error::InvalidBrightnessExpression`].
+    pub fn parse(s: &str) -> Result<BrightnessExpr, Error> {
+        let invalid = || Error::InvalidBrightnessExpression {
+            given: s.to_string(),
+        };
+        let expr = s.trim();
+        match expr.to_ascii_lowercase().as_str() {
+            "max" => return Ok(BrightnessExpr::Set(Level::Percent(100.0))),
+            "min" => return Ok(BrightnessExpr::Set(Level::Raw(0))),
+            _ => (),
+        }
+
+        let (sign, amount) = match (expr.chars().next(), expr.chars().last()) {
+            (Some(c @ ('+' | '-')), _) => (Some(c), &expr[1..]),
+            (_, Some(c @ ('+' | '-'))) => (Some(c), &expr[..expr.len() - 1]),
+            _ => (None, expr),
+        };
+        if amount.starts_with(['+', '-']) || amount.ends_with(['+', '-']) {
+            return Err(invalid());
+        }
+        let level = match amount.strip_suffix('%') {
+            Some(percent) => {
+                let percent: f64 = percent.trim().parse().map_err(|_| invalid())?;
+                if !percent.is_finite() || percent < 0.0 || (sign.is_none() && percent > 100.0) {
+                    return Err(invalid());
+                }
+                Level::Percent(percent)
+            }
+            None => Level::Raw(amount.trim().parse().map_err(|_| invalid())?),
+        };
+        Ok(match sign {
+            Some('+') => BrightnessExpr::Up(level),
+            Some(_) => BrightnessExpr::Down(level),
+            None => BrightnessExpr::Set(level),
+        })
+    }
+}
+
+impl FromStr for BrightnessExpr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BrightnessExpr::parse(s)
+    }
+}
+
+impl fmt::Display for BrightnessExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            BrightnessExpr::Set(level) => write!(f, "{}", level),
+            BrightnessExpr::Up(level) => write!(f, "+{}", level),
+            BrightnessExpr::Down(level) => write!(f, "-{}", level),
+        }
+    }
+}
+
+/// A brightness level bound to the maximum of its device.
+///
+/// Converts between raw levels, fractions and percentages with explicit
+/// [`Rounding`], and never leaves `0..=max`.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::brightness::{Brightness, Level, Rounding};
+///
+/// let level = Brightness::from_percent(33.0, 255, Rounding::Nearest).unwrap();
+/// assert_eq!(level.get_raw(), 84);
+/// assert_eq!(Brightness::from_percent(33.0, 255, Rounding::Down).unwrap().get_raw(), 84);
+/// assert_eq!(Brightness::from_percent(33.0, 255, Rounding::Up).unwrap().get_raw(), 85);
+///
+/// let level = level.saturating_add(Level::Percent(80.0));
+/// assert_eq!(level.get_raw(), 255);
+/// assert_eq!(level.saturating_sub(Level::Raw(300)).get_raw(), 0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct Brightness {
+    raw: u32,
+    max: u32,
+}
+
+impl Brightness {
+    /// Level `raw` out of `max`.
+    ///
+    /// Fails with [`Error::InvalidBrightnessLevel`] if `raw` is above `max`.
+    pub fn new(raw: u32, max: u32) -> Result<Brightness, Error> {
+        if raw > max {
+            return Err(Error::InvalidBrightnessLevel { given: raw, max });
+        }
+        Ok(Brightness { raw, max })
+    }
+
+    /// Level `raw` out of `max`, clamped to `max`.
+    pub fn saturating(raw: u32, max: u32) -> Brightness {
+        Brightness {
+            raw: raw.min(max),
+            max,
+        }
+    }
+
+    /// Lowest level out of `max`.
+    pub fn off(max: u32) -> Brightness {
+        Brightness { raw: 0, max }
+    }
+
+    /// Highest level out of `max`.
+    pub fn full(max: u32) -> Brightness {
+        Brightness { raw: max, max }
+    }
+
+    /// Level at `fraction` (`0.0..=1.0`) of `max`.
+    ///
+    /// Fails with [`Error::InvalidPercentage`] if `fraction` is outside
+    /// `0.0..=1.0`.
+    pub fn from_fraction(fraction: f64, max: u32, rounding: Rounding) -> Result<Brightness, Error> {
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(Error::InvalidPercentage {
+                given: fraction * 100.0,
+            });
+        }
+        let raw = rounding.round(fraction * f64::from(max));
+        Ok(Brightness::saturating(raw as u32, max))
+    }
+
+    /// Level at `percent` (`0.0..=100.0`) of `max`.
+    pub fn from_percent(percent: f64, max: u32, rounding: Rounding) -> Result<Brightness, Error> {
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(Error::InvalidPercentage { given: percent });
+        }
+        Brightness::from_fraction(percent / 100.0, max, rounding)
+    }
+
+    /// Level at `percent` of perceived brightness along `curve`.
+    pub fn from_percent_with(
+        percent: f64,
+        max: u32,
+        curve: Curve,
+        rounding: Rounding,
+    ) -> Result<Brightness, Error> {
+        if !(0.0..=100.0).contains(&percent) {
+            return Err(Error::InvalidPercentage { given: percent });
+        }
+        Brightness::from_fraction(curve.to_fraction(percent / 100.0), max, rounding)
+    }
+
+    /// Get the raw level.
+    pub fn get_raw(self) -> u32 {
+        self.raw
+    }
+
+    /// Get the maximum level.
+    pub fn get_max(self) -> u32 {
+        self.max
+    }
+
+    /// Fraction (`0.0..=1.0`) of the maximum, `0.0` if the maximum is `0`.
+    pub fn to_fraction(self) -> f64 {
+        if self.max == 0 {
+            0.0
+        } else {
+            f64::from(self.raw) / f64::from(self.max)
+        }
+    }
+
+    /// Percent of the maximum.
+    pub fn to_percent(self) -> f64 {
+        self.to_fraction() * 100.0
+    }
+
+    /// Percent of perceived brightness along `curve`.
+    pub fn to_percent_with(self, curve: Curve) -> f64 {
+        curve.to_percent(self.raw, self.max)
+    }
+
+    /// Level `raw` out of the same maximum, clamped to it.
+    pub fn with_raw(self, raw: u32) -> Brightness {
+        Brightness::saturating(raw, self.max)
+    }
+
+    /// Same fraction of a different maximum.
+    pub fn rescale(self, max: u32, rounding: Rounding) -> Brightness {
+        let raw = rounding.round(self.to_fraction() * f64::from(max));
+        Brightness::saturating(raw as u32, max)
+    }
+
+    /// Raw levels `amount` stands for, percentages of the maximum rounded
+    /// to the nearest level.
+    pub fn raw_amount(self, amount: Level) -> u32 {
+        match amount {
+            Level::Raw(raw) => raw,
+            Level::Percent(percent) => {
+                let raw = (percent.max(0.0) / 100.0 * f64::from(self.max)).round();
+                raw.min(f64::from(u32::MAX)) as u32
+            }
+        }
+    }
+
+    /// Level raised by `amount`, stopping at the maximum.
+    pub fn saturating_add(self, amount: Level) -> Brightness {
+        self.with_raw(self.raw.saturating_add(self.raw_amount(amount)))
+    }
+
+    /// Level lowered by `amount`, stopping at `0`.
+    pub fn saturating_sub(self, amount: Level) -> Brightness {
+        self.with_raw(self.raw.saturating_sub(self.raw_amount(amount)))
+    }
+
+    /// Level after `expr`, with percentages taken as perceived
+    /// brightness along `curve`.
+    ///
+    /// Relative changes stop at `0` and the maximum.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{brightness::Brightness, curve::Curve};
+    ///
+    /// let level = Brightness::saturating(100, 1000);
+    /// let expr = "+10%".parse().unwrap();
+    /// assert_eq!(level.apply(expr, Curve::Linear).get_raw(), 200);
+    /// assert_eq!(level.apply("-3".parse().unwrap(), Curve::Linear).get_raw(), 97);
+    /// assert_eq!(level.apply("max".parse().unwrap(), Curve::Cie1931).get_raw(), 1000);
+    /// ```
+    pub fn apply(self, expr: BrightnessExpr, curve: Curve) -> Brightness {
+        let perceived = |percent: f64| {
+            let percent = if percent.is_nan() {
+                0.0
+            } else {
+                percent.clamp(0.0, 100.0)
+            };
+            Brightness::saturating(curve.to_raw(percent, self.max), self.max)
+        };
+        match expr {
+            BrightnessExpr::Set(Level::Raw(raw)) => self.with_raw(raw),
+            BrightnessExpr::Set(Level::Percent(percent)) => perceived(percent),
+            BrightnessExpr::Up(Level::Raw(raw)) => self.with_raw(self.raw.saturating_add(raw)),
+            BrightnessExpr::Up(Level::Percent(percent)) => {
+                perceived(self.to_percent_with(curve) + percent)
+            }
+            BrightnessExpr::Down(Level::Raw(raw)) => self.with_raw(self.raw.saturating_sub(raw)),
+            BrightnessExpr::Down(Level::Percent(percent)) => {
+                perceived(self.to_percent_with(curve) - percent)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Brightness {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.raw, self.max)
+    }
+}
+
+/// How big the steps of [`StepOptions`] are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum StepMode {
+    /// Every step moves the same number of raw levels.
+    #[default]
+    Linear,
+    /// Every step multiplies or divides the raw level by the same factor,
+    /// so steps are fine near black and coarse near full brightness, which
+    /// matches how the eye perceives them.
+    Exponential,
+}
+
+/// How to step brightness up or down, e.g. from brightness hotkeys.
+///
+/// Every step moves at least one raw level, and stepping down stops at a
+/// floor that is never black unless [`with_allow_off`](StepOptions::with_allow_off)
+/// says so.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::brightness::{Brightness, StepMode, StepOptions};
+///
+/// let options = StepOptions::new().with_steps(10);
+/// let level = Brightness::saturating(500, 1000);
+/// assert_eq!(options.step_up(level).get_raw(), 600);
+/// assert_eq!(options.step_down(Brightness::saturating(50, 1000)).get_raw(), 1);
+///
+/// let options = options.with_mode(StepMode::Exponential);
+/// assert_eq!(options.step_up(Brightness::saturating(1, 1000)).get_raw(), 2);
+/// assert_eq!(options.step_down(level).get_raw(), 250);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct StepOptions {
+    steps: u32,
+    mode: StepMode,
+    min_percent: f64,
+    allow_off: bool,
+}
+
+impl Default for StepOptions {
+    fn default() -> StepOptions {
+        StepOptions {
+            steps: 20,
+            mode: StepMode::Linear,
+            min_percent: 0.0,
+            allow_off: false,
+        }
+    }
+}
+
+impl StepOptions {
+    /// 20 linear steps from the lowest lit level to full brightness.
+    pub fn new() -> StepOptions {
+        StepOptions::default()
+    }
+
+    /// Go from black to full brightness in `steps` steps.
+    pub fn with_steps(mut self, steps: u32) -> StepOptions {
+        self.steps = steps.max(1);
+        self
+    }
+
+    /// Use `mode` step sizes.
+    pub fn with_mode(mut self, mode: StepMode) -> StepOptions {
+        self.mode = mode;
+        self
+    }
+
+    /// Never step down below `min_percent` of the maximum brightness.
+    pub fn with_min_percent(mut self, min_percent: f64) -> StepOptions {
+        self.min_percent = min_percent;
+        self
+    }
+
+    /// Whether stepping down may reach 0, turning the light off.
+    pub fn with_allow_off(mut self, allow_off: bool) -> StepOptions {
+        self.allow_off = allow_off;
+        self
+    }
+
+    /// Get the number of steps from black to full brightness.
+    pub fn get_steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// Get the step sizes.
+    pub fn get_mode(&self) -> StepMode {
+        self.mode
+    }
+
+    /// Lowest level stepping down reaches for `max`.
+    pub fn floor(&self, max: u32) -> u32 {
+        floor(self.min_percent, max, self.allow_off)
+    }
+
+    /// Level one step above `level`.
+    pub fn step_up(&self, level: Brightness) -> Brightness {
+        let (raw, max) = (level.get_raw(), level.get_max());
+        let next = match self.mode {
+            StepMode::Linear => f64::from(raw) + self.linear_step(max),
+            StepMode::Exponential => f64::from(raw) * self.factor(max),
+        };
+        let next = (next.ceil().min(f64::from(max)) as u32)
+            .max(raw.saturating_add(1))
+            .max(self.floor(max));
+        level.with_raw(next)
+    }
+
+    /// Level one step below `level`, but not below the
+    /// [`floor`](StepOptions::floor) unless it already was.
+    pub fn step_down(&self, level: Brightness) -> Brightness {
+        let (raw, max) = (level.get_raw(), level.get_max());
+        let floor = self.floor(max);
+        if raw <= floor {
+            return level;
+        }
+        let next = match self.mode {
+            StepMode::Linear => f64::from(raw) - self.linear_step(max),
+            StepMode::Exponential => f64::from(raw) / self.factor(max),
+        };
+        let next = (next.max(0.0).floor() as u32).min(raw - 1).max(floor);
+        level.with_raw(next)
+    }
+
+    fn linear_step(&self, max: u32) -> f64 {
+        (f64::from(max) / f64::from(self.steps)).round()
+    }
+
+    /// Factor that takes 1 to `max` in `steps` steps.
+    fn factor(&self, max: u32) -> f64 {
+        f64::from(max.max(1)).powf(1.0 / f64::from(self.steps))
+    }
+}
+
+/// Lowest level out of `max` that keeps `min_percent` of it, rounded up,
+/// and at least 1 unless `allow_off`.
+pub(crate) fn floor(min_percent: f64, max: u32, allow_off: bool) -> u32 {
+    let min_percent = if min_percent.is_nan() {
+        0.0
+    } else {
+        min_percent.clamp(0.0, 100.0)
+    };
+    let floor = (min_percent / 100.0 * f64::from(max)).ceil() as u32;
+    let floor = if allow_off { floor } else { floor.max(1) };
+    floor.min(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let cases = [
+            ("50%", BrightnessExpr::Set(Level::Percent(50.0))),
+            ("500", BrightnessExpr::Set(Level::Raw(500))),
+            ("+10%", BrightnessExpr::Up(Level::Percent(10.0))),
+            ("10%+", BrightnessExpr::Up(Level::Percent(10.0))),
+            ("-3", BrightnessExpr::Down(Level::Raw(3))),
+            ("3-", BrightnessExpr::Down(Level::Raw(3))),
+            ("2.5%-", BrightnessExpr::Down(Level::Percent(2.5))),
+            ("+150%", BrightnessExpr::Up(Level::Percent(150.0))),
+            (" MAX ", BrightnessExpr::Set(Level::Percent(100.0))),
+            ("min", BrightnessExpr::Set(Level::Raw(0))),
+        ];
+        for (s, expr) in cases.iter() {
+            assert_eq!(BrightnessExpr::parse(s).unwrap(), *expr, "{:?}", s);
+        }
+        for expr in cases.iter().map(|(_, expr)| expr) {
+            assert_eq!(BrightnessExpr::parse(&expr.to_string()).unwrap(), *expr);
+        }
+
+        let bad = [
+            "",
+            "%",
+            "+",
+            "+-3",
+            "+3-",
+            "--3",
+            "3.5",
+            "-3.5",
+            "101%",
+            "50%%",
+            "abc",
+            "+inf%",
+            "NaN%",
+            "-1%-",
+            "99999999999",
+        ];
+        for s in bad.iter() {
+            assert!(
+                matches!(
+                    BrightnessExpr::parse(s),
+                    Err(Error::InvalidBrightnessExpression { .. })
+                ),
+                "{:?}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        assert!(matches!(
+            Brightness::new(256, 255),
+            Err(Error::InvalidBrightnessLevel {
+                given: 256,
+                max: 255
+            })
+        ));
+        assert_eq!(Brightness::saturating(300, 255), Brightness::full(255));
+        assert_eq!(Brightness::new(0, 255).unwrap(), Brightness::off(255));
+
+        let half = Brightness::from_fraction(0.5, 7, Rounding::Nearest).unwrap();
+        assert_eq!(half.get_raw(), 4);
+        assert_eq!(
+            Brightness::from_fraction(0.5, 7, Rounding::Down)
+                .unwrap()
+                .get_raw(),
+            3
+        );
+        assert!(matches!(
+            Brightness::from_percent(100.5, 7, Rounding::Nearest),
+            Err(Error::InvalidPercentage { .. })
+        ));
+        assert_eq!(
+            Brightness::from_percent_with(50.0, 1000, Curve::Cie1931, Rounding::Nearest)
+                .unwrap()
+                .get_raw(),
+            184
+        );
+        assert!((half.to_percent() - 400.0 / 7.0).abs() < 1e-9);
+        assert_eq!(Brightness::off(0).to_fraction(), 0.0);
+        assert_eq!(half.rescale(100, Rounding::Down).get_raw(), 57);
+        assert_eq!(half.to_string(), "4/7");
+    }
+
+    #[test]
+    fn steps() {
+        let level = Brightness::saturating(50, 100);
+        assert_eq!(level.saturating_add(Level::Raw(u32::MAX)).get_raw(), 100);
+        assert_eq!(level.saturating_sub(Level::Percent(60.0)).get_raw(), 0);
+        assert_eq!(level.saturating_add(Level::Percent(5.0)).get_raw(), 55);
+        assert_eq!(level.saturating_add(Level::Percent(1e12)).get_raw(), 100);
+
+        let apply = |s: &str, curve| level.apply(s.parse().unwrap(), curve).get_raw();
+        assert_eq!(apply("+10%", Curve::Linear), 60);
+        assert_eq!(apply("10%-", Curve::Linear), 40);
+        assert_eq!(apply("-200%", Curve::Linear), 0);
+        assert_eq!(apply("+200", Curve::Linear), 100);
+        assert_eq!(apply("70", Curve::Linear), 70);
+        assert_eq!(apply("0%", Curve::Cie1931), 0);
+        // 50 out of 100 is ~76% lightness
+        assert_eq!(apply("+10%", Curve::Cie1931), 68);
+    }
+
+    #[test]
+    fn linear_steps() {
+        let options = StepOptions::new();
+        let mut level = Brightness::off(1000);
+        let mut levels = Vec::new();
+        while level.get_raw() < 1000 {
+            level = options.step_up(level);
+            levels.push(level.get_raw());
+        }
+        assert_eq!(levels.len(), 20);
+        assert_eq!(levels[..3], [50, 100, 150]);
+
+        // never black, and up again from the floor
+        let level = options.step_down(Brightness::saturating(30, 1000));
+        assert_eq!(level.get_raw(), 1);
+        assert_eq!(options.step_down(level), level);
+        assert_eq!(options.step_up(level).get_raw(), 51);
+        let options = options.with_allow_off(true);
+        assert_eq!(options.step_down(level).get_raw(), 0);
+
+        // at least one raw level per step
+        let options = StepOptions::new().with_steps(100);
+        assert_eq!(options.step_up(Brightness::saturating(3, 7)).get_raw(), 4);
+        assert_eq!(options.step_down(Brightness::saturating(3, 7)).get_raw(), 2);
+        assert_eq!(options.step_up(Brightness::full(7)), Brightness::full(7));
+    }
+
+    #[test]
+    fn exponential_steps() {
+        let options = StepOptions::new()
+            .with_mode(StepMode::Exponential)
+            .with_steps(10);
+        let mut level = Brightness::off(1000);
+        let mut levels = Vec::new();
+        while level.get_raw() < 1000 {
+            level = options.step_up(level);
+            levels.push(level.get_raw());
+        }
+        assert_eq!(levels[..4], [1, 2, 4, 8]);
+        assert!(levels.len() <= 12, "{:?}", levels);
+        assert!(levels.windows(2).all(|w| w[1] > w[0]));
+
+        let mut downs = vec![level.get_raw()];
+        while level.get_raw() > 1 {
+            level = options.step_down(level);
+            downs.push(level.get_raw());
+        }
+        assert_eq!(downs[..3], [1000, 501, 251]);
+        assert_eq!(level.get_raw(), 1);
+
+        // binary LED
+        let led = Brightness::off(1);
+        assert_eq!(options.step_up(led).get_raw(), 1);
+        assert_eq!(options.step_down(Brightness::full(1)).get_raw(), 1);
+
+        let options = options.with_min_percent(10.0);
+        assert_eq!(options.floor(1000), 100);
+        assert_eq!(
+            options
+                .step_down(Brightness::saturating(150, 1000))
+                .get_raw(),
+            100
+        );
+        assert_eq!(options.step_up(Brightness::off(1000)).get_raw(), 100);
+    }
+}
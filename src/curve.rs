@@ -0,0 +1,203 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::fmt;
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Mapping between a percentage (what a user sees on a slider) and a raw
+/// brightness level (what the driver is given).
+///
+/// Percentages are `0.0..=100.0` and raw levels `0..=max_brightness`.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::curve::Curve;
+///
+/// assert_eq!(Curve::Linear.to_raw(50.0, 120000), 60000);
+/// assert_eq!(Curve::Exponential(4.0).to_raw(50.0, 120000), 7500);
+/// assert_eq!(Curve::Linear.to_raw(40.0, 1), 0);
+/// assert_eq!(Curve::Linear.to_raw(60.0, 1), 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum Curve {
+    /// Percentage is directly proportional to the raw level.
+    #[default]
+    Linear,
+    /// `raw = max * (percent / 100) ^ exponent`, the same curve brightnessctl
+    /// uses with `-e` (which defaults to an exponent of 4), or a gamma curve,
+    /// e.g. `2.2` for sRGB-like displays.
+    ///
+    /// Exponents that are not finite and greater than 0 are treated as 1.
+    #[doc(alias = "gamma")]
+    Exponential(f64),
+    /// Percentage is the CIE 1931 lightness `L*` and the raw level its luminance.
+    Cie1931,
+}
+
+impl Curve {
+    /// Fraction (`0.0..=1.0`) of `max_brightness` for fraction of perceived brightness.
+    pub fn to_fraction(self, perceived: f64) -> f64 {
+        let perceived = clamp_fraction(perceived);
+        let fraction = match self {
+            Curve::Linear => perceived,
+            Curve::Exponential(exponent) => perceived.powf(valid_exponent(exponent)),
+            Curve::Cie1931 => {
+                let lightness = perceived * 100.0;
+                if lightness > 8.0 {
+                    ((lightness + 16.0) / 116.0).powi(3)
+                } else {
+                    lightness / 903.3
+                }
+            }
+        };
+        clamp_fraction(fraction)
+    }
+
+    /// Fraction of perceived brightness for fraction (`0.0..=1.0`) of `max_brightness`.
+    pub fn from_fraction(self, fraction: f64) -> f64 {
+        let fraction = clamp_fraction(fraction);
+        let perceived = match self {
+            Curve::Linear => fraction,
+            Curve::Exponential(exponent) => fraction.powf(1.0 / valid_exponent(exponent)),
+            Curve::Cie1931 => {
+                if fraction > 0.008856 {
+                    (116.0 * fraction.cbrt() - 16.0) / 100.0
+                } else {
+                    903.3 * fraction / 100.0
+                }
+            }
+        };
+        clamp_fraction(perceived)
+    }
+
+    /// Raw level for `percent` of perceived brightness, rounded to the nearest level.
+    pub fn to_raw(self, percent: f64, max_brightness: u32) -> u32 {
+        let raw = (self.to_fraction(percent / 100.0) * f64::from(max_brightness)).round();
+        (raw as u32).min(max_brightness)
+    }
+
+    /// Percentage of perceived brightness for `raw` level.
+    pub fn to_percent(self, raw: u32, max_brightness: u32) -> f64 {
+        if max_brightness == 0 {
+            return 0.0;
+        }
+        self.from_fraction(f64::from(raw) / f64::from(max_brightness)) * 100.0
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            Curve::Linear => write!(f, "linear"),
+            Curve::Exponential(exponent) => write!(f, "exponential({})", exponent),
+            Curve::Cie1931 => write!(f, "cie1931"),
+        }
+    }
+}
+
+fn valid_exponent(exponent: f64) -> f64 {
+    if exponent.is_finite() && exponent > 0.0 {
+        exponent
+    } else {
+        1.0
+    }
+}
+
+fn clamp_fraction(fraction: f64) -> f64 {
+    if fraction.is_nan() {
+        0.0
+    } else {
+        fraction.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURVES: [Curve; 4] = [
+        Curve::Linear,
+        Curve::Exponential(4.0),
+        Curve::Cie1931,
+        Curve::Exponential(2.2),
+    ];
+
+    #[test]
+    fn end_points() {
+        for curve in CURVES.iter() {
+            assert_eq!(curve.to_raw(0.0, 120000), 0, "{}", curve);
+            assert_eq!(curve.to_raw(100.0, 120000), 120000, "{}", curve);
+            assert_eq!(curve.to_raw(150.0, 255), 255, "{}", curve);
+            assert_eq!(curve.to_raw(-5.0, 255), 0, "{}", curve);
+            assert!(curve.to_percent(0, 255).abs() < 1e-9, "{}", curve);
+            assert!(
+                (curve.to_percent(255, 255) - 100.0).abs() < 1e-9,
+                "{}",
+                curve
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for curve in CURVES.iter() {
+            for raw in (0..=120000).step_by(997) {
+                let percent = curve.to_percent(raw, 120000);
+                assert_eq!(curve.to_raw(percent, 120000), raw, "{} {}", curve, percent);
+            }
+        }
+    }
+
+    #[test]
+    fn monotonic() {
+        for curve in CURVES.iter() {
+            let mut last = 0;
+            for percent in (0..=100).map(f64::from) {
+                let raw = curve.to_raw(percent, 937);
+                assert!(raw >= last, "{} {}", curve, percent);
+                last = raw;
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_exponents() {
+        for exponent in [-2.0, 0.0, f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let curve = Curve::Exponential(exponent);
+            assert_eq!(curve.to_raw(0.0, 255), 0, "{}", curve);
+            assert_eq!(curve.to_raw(50.0, 1000), 500, "{}", curve);
+            assert_eq!(curve.to_raw(100.0, 255), 255, "{}", curve);
+            assert_eq!(curve.to_percent(500, 1000), 50.0, "{}", curve);
+        }
+    }
+
+    #[test]
+    fn cie1931_lightness() {
+        // 50% lightness is ~18.4% luminance
+        assert_eq!(Curve::Cie1931.to_raw(50.0, 1000), 184);
+        assert!((Curve::Cie1931.to_percent(184, 1000) - 50.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn binary_led() {
+        assert_eq!(Curve::Linear.to_raw(50.0, 1), 1);
+        assert_eq!(Curve::Cie1931.to_raw(50.0, 1), 0);
+        assert_eq!(Curve::Cie1931.to_percent(1, 1), 100.0);
+    }
+
+    #[test]
+    fn zero_max_brightness() {
+        assert_eq!(Curve::Linear.to_raw(50.0, 0), 0);
+        assert_eq!(Curve::Linear.to_percent(0, 0), 0.0);
+    }
+}
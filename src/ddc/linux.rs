@@ -0,0 +1,474 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, prelude::*},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use super::{
+    Capabilities, Ddc, DdcPowerMode, DdcTiming, I2cBus, VcpValue, VCP_CONTRAST, VCP_LUMINANCE,
+    VCP_POWER_MODE,
+};
+use crate::{
+    curve::Curve,
+    drm::DrmConnector,
+    edid::Edid,
+    error::Error,
+    sysfs::{Scan, Sysfs},
+};
+
+/// `ioctl` selecting the address later reads and writes go to, from
+/// `linux/i2c-dev.h`.
+const I2C_SLAVE: libc::c_ulong = 0x0703;
+
+/// An I2C bus device, `/dev/i2c-<N>`.
+#[derive(Debug)]
+pub struct I2cDevice {
+    path: PathBuf,
+    file: File,
+    address: Option<u16>,
+}
+
+impl I2cDevice {
+    /// Open the I2C bus at `path`, e.g. `/dev/i2c-4`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<I2cDevice, Error> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        Ok(I2cDevice {
+            path,
+            file,
+            address: None,
+        })
+    }
+
+    /// Get the path of the bus.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    fn select(&mut self, address: u16) -> io::Result<()> {
+        if self.address != Some(address) {
+            // SAFETY: I2C_SLAVE takes the address as an integer argument and
+            // the file descriptor is open for as long as `self.file` lives.
+            let res = unsafe {
+                libc::ioctl(
+                    self.file.as_raw_fd(),
+                    I2C_SLAVE as _,
+                    libc::c_ulong::from(address),
+                )
+            };
+            if res < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.address = Some(address);
+        }
+        Ok(())
+    }
+}
+
+impl I2cBus for I2cDevice {
+    fn write(&mut self, address: u16, data: &[u8]) -> io::Result<()> {
+        self.select(address)?;
+        self.file.write_all(data)
+    }
+
+    fn read(&mut self, address: u16, buf: &mut [u8]) -> io::Result<()> {
+        self.select(address)?;
+        self.file.read_exact(buf)
+    }
+}
+
+type SharedDdc = Arc<Mutex<Ddc<Box<dyn I2cBus + Send>>>>;
+
+/// External monitor controlled over DDC/CI.
+///
+/// Offers the same brightness methods as
+/// [`MonitorDevice`](crate::monitor::MonitorDevice), with brightness being
+/// the display's luminance (VCP `0x10`). Clones share the same connection.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::{curve::Curve, ddc::DdcMonitor};
+///
+/// for monitor in DdcMonitor::get_all_ddc_monitors().unwrap() {
+///     println!("{}: {}%", monitor.get_device_name(), monitor.get_percent(Curve::Linear));
+///     monitor.set_percent(40.0, Curve::Linear).unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct DdcMonitor {
+    /// Name of the I2C bus, e.g. `i2c-4`.
+    pub device: String,
+    /// DRM connector the display is attached to, e.g. `card0-DP-1`.
+    pub connector: Option<String>,
+    /// Luminance when the monitor was read.
+    pub brightness: u32,
+    /// Maximum luminance.
+    pub max_brightness: u32,
+    power: bool,
+    ddc: SharedDdc,
+}
+
+impl fmt::Debug for DdcMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DdcMonitor")
+            .field("device", &self.device)
+            .field("connector", &self.connector)
+            .field("brightness", &self.brightness)
+            .field("max_brightness", &self.max_brightness)
+            .field("power", &self.power)
+            .finish()
+    }
+}
+
+impl DdcMonitor {
+    /// Get the monitor on `/dev/<device>`, e.g. `i2c-4`.
+    pub fn get_ddc_monitor(device: &str) -> Result<DdcMonitor, Error> {
+        let bus = I2cDevice::open(Path::new("/dev").join(device))?;
+        DdcMonitor::from_bus(device, Box::new(bus), DdcTiming::default())
+    }
+
+    /// Get the external monitor whose EDID has the serial number `serial`.
+    ///
+    /// Fails with [`Error::SerialNotFound`] if no connected external
+    /// connector has the serial number.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::ddc::DdcMonitor;
+    ///
+    /// let monitor = DdcMonitor::get_ddc_monitor_by_serial("JKNM0C3").unwrap();
+    /// ```
+    pub fn get_ddc_monitor_by_serial(serial: &str) -> Result<DdcMonitor, Error> {
+        let (bus, connector) = DdcMonitor::ddc_bus_by_serial_in(&Sysfs::default(), serial)?;
+        let mut monitor = DdcMonitor::get_ddc_monitor(&bus)?;
+        monitor.connector = Some(connector);
+        Ok(monitor)
+    }
+
+    /// Get the monitor answering on `bus`, reading its luminance and
+    /// probing its power mode.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::ddc::{DdcMonitor, DdcTiming, I2cDevice};
+    ///
+    /// let bus = I2cDevice::open("/dev/i2c-4").unwrap();
+    /// let timing = DdcTiming {
+    ///     attempts: 5,
+    ///     ..DdcTiming::default()
+    /// };
+    /// let monitor = DdcMonitor::from_bus("i2c-4", Box::new(bus), timing).unwrap();
+    /// println!("{}/{}", monitor.get_brightness(), monitor.get_max_brightness());
+    /// ```
+    pub fn from_bus(
+        device: &str,
+        bus: Box<dyn I2cBus + Send>,
+        timing: DdcTiming,
+    ) -> Result<DdcMonitor, Error> {
+        let mut monitor = DdcMonitor {
+            device: device.to_string(),
+            connector: None,
+            brightness: 0,
+            max_brightness: 0,
+            power: false,
+            ddc: Arc::new(Mutex::new(Ddc::new(bus).with_timing(timing))),
+        };
+        monitor.refresh()?;
+        monitor.power = monitor.get_vcp(VCP_POWER_MODE).is_ok();
+        Ok(monitor)
+    }
+
+    /// Get every external monitor that answers DDC/CI.
+    ///
+    /// Only the DDC buses of connected external DRM connectors are probed,
+    /// other I2C buses may hold devices that must not be written to.
+    /// Displays that cannot be opened, usually for lack of permission on
+    /// `/dev/i2c-*`, or do not answer are skipped, use
+    /// [`scan_ddc_monitors`](DdcMonitor::scan_ddc_monitors) to find out why.
+    pub fn get_all_ddc_monitors() -> Result<Vec<DdcMonitor>, Error> {
+        Ok(DdcMonitor::scan_ddc_monitors()?.devices)
+    }
+
+    /// Get every external monitor that answers DDC/CI along with the errors
+    /// for those that could not be opened or read, named by their bus.
+    pub fn scan_ddc_monitors() -> Result<Scan<DdcMonitor>, Error> {
+        DdcMonitor::scan_ddc_monitors_in(&Sysfs::default(), Path::new("/dev"))
+    }
+
+    /// Like [`scan_ddc_monitors`](DdcMonitor::scan_ddc_monitors), with the
+    /// buses listed from `sysfs` and opened in `dev`.
+    pub(crate) fn scan_ddc_monitors_in(
+        sysfs: &Sysfs,
+        dev: &Path,
+    ) -> Result<Scan<DdcMonitor>, Error> {
+        let mut scan = Scan::default();
+        for (bus, connector) in DdcMonitor::ddc_buses_in(sysfs)? {
+            let res = I2cDevice::open(dev.join(&bus))
+                .and_then(|i2c| DdcMonitor::from_bus(&bus, Box::new(i2c), DdcTiming::default()));
+            match res {
+                Ok(mut monitor) => {
+                    monitor.connector = Some(connector);
+                    scan.devices.push(monitor);
+                }
+                Err(e) => scan.errors.push((bus, e)),
+            }
+        }
+        Ok(scan)
+    }
+
+    /// Get the DDC bus and connector name of every connected external
+    /// connector in the given sysfs tree, e.g. `("i2c-4", "card0-DP-1")`.
+    pub fn ddc_buses_in(sysfs: &Sysfs) -> Result<Vec<(String, String)>, Error> {
+        let mut buses = Vec::new();
+        for connector in DrmConnector::get_all_connectors_in(sysfs)? {
+            if connector.is_internal() || !connector.is_connected() {
+                continue;
+            }
+            let dir = sysfs.drm_dir().join(connector.get_name());
+            let bus = fs::read_link(dir.join("ddc"))
+                .ok()
+                .and_then(|link| link.file_name()?.to_str().map(str::to_string));
+            if let Some(bus) = bus {
+                buses.push((bus, connector.name));
+            }
+        }
+        Ok(buses)
+    }
+
+    /// Get the DDC bus and connector name of the connected external
+    /// connector whose EDID has the serial number `serial` in the given
+    /// sysfs tree.
+    ///
+    /// Connectors whose EDID cannot be read are skipped.
+    pub fn ddc_bus_by_serial_in(sysfs: &Sysfs, serial: &str) -> Result<(String, String), Error> {
+        DdcMonitor::ddc_buses_in(sysfs)?
+            .into_iter()
+            .find(|(_, connector)| {
+                let edid = DrmConnector::get_connector_in(sysfs, connector)
+                    .and_then(|connector| connector.read_edid())
+                    .ok()
+                    .flatten();
+                edid.and_then(|edid| edid.get_serial()).as_deref() == Some(serial)
+            })
+            .ok_or_else(|| Error::SerialNotFound {
+                serial: serial.to_string(),
+            })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Ddc<Box<dyn I2cBus + Send>>> {
+        self.ddc.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get name of the I2C bus of monitor.
+    pub fn get_device_name(&self) -> &str {
+        &self.device
+    }
+
+    /// Read the EDID of monitor from its DRM connector.
+    ///
+    /// `None` if the connector of monitor is not known.
+    pub fn get_edid(&self) -> Result<Option<Edid>, Error> {
+        match &self.connector {
+            Some(connector) => DrmConnector::get_connector(connector)?.read_edid(),
+            None => Ok(None),
+        }
+    }
+
+    /// Get luminance of monitor when it was last read.
+    pub fn get_brightness(&self) -> u32 {
+        self.brightness
+    }
+
+    /// Get maximum luminance of monitor.
+    pub fn get_max_brightness(&self) -> u32 {
+        self.max_brightness
+    }
+
+    /// Get percentage of perceived brightness of monitor.
+    pub fn get_percent(&self, curve: Curve) -> f64 {
+        curve.to_percent(self.brightness, self.max_brightness)
+    }
+
+    /// Re-read luminance of monitor from the display.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        let value = self.get_vcp(VCP_LUMINANCE)?;
+        self.brightness = u32::from(value.current);
+        self.max_brightness = u32::from(value.max);
+        Ok(())
+    }
+
+    /// Read the current luminance of monitor from the display, without
+    /// updating [`get_brightness`](DdcMonitor::get_brightness).
+    pub fn read_brightness(&self) -> Result<u32, Error> {
+        Ok(u32::from(self.get_vcp(VCP_LUMINANCE)?.current))
+    }
+
+    /// Set luminance of monitor.
+    pub fn set_brightness(&self, level: u32) -> Result<(), Error> {
+        if level > self.max_brightness {
+            return Err(Error::InvalidBrightnessLevel {
+                given: level,
+                max: self.max_brightness,
+            });
+        }
+        self.set_vcp(VCP_LUMINANCE, level as u16)
+    }
+
+    /// Set luminance of monitor to a percentage of perceived brightness.
+    pub fn set_percent(&self, percent: f64, curve: Curve) -> Result<(), Error> {
+        if (0.0..=100.0).contains(&percent) {
+            self.set_brightness(curve.to_raw(percent, self.max_brightness))
+        } else {
+            Err(Error::InvalidPercentage { given: percent })
+        }
+    }
+
+    /// Read contrast of monitor.
+    pub fn get_contrast(&self) -> Result<VcpValue, Error> {
+        self.get_vcp(VCP_CONTRAST)
+    }
+
+    /// Set contrast of monitor.
+    pub fn set_contrast(&self, contrast: u16) -> Result<(), Error> {
+        self.set_vcp(VCP_CONTRAST, contrast)
+    }
+
+    /// Whether the display answered a read of its power mode (VCP `0xD6`)
+    /// when it was opened.
+    pub fn supports_power_mode(&self) -> bool {
+        self.power
+    }
+
+    /// Read power mode of monitor.
+    pub fn get_power_mode(&self) -> Result<DdcPowerMode, Error> {
+        Ok(DdcPowerMode::from_id(self.get_vcp(VCP_POWER_MODE)?.current))
+    }
+
+    /// Set power mode of monitor.
+    pub fn set_power_mode(&self, mode: DdcPowerMode) -> Result<(), Error> {
+        self.set_vcp(VCP_POWER_MODE, mode.id())
+    }
+
+    /// Read any VCP feature of monitor.
+    pub fn get_vcp(&self, code: u8) -> Result<VcpValue, Error> {
+        self.lock().get_vcp(code)
+    }
+
+    /// Write any VCP feature of monitor.
+    pub fn set_vcp(&self, code: u8, value: u16) -> Result<(), Error> {
+        self.lock().set_vcp(code, value)
+    }
+
+    /// Read and parse the capabilities of monitor, i.e. which VCP features
+    /// it supports and which values they accept.
+    pub fn get_capabilities(&self) -> Result<Capabilities, Error> {
+        self.lock().get_capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{FakeConnector, FakeDdcDisplay, FakeSysfs};
+
+    fn fake_monitor() -> (FakeDdcDisplay, DdcMonitor) {
+        let display = FakeDdcDisplay::new()
+            .feature(VCP_LUMINANCE, 30, 60)
+            .feature(VCP_CONTRAST, 75, 100);
+        let monitor =
+            DdcMonitor::from_bus("i2c-4", Box::new(display.clone()), DdcTiming::immediate())
+                .unwrap();
+        (display, monitor)
+    }
+
+    #[test]
+    fn brightness() {
+        let (display, mut monitor) = fake_monitor();
+        assert_eq!(monitor.get_brightness(), 30);
+        assert_eq!(monitor.get_max_brightness(), 60);
+        assert_eq!(monitor.get_percent(Curve::Linear), 50.0);
+
+        monitor.set_percent(100.0, Curve::Linear).unwrap();
+        assert_eq!(display.get(VCP_LUMINANCE), Some(60));
+        assert!(matches!(
+            monitor.set_brightness(61),
+            Err(Error::InvalidBrightnessLevel { given: 61, max: 60 })
+        ));
+
+        display.set(VCP_LUMINANCE, 12);
+        assert_eq!(monitor.read_brightness().unwrap(), 12);
+        assert_eq!(monitor.get_brightness(), 30);
+        monitor.refresh().unwrap();
+        assert_eq!(monitor.get_brightness(), 12);
+    }
+
+    #[test]
+    fn contrast_and_power() {
+        let (display, monitor) = fake_monitor();
+        assert_eq!(monitor.get_contrast().unwrap().current, 75);
+        monitor.set_contrast(50).unwrap();
+        assert_eq!(display.get(VCP_CONTRAST), Some(50));
+
+        assert_eq!(monitor.get_power_mode().unwrap(), DdcPowerMode::On);
+        monitor.set_power_mode(DdcPowerMode::Standby).unwrap();
+        assert_eq!(monitor.get_power_mode().unwrap(), DdcPowerMode::Standby);
+    }
+
+    #[test]
+    fn buses() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_connector(&FakeConnector::new("card0-eDP-1").ddc("i2c-3"))
+            .unwrap();
+        fake.add_connector(&FakeConnector::new("card0-DP-1").ddc("i2c-4"))
+            .unwrap();
+        fake.add_connector(
+            &FakeConnector::new("card0-HDMI-A-1")
+                .ddc("i2c-5")
+                .status("disconnected"),
+        )
+        .unwrap();
+        fake.add_connector(&FakeConnector::new("card0-DP-2"))
+            .unwrap();
+        assert_eq!(
+            DdcMonitor::ddc_buses_in(&fake.sysfs()).unwrap(),
+            vec![(String::from("i2c-4"), String::from("card0-DP-1"))]
+        );
+    }
+
+    #[test]
+    fn bus_by_serial() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_connector(&FakeConnector::new("card0-DP-1").ddc("i2c-4"))
+            .unwrap();
+        fake.add_connector(
+            &FakeConnector::new("card0-DP-2")
+                .ddc("i2c-6")
+                .edid(&crate::edid::sample_edid()),
+        )
+        .unwrap();
+        assert_eq!(
+            DdcMonitor::ddc_bus_by_serial_in(&fake.sysfs(), "JKNM0C3").unwrap(),
+            (String::from("i2c-6"), String::from("card0-DP-2"))
+        );
+        assert!(matches!(
+            DdcMonitor::ddc_bus_by_serial_in(&fake.sysfs(), "nope"),
+            Err(Error::SerialNotFound { serial }) if serial == "nope"
+        ));
+    }
+}
@@ -0,0 +1,554 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{collections::BTreeMap, fmt};
+
+use crate::error::Error;
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// A VCP feature a display lists in its capabilities.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct VcpFeature {
+    /// VCP code, e.g. `0x60` for the input source.
+    pub code: u8,
+    /// Values the display accepts, empty if any value up to the maximum
+    /// it reports is accepted.
+    pub values: Vec<u8>,
+}
+
+impl VcpFeature {
+    /// Get what the MCCS standard says about this feature.
+    pub fn get_info(&self) -> Option<&'static VcpInfo> {
+        VcpInfo::lookup(self.code)
+    }
+
+    /// Whether the display accepts `value` for this feature.
+    pub fn accepts(&self, value: u16) -> bool {
+        self.values.is_empty() || self.values.iter().any(|&v| u16::from(v) == value)
+    }
+}
+
+/// Parsed MCCS capabilities string of a display.
+///
+/// Displays report which VCP features they support, and which values of
+/// non-continuous features they accept, in a string such as
+/// `(prot(monitor)type(LCD)model(U2415)vcp(10 12 60(0F 11 12) D6(01 04))mccs_ver(2.1))`.
+///
+/// The parser is lenient like the displays that produce these strings:
+/// the outer parentheses and a truncated end are tolerated, and hex codes
+/// may be written without spaces between them.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::ddc::{Capabilities, VCP_LUMINANCE};
+///
+/// let caps = Capabilities::parse(
+///     "(prot(monitor)type(LCD)model(U2415)vcp(10 12 60(0F 11 12))mccs_ver(2.1))",
+/// )
+/// .unwrap();
+/// assert_eq!(caps.model.as_deref(), Some("U2415"));
+/// assert!(caps.supports(VCP_LUMINANCE));
+/// assert_eq!(caps.get_values(0x60), Some(&[0x0F, 0x11, 0x12][..]));
+/// assert_eq!(caps.mccs_version, Some((2, 1)));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct Capabilities {
+    /// `prot`, usually `monitor`.
+    pub protocol: Option<String>,
+    /// `type`, e.g. `LCD` or `CRT`.
+    pub display_type: Option<String>,
+    /// `model`, e.g. `U2415`.
+    pub model: Option<String>,
+    /// `cmds`, the DDC/CI commands the display understands.
+    pub commands: Vec<u8>,
+    /// `vcp`, the supported VCP features by code.
+    pub features: BTreeMap<u8, VcpFeature>,
+    /// `mccs_ver`, e.g. `(2, 1)`.
+    pub mccs_version: Option<(u8, u8)>,
+    /// `vcpname`, names the display gives to manufacturer specific features.
+    pub feature_names: BTreeMap<u8, String>,
+    /// Every other entry, e.g. `("mswhql", "1")`.
+    pub other: Vec<(String, String)>,
+}
+
+impl Capabilities {
+    /// Parse a capabilities string.
+    ///
+    /// Fails with [`Error::ParseCapabilitiesError`] if the string is not a
+    /// list of `key(value)` entries or `cmds` or `vcp` contain something
+    /// other than hex codes.
+    pub fn parse(s: &str) -> Result<Capabilities, Error> {
+        let mut caps = Capabilities::default();
+        for (key, value) in entries(s)? {
+            match key.as_str() {
+                "prot" => caps.protocol = Some(value.trim().to_string()),
+                "type" => caps.display_type = Some(value.trim().to_string()),
+                "model" => caps.model = Some(value.trim().to_string()),
+                "cmds" => {
+                    caps.commands = codes(&value)?.into_iter().map(|(code, _)| code).collect()
+                }
+                "vcp" => {
+                    for (code, values) in codes(&value)? {
+                        let values = match values {
+                            Some(values) => hex_list(&values)?,
+                            None => Vec::new(),
+                        };
+                        caps.features.insert(code, VcpFeature { code, values });
+                    }
+                }
+                "mccs_ver" => {
+                    caps.mccs_version = value.trim().split_once('.').and_then(|(major, minor)| {
+                        Some((major.trim().parse().ok()?, minor.trim().parse().ok()?))
+                    })
+                }
+                "vcpname" => {
+                    for (code, name) in codes(&value)? {
+                        if let Some(name) = name {
+                            caps.feature_names.insert(code, name.trim().to_string());
+                        }
+                    }
+                }
+                _ => caps.other.push((key, value)),
+            }
+        }
+        Ok(caps)
+    }
+
+    /// Whether the display supports VCP feature `code`.
+    pub fn supports(&self, code: u8) -> bool {
+        self.features.contains_key(&code)
+    }
+
+    /// Get VCP feature `code` if the display supports it.
+    pub fn get_feature(&self, code: u8) -> Option<&VcpFeature> {
+        self.features.get(&code)
+    }
+
+    /// Get the values the display accepts for VCP feature `code`, empty
+    /// for continuous features and `None` if it is not supported.
+    pub fn get_values(&self, code: u8) -> Option<&[u8]> {
+        self.features.get(&code).map(|feature| &feature.values[..])
+    }
+
+    /// Get the name of VCP feature `code`, preferring the name the display
+    /// gives it.
+    pub fn get_feature_name(&self, code: u8) -> Option<&str> {
+        self.feature_names
+            .get(&code)
+            .map(String::as_str)
+            .or_else(|| VcpInfo::lookup(code).map(|info| info.name))
+    }
+}
+
+/// Split `key(value)key(value)...` into its entries, nested parentheses
+/// stay in the value.
+fn entries(s: &str) -> Result<Vec<(String, String)>, Error> {
+    let invalid = |reason: String| Err(Error::ParseCapabilitiesError { reason });
+    let mut s = s.trim().trim_end_matches('\0').trim();
+    if let Some(inner) = s.strip_prefix('(') {
+        s = inner.strip_suffix(')').unwrap_or(inner);
+    }
+    if s.is_empty() {
+        return invalid(String::from("empty string"));
+    }
+
+    let mut entries = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let open = match s[start..].find('(') {
+            Some(i) => start + i,
+            None => return invalid(format!("{:?} has no value", &s[start..])),
+        };
+        let key = s[start..open].trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return invalid(format!("{:?} is not a key", key));
+        }
+        while chars.next_if(|&(i, _)| i <= open).is_some() {}
+
+        let mut depth = 1;
+        let mut end = s.len();
+        for (i, c) in chars.by_ref() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                end = i;
+                break;
+            }
+        }
+        // a truncated string ends inside the last value
+        entries.push((key.to_string(), s[open + 1..end].to_string()));
+    }
+    Ok(entries)
+}
+
+/// Parse `02 10 60(0F 11) E3(Eco View)` into codes with their parenthesised
+/// values, codes may also be packed as `021060`.
+fn codes(s: &str) -> Result<Vec<(u8, Option<String>)>, Error> {
+    let mut codes: Vec<(u8, Option<String>)> = Vec::new();
+    let mut rest = s;
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(codes);
+        }
+        if let Some(inner) = rest.strip_prefix('(') {
+            let mut depth = 1;
+            let end = inner
+                .char_indices()
+                .find(|&(_, c)| {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => (),
+                    }
+                    depth == 0
+                })
+                .map_or(inner.len(), |(i, _)| i);
+            match codes.last_mut() {
+                Some((_, values @ None)) => *values = Some(inner[..end].to_string()),
+                _ => {
+                    return Err(Error::ParseCapabilitiesError {
+                        reason: format!("values ({}) without a code", &inner[..end]),
+                    })
+                }
+            }
+            rest = inner.get(end + 1..).unwrap_or("");
+            continue;
+        }
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == '(')
+            .unwrap_or(rest.len());
+        for code in hex_run(&rest[..len])? {
+            codes.push((code, None));
+        }
+        rest = &rest[len..];
+    }
+}
+
+/// Parse space separated, possibly packed, hex bytes.
+fn hex_list(s: &str) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+    for run in s.split_whitespace() {
+        bytes.extend(hex_run(run)?);
+    }
+    Ok(bytes)
+}
+
+/// Parse `0F` or packed `0F1011` into bytes.
+fn hex_run(run: &str) -> Result<Vec<u8>, Error> {
+    let invalid = || Error::ParseCapabilitiesError {
+        reason: format!("{:?} is not a hex code", run),
+    };
+    if run.len() % 2 != 0 || !run.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    (0..run.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&run[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+/// What the MCCS standard says about a VCP feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VcpInfo {
+    /// VCP code.
+    pub code: u8,
+    /// Name of the feature.
+    pub name: &'static str,
+    /// Whether the feature takes any value up to a maximum (continuous) or
+    /// one of a list of values (non-continuous).
+    pub continuous: bool,
+    /// Names of the values of a non-continuous feature.
+    pub values: &'static [(u8, &'static str)],
+}
+
+const INPUT_SOURCES: [(u8, &str); 19] = [
+    (0x01, "VGA-1"),
+    (0x02, "VGA-2"),
+    (0x03, "DVI-1"),
+    (0x04, "DVI-2"),
+    (0x05, "Composite video 1"),
+    (0x06, "Composite video 2"),
+    (0x07, "S-Video-1"),
+    (0x08, "S-Video-2"),
+    (0x09, "Tuner-1"),
+    (0x0A, "Tuner-2"),
+    (0x0B, "Tuner-3"),
+    (0x0C, "Component video 1"),
+    (0x0D, "Component video 2"),
+    (0x0E, "Component video 3"),
+    (0x0F, "DisplayPort-1"),
+    (0x10, "DisplayPort-2"),
+    (0x11, "HDMI-1"),
+    (0x12, "HDMI-2"),
+    (0x1B, "USB-C"),
+];
+
+const COLOR_PRESETS: [(u8, &str); 13] = [
+    (0x01, "sRGB"),
+    (0x02, "Display native"),
+    (0x03, "4000 K"),
+    (0x04, "5000 K"),
+    (0x05, "6500 K"),
+    (0x06, "7500 K"),
+    (0x07, "8200 K"),
+    (0x08, "9300 K"),
+    (0x09, "10000 K"),
+    (0x0A, "11500 K"),
+    (0x0B, "User 1"),
+    (0x0C, "User 2"),
+    (0x0D, "User 3"),
+];
+
+const POWER_MODES: [(u8, &str); 5] = [
+    (0x01, "On"),
+    (0x02, "Standby"),
+    (0x03, "Suspend"),
+    (0x04, "Off"),
+    (0x05, "Power off"),
+];
+
+const MUTE: [(u8, &str); 2] = [(0x01, "Muted"), (0x02, "Unmuted")];
+
+const OSD: [(u8, &str); 2] = [(0x01, "Disabled"), (0x02, "Enabled")];
+
+const fn continuous(code: u8, name: &'static str) -> VcpInfo {
+    VcpInfo {
+        code,
+        name,
+        continuous: true,
+        values: &[],
+    }
+}
+
+const fn table(code: u8, name: &'static str, values: &'static [(u8, &'static str)]) -> VcpInfo {
+    VcpInfo {
+        code,
+        name,
+        continuous: false,
+        values,
+    }
+}
+
+/// Common VCP features from MCCS 2.2, sorted by code.
+const VCP_FEATURES: [VcpInfo; 30] = [
+    table(0x02, "New control value", &[]),
+    table(0x04, "Restore factory defaults", &[]),
+    table(0x05, "Restore factory brightness/contrast defaults", &[]),
+    table(0x08, "Restore color defaults", &[]),
+    continuous(0x0B, "Color temperature increment"),
+    continuous(0x0C, "Color temperature request"),
+    continuous(0x10, "Brightness"),
+    continuous(0x12, "Contrast"),
+    table(0x14, "Select color preset", &COLOR_PRESETS),
+    continuous(0x16, "Video gain: Red"),
+    continuous(0x18, "Video gain: Green"),
+    continuous(0x1A, "Video gain: Blue"),
+    continuous(0x52, "Active control"),
+    table(0x60, "Input source", &INPUT_SOURCES),
+    continuous(0x62, "Audio speaker volume"),
+    continuous(0x6C, "Video black level: Red"),
+    continuous(0x6E, "Video black level: Green"),
+    continuous(0x70, "Video black level: Blue"),
+    continuous(0x87, "Sharpness"),
+    table(0x8D, "Audio mute", &MUTE),
+    table(0xAA, "Screen orientation", &[]),
+    continuous(0xAC, "Horizontal frequency"),
+    continuous(0xAE, "Vertical frequency"),
+    table(0xB6, "Display technology type", &[]),
+    continuous(0xC6, "Application enable key"),
+    table(0xC8, "Display controller type", &[]),
+    continuous(0xC9, "Display firmware level"),
+    table(0xCA, "OSD", &OSD),
+    table(0xD6, "Power mode", &POWER_MODES),
+    table(0xDC, "Display mode", &[]),
+];
+
+impl VcpInfo {
+    /// Get what the MCCS standard says about VCP feature `code`, `None` for
+    /// manufacturer specific or uncommon features.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::ddc::VcpInfo;
+    ///
+    /// let input = VcpInfo::lookup(0x60).unwrap();
+    /// assert_eq!(input.name, "Input source");
+    /// assert_eq!(input.get_value_name(0x0F), Some("DisplayPort-1"));
+    /// assert!(VcpInfo::lookup(0x10).unwrap().continuous);
+    /// ```
+    pub fn lookup(code: u8) -> Option<&'static VcpInfo> {
+        VCP_FEATURES
+            .binary_search_by_key(&code, |info| info.code)
+            .ok()
+            .map(|i| &VCP_FEATURES[i])
+    }
+
+    /// Get the name of `value` of a non-continuous feature.
+    pub fn get_value_name(&self, value: u8) -> Option<&'static str> {
+        self.values
+            .iter()
+            .find(|(v, _)| *v == value)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl fmt::Display for VcpInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:02X} {}", self.code, self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Capabilities strings as reported by displays, quirks included.
+    const CORPUS: [&str; 7] = [
+        // Dell U2415
+        "(prot(monitor)type(LCD)model(U2415)cmds(01 02 03 07 0C E3 F3)vcp(02 04 05 08 \
+         10 12 14(01 04 05 06 08 09 0B 0C) 16 18 1A 52 60(01 0F 11 12) AA(01 02 04) AC \
+         AE B2 B6 C6 C8 C9 D6(01 04 05) DC(00 02 03 05) DF E0 E1 E2(00 01 02 04 0E 12 \
+         14 19) F0(00 08) F1(01 02) F2 FD)mswhql(1)asset_eep(40)mccs_ver(2.1))",
+        // LG 27UK850, mccs_ver before mswhql
+        "(prot(monitor)type(LCD)model(LG HDR 4K)cmds(01 02 03 0C E3 F3)vcp(02 04 05 08 \
+         10 12 14(05 08 0B) 16 18 1A 52 60(11 12 0F 10) 62 8D(01 02) AC AE B6 C0 C6 C8 \
+         C9 D6(01 04) DF E4 E5 E6 E7 E8 E9 EA EB ED EE EF F0 F1 F2 F3 F4 F5 F6 F7 F8 F9 \
+         FA FB FD FE FF)mccs_ver(2.1)mswhql(1))",
+        // HP Z27n
+        "(prot(monitor)type(lcd)model(HP Z27n)cmds(01 02 03 07 0C E3 F3)vcp(02 04 05 08 \
+         0B 0C 10 12 14(01 05 06 08 0B) 16 18 1A 52 60(01 03 0F) 62 6C 6E 70 87 8D(01 \
+         02) AC AE B6 C0 C6 C8 C9 CA CC(01 02 03 04 05 06 07 08 09 0A 0C 0D 14 16 1E) \
+         D6(01 04 05) DC(00 02 03 05 08) DF E6 E7 E9 F0 F1 F2 F4 F6 F7 FD \
+         FF)mswhql(1)asset_eep(40)mccs_ver(2.2))",
+        // BenQ GW2765, truncated before the closing parenthesis
+        "(prot(monitor)type(lcd)model(GW2765)cmds(01 02 03 07 0C F3)vcp(02 04 05 08 0B \
+         0C 10 12 14(04 05 08 0B) 16 18 1A 52 60(01 03 0F) 62 6C 6E 70 86(02 05) 87 \
+         8D(01 02) AC AE B2 B6 C6 C8 CA CC(01 02 03 04 05 06 07 09 0A 0B 0D 0E 12 14 16 \
+         1A 1E 1F 20 24) D6(01 05) DC(00 0B 0C 0E 12 13 14 17) \
+         DF)mswhql(1)mccs_ver(2.2)asset_eep(32)mpu(01",
+        // EIZO EV2450, manufacturer feature names
+        "(prot(monitor)type(LCD)model(EV2450)cmds(01 02 03 07 0C F3)vcp(02 04 05 08 10 \
+         12 14(01 02 05 06 08 0B) 16 18 1A 60(01 03 0F 11) 62 6C 6E 70 8D(01 02) AC AE \
+         B6 C6 C8 C9 D6(01 04 05) DF E3(00 01 02))mccs_ver(2.1)vcpname(E3(EcoView \
+         Optimizer))mswhql(1))",
+        // Philips 243V7, no outer parentheses
+        "prot(monitor)type(LCD)model(PHL 243V7)cmds(01 02 03 07 0C E3 F3)vcp(02 04 05 \
+         08 10 12 14(05 08 0B) 16 18 1A 52 60(01 03 0F) 87 AC AE B2 B6 C6 C8 CA CC(02 \
+         03 04 05 06 07 08 09 0A 0D 14 16 1E) D6(01 04) DF)mccs_ver(2.2)",
+        // Samsung SyncMaster, packed codes and a trailing NUL
+        "(prot(monitor)type(LCD)model(SyncMaster)cmds(01 02 03 07 0C E3 \
+         F3)vcp(0204050810121416181A60(010305)B6C6C8C9CA(0102)D6(0104)DC(01020304)DF)\
+         mccs_ver(2.0)mswhql(1))\0",
+    ];
+
+    #[test]
+    fn corpus() {
+        for s in CORPUS.iter() {
+            let caps = Capabilities::parse(s).unwrap_or_else(|e| panic!("{}: {}", s, e));
+            assert_eq!(caps.protocol.as_deref(), Some("monitor"), "{}", s);
+            assert!(caps.supports(0x10), "{}", s);
+            assert!(caps.supports(0xD6), "{}", s);
+            assert!(caps.mccs_version.is_some(), "{}", s);
+            assert!(
+                caps.get_values(0x60).is_some_and(|v| !v.is_empty()),
+                "{}",
+                s
+            );
+        }
+
+        let dell = Capabilities::parse(CORPUS[0]).unwrap();
+        assert_eq!(dell.model.as_deref(), Some("U2415"));
+        assert_eq!(dell.display_type.as_deref(), Some("LCD"));
+        assert_eq!(
+            dell.commands,
+            vec![0x01, 0x02, 0x03, 0x07, 0x0C, 0xE3, 0xF3]
+        );
+        assert_eq!(dell.features.len(), 30);
+        assert_eq!(dell.get_values(0x60), Some(&[0x01, 0x0F, 0x11, 0x12][..]));
+        assert_eq!(dell.get_values(0x10), Some(&[][..]));
+        assert_eq!(dell.get_values(0x62), None);
+        assert!(dell.get_feature(0xD6).unwrap().accepts(4));
+        assert!(!dell.get_feature(0xD6).unwrap().accepts(2));
+        assert!(dell.get_feature(0x12).unwrap().accepts(75));
+        assert_eq!(
+            dell.other,
+            vec![
+                (String::from("mswhql"), String::from("1")),
+                (String::from("asset_eep"), String::from("40")),
+            ]
+        );
+
+        let benq = Capabilities::parse(CORPUS[3]).unwrap();
+        assert_eq!(
+            benq.other.last().unwrap(),
+            &(String::from("mpu"), String::from("01"))
+        );
+
+        let eizo = Capabilities::parse(CORPUS[4]).unwrap();
+        assert_eq!(eizo.get_feature_name(0xE3), Some("EcoView Optimizer"));
+        assert_eq!(eizo.get_feature_name(0x10), Some("Brightness"));
+        assert_eq!(eizo.get_feature_name(0xE4), None);
+
+        let samsung = Capabilities::parse(CORPUS[6]).unwrap();
+        assert_eq!(samsung.features.len(), 19);
+        assert_eq!(samsung.get_values(0x60), Some(&[0x01, 0x03, 0x05][..]));
+        assert_eq!(
+            samsung.get_values(0xDC),
+            Some(&[0x01, 0x02, 0x03, 0x04][..])
+        );
+        assert_eq!(samsung.mccs_version, Some((2, 0)));
+    }
+
+    #[test]
+    fn invalid() {
+        let bad = [
+            "",
+            "()",
+            "(prot(monitor)garbage)",
+            "(vcp(10 1G))",
+            "(vcp(10 123))",
+            "(vcp((01) 10))",
+            "(model(x)(y))",
+        ];
+        for s in bad.iter() {
+            assert!(
+                matches!(
+                    Capabilities::parse(s),
+                    Err(Error::ParseCapabilitiesError { .. })
+                ),
+                "{:?}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn vcp_table() {
+        assert!(VCP_FEATURES.windows(2).all(|w| w[0].code < w[1].code));
+        let power = VcpInfo::lookup(0xD6).unwrap();
+        assert!(!power.continuous);
+        assert_eq!(power.get_value_name(0x04), Some("Off"));
+        assert_eq!(power.to_string(), "0xD6 Power mode");
+        assert_eq!(VcpInfo::lookup(0xE3), None);
+    }
+}
@@ -0,0 +1,22 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+#[cfg(target_os = "linux")]
+mod linux;
+mod mccs;
+mod protocol;
+
+#[cfg(target_os = "linux")]
+pub use self::linux::{DdcMonitor, I2cDevice};
+pub use self::mccs::{Capabilities, VcpFeature, VcpInfo};
+pub use self::protocol::{
+    decode_capabilities_reply, decode_vcp_reply, encode_get_capabilities, encode_get_vcp,
+    encode_set_vcp, Ddc, DdcPowerMode, DdcTiming, I2cBus, VcpValue, DDC_ADDRESS, VCP_CONTRAST,
+    VCP_LUMINANCE, VCP_POWER_MODE,
+};
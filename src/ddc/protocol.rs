@@ -0,0 +1,568 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    fmt, io, thread,
+    time::{Duration, Instant},
+};
+
+use crate::{ddc::Capabilities, error::Error};
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// I2C address displays answer DDC/CI on.
+pub const DDC_ADDRESS: u16 = 0x37;
+/// VCP code of the display's luminance (brightness).
+pub const VCP_LUMINANCE: u8 = 0x10;
+/// VCP code of the display's contrast.
+pub const VCP_CONTRAST: u8 = 0x12;
+/// VCP code of the display's power mode, see [`DdcPowerMode`].
+pub const VCP_POWER_MODE: u8 = 0xD6;
+
+/// Destination address of messages to the display, `DDC_ADDRESS << 1`.
+const DISPLAY_WRITE: u8 = 0x6E;
+/// Source address of messages from the host.
+const HOST: u8 = 0x51;
+/// Address replies are checksummed against.
+const HOST_READ: u8 = 0x50;
+const GET_VCP: u8 = 0x01;
+const GET_VCP_REPLY: u8 = 0x02;
+const SET_VCP: u8 = 0x03;
+const CAPABILITIES: u8 = 0xF3;
+const CAPABILITIES_REPLY: u8 = 0xE3;
+/// Length of a Get VCP Feature reply including address, length and checksum.
+const REPLY_LEN: usize = 11;
+/// Longest Capabilities reply, 32 bytes of string plus framing.
+const CAPABILITIES_REPLY_LEN: usize = 38;
+/// Give up on capabilities strings longer than this.
+const CAPABILITIES_MAX_LEN: usize = 8192;
+
+/// A bus that can talk to I2C devices, e.g. `/dev/i2c-4` or an in-memory
+/// display in tests.
+pub trait I2cBus {
+    /// Write `data` to the device at `address`.
+    fn write(&mut self, address: u16, data: &[u8]) -> io::Result<()>;
+    /// Fill `buf` from the device at `address`.
+    fn read(&mut self, address: u16, buf: &mut [u8]) -> io::Result<()>;
+}
+
+impl<B: I2cBus + ?Sized> I2cBus for Box<B> {
+    fn write(&mut self, address: u16, data: &[u8]) -> io::Result<()> {
+        (**self).write(address, data)
+    }
+
+    fn read(&mut self, address: u16, buf: &mut [u8]) -> io::Result<()> {
+        (**self).read(address, buf)
+    }
+}
+
+/// Value of a VCP feature as reported by the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct VcpValue {
+    /// VCP code, e.g. [`VCP_LUMINANCE`].
+    pub code: u8,
+    /// `0` for a set parameter, `1` for a momentary feature.
+    pub kind: u8,
+    /// Maximum value.
+    pub max: u16,
+    /// Current value.
+    pub current: u16,
+}
+
+/// Power mode of a display, VCP code `0xD6`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum DdcPowerMode {
+    /// Display is on (DPM On, 1).
+    On,
+    /// Display is in standby (DPM Standby, 2).
+    Standby,
+    /// Display is suspended (DPM Suspend, 3).
+    Suspend,
+    /// Display is off but wakes on video (DPM Off, 4).
+    Off,
+    /// Display is off as if by its power button (5).
+    PowerOff,
+    /// Any other value reported by the display.
+    Unknown(u16),
+}
+
+impl DdcPowerMode {
+    /// Power mode for a raw VCP value.
+    pub fn from_id(id: u16) -> DdcPowerMode {
+        match id {
+            1 => DdcPowerMode::On,
+            2 => DdcPowerMode::Standby,
+            3 => DdcPowerMode::Suspend,
+            4 => DdcPowerMode::Off,
+            5 => DdcPowerMode::PowerOff,
+            n => DdcPowerMode::Unknown(n),
+        }
+    }
+
+    /// Raw VCP value of power mode.
+    pub fn id(self) -> u16 {
+        match self {
+            DdcPowerMode::On => 1,
+            DdcPowerMode::Standby => 2,
+            DdcPowerMode::Suspend => 3,
+            DdcPowerMode::Off => 4,
+            DdcPowerMode::PowerOff => 5,
+            DdcPowerMode::Unknown(n) => n,
+        }
+    }
+}
+
+impl fmt::Display for DdcPowerMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            DdcPowerMode::On => write!(f, "On"),
+            DdcPowerMode::Standby => write!(f, "Standby"),
+            DdcPowerMode::Suspend => write!(f, "Suspend"),
+            DdcPowerMode::Off => write!(f, "Off"),
+            DdcPowerMode::PowerOff => write!(f, "Power Off"),
+            DdcPowerMode::Unknown(n) => write!(f, "Unknown ({})", n),
+        }
+    }
+}
+
+/// XOR checksum of `bytes` seeded with `seed`.
+fn checksum(seed: u8, bytes: &[u8]) -> u8 {
+    bytes.iter().fold(seed, |sum, b| sum ^ b)
+}
+
+/// Get VCP Feature request for `code`, as written to [`DDC_ADDRESS`].
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::ddc::{encode_get_vcp, VCP_LUMINANCE};
+///
+/// assert_eq!(encode_get_vcp(VCP_LUMINANCE), [0x51, 0x82, 0x01, 0x10, 0xAC]);
+/// ```
+pub fn encode_get_vcp(code: u8) -> [u8; 5] {
+    let mut msg = [HOST, 0x80 | 2, GET_VCP, code, 0];
+    msg[4] = checksum(DISPLAY_WRITE, &msg[..4]);
+    msg
+}
+
+/// Set VCP Feature request for `code`, as written to [`DDC_ADDRESS`].
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::ddc::{encode_set_vcp, VCP_LUMINANCE};
+///
+/// assert_eq!(
+///     encode_set_vcp(VCP_LUMINANCE, 50),
+///     [0x51, 0x84, 0x03, 0x10, 0x00, 0x32, 0x9A]
+/// );
+/// ```
+pub fn encode_set_vcp(code: u8, value: u16) -> [u8; 7] {
+    let [high, low] = value.to_be_bytes();
+    let mut msg = [HOST, 0x80 | 4, SET_VCP, code, high, low, 0];
+    msg[6] = checksum(DISPLAY_WRITE, &msg[..6]);
+    msg
+}
+
+/// Decode the display's reply to a Get VCP Feature request for `code`.
+///
+/// A null message, i.e. the display asking to be asked again later, a bad
+/// checksum or a reply to another request fail with [`Error::Ddc`]; a
+/// feature the display does not have fails with
+/// [`Error::UnsupportedVcpCode`].
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::ddc::{decode_vcp_reply, VCP_LUMINANCE};
+///
+/// let reply = [0x6E, 0x88, 0x02, 0x00, 0x10, 0x00, 0x00, 0x64, 0x00, 0x32, 0xF2];
+/// let value = decode_vcp_reply(VCP_LUMINANCE, &reply).unwrap();
+/// assert_eq!((value.current, value.max), (50, 100));
+/// ```
+pub fn decode_vcp_reply(code: u8, reply: &[u8]) -> Result<VcpValue, Error> {
+    let invalid = |reason: String| Err(Error::Ddc { reason });
+    if reply.len() < 3 {
+        return invalid(format!("reply of {} bytes is too short", reply.len()));
+    }
+    let len = usize::from(reply[1] & 0x7F);
+    if reply[0] != DISPLAY_WRITE || reply[1] & 0x80 == 0 || reply.len() < len + 3 {
+        return invalid(format!("malformed reply {:02X?}", reply));
+    }
+    if checksum(HOST_READ, &reply[..len + 2]) != reply[len + 2] {
+        return invalid(format!("bad checksum in reply {:02X?}", &reply[..len + 3]));
+    }
+    if len == 0 {
+        return invalid(String::from("display is busy"));
+    }
+    let body = &reply[2..len + 2];
+    if len != 8 || body[0] != GET_VCP_REPLY {
+        return invalid(format!("unexpected reply {:02X?}", body));
+    }
+    if body[2] != code {
+        return invalid(format!(
+            "reply for 0x{:02X} instead of 0x{:02X}",
+            body[2], code
+        ));
+    }
+    match body[1] {
+        0 => Ok(VcpValue {
+            code,
+            kind: body[3],
+            max: u16::from_be_bytes([body[4], body[5]]),
+            current: u16::from_be_bytes([body[6], body[7]]),
+        }),
+        1 => Err(Error::UnsupportedVcpCode { code }),
+        n => invalid(format!("result code {} for 0x{:02X}", n, code)),
+    }
+}
+
+/// Capabilities Request for the fragment at `offset`, as written to
+/// [`DDC_ADDRESS`].
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::ddc::encode_get_capabilities;
+///
+/// assert_eq!(encode_get_capabilities(0), [0x51, 0x83, 0xF3, 0x00, 0x00, 0x4F]);
+/// ```
+pub fn encode_get_capabilities(offset: u16) -> [u8; 6] {
+    let [high, low] = offset.to_be_bytes();
+    let mut msg = [HOST, 0x80 | 3, CAPABILITIES, high, low, 0];
+    msg[5] = checksum(DISPLAY_WRITE, &msg[..5]);
+    msg
+}
+
+/// Decode the display's reply to a Capabilities Request for `offset` into
+/// the fragment of the capabilities string it carries, empty at the end of
+/// the string.
+///
+/// Fails with [`Error::Ddc`] like [`decode_vcp_reply`].
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::ddc::decode_capabilities_reply;
+///
+/// let reply = [0x6E, 0x85, 0xE3, 0x00, 0x00, b'(', b')', 0x59];
+/// assert_eq!(decode_capabilities_reply(0, &reply).unwrap(), b"()");
+/// ```
+pub fn decode_capabilities_reply(offset: u16, reply: &[u8]) -> Result<&[u8], Error> {
+    let invalid = |reason: String| Err(Error::Ddc { reason });
+    if reply.len() < 3 {
+        return invalid(format!("reply of {} bytes is too short", reply.len()));
+    }
+    let len = usize::from(reply[1] & 0x7F);
+    if reply[0] != DISPLAY_WRITE || reply[1] & 0x80 == 0 || reply.len() < len + 3 {
+        return invalid(format!("malformed reply {:02X?}", reply));
+    }
+    if checksum(HOST_READ, &reply[..len + 2]) != reply[len + 2] {
+        return invalid(format!("bad checksum in reply {:02X?}", &reply[..len + 3]));
+    }
+    if len == 0 {
+        return invalid(String::from("display is busy"));
+    }
+    let body = &reply[2..len + 2];
+    if len < 3 || body[0] != CAPABILITIES_REPLY {
+        return invalid(format!("unexpected reply {:02X?}", body));
+    }
+    let given = u16::from_be_bytes([body[1], body[2]]);
+    if given != offset {
+        return invalid(format!("reply for offset {} instead of {}", given, offset));
+    }
+    Ok(&body[3..])
+}
+
+/// Delays and retries of a DDC/CI exchange.
+///
+/// Defaults to the DDC/CI specification: 40 ms between a request and
+/// reading its reply, 50 ms between two requests and 3 attempts per
+/// request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct DdcTiming {
+    /// Wait between writing a request and reading the reply.
+    pub reply_delay: Duration,
+    /// Minimum wait between the end of a request and the next one.
+    pub command_interval: Duration,
+    /// Attempts per request before giving up.
+    pub attempts: u32,
+}
+
+impl DdcTiming {
+    /// No delays, for in-memory buses.
+    pub fn immediate() -> DdcTiming {
+        DdcTiming {
+            reply_delay: Duration::ZERO,
+            command_interval: Duration::ZERO,
+            ..DdcTiming::default()
+        }
+    }
+}
+
+impl Default for DdcTiming {
+    fn default() -> DdcTiming {
+        DdcTiming {
+            reply_delay: Duration::from_millis(40),
+            command_interval: Duration::from_millis(50),
+            attempts: 3,
+        }
+    }
+}
+
+/// DDC/CI connection to a display over an I2C bus.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::ddc::{Ddc, I2cDevice, VCP_LUMINANCE};
+///
+/// let mut ddc = Ddc::new(I2cDevice::open("/dev/i2c-4").unwrap());
+/// let luminance = ddc.get_vcp(VCP_LUMINANCE).unwrap();
+/// ddc.set_vcp(VCP_LUMINANCE, luminance.max / 2).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Ddc<B> {
+    bus: B,
+    timing: DdcTiming,
+    last: Option<Instant>,
+}
+
+impl<B: I2cBus> Ddc<B> {
+    /// Talk DDC/CI over `bus` with the default timing.
+    pub fn new(bus: B) -> Ddc<B> {
+        Ddc {
+            bus,
+            timing: DdcTiming::default(),
+            last: None,
+        }
+    }
+
+    /// Use `timing` instead of the default timing.
+    pub fn with_timing(mut self, timing: DdcTiming) -> Ddc<B> {
+        self.timing = timing;
+        self
+    }
+
+    /// Get the bus.
+    pub fn get_bus(&self) -> &B {
+        &self.bus
+    }
+
+    /// Get the timing.
+    pub fn get_timing(&self) -> DdcTiming {
+        self.timing
+    }
+
+    /// Read VCP feature `code`, retrying if the display is busy or the
+    /// reply is garbled.
+    pub fn get_vcp(&mut self, code: u8) -> Result<VcpValue, Error> {
+        self.retry(|ddc| {
+            ddc.send(&encode_get_vcp(code))?;
+            thread::sleep(ddc.timing.reply_delay);
+            let mut reply = [0; REPLY_LEN];
+            let res = ddc.bus.read(DDC_ADDRESS, &mut reply);
+            ddc.last = Some(Instant::now());
+            res?;
+            decode_vcp_reply(code, &reply)
+        })
+    }
+
+    /// Write VCP feature `code`, retrying if the bus fails.
+    pub fn set_vcp(&mut self, code: u8, value: u16) -> Result<(), Error> {
+        self.retry(|ddc| ddc.send(&encode_set_vcp(code, value)))
+    }
+
+    /// Read the display's capabilities string, fragment by fragment.
+    pub fn get_capabilities_string(&mut self) -> Result<String, Error> {
+        let mut caps: Vec<u8> = Vec::new();
+        loop {
+            let offset = caps.len() as u16;
+            let fragment = self.retry(|ddc| {
+                ddc.send(&encode_get_capabilities(offset))?;
+                thread::sleep(ddc.timing.reply_delay);
+                let mut reply = [0; CAPABILITIES_REPLY_LEN];
+                let res = ddc.bus.read(DDC_ADDRESS, &mut reply);
+                ddc.last = Some(Instant::now());
+                res?;
+                decode_capabilities_reply(offset, &reply).map(<[u8]>::to_vec)
+            })?;
+            if fragment.is_empty() {
+                break;
+            }
+            caps.extend(fragment);
+            if caps.len() > CAPABILITIES_MAX_LEN {
+                return Err(Error::Ddc {
+                    reason: format!("capabilities longer than {} bytes", CAPABILITIES_MAX_LEN),
+                });
+            }
+        }
+        // some displays terminate the string with NUL
+        while caps.last() == Some(&0) {
+            caps.pop();
+        }
+        Ok(String::from_utf8_lossy(&caps).into_owned())
+    }
+
+    /// Read and parse the display's capabilities.
+    pub fn get_capabilities(&mut self) -> Result<Capabilities, Error> {
+        Capabilities::parse(&self.get_capabilities_string()?)
+    }
+
+    fn send(&mut self, msg: &[u8]) -> Result<(), Error> {
+        if let Some(last) = self.last {
+            let since = last.elapsed();
+            if since < self.timing.command_interval {
+                thread::sleep(self.timing.command_interval - since);
+            }
+        }
+        let res = self.bus.write(DDC_ADDRESS, msg);
+        self.last = Some(Instant::now());
+        Ok(res?)
+    }
+
+    fn retry<T, F>(&mut self, mut f: F) -> Result<T, Error>
+    where
+        F: FnMut(&mut Ddc<B>) -> Result<T, Error>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f(self) {
+                Err(Error::UnsupportedVcpCode { code }) => {
+                    return Err(Error::UnsupportedVcpCode { code })
+                }
+                Err(_) if attempt < self.timing.attempts => attempt += 1,
+                res => return res,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::FakeDdcDisplay;
+
+    #[test]
+    fn checksums() {
+        assert_eq!(
+            encode_get_vcp(VCP_POWER_MODE),
+            [0x51, 0x82, 0x01, 0xD6, 0x6A]
+        );
+        assert_eq!(
+            encode_set_vcp(VCP_CONTRAST, 0x1234),
+            [0x51, 0x84, 0x03, 0x12, 0x12, 0x34, 0x8C]
+        );
+
+        // null message
+        assert!(matches!(
+            decode_vcp_reply(VCP_LUMINANCE, &[0x6E, 0x80, 0xBE]),
+            Err(Error::Ddc { .. })
+        ));
+        let mut reply = [
+            0x6E, 0x88, 0x02, 0x01, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0,
+        ];
+        reply[10] = checksum(HOST_READ, &reply[..10]);
+        assert!(matches!(
+            decode_vcp_reply(VCP_LUMINANCE, &reply),
+            Err(Error::UnsupportedVcpCode { code: 0x10 })
+        ));
+        assert!(matches!(
+            decode_vcp_reply(VCP_CONTRAST, &reply),
+            Err(Error::Ddc { .. })
+        ));
+        reply[10] ^= 1;
+        assert!(matches!(
+            decode_vcp_reply(VCP_LUMINANCE, &reply),
+            Err(Error::Ddc { .. })
+        ));
+        assert!(matches!(
+            decode_vcp_reply(VCP_LUMINANCE, &[0xFF; 11]),
+            Err(Error::Ddc { .. })
+        ));
+    }
+
+    #[test]
+    fn get_and_set() {
+        let display = FakeDdcDisplay::new().feature(VCP_CONTRAST, 70, 100);
+        let mut ddc = Ddc::new(display.clone()).with_timing(DdcTiming::immediate());
+
+        let luminance = ddc.get_vcp(VCP_LUMINANCE).unwrap();
+        assert_eq!((luminance.current, luminance.max), (50, 100));
+        ddc.set_vcp(VCP_LUMINANCE, 80).unwrap();
+        assert_eq!(display.get(VCP_LUMINANCE), Some(80));
+        assert_eq!(ddc.get_vcp(VCP_CONTRAST).unwrap().current, 70);
+        assert!(matches!(
+            ddc.get_vcp(0x60),
+            Err(Error::UnsupportedVcpCode { code: 0x60 })
+        ));
+        // unsupported codes are not retried
+        assert_eq!(display.requests(), 4);
+    }
+
+    #[test]
+    fn retries() {
+        let display = FakeDdcDisplay::new();
+        let mut ddc = Ddc::new(display.clone()).with_timing(DdcTiming::immediate());
+
+        display.busy(2);
+        assert_eq!(ddc.get_vcp(VCP_LUMINANCE).unwrap().current, 50);
+        assert_eq!(display.requests(), 3);
+
+        display.corrupt(3);
+        assert!(matches!(ddc.get_vcp(VCP_LUMINANCE), Err(Error::Ddc { .. })));
+        assert_eq!(display.requests(), 6);
+    }
+
+    #[test]
+    fn capabilities() {
+        let caps = "(prot(monitor)type(LCD)model(U2415)cmds(01 02 03 07 0C E3 F3)\
+                    vcp(02 04 05 08 10 12 14(01 04 05 06 08 09 0B 0C) 60(01 0F 11 12) D6(01 04 05))\
+                    mccs_ver(2.1))";
+        let display = FakeDdcDisplay::new().capabilities(caps);
+        let mut ddc = Ddc::new(display.clone()).with_timing(DdcTiming::immediate());
+
+        display.busy(1);
+        assert_eq!(ddc.get_capabilities_string().unwrap(), caps);
+        // one busy retry, five fragments and the empty end
+        assert_eq!(display.requests(), 1 + caps.len() / 32 + 2);
+
+        let caps = ddc.get_capabilities().unwrap();
+        assert_eq!(caps.model.as_deref(), Some("U2415"));
+        assert_eq!(caps.get_values(0x60), Some(&[0x01, 0x0F, 0x11, 0x12][..]));
+
+        let mut reply = [0x6E, 0x83, 0xE3, 0x00, 0x20, 0];
+        reply[5] = checksum(HOST_READ, &reply[..5]);
+        assert_eq!(decode_capabilities_reply(32, &reply).unwrap(), b"");
+        assert!(matches!(
+            decode_capabilities_reply(0, &reply),
+            Err(Error::Ddc { .. })
+        ));
+    }
+
+    #[test]
+    fn command_interval() {
+        let timing = DdcTiming {
+            reply_delay: Duration::from_millis(5),
+            command_interval: Duration::from_millis(20),
+            attempts: 1,
+        };
+        let mut ddc = Ddc::new(FakeDdcDisplay::new()).with_timing(timing);
+        let start = Instant::now();
+        ddc.set_vcp(VCP_LUMINANCE, 10).unwrap();
+        ddc.get_vcp(VCP_LUMINANCE).unwrap();
+        ddc.set_vcp(VCP_LUMINANCE, 20).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(45));
+    }
+}
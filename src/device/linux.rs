@@ -0,0 +1,673 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{fmt, path::Path};
+
+use crate::{
+    brightness::{Brightness, BrightnessExpr},
+    curve::Curve,
+    ddc::DdcMonitor,
+    error::Error,
+    hid::HidMonitor,
+    misc::LedDevice,
+    monitor::MonitorDevice,
+    sysfs::{DeviceId, Scan, Sysfs},
+};
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Kind of light a [`LightDevice`] is.
+///
+/// Its [`Display`](fmt::Display) form starts every key, see
+/// [`get_key`](LightDevice::get_key), and for lights in sysfs is the
+/// class directory, like [`DeviceClass`](crate::sysfs::DeviceClass).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum LightClass {
+    /// Backlight in `/sys/class/backlight`.
+    Backlight,
+    /// LED in `/sys/class/leds`.
+    Led,
+    /// External monitor controlled over DDC/CI.
+    Ddc,
+    /// External monitor controlled over USB HID.
+    Hid,
+}
+
+impl LightClass {
+    /// Trys to parse str into LightClass.
+    pub fn from_id(s: &str) -> Option<Self> {
+        match s {
+            "backlight" => Some(LightClass::Backlight),
+            "leds" => Some(LightClass::Led),
+            "ddc" => Some(LightClass::Ddc),
+            "hid" => Some(LightClass::Hid),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LightClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            LightClass::Backlight => write!(f, "backlight"),
+            LightClass::Led => write!(f, "leds"),
+            LightClass::Ddc => write!(f, "ddc"),
+            LightClass::Hid => write!(f, "hid"),
+        }
+    }
+}
+
+/// What a [`LightDevice`] can do besides getting and setting its level.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct LightCapabilities {
+    /// Can be switched off and on without changing its level.
+    pub power: bool,
+    /// Reports the level the hardware actually runs at.
+    pub actual_brightness: bool,
+    /// Can be driven by a kernel trigger.
+    pub trigger: bool,
+    /// Has several color channels.
+    pub multicolor: bool,
+    /// Supports [`fade_to`](MonitorDevice::fade_to).
+    pub fade: bool,
+    /// Identifies its display through EDID.
+    pub edid: bool,
+}
+
+/// A light whose level can be read and changed, whatever the kind.
+///
+/// Implemented by [`MonitorDevice`], [`LedDevice`], [`DdcMonitor`],
+/// [`HidMonitor`] and [`AnyDevice`], so code that only adjusts levels does
+/// not have to care which one it holds.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::{curve::Curve, device::LightDevice};
+///
+/// fn dim<L: LightDevice>(light: &L) {
+///     let percent = light.get_percent(Curve::Linear);
+///     light.set_percent(percent / 2.0, Curve::Linear).unwrap();
+/// }
+///
+/// for light in bulbb::device::enumerate_all().unwrap() {
+///     dim(&light);
+/// }
+/// ```
+pub trait LightDevice {
+    /// Get name of the light, e.g. `intel_backlight` or `i2c-4`.
+    fn get_device_name(&self) -> &str;
+
+    /// Get kind of the light.
+    fn get_class(&self) -> LightClass;
+
+    /// Get stable identity of the light, `None` for lights outside sysfs.
+    fn get_id(&self) -> Option<&DeviceId> {
+        None
+    }
+
+    /// Get key identifying the light, its [`DeviceId`] if it has one and
+    /// `<class>/<name>` otherwise.
+    fn get_key(&self) -> String {
+        match self.get_id() {
+            Some(id) => id.to_string(),
+            None => format!("{}/{}", self.get_class(), self.get_device_name()),
+        }
+    }
+
+    /// Get level of the light when it was last read.
+    fn get_brightness(&self) -> u32;
+
+    /// Get maximum level of the light.
+    fn get_max_brightness(&self) -> u32;
+
+    /// Read the current level of the light, without updating
+    /// [`get_brightness`](LightDevice::get_brightness).
+    fn read_brightness(&self) -> Result<u32, Error>;
+
+    /// Set level of the light.
+    fn set_brightness(&self, level: u32) -> Result<(), Error>;
+
+    /// Re-read the state of the light.
+    fn refresh(&mut self) -> Result<(), Error>;
+
+    /// Get what the light can do besides getting and setting its level.
+    fn get_light_capabilities(&self) -> LightCapabilities;
+
+    /// Get percentage of perceived brightness of the light.
+    fn get_percent(&self, curve: Curve) -> f64 {
+        curve.to_percent(self.get_brightness(), self.get_max_brightness())
+    }
+
+    /// Set level of the light to a percentage of perceived brightness.
+    fn set_percent(&self, percent: f64, curve: Curve) -> Result<(), Error> {
+        if (0.0..=100.0).contains(&percent) {
+            self.set_brightness(curve.to_raw(percent, self.get_max_brightness()))
+        } else {
+            Err(Error::InvalidPercentage { given: percent })
+        }
+    }
+
+    /// Get level of the light when it was last read, bound to its maximum.
+    fn get_level(&self) -> Brightness {
+        Brightness::saturating(self.get_brightness(), self.get_max_brightness())
+    }
+
+    /// Change level of the light as brightnessctl would with `expr`, e.g.
+    /// `"+10%".parse()?`, returning the level written.
+    ///
+    /// Relative expressions apply to [`get_level`](LightDevice::get_level),
+    /// so refresh the light first if something else may have changed it.
+    fn apply_expr(&self, expr: BrightnessExpr, curve: Curve) -> Result<Brightness, Error> {
+        let level = self.get_level().apply(expr, curve);
+        self.set_brightness(level.get_raw())?;
+        Ok(level)
+    }
+}
+
+impl LightDevice for MonitorDevice {
+    fn get_device_name(&self) -> &str {
+        MonitorDevice::get_device_name(self)
+    }
+
+    fn get_class(&self) -> LightClass {
+        LightClass::Backlight
+    }
+
+    fn get_id(&self) -> Option<&DeviceId> {
+        Some(MonitorDevice::get_id(self))
+    }
+
+    fn get_brightness(&self) -> u32 {
+        MonitorDevice::get_brightness(self)
+    }
+
+    fn get_max_brightness(&self) -> u32 {
+        MonitorDevice::get_max_brightness(self)
+    }
+
+    fn read_brightness(&self) -> Result<u32, Error> {
+        MonitorDevice::read_brightness(self)
+    }
+
+    fn set_brightness(&self, level: u32) -> Result<(), Error> {
+        MonitorDevice::set_brightness(self, level)
+    }
+
+    fn refresh(&mut self) -> Result<(), Error> {
+        MonitorDevice::refresh(self)
+    }
+
+    fn get_light_capabilities(&self) -> LightCapabilities {
+        LightCapabilities {
+            power: self.get_backend().can_set_power(),
+            actual_brightness: true,
+            fade: true,
+            edid: self.id.drm_connector.is_some(),
+            ..LightCapabilities::default()
+        }
+    }
+}
+
+impl LightDevice for LedDevice {
+    fn get_device_name(&self) -> &str {
+        LedDevice::get_device_name(self)
+    }
+
+    fn get_class(&self) -> LightClass {
+        LightClass::Led
+    }
+
+    fn get_id(&self) -> Option<&DeviceId> {
+        Some(LedDevice::get_id(self))
+    }
+
+    fn get_brightness(&self) -> u32 {
+        LedDevice::get_brightness(self)
+    }
+
+    fn get_max_brightness(&self) -> u32 {
+        LedDevice::get_max_brightness(self)
+    }
+
+    fn read_brightness(&self) -> Result<u32, Error> {
+        LedDevice::read_brightness(self)
+    }
+
+    fn set_brightness(&self, level: u32) -> Result<(), Error> {
+        LedDevice::set_brightness(self, level)
+    }
+
+    fn refresh(&mut self) -> Result<(), Error> {
+        LedDevice::refresh(self)
+    }
+
+    /// Looks for `multi_index` in sysfs to tell multicolor LEDs apart.
+    fn get_light_capabilities(&self) -> LightCapabilities {
+        let dir = self.get_sysfs().led_device(self.get_device_name());
+        LightCapabilities {
+            trigger: self.trigger.is_some(),
+            multicolor: dir.join("multi_index").is_file(),
+            fade: true,
+            ..LightCapabilities::default()
+        }
+    }
+}
+
+impl LightDevice for DdcMonitor {
+    fn get_device_name(&self) -> &str {
+        DdcMonitor::get_device_name(self)
+    }
+
+    fn get_class(&self) -> LightClass {
+        LightClass::Ddc
+    }
+
+    /// `ddc/<connector>` if the connector is known, as bus numbers change.
+    fn get_key(&self) -> String {
+        let name = self.connector.as_deref().unwrap_or(&self.device);
+        format!("{}/{}", LightClass::Ddc, name)
+    }
+
+    fn get_brightness(&self) -> u32 {
+        DdcMonitor::get_brightness(self)
+    }
+
+    fn get_max_brightness(&self) -> u32 {
+        DdcMonitor::get_max_brightness(self)
+    }
+
+    fn read_brightness(&self) -> Result<u32, Error> {
+        DdcMonitor::read_brightness(self)
+    }
+
+    fn set_brightness(&self, level: u32) -> Result<(), Error> {
+        DdcMonitor::set_brightness(self, level)
+    }
+
+    fn refresh(&mut self) -> Result<(), Error> {
+        DdcMonitor::refresh(self)
+    }
+
+    fn get_light_capabilities(&self) -> LightCapabilities {
+        LightCapabilities {
+            power: self.supports_power_mode(),
+            edid: self.connector.is_some(),
+            ..LightCapabilities::default()
+        }
+    }
+}
+
+impl LightDevice for HidMonitor {
+    fn get_device_name(&self) -> &str {
+        HidMonitor::get_device_name(self)
+    }
+
+    fn get_class(&self) -> LightClass {
+        LightClass::Hid
+    }
+
+    fn get_brightness(&self) -> u32 {
+        HidMonitor::get_brightness(self)
+    }
+
+    fn get_max_brightness(&self) -> u32 {
+        HidMonitor::get_max_brightness(self)
+    }
+
+    fn read_brightness(&self) -> Result<u32, Error> {
+        HidMonitor::read_brightness(self)
+    }
+
+    fn set_brightness(&self, level: u32) -> Result<(), Error> {
+        HidMonitor::set_brightness(self, level)
+    }
+
+    fn refresh(&mut self) -> Result<(), Error> {
+        HidMonitor::refresh(self)
+    }
+
+    fn get_light_capabilities(&self) -> LightCapabilities {
+        LightCapabilities::default()
+    }
+}
+
+/// Any light bulbb can control.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::device::{AnyDevice, LightDevice};
+///
+/// for light in bulbb::device::enumerate_all().unwrap() {
+///     match &light {
+///         AnyDevice::Monitor(monitor) => println!("backlight {}", monitor.get_device_name()),
+///         other => println!("{} {}", other.get_class(), other.get_key()),
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub enum AnyDevice {
+    /// A backlight.
+    Monitor(MonitorDevice),
+    /// An LED.
+    Led(LedDevice),
+    /// An external monitor controlled over DDC/CI.
+    Ddc(DdcMonitor),
+    /// An external monitor controlled over USB HID.
+    Hid(HidMonitor),
+}
+
+impl AnyDevice {
+    fn light(&self) -> &dyn LightDevice {
+        match self {
+            AnyDevice::Monitor(monitor) => monitor,
+            AnyDevice::Led(led) => led,
+            AnyDevice::Ddc(monitor) => monitor,
+            AnyDevice::Hid(monitor) => monitor,
+        }
+    }
+
+    fn light_mut(&mut self) -> &mut dyn LightDevice {
+        match self {
+            AnyDevice::Monitor(monitor) => monitor,
+            AnyDevice::Led(led) => led,
+            AnyDevice::Ddc(monitor) => monitor,
+            AnyDevice::Hid(monitor) => monitor,
+        }
+    }
+}
+
+impl LightDevice for AnyDevice {
+    fn get_device_name(&self) -> &str {
+        self.light().get_device_name()
+    }
+
+    fn get_class(&self) -> LightClass {
+        self.light().get_class()
+    }
+
+    fn get_id(&self) -> Option<&DeviceId> {
+        self.light().get_id()
+    }
+
+    fn get_key(&self) -> String {
+        self.light().get_key()
+    }
+
+    fn get_brightness(&self) -> u32 {
+        self.light().get_brightness()
+    }
+
+    fn get_max_brightness(&self) -> u32 {
+        self.light().get_max_brightness()
+    }
+
+    fn read_brightness(&self) -> Result<u32, Error> {
+        self.light().read_brightness()
+    }
+
+    fn set_brightness(&self, level: u32) -> Result<(), Error> {
+        self.light().set_brightness(level)
+    }
+
+    fn refresh(&mut self) -> Result<(), Error> {
+        self.light_mut().refresh()
+    }
+
+    fn get_light_capabilities(&self) -> LightCapabilities {
+        self.light().get_light_capabilities()
+    }
+}
+
+impl From<MonitorDevice> for AnyDevice {
+    fn from(val: MonitorDevice) -> Self {
+        AnyDevice::Monitor(val)
+    }
+}
+
+impl From<LedDevice> for AnyDevice {
+    fn from(val: LedDevice) -> Self {
+        AnyDevice::Led(val)
+    }
+}
+
+impl From<DdcMonitor> for AnyDevice {
+    fn from(val: DdcMonitor) -> Self {
+        AnyDevice::Ddc(val)
+    }
+}
+
+impl From<HidMonitor> for AnyDevice {
+    fn from(val: HidMonitor) -> Self {
+        AnyDevice::Hid(val)
+    }
+}
+
+impl fmt::Display for AnyDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}/{})",
+            self.get_key(),
+            self.get_brightness(),
+            self.get_max_brightness()
+        )
+    }
+}
+
+/// Get every controllable light in the system: backlights, LEDs, and
+/// external monitors answering DDC/CI or USB HID.
+///
+/// Lights that cannot be read, including external monitors that cannot be
+/// opened for lack of permission on `/dev/i2c-*` or `/dev/hidraw*`, are
+/// left out, use [`scan_all`] to find out why.
+pub fn enumerate_all() -> Result<Vec<AnyDevice>, Error> {
+    Ok(scan_all()?.devices)
+}
+
+/// Get every controllable light in the system along with the errors for
+/// those that could not be read.
+///
+/// A source of external monitors that cannot be listed at all is reported
+/// as an error named `ddc` or `hid`, without losing the other lights.
+pub fn scan_all() -> Result<Scan<AnyDevice>, Error> {
+    scan_all_with_dev(&Sysfs::default(), Path::new("/dev"))
+}
+
+/// Like [`scan_all`], with external monitors listed from `sysfs` and opened
+/// in `dev`.
+fn scan_all_with_dev(sysfs: &Sysfs, dev: &Path) -> Result<Scan<AnyDevice>, Error> {
+    let mut scan = scan_all_in(sysfs)?;
+    match DdcMonitor::scan_ddc_monitors_in(sysfs, dev) {
+        Ok(monitors) => {
+            scan.devices
+                .extend(monitors.devices.into_iter().map(AnyDevice::from));
+            scan.errors.extend(monitors.errors);
+        }
+        Err(e) => scan.errors.push((LightClass::Ddc.to_string(), e)),
+    }
+    match HidMonitor::scan_hid_monitors_in(sysfs, dev) {
+        Ok(monitors) => {
+            scan.devices
+                .extend(monitors.devices.into_iter().map(AnyDevice::from));
+            scan.errors.extend(monitors.errors);
+        }
+        Err(e) => scan.errors.push((LightClass::Hid.to_string(), e)),
+    }
+    Ok(scan)
+}
+
+/// Get every backlight and LED in the given sysfs tree.
+///
+/// External monitors are not included, as talking to them needs devices
+/// in `/dev`. Lights that cannot be read are left out, use
+/// [`scan_all_in`] to find out why.
+pub fn enumerate_all_in(sysfs: &Sysfs) -> Result<Vec<AnyDevice>, Error> {
+    Ok(scan_all_in(sysfs)?.devices)
+}
+
+/// Get every backlight and LED in the given sysfs tree along with the
+/// errors for those that could not be read.
+pub fn scan_all_in(sysfs: &Sysfs) -> Result<Scan<AnyDevice>, Error> {
+    let monitors = MonitorDevice::scan_monitor_devices_in(sysfs)?;
+    let leds = LedDevice::scan_led_devices_in(sysfs)?;
+    let mut scan = Scan {
+        devices: monitors.devices.into_iter().map(AnyDevice::from).collect(),
+        errors: monitors.errors,
+    };
+    scan.devices
+        .extend(leds.devices.into_iter().map(AnyDevice::from));
+    scan.errors.extend(leds.errors);
+    Ok(scan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        ddc::DdcTiming,
+        testing::{FakeBacklight, FakeConnector, FakeDdcDisplay, FakeLed, FakeSysfs},
+    };
+
+    #[test]
+    fn enumerate() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_backlight(
+            &FakeBacklight::new("intel_backlight")
+                .brightness(100)
+                .max_brightness(200),
+        )
+        .unwrap();
+        fake.add_led(
+            &FakeLed::new("input3::capslock")
+                .max_brightness(1)
+                .trigger("[none] kbd-capslock"),
+        )
+        .unwrap();
+
+        let mut devices = enumerate_all_in(&fake.sysfs()).unwrap();
+        let keys: Vec<String> = devices.iter().map(LightDevice::get_key).collect();
+        assert_eq!(keys, ["backlight/intel_backlight", "leds/input::capslock"]);
+        for device in devices.iter() {
+            let class = device.get_class().to_string();
+            assert!(device.get_key().starts_with(&format!("{}/", class)));
+            assert_eq!(LightClass::from_id(&class), Some(device.get_class()));
+        }
+        assert_eq!(devices[0].get_class(), LightClass::Backlight);
+        assert_eq!(devices[0].get_percent(Curve::Linear), 50.0);
+        assert!(devices[0].get_light_capabilities().power);
+        #[cfg(feature = "dbus")]
+        {
+            // logind cannot change power
+            let monitor = MonitorDevice::get_monitor_device_in(
+                &fake.sysfs(),
+                String::from("intel_backlight"),
+            )
+            .unwrap()
+            .with_backend(std::sync::Arc::new(crate::backend::LogindBackend::new()));
+            assert!(!monitor.get_light_capabilities().power);
+        }
+        assert!(devices[1].get_light_capabilities().trigger);
+        assert!(!devices[1].get_light_capabilities().multicolor);
+
+        for device in devices.iter() {
+            device.set_percent(100.0, Curve::Linear).unwrap();
+        }
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "brightness")
+                .unwrap(),
+            "200"
+        );
+        assert_eq!(
+            fake.read_led("input3::capslock", "brightness").unwrap(),
+            "1"
+        );
+        devices[0].refresh().unwrap();
+        assert_eq!(devices[0].get_brightness(), 200);
+        assert!(matches!(
+            devices[1].set_percent(101.0, Curve::Linear),
+            Err(Error::InvalidPercentage { .. })
+        ));
+
+        let level = devices[0]
+            .apply_expr("-25%".parse().unwrap(), Curve::Linear)
+            .unwrap();
+        assert_eq!(level.get_raw(), 150);
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "brightness")
+                .unwrap(),
+            "150"
+        );
+    }
+
+    #[test]
+    fn scan_reports_broken_lights() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight"))
+            .unwrap();
+        fake.add_led(&FakeLed::new("input3::capslock").without("brightness"))
+            .unwrap();
+
+        let scan = scan_all_in(&fake.sysfs()).unwrap();
+        assert_eq!(scan.devices.len(), 1);
+        assert_eq!(scan.devices[0].get_device_name(), "intel_backlight");
+        assert_eq!(scan.errors.len(), 1);
+        assert_eq!(scan.errors[0].0, "input3::capslock");
+        assert_eq!(enumerate_all_in(&fake.sysfs()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn scan_reports_unopenable_monitors() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight"))
+            .unwrap();
+        fake.add_connector(&FakeConnector::new("card0-DP-1").ddc("i2c-4"))
+            .unwrap();
+
+        // nothing in the fake /dev, so the bus cannot be opened
+        let scan = scan_all_with_dev(&fake.sysfs(), &fake.root().join("dev")).unwrap();
+        assert_eq!(scan.devices.len(), 1);
+        assert_eq!(scan.errors.len(), 1);
+        assert_eq!(scan.errors[0].0, "i2c-4");
+        assert!(matches!(scan.errors[0].1, Error::Io(_)));
+    }
+
+    #[test]
+    fn ddc() {
+        let display = FakeDdcDisplay::new();
+        let mut monitor =
+            DdcMonitor::from_bus("i2c-4", Box::new(display.clone()), DdcTiming::immediate())
+                .unwrap();
+        assert_eq!(LightDevice::get_key(&monitor), "ddc/i2c-4");
+        monitor.connector = Some(String::from("card0-DP-1"));
+
+        let mut light = AnyDevice::from(monitor);
+        assert_eq!(light.get_key(), "ddc/card0-DP-1");
+        assert!(light.get_light_capabilities().power);
+        assert_eq!(light.get_id(), None);
+        light.set_brightness(70).unwrap();
+        assert_eq!(display.get(crate::ddc::VCP_LUMINANCE), Some(70));
+        light.refresh().unwrap();
+        assert_eq!(light.to_string(), "ddc/card0-DP-1 (70/100)");
+        assert_eq!(
+            LightClass::from_id(&light.get_class().to_string()),
+            Some(LightClass::Ddc)
+        );
+
+        let display = FakeDdcDisplay::new().without(crate::ddc::VCP_POWER_MODE);
+        let monitor =
+            DdcMonitor::from_bus("i2c-5", Box::new(display), DdcTiming::immediate()).unwrap();
+        assert!(!monitor.get_light_capabilities().power);
+    }
+}
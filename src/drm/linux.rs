@@ -0,0 +1,360 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    edid::Edid,
+    error::Error,
+    sysfs::{drm_connector, scan_dir, Scan, Sysfs},
+    utils::device_error,
+};
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Connectors of built-in panels.
+const INTERNAL_CONNECTORS: [&str; 3] = ["eDP", "LVDS", "DSI"];
+
+/// Whether something is plugged into a [`DrmConnector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum ConnectorStatus {
+    /// A display is attached.
+    Connected,
+    /// Nothing is attached.
+    Disconnected,
+    /// The driver cannot tell.
+    Unknown,
+}
+
+impl ConnectorStatus {
+    /// Trys to parse str into ConnectorStatus.
+    pub fn from_id(s: &str) -> Option<Self> {
+        match s {
+            "connected" => Some(ConnectorStatus::Connected),
+            "disconnected" => Some(ConnectorStatus::Disconnected),
+            "unknown" => Some(ConnectorStatus::Unknown),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ConnectorStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            ConnectorStatus::Connected => write!(f, "connected"),
+            ConnectorStatus::Disconnected => write!(f, "disconnected"),
+            ConnectorStatus::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// A display output of a graphics card.
+///
+/// Connectors are extracted from the `/sys/class/drm/card*-*/` directories.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::drm::DrmConnector;
+///
+/// for connector in DrmConnector::get_all_connectors().unwrap() {
+///     println!("{}: {}", connector.get_name(), connector.get_status());
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct DrmConnector {
+    /// Name of the connector in `/sys/class/drm/`, e.g. `card0-eDP-1`.
+    pub name: String,
+    /// Value taken from `/sys/class/drm/<connector>/status`.
+    pub status: ConnectorStatus,
+    /// Value taken from `/sys/class/drm/<connector>/enabled`.
+    ///
+    /// Whether the connector is driving a display.
+    pub enabled: bool,
+    /// Resolved location of the connector in `/sys/devices`.
+    pub path: PathBuf,
+    /// Sysfs tree the connector was read from.
+    #[cfg_attr(feature = "dbus", serde(skip))]
+    sysfs: Sysfs,
+}
+
+impl DrmConnector {
+    /// Get connector by name, e.g. `card0-eDP-1`.
+    pub fn get_connector(name: &str) -> Result<DrmConnector, Error> {
+        DrmConnector::get_connector_in(&Sysfs::default(), name)
+    }
+
+    /// Get connector by name from the given sysfs tree.
+    pub fn get_connector_in(sysfs: &Sysfs, name: &str) -> Result<DrmConnector, Error> {
+        let dir = sysfs.drm_dir().join(name);
+        if drm_connector(name).is_none() || !dir.is_dir() {
+            return Err(Error::InvalidDeviceName {
+                device: name.to_string(),
+            });
+        }
+        let status = read_attribute(&dir, "status")?;
+        let status = ConnectorStatus::from_id(&status).unwrap_or(ConnectorStatus::Unknown);
+        let enabled = read_attribute(&dir, "enabled")? == "enabled";
+        let path = fs::canonicalize(&dir).map_err(|e| device_error(&dir.join("status"), e))?;
+
+        Ok(DrmConnector {
+            name: name.to_string(),
+            status,
+            enabled,
+            path,
+            sysfs: sysfs.clone(),
+        })
+    }
+
+    /// Get all connectors of every card.
+    pub fn get_all_connectors() -> Result<Vec<DrmConnector>, Error> {
+        DrmConnector::get_all_connectors_in(&Sysfs::default())
+    }
+
+    /// Get all connectors of every card from the given sysfs tree.
+    ///
+    /// Connectors that cannot be read are skipped, use
+    /// [`scan_connectors_in`](DrmConnector::scan_connectors_in) to find out why.
+    pub fn get_all_connectors_in(sysfs: &Sysfs) -> Result<Vec<DrmConnector>, Error> {
+        Ok(DrmConnector::scan_connectors_in(sysfs)?.devices)
+    }
+
+    /// Get all connectors from the given sysfs tree along with the errors
+    /// for those that could not be read.
+    pub fn scan_connectors_in(sysfs: &Sysfs) -> Result<Scan<DrmConnector>, Error> {
+        scan_dir(&sysfs.drm_dir(), |name| {
+            drm_connector(&name)?;
+            Some(DrmConnector::get_connector_in(sysfs, &name))
+        })
+    }
+
+    /// Connector of the panel whose backlight hangs off `parent`, the
+    /// resolved `device` of a backlight.
+    ///
+    /// `parent` is either the connector itself or one of its parents (e.g.
+    /// the graphics card for `acpi_video0` or `amdgpu_bl0`), in which case
+    /// the built-in panel connector of that card is picked, preferring one
+    /// that is lit.
+    pub(crate) fn find_for_parent(
+        sysfs: &Sysfs,
+        parent: &Path,
+    ) -> Result<Option<DrmConnector>, Error> {
+        let mut candidates: Vec<DrmConnector> = DrmConnector::get_all_connectors_in(sysfs)?
+            .into_iter()
+            .filter(|connector| {
+                parent.starts_with(&connector.path) || connector.path.starts_with(parent)
+            })
+            .collect();
+        if let Some(i) = candidates
+            .iter()
+            .position(|connector| parent.starts_with(&connector.path))
+        {
+            return Ok(Some(candidates.swap_remove(i)));
+        }
+        candidates.retain(DrmConnector::is_internal);
+        candidates.sort_by_key(|connector| (!connector.is_lit(), !connector.is_connected()));
+        Ok(candidates.into_iter().next())
+    }
+
+    /// Get name of connector, e.g. `card0-eDP-1`.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get name of connector without its card, e.g. `eDP-1`.
+    pub fn get_connector_name(&self) -> &str {
+        drm_connector(&self.name).unwrap_or(&self.name)
+    }
+
+    /// Get type of connector, e.g. `eDP`, `LVDS` or `HDMI-A`.
+    pub fn get_connector_type(&self) -> &str {
+        let connector = self.get_connector_name();
+        match connector.rfind('-') {
+            Some(i) if connector[i + 1..].bytes().all(|c| c.is_ascii_digit()) => &connector[..i],
+            _ => connector,
+        }
+    }
+
+    /// Get connection status of connector.
+    pub fn get_status(&self) -> ConnectorStatus {
+        self.status
+    }
+
+    /// Get sysfs tree the connector was read from.
+    pub fn get_sysfs(&self) -> &Sysfs {
+        &self.sysfs
+    }
+
+    /// Whether connector belongs to a built-in panel (eDP, LVDS or DSI).
+    pub fn is_internal(&self) -> bool {
+        INTERNAL_CONNECTORS.contains(&self.get_connector_type())
+    }
+
+    /// Whether a display is attached to connector.
+    pub fn is_connected(&self) -> bool {
+        self.status == ConnectorStatus::Connected
+    }
+
+    /// Whether connector is driving a display.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Whether the display on connector is attached and showing something.
+    pub fn is_lit(&self) -> bool {
+        self.is_connected() && self.is_enabled()
+    }
+
+    /// Read the EDID of the display on connector.
+    ///
+    /// `None` if nothing is connected or the driver does not expose one.
+    pub fn read_edid(&self) -> Result<Option<Edid>, Error> {
+        let path = self.sysfs.drm_dir().join(&self.name).join("edid");
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(device_error(&path, e)),
+        };
+        if bytes.is_empty() {
+            Ok(None)
+        } else {
+            Edid::parse(&bytes).map(Some)
+        }
+    }
+
+    /// Re-read status and enabled state of connector from sysfs.
+    ///
+    /// Fails with [`Error::DeviceGone`] if the connector has been removed.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        let fresh = match DrmConnector::get_connector_in(&self.sysfs, &self.name) {
+            Err(Error::InvalidDeviceName { device }) => return Err(Error::DeviceGone { device }),
+            res => res?,
+        };
+        self.status = fresh.status;
+        self.enabled = fresh.enabled;
+        self.path = fresh.path;
+        Ok(())
+    }
+}
+
+impl fmt::Display for DrmConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, {})",
+            self.name,
+            self.status,
+            if self.enabled { "enabled" } else { "disabled" }
+        )
+    }
+}
+
+fn read_attribute(dir: &Path, attribute: &str) -> Result<String, Error> {
+    let path = dir.join(attribute);
+    fs::read_to_string(&path)
+        .map(|s| s.trim().to_string())
+        .map_err(|e| device_error(&path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        monitor::MonitorDevice,
+        testing::{FakeBacklight, FakeConnector, FakeSysfs},
+    };
+
+    /// Hybrid laptop: Intel drives the panel, Nvidia only HDMI.
+    fn hybrid_tree() -> FakeSysfs {
+        let fake = FakeSysfs::new().unwrap();
+        let edp = FakeConnector::new("card0-eDP-1").parent("pci0000:00/0000:00:02.0");
+        fake.add_connector(&edp).unwrap();
+        fake.add_connector(
+            &FakeConnector::new("card0-DP-1")
+                .parent("pci0000:00/0000:00:02.0")
+                .status("disconnected")
+                .enabled(false),
+        )
+        .unwrap();
+        fake.add_connector(
+            &FakeConnector::new("card1-eDP-2")
+                .parent("pci0000:00/0000:00:01.0/0000:01:00.0")
+                .status("disconnected")
+                .enabled(false),
+        )
+        .unwrap();
+        fake.add_connector(
+            &FakeConnector::new("card1-HDMI-A-1").parent("pci0000:00/0000:00:01.0/0000:01:00.0"),
+        )
+        .unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight").device(&edp.devpath()))
+            .unwrap();
+        fake.add_backlight(
+            &FakeBacklight::new("nvidia_0").device("pci0000:00/0000:00:01.0/0000:01:00.0"),
+        )
+        .unwrap();
+        fake.add_backlight(&FakeBacklight::new("acpi_video0").device("pci0000:00/0000:00:02.0"))
+            .unwrap();
+        fake.add_backlight(&FakeBacklight::new("ddcci5")).unwrap();
+        fake
+    }
+
+    #[test]
+    fn connectors() {
+        let fake = hybrid_tree();
+        let connectors = DrmConnector::get_all_connectors_in(&fake.sysfs()).unwrap();
+        let names: Vec<&str> = connectors.iter().map(DrmConnector::get_name).collect();
+        assert_eq!(
+            names,
+            ["card0-DP-1", "card0-eDP-1", "card1-HDMI-A-1", "card1-eDP-2"]
+        );
+
+        let hdmi = &connectors[2];
+        assert_eq!(hdmi.get_connector_name(), "HDMI-A-1");
+        assert_eq!(hdmi.get_connector_type(), "HDMI-A");
+        assert!(!hdmi.is_internal());
+        assert!(hdmi.is_lit());
+        assert_eq!(
+            connectors[0].to_string(),
+            "card0-DP-1 (disconnected, disabled)"
+        );
+        assert!(connectors[1].is_internal());
+
+        let mut edp = connectors[1].clone();
+        fake.remove_connector("card0-eDP-1").unwrap();
+        assert!(matches!(edp.refresh(), Err(Error::DeviceGone { .. })));
+    }
+
+    #[test]
+    fn backlight_connectors() {
+        let fake = hybrid_tree();
+        let connector = |device: &str| {
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), device.to_string())
+                .unwrap()
+                .get_connector()
+                .unwrap()
+                .map(|connector| connector.name)
+        };
+        assert_eq!(connector("intel_backlight").as_deref(), Some("card0-eDP-1"));
+        assert_eq!(connector("acpi_video0").as_deref(), Some("card0-eDP-1"));
+        assert_eq!(connector("nvidia_0").as_deref(), Some("card1-eDP-2"));
+        assert_eq!(connector("ddcci5"), None);
+
+        let nvidia =
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("nvidia_0")).unwrap();
+        assert!(!nvidia.get_connector().unwrap().unwrap().is_lit());
+    }
+}
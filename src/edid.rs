@@ -0,0 +1,332 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::fmt;
+
+use crate::error::Error;
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+/// Length of the base EDID block, extension blocks follow it.
+const BLOCK_LEN: usize = 128;
+/// Offsets of the four 18 byte descriptors.
+const DESCRIPTORS: [usize; 4] = [54, 72, 90, 108];
+const DESCRIPTOR_SERIAL: u8 = 0xFF;
+const DESCRIPTOR_NAME: u8 = 0xFC;
+
+/// Vendor names of common PNP IDs.
+const VENDORS: [(&str, &str); 32] = [
+    ("ACI", "ASUS"),
+    ("ACR", "Acer"),
+    ("AOC", "AOC"),
+    ("APP", "Apple"),
+    ("AUO", "AU Optronics"),
+    ("AUS", "ASUS"),
+    ("BNQ", "BenQ"),
+    ("BOE", "BOE"),
+    ("CMN", "Chimei Innolux"),
+    ("CMO", "Chi Mei"),
+    ("DEL", "Dell"),
+    ("ENC", "EIZO"),
+    ("FUS", "Fujitsu"),
+    ("GBT", "Gigabyte"),
+    ("GSM", "LG"),
+    ("HPN", "HP"),
+    ("HWP", "HP"),
+    ("IVM", "Iiyama"),
+    ("LEN", "Lenovo"),
+    ("LGD", "LG Display"),
+    ("MEI", "Panasonic"),
+    ("MSI", "MSI"),
+    ("NEC", "NEC"),
+    ("PHL", "Philips"),
+    ("SAM", "Samsung"),
+    ("SDC", "Samsung Display"),
+    ("SEC", "Samsung"),
+    ("SHP", "Sharp"),
+    ("SNY", "Sony"),
+    ("TSB", "Toshiba"),
+    ("VSC", "ViewSonic"),
+    ("XMI", "Xiaomi"),
+];
+
+/// Identity and size of a display, parsed from its EDID.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::fs;
+///
+/// use bulbb::edid::Edid;
+///
+/// let edid = Edid::parse(&fs::read("/sys/class/drm/card0-DP-1/edid").unwrap()).unwrap();
+/// println!("{}", edid); // e.g. Dell U2720Q (27")
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct Edid {
+    /// Three letter PNP ID of the manufacturer, e.g. `DEL`.
+    pub manufacturer_id: String,
+    /// Manufacturer's product code.
+    pub product_code: u16,
+    /// Numeric serial number, 0 if not set.
+    pub serial_number: u32,
+    /// Monitor name descriptor, e.g. `DELL U2720Q`.
+    pub model: Option<String>,
+    /// Serial number descriptor.
+    pub serial: Option<String>,
+    /// Week of manufacture, 0 if not set.
+    pub week: u8,
+    /// Year of manufacture, or model year.
+    pub year: u16,
+    /// EDID version and revision, e.g. `(1, 4)`.
+    pub version: (u8, u8),
+    /// Width of the visible area in millimetres, 0 for projectors.
+    pub width_mm: u32,
+    /// Height of the visible area in millimetres, 0 for projectors.
+    pub height_mm: u32,
+}
+
+impl Edid {
+    /// Parse an EDID blob, only the base block is used.
+    ///
+    /// Fails with [`Error::ParseEdidError`] if the blob is too short, has no
+    /// EDID header or its checksum is wrong.
+    pub fn parse(bytes: &[u8]) -> Result<Edid, Error> {
+        let invalid = |reason: String| Err(Error::ParseEdidError { reason });
+        if bytes.len() < BLOCK_LEN {
+            return invalid(format!("{} bytes is shorter than a block", bytes.len()));
+        }
+        let block = &bytes[..BLOCK_LEN];
+        if block[..8] != HEADER {
+            return invalid(String::from("missing header"));
+        }
+        if block.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return invalid(String::from("bad checksum"));
+        }
+
+        let id = u16::from_be_bytes([block[8], block[9]]);
+        let manufacturer_id: String = [10, 5, 0]
+            .iter()
+            .map(|shift| char::from(b'@' + ((id >> shift) & 0x1F) as u8))
+            .collect();
+
+        let mut model = None;
+        let mut serial = None;
+        for &offset in DESCRIPTORS.iter() {
+            let descriptor = &block[offset..offset + 18];
+            // display descriptors start with a zero pixel clock
+            if descriptor[..3] != [0, 0, 0] {
+                continue;
+            }
+            match descriptor[3] {
+                DESCRIPTOR_NAME => model = descriptor_text(descriptor),
+                DESCRIPTOR_SERIAL => serial = descriptor_text(descriptor),
+                _ => (),
+            }
+        }
+
+        // the preferred timing has the size in millimetres, the basic
+        // parameters only in centimetres
+        let timing = &block[DESCRIPTORS[0]..DESCRIPTORS[0] + 18];
+        let (mut width_mm, mut height_mm) = if timing[..2] != [0, 0] {
+            (
+                u32::from(timing[12]) | (u32::from(timing[14] & 0xF0) << 4),
+                u32::from(timing[13]) | (u32::from(timing[14] & 0x0F) << 8),
+            )
+        } else {
+            (0, 0)
+        };
+        if width_mm == 0 || height_mm == 0 {
+            width_mm = u32::from(block[21]) * 10;
+            height_mm = u32::from(block[22]) * 10;
+        }
+
+        Ok(Edid {
+            manufacturer_id,
+            product_code: u16::from_le_bytes([block[10], block[11]]),
+            serial_number: u32::from_le_bytes([block[12], block[13], block[14], block[15]]),
+            model,
+            serial,
+            week: block[16],
+            year: 1990 + u16::from(block[17]),
+            version: (block[18], block[19]),
+            width_mm,
+            height_mm,
+        })
+    }
+
+    /// Get the manufacturer's name, e.g. `Dell`, or its PNP ID if unknown.
+    pub fn get_manufacturer(&self) -> &str {
+        VENDORS
+            .iter()
+            .find(|(id, _)| *id == self.manufacturer_id)
+            .map_or(&self.manufacturer_id, |(_, name)| name)
+    }
+
+    /// Get the serial number, preferring the serial number descriptor.
+    pub fn get_serial(&self) -> Option<String> {
+        match (&self.serial, self.serial_number) {
+            (Some(serial), _) => Some(serial.clone()),
+            (None, 0) => None,
+            (None, n) => Some(n.to_string()),
+        }
+    }
+
+    /// Get a name for the display, e.g. `Dell U2720Q`.
+    ///
+    /// The model name is used without a repeated manufacturer name; without
+    /// a model name the product code is used.
+    pub fn get_name(&self) -> String {
+        let manufacturer = self.get_manufacturer();
+        match &self.model {
+            Some(model) => {
+                let model = match model.split_once(' ') {
+                    Some((first, rest))
+                        if first.eq_ignore_ascii_case(manufacturer)
+                            || first.eq_ignore_ascii_case(&self.manufacturer_id) =>
+                    {
+                        rest
+                    }
+                    _ => model,
+                };
+                format!("{} {}", manufacturer, model)
+            }
+            None => format!("{} {:04X}", manufacturer, self.product_code),
+        }
+    }
+
+    /// Get the diagonal of the visible area in inches, `None` if the size
+    /// is unknown.
+    pub fn get_diagonal_inches(&self) -> Option<f64> {
+        if self.width_mm == 0 || self.height_mm == 0 {
+            return None;
+        }
+        let (w, h) = (f64::from(self.width_mm), f64::from(self.height_mm));
+        Some((w * w + h * h).sqrt() / 25.4)
+    }
+}
+
+impl fmt::Display for Edid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.get_diagonal_inches() {
+            Some(inches) => write!(f, "{} ({:.0}\")", self.get_name(), inches),
+            None => write!(f, "{}", self.get_name()),
+        }
+    }
+}
+
+/// Text of a display descriptor, ended by a newline and padded with spaces.
+fn descriptor_text(descriptor: &[u8]) -> Option<String> {
+    let text = &descriptor[5..18];
+    let end = text.iter().position(|&b| b == b'\n').unwrap_or(text.len());
+    let text: String = text[..end]
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                char::from(b)
+            } else {
+                '?'
+            }
+        })
+        .collect();
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+/// Base EDID block of a Dell U2720Q with a fixed checksum, for tests.
+#[cfg(test)]
+pub(crate) fn sample_edid() -> Vec<u8> {
+    let mut edid = vec![0; BLOCK_LEN];
+    edid[..8].copy_from_slice(&HEADER);
+    // "DEL", product 0xA0F4, serial 0x4C4A4D42, week 12 of 2020, EDID 1.4
+    edid[8..20].copy_from_slice(&[
+        0x10, 0xAC, 0xF4, 0xA0, 0x42, 0x4D, 0x4A, 0x4C, 0x0C, 0x1E, 0x01, 0x04,
+    ]);
+    // 60 x 34 cm
+    edid[21] = 60;
+    edid[22] = 34;
+    // 3840x2160 preferred timing, 597 x 336 mm
+    edid[54..72].copy_from_slice(&[
+        0x4D, 0xD0, 0x00, 0xA0, 0xF0, 0x70, 0x3E, 0x80, 0x30, 0x20, 0x35, 0x00, 0x55, 0x50, 0x21,
+        0x00, 0x00, 0x1A,
+    ]);
+    edid[72..90].copy_from_slice(b"\0\0\0\xFF\0JKNM0C3\n     ");
+    edid[90..108].copy_from_slice(b"\0\0\0\xFC\0DELL U2720Q\n ");
+    edid[108..126].copy_from_slice(b"\0\0\0\xFD\0\x18\x4B\x1E\x8C\x3C\0\n      ");
+    edid[126] = 1;
+    let sum = edid.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    edid[127] = 0u8.wrapping_sub(sum);
+    edid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let edid = Edid::parse(&sample_edid()).unwrap();
+        assert_eq!(edid.manufacturer_id, "DEL");
+        assert_eq!(edid.get_manufacturer(), "Dell");
+        assert_eq!(edid.product_code, 0xA0F4);
+        assert_eq!(edid.model.as_deref(), Some("DELL U2720Q"));
+        assert_eq!(edid.get_serial().as_deref(), Some("JKNM0C3"));
+        assert_eq!(edid.serial_number, 0x4C4A_4D42);
+        assert_eq!((edid.week, edid.year), (12, 2020));
+        assert_eq!(edid.version, (1, 4));
+        assert_eq!((edid.width_mm, edid.height_mm), (597, 336));
+        assert_eq!(edid.get_name(), "Dell U2720Q");
+        assert_eq!(edid.to_string(), "Dell U2720Q (27\")");
+    }
+
+    #[test]
+    fn fallbacks() {
+        let mut bytes = sample_edid();
+        // unknown vendor "ZZZ", no descriptors, size in centimetres only
+        bytes[8..10].copy_from_slice(&0x6B5Au16.to_be_bytes());
+        for b in bytes[54..126].iter_mut() {
+            *b = 0;
+        }
+        let sum = bytes[..127].iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        bytes[127] = 0u8.wrapping_sub(sum);
+
+        let edid = Edid::parse(&bytes).unwrap();
+        assert_eq!(edid.get_manufacturer(), "ZZZ");
+        assert_eq!(edid.model, None);
+        assert_eq!(edid.get_serial().as_deref(), Some("1279937858"));
+        assert_eq!((edid.width_mm, edid.height_mm), (600, 340));
+        assert_eq!(edid.to_string(), "ZZZ A0F4 (27\")");
+    }
+
+    #[test]
+    fn errors() {
+        let mut bytes = sample_edid();
+        assert!(matches!(
+            Edid::parse(&bytes[..100]),
+            Err(Error::ParseEdidError { .. })
+        ));
+        bytes[127] ^= 1;
+        assert!(matches!(
+            Edid::parse(&bytes),
+            Err(Error::ParseEdidError { .. })
+        ));
+        bytes[0] = 1;
+        assert!(matches!(
+            Edid::parse(&bytes),
+            Err(Error::ParseEdidError { .. })
+        ));
+    }
+}
@@ -9,8 +9,6 @@ except according to those terms.
 */
 use std::{error, fmt, io, num};
 
-use crate::monitor::BACKLIGHT_DIR;
-
 #[cfg(feature = "dbus")]
 use zbus::Error as ZBusError;
 
@@ -32,6 +30,41 @@ pub enum Error {
     ParseBrightnessError(num::ParseIntError),
     /// Invalid device name.
     InvalidDeviceName { device: String },
+    /// Percentage was outside of `0.0..=100.0`.
+    InvalidPercentage { given: f64 },
+    /// Operation is not supported by the backend.
+    Unsupported { operation: &'static str },
+    /// Backlight `type` was not `firmware`, `platform` or `raw`.
+    UnknownBacklightType { device: String, given: String },
+    /// LED has no `multi_index`, so is not a multicolor LED.
+    NotMulticolor { device: String },
+    /// Multicolor LED reported a channel color bulbb does not know.
+    UnknownLedColor { device: String, given: String },
+    /// Number of intensities did not match the number of channels.
+    InvalidIntensities { given: usize, channels: usize },
+    /// Device was removed while in use.
+    DeviceGone { device: String },
+    /// Error parsing a saved state file.
+    ParseStateError { line: usize, reason: String },
+    /// A lux to brightness curve is not usable.
+    InvalidLuxCurve { reason: String },
+    /// A DDC/CI exchange with a display failed.
+    Ddc { reason: String },
+    /// Display does not support a VCP feature.
+    UnsupportedVcpCode { code: u8 },
+    /// EDID blob could not be parsed.
+    ParseEdidError { reason: String },
+    /// MCCS capabilities string could not be parsed.
+    ParseCapabilitiesError { reason: String },
+    /// HID report descriptor could not be parsed.
+    ParseHidDescriptorError { reason: String },
+    /// [`BrightnessExpr`](crate::brightness::BrightnessExpr) is not
+    /// brightnessctl-style, e.g. `50%` or `+10%`.
+    InvalidBrightnessExpression { given: String },
+    /// No monitor has the requested EDID serial number.
+    SerialNotFound { serial: String },
+    /// Several devices have the requested stable id.
+    AmbiguousDeviceId { id: String },
 }
 
 /// The error type for this crate.
@@ -50,6 +83,41 @@ pub enum Error {
     ParseBrightnessError(num::ParseIntError),
     /// Invalid device name.
     InvalidDeviceName { device: String },
+    /// Percentage was outside of `0.0..=100.0`.
+    InvalidPercentage { given: f64 },
+    /// Operation is not supported by the backend.
+    Unsupported { operation: &'static str },
+    /// Backlight `type` was not `firmware`, `platform` or `raw`.
+    UnknownBacklightType { device: String, given: String },
+    /// LED has no `multi_index`, so is not a multicolor LED.
+    NotMulticolor { device: String },
+    /// Multicolor LED reported a channel color bulbb does not know.
+    UnknownLedColor { device: String, given: String },
+    /// Number of intensities did not match the number of channels.
+    InvalidIntensities { given: usize, channels: usize },
+    /// Device was removed while in use.
+    DeviceGone { device: String },
+    /// Error parsing a saved state file.
+    ParseStateError { line: usize, reason: String },
+    /// A lux to brightness curve is not usable.
+    InvalidLuxCurve { reason: String },
+    /// A DDC/CI exchange with a display failed.
+    Ddc { reason: String },
+    /// Display does not support a VCP feature.
+    UnsupportedVcpCode { code: u8 },
+    /// EDID blob could not be parsed.
+    ParseEdidError { reason: String },
+    /// MCCS capabilities string could not be parsed.
+    ParseCapabilitiesError { reason: String },
+    /// HID report descriptor could not be parsed.
+    ParseHidDescriptorError { reason: String },
+    /// [`BrightnessExpr`](crate::brightness::BrightnessExpr) is not
+    /// brightnessctl-style, e.g. `50%` or `+10%`.
+    InvalidBrightnessExpression { given: String },
+    /// No monitor has the requested EDID serial number.
+    SerialNotFound { serial: String },
+    /// Several devices have the requested stable id.
+    AmbiguousDeviceId { id: String },
 }
 
 impl error::Error for Error {
@@ -61,6 +129,32 @@ impl error::Error for Error {
             Error::Io(e) => Some(e),
             Error::ParseBrightnessError(e) => Some(e),
             Error::InvalidDeviceName { device: _ } => None,
+            Error::InvalidPercentage { given: _ } => None,
+            Error::Unsupported { operation: _ } => None,
+            Error::UnknownBacklightType {
+                device: _,
+                given: _,
+            } => None,
+            Error::NotMulticolor { device: _ } => None,
+            Error::UnknownLedColor {
+                device: _,
+                given: _,
+            } => None,
+            Error::InvalidIntensities {
+                given: _,
+                channels: _,
+            } => None,
+            Error::DeviceGone { device: _ } => None,
+            Error::ParseStateError { line: _, reason: _ } => None,
+            Error::InvalidLuxCurve { reason: _ } => None,
+            Error::Ddc { reason: _ } => None,
+            Error::UnsupportedVcpCode { code: _ } => None,
+            Error::ParseEdidError { reason: _ } => None,
+            Error::ParseCapabilitiesError { reason: _ } => None,
+            Error::ParseHidDescriptorError { reason: _ } => None,
+            Error::InvalidBrightnessExpression { given: _ } => None,
+            Error::SerialNotFound { serial: _ } => None,
+            Error::AmbiguousDeviceId { id: _ } => None,
         }
     }
     #[cfg(not(feature = "dbus"))]
@@ -70,6 +164,32 @@ impl error::Error for Error {
             Error::Io(e) => Some(e),
             Error::ParseBrightnessError(e) => Some(e),
             Error::InvalidDeviceName { device: _ } => None,
+            Error::InvalidPercentage { given: _ } => None,
+            Error::Unsupported { operation: _ } => None,
+            Error::UnknownBacklightType {
+                device: _,
+                given: _,
+            } => None,
+            Error::NotMulticolor { device: _ } => None,
+            Error::UnknownLedColor {
+                device: _,
+                given: _,
+            } => None,
+            Error::InvalidIntensities {
+                given: _,
+                channels: _,
+            } => None,
+            Error::DeviceGone { device: _ } => None,
+            Error::ParseStateError { line: _, reason: _ } => None,
+            Error::InvalidLuxCurve { reason: _ } => None,
+            Error::Ddc { reason: _ } => None,
+            Error::UnsupportedVcpCode { code: _ } => None,
+            Error::ParseEdidError { reason: _ } => None,
+            Error::ParseCapabilitiesError { reason: _ } => None,
+            Error::ParseHidDescriptorError { reason: _ } => None,
+            Error::InvalidBrightnessExpression { given: _ } => None,
+            Error::SerialNotFound { serial: _ } => None,
+            Error::AmbiguousDeviceId { id: _ } => None,
         }
     }
 }
@@ -86,10 +206,69 @@ impl fmt::Display for Error {
             ),
             Error::Io(e) => write!(f, "I/O error: {}", e),
             Error::ParseBrightnessError(e) => write!(f, "Unexpected Brightness {}", e),
-            Error::InvalidDeviceName { device } => write!(
+            Error::InvalidDeviceName { device } => {
+                write!(f, "Invalid Device Name: no such device: {}.", device)
+            }
+            Error::InvalidPercentage { given } => write!(
+                f,
+                "Invalid Percentage: expected number between 0 and 100 but received {}.",
+                given
+            ),
+            Error::Unsupported { operation } => write!(
+                f,
+                "Unsupported Operation: {} is not supported by this backend.",
+                operation
+            ),
+            Error::UnknownBacklightType { device, given } => write!(
+                f,
+                "Unknown Backlight Type: {} reported type {:?}.",
+                device, given
+            ),
+            Error::NotMulticolor { device } => {
+                write!(f, "Not Multicolor: {} has no multi_index.", device)
+            }
+            Error::UnknownLedColor { device, given } => write!(
+                f,
+                "Unknown LED Color: {} reported color {:?}.",
+                device, given
+            ),
+            Error::InvalidIntensities { given, channels } => write!(
+                f,
+                "Invalid Intensities: expected {} values but received {}.",
+                channels, given
+            ),
+            Error::DeviceGone { device } => write!(f, "Device Gone: {} has been removed.", device),
+            Error::ParseStateError { line, reason } => {
+                write!(f, "Invalid State: line {}: {}.", line, reason)
+            }
+            Error::InvalidLuxCurve { reason } => write!(f, "Invalid Lux Curve: {}.", reason),
+            Error::Ddc { reason } => write!(f, "DDC/CI Error: {}.", reason),
+            Error::UnsupportedVcpCode { code } => write!(
+                f,
+                "Unsupported VCP Code: display does not support 0x{:02X}.",
+                code
+            ),
+            Error::ParseEdidError { reason } => write!(f, "Invalid EDID: {}.", reason),
+            Error::ParseCapabilitiesError { reason } => {
+                write!(f, "Invalid Capabilities: {}.", reason)
+            }
+            Error::ParseHidDescriptorError { reason } => {
+                write!(f, "Invalid HID Descriptor: {}.", reason)
+            }
+            Error::InvalidBrightnessExpression { given } => write!(
+                f,
+                "Invalid Brightness Expression: {:?} is not like 50%, +10%, -3 or max.",
+                given
+            ),
+            Error::SerialNotFound { serial } => write!(
+                f,
+                "Serial Not Found: no monitor has the EDID serial number {}.",
+                serial
+            ),
+            Error::AmbiguousDeviceId { id } => write!(
                 f,
-                "Invalid Device Name: {}/{}/ doest not exist.",
-                BACKLIGHT_DIR, device
+                "Ambiguous Device Id: several devices have the id {}.",
+                id
             ),
         }
     }
@@ -103,10 +282,69 @@ impl fmt::Display for Error {
             ),
             Error::Io(e) => write!(f, "I/O error: {}", e),
             Error::ParseBrightnessError(e) => write!(f, "Unexpected Brightness {}", e),
-            Error::InvalidDeviceName { device } => write!(
+            Error::InvalidDeviceName { device } => {
+                write!(f, "Invalid Device Name: no such device: {}.", device)
+            }
+            Error::InvalidPercentage { given } => write!(
+                f,
+                "Invalid Percentage: expected number between 0 and 100 but received {}.",
+                given
+            ),
+            Error::Unsupported { operation } => write!(
+                f,
+                "Unsupported Operation: {} is not supported by this backend.",
+                operation
+            ),
+            Error::UnknownBacklightType { device, given } => write!(
+                f,
+                "Unknown Backlight Type: {} reported type {:?}.",
+                device, given
+            ),
+            Error::NotMulticolor { device } => {
+                write!(f, "Not Multicolor: {} has no multi_index.", device)
+            }
+            Error::UnknownLedColor { device, given } => write!(
+                f,
+                "Unknown LED Color: {} reported color {:?}.",
+                device, given
+            ),
+            Error::InvalidIntensities { given, channels } => write!(
+                f,
+                "Invalid Intensities: expected {} values but received {}.",
+                channels, given
+            ),
+            Error::DeviceGone { device } => write!(f, "Device Gone: {} has been removed.", device),
+            Error::ParseStateError { line, reason } => {
+                write!(f, "Invalid State: line {}: {}.", line, reason)
+            }
+            Error::InvalidLuxCurve { reason } => write!(f, "Invalid Lux Curve: {}.", reason),
+            Error::Ddc { reason } => write!(f, "DDC/CI Error: {}.", reason),
+            Error::UnsupportedVcpCode { code } => write!(
+                f,
+                "Unsupported VCP Code: display does not support 0x{:02X}.",
+                code
+            ),
+            Error::ParseEdidError { reason } => write!(f, "Invalid EDID: {}.", reason),
+            Error::ParseCapabilitiesError { reason } => {
+                write!(f, "Invalid Capabilities: {}.", reason)
+            }
+            Error::ParseHidDescriptorError { reason } => {
+                write!(f, "Invalid HID Descriptor: {}.", reason)
+            }
+            Error::InvalidBrightnessExpression { given } => write!(
+                f,
+                "Invalid Brightness Expression: {:?} is not like 50%, +10%, -3 or max.",
+                given
+            ),
+            Error::SerialNotFound { serial } => write!(
+                f,
+                "Serial Not Found: no monitor has the EDID serial number {}.",
+                serial
+            ),
+            Error::AmbiguousDeviceId { id } => write!(
                 f,
-                "Invalid Device Name: {}/{}/ doest not exist.",
-                BACKLIGHT_DIR, device
+                "Ambiguous Device Id: several devices have the id {}.",
+                id
             ),
         }
     }
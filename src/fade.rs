@@ -0,0 +1,535 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, Weak},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::error::Error;
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Shortest time between two intermediate writes (roughly 60 per second).
+const MIN_STEP: Duration = Duration::from_millis(16);
+
+/// Fades by device directory, so that a new fade of a device stops the one
+/// already running.
+static RUNNING: Mutex<BTreeMap<PathBuf, Weak<Shared>>> = Mutex::new(BTreeMap::new());
+
+/// Rate of change over the course of a fade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum Easing {
+    /// Constant speed.
+    #[default]
+    Linear,
+    /// Start slow, end fast.
+    EaseIn,
+    /// Start fast, end slow.
+    EaseOut,
+    /// Start and end slow.
+    EaseInOut,
+}
+
+impl Easing {
+    /// Progress (`0.0..=1.0`) of the fade after `t` (`0.0..=1.0`) of its duration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::fade::Easing;
+    ///
+    /// assert_eq!(Easing::Linear.apply(0.25), 0.25);
+    /// assert!(Easing::EaseIn.apply(0.25) < 0.25);
+    /// assert!(Easing::EaseOut.apply(0.25) > 0.25);
+    /// ```
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Easing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            Easing::Linear => write!(f, "linear"),
+            Easing::EaseIn => write!(f, "ease-in"),
+            Easing::EaseOut => write!(f, "ease-out"),
+            Easing::EaseInOut => write!(f, "ease-in-out"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct FadeState {
+    target: u32,
+    duration: Duration,
+    easing: Easing,
+    retargeted: bool,
+    cancelled: bool,
+    finished: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    state: Mutex<FadeState>,
+    wake: Condvar,
+}
+
+/// A fade running in a background thread.
+///
+/// Returned by `fade_to_in_background` on
+/// [`MonitorDevice`](crate::monitor::MonitorDevice) and
+/// [`LedDevice`](crate::misc::LedDevice). Dropping it lets the fade run to
+/// completion, unless another fade of the same device is started.
+#[derive(Debug)]
+pub struct Fade {
+    shared: Arc<Shared>,
+    max_brightness: u32,
+    thread: Option<JoinHandle<Result<(), Error>>>,
+}
+
+impl Fade {
+    /// Fade `device` from the level `read` returns to `target` in a
+    /// background thread, stopping the fade of `device` already running.
+    pub(crate) fn spawn<R, F>(
+        device: &Path,
+        read: R,
+        target: u32,
+        max_brightness: u32,
+        duration: Duration,
+        easing: Easing,
+        write: F,
+    ) -> Result<Fade, Error>
+    where
+        R: FnOnce() -> Result<u32, Error> + Send + 'static,
+        F: FnMut(u32) -> Result<(), Error> + Send + 'static,
+    {
+        check_level(target, max_brightness)?;
+        let shared = Arc::new(Shared::new(target, duration, easing));
+        take_over(device, &shared);
+        let thread_shared = shared.clone();
+        let thread = match thread::Builder::new()
+            .name(String::from("bulbb-fade"))
+            .spawn(move || run(read, &thread_shared, write))
+        {
+            Ok(thread) => thread,
+            Err(e) => {
+                shared.finish();
+                return Err(Error::Io(e));
+            }
+        };
+
+        Ok(Fade {
+            shared,
+            max_brightness,
+            thread: Some(thread),
+        })
+    }
+
+    /// Stop the fade, leaving the device at whatever level it reached.
+    pub fn cancel(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.cancelled = true;
+        self.shared.wake.notify_all();
+    }
+
+    /// Fade towards `level` instead, starting from the level reached so far.
+    pub fn retarget(&self, level: u32, duration: Duration, easing: Easing) -> Result<(), Error> {
+        check_level(level, self.max_brightness)?;
+        let mut state = self.shared.state.lock().unwrap();
+        state.target = level;
+        state.duration = duration;
+        state.easing = easing;
+        state.retargeted = true;
+        self.shared.wake.notify_all();
+        Ok(())
+    }
+
+    /// Whether the fade has reached its target, been cancelled or failed.
+    pub fn is_finished(&self) -> bool {
+        self.shared.state.lock().unwrap().finished
+    }
+
+    /// Wait for the fade to end.
+    pub fn join(mut self) -> Result<(), Error> {
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .unwrap_or_else(|_| Err(Error::Io(std::io::Error::other("fade thread panicked")))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Shared {
+    fn new(target: u32, duration: Duration, easing: Easing) -> Shared {
+        Shared {
+            state: Mutex::new(FadeState {
+                target,
+                duration,
+                easing,
+                retargeted: false,
+                cancelled: false,
+                finished: false,
+            }),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// Cancel the fade and wait until it stops writing.
+    fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.cancelled = true;
+        self.wake.notify_all();
+        while !state.finished {
+            state = self.wake.wait(state).unwrap();
+        }
+    }
+
+    fn finish(&self) {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .finished = true;
+        self.wake.notify_all();
+    }
+}
+
+/// Make `shared` the fade of `device`, stopping the one it replaces.
+fn take_over(device: &Path, shared: &Arc<Shared>) {
+    let old = {
+        let mut running = RUNNING.lock().unwrap_or_else(|e| e.into_inner());
+        running.retain(|_, fade| fade.strong_count() > 0);
+        running.insert(device.to_path_buf(), Arc::downgrade(shared))
+    };
+    // waited for without holding the registry, so a slow write only delays
+    // fades of its own device
+    if let Some(old) = old.and_then(|old| old.upgrade()) {
+        old.stop();
+    }
+}
+
+fn check_level(level: u32, max_brightness: u32) -> Result<(), Error> {
+    if level <= max_brightness {
+        Ok(())
+    } else {
+        Err(Error::InvalidBrightnessLevel {
+            given: level,
+            max: max_brightness,
+        })
+    }
+}
+
+/// Fade `device` from the level `read` returns to `target` in the calling
+/// thread, stopping the fade of `device` already running.
+pub(crate) fn fade_blocking<R, F>(
+    device: &Path,
+    read: R,
+    target: u32,
+    max_brightness: u32,
+    duration: Duration,
+    easing: Easing,
+    write: F,
+) -> Result<(), Error>
+where
+    R: FnOnce() -> Result<u32, Error>,
+    F: FnMut(u32) -> Result<(), Error>,
+{
+    check_level(target, max_brightness)?;
+    let shared = Arc::new(Shared::new(target, duration, easing));
+    take_over(device, &shared);
+    run(read, &shared, write)
+}
+
+fn run<R, F>(read: R, shared: &Shared, mut write: F) -> Result<(), Error>
+where
+    R: FnOnce() -> Result<u32, Error>,
+    F: FnMut(u32) -> Result<(), Error>,
+{
+    // marks the fade finished even if `write` panics, so no one waits on it
+    // forever
+    struct Finish<'a>(&'a Shared);
+
+    impl Drop for Finish<'_> {
+        fn drop(&mut self) {
+            self.0.finish();
+        }
+    }
+
+    let _finish = Finish(shared);
+    // read only once the fade it replaces has stopped writing
+    read().and_then(|from| step(from, shared, &mut write))
+}
+
+fn step<F>(mut from: u32, shared: &Shared, write: &mut F) -> Result<(), Error>
+where
+    F: FnMut(u32) -> Result<(), Error>,
+{
+    let mut current = from;
+    let mut written = None;
+    let mut start = Instant::now();
+    let mut state = shared.state.lock().unwrap();
+
+    loop {
+        if state.cancelled {
+            return Ok(());
+        }
+        if state.retargeted {
+            state.retargeted = false;
+            from = current;
+            start = Instant::now();
+        }
+
+        let (target, duration, easing) = (state.target, state.duration, state.easing);
+        let elapsed = start.elapsed();
+        let t = if duration.as_secs_f64() > 0.0 {
+            elapsed.as_secs_f64() / duration.as_secs_f64()
+        } else {
+            1.0
+        };
+        let level = if t >= 1.0 {
+            target
+        } else {
+            let delta = f64::from(target) - f64::from(from);
+            (f64::from(from) + delta * easing.apply(t)).round() as u32
+        };
+
+        // the target is written at the end even if the fade started there,
+        // but not twice
+        if level != current || (t >= 1.0 && written != Some(level)) {
+            drop(state);
+            write(level)?;
+            current = level;
+            written = Some(level);
+            state = shared.state.lock().unwrap();
+            if t >= 1.0 && !state.retargeted && !state.cancelled {
+                return Ok(());
+            }
+            continue;
+        }
+        if t >= 1.0 {
+            return Ok(());
+        }
+
+        let interval = (duration / target.abs_diff(from).max(1)).max(MIN_STEP);
+        let remaining = duration.checked_sub(elapsed).unwrap_or_default();
+        state = shared
+            .wake
+            .wait_timeout(state, interval.min(remaining))
+            .unwrap()
+            .0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    type Levels = Arc<Mutex<Vec<u32>>>;
+
+    fn recorder() -> (Levels, impl FnMut(u32) -> Result<(), Error>) {
+        let levels = Arc::new(Mutex::new(Vec::new()));
+        let writer = levels.clone();
+        (levels, move |level| {
+            writer.lock().unwrap().push(level);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn easing_end_points() {
+        for easing in [
+            Easing::Linear,
+            Easing::EaseIn,
+            Easing::EaseOut,
+            Easing::EaseInOut,
+        ]
+        .iter()
+        {
+            assert_eq!(easing.apply(0.0), 0.0, "{}", easing);
+            assert_eq!(easing.apply(1.0), 1.0, "{}", easing);
+        }
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+
+        let t = 0.25;
+        assert_eq!(Easing::Linear.apply(t), t);
+        assert!(Easing::EaseIn.apply(t) < t);
+        assert!(Easing::EaseOut.apply(t) > t);
+        assert!(Easing::EaseInOut.apply(t) < t);
+        assert!(Easing::EaseInOut.apply(1.0 - t) > 1.0 - t);
+    }
+
+    #[test]
+    fn blocking_fade_is_monotonic() {
+        let (levels, write) = recorder();
+        fade_blocking(
+            Path::new("monotonic"),
+            || Ok(0),
+            100,
+            255,
+            Duration::from_millis(80),
+            Easing::EaseInOut,
+            write,
+        )
+        .unwrap();
+
+        let levels = levels.lock().unwrap();
+        assert_eq!(*levels.last().unwrap(), 100);
+        assert!(levels.len() > 1);
+        assert!(levels.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn zero_duration_jumps() {
+        let (levels, write) = recorder();
+        fade_blocking(
+            Path::new("jump"),
+            || Ok(50),
+            10,
+            255,
+            Duration::from_secs(0),
+            Easing::Linear,
+            write,
+        )
+        .unwrap();
+        assert_eq!(*levels.lock().unwrap(), vec![10]);
+    }
+
+    #[test]
+    fn invalid_target() {
+        let (levels, write) = recorder();
+        assert!(fade_blocking(
+            Path::new("invalid"),
+            || Ok(0),
+            300,
+            255,
+            Duration::from_secs(0),
+            Easing::Linear,
+            write
+        )
+        .is_err());
+        assert!(levels.lock().unwrap().is_empty());
+    }
+
+    fn spawn<F>(device: &str, target: u32, duration: Duration, write: F) -> Fade
+    where
+        F: FnMut(u32) -> Result<(), Error> + Send + 'static,
+    {
+        Fade::spawn(
+            Path::new(device),
+            || Ok(0),
+            target,
+            255,
+            duration,
+            Easing::Linear,
+            write,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn cancel() {
+        let (levels, write) = recorder();
+        let fade = spawn("cancel", 255, Duration::from_secs(10), write);
+        thread::sleep(Duration::from_millis(50));
+        fade.cancel();
+        fade.join().unwrap();
+        assert!(levels.lock().unwrap().iter().all(|l| *l < 255));
+    }
+
+    #[test]
+    fn retarget() {
+        let (levels, write) = recorder();
+        let fade = spawn("retarget", 255, Duration::from_secs(10), write);
+        thread::sleep(Duration::from_millis(50));
+        assert!(fade
+            .retarget(256, Duration::from_millis(10), Easing::Linear)
+            .is_err());
+        fade.retarget(0, Duration::from_millis(30), Easing::EaseOut)
+            .unwrap();
+        fade.join().unwrap();
+        assert_eq!(*levels.lock().unwrap().last().unwrap(), 0);
+    }
+
+    #[test]
+    fn write_error_stops_fade() {
+        let fade = spawn("error", 10, Duration::from_millis(50), |_| {
+            Err(Error::Io(std::io::Error::from(
+                std::io::ErrorKind::NotFound,
+            )))
+        });
+        assert!(fade.join().is_err());
+    }
+
+    #[test]
+    fn new_fade_stops_running_one() {
+        let (first_levels, write) = recorder();
+        let first = spawn("replace", 255, Duration::from_secs(10), write);
+        thread::sleep(Duration::from_millis(50));
+
+        let (levels, write) = recorder();
+        let second = spawn("replace", 0, Duration::from_millis(0), write);
+        // the first fade stopped writing before the second one started
+        assert!(first.is_finished());
+        let written = first_levels.lock().unwrap().len();
+        second.join().unwrap();
+        first.join().unwrap();
+        assert_eq!(first_levels.lock().unwrap().len(), written);
+        assert_eq!(*levels.lock().unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn slow_write_does_not_block_other_devices() {
+        let (entered_tx, entered) = mpsc::channel();
+        let (release, release_rx) = mpsc::channel::<()>();
+        let slow = spawn("slow", 255, Duration::from_secs(0), move |_| {
+            entered_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+            Ok(())
+        });
+        entered.recv().unwrap();
+        // waits for the slow write to finish
+        let replacement = thread::spawn(|| spawn("slow", 0, Duration::from_secs(0), |_| Ok(())));
+        thread::sleep(Duration::from_millis(50));
+
+        let (done_tx, done) = mpsc::channel();
+        thread::spawn(move || {
+            let (levels, write) = recorder();
+            spawn("other", 10, Duration::from_secs(0), write)
+                .join()
+                .unwrap();
+            done_tx.send(levels.lock().unwrap().clone()).unwrap();
+        });
+        assert_eq!(done.recv_timeout(Duration::from_secs(5)).unwrap(), vec![10]);
+
+        release.send(()).unwrap();
+        slow.join().unwrap();
+        replacement.join().unwrap().join().unwrap();
+    }
+}
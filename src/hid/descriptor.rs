@@ -0,0 +1,518 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::collections::BTreeMap;
+
+use crate::error::Error;
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Usage page of the USB Monitor Control class.
+pub const HID_MONITOR_PAGE: u16 = 0x80;
+/// Usage page of the VESA Virtual Controls, whose usages mirror the MCCS
+/// VCP codes.
+pub const HID_VESA_PAGE: u16 = 0x82;
+/// Usage of the display's brightness on [`HID_VESA_PAGE`].
+pub const HID_BRIGHTNESS: u16 = 0x10;
+/// Usage of the display's contrast on [`HID_VESA_PAGE`].
+pub const HID_CONTRAST: u16 = 0x12;
+
+const MAIN: u8 = 0;
+const GLOBAL: u8 = 1;
+const LOCAL: u8 = 2;
+const LONG_ITEM: u8 = 0xFE;
+
+/// Most values a single main item may describe, `HID_MAX_USAGES` in the
+/// kernel.
+const MAX_REPORT_COUNT: usize = 12288;
+/// Largest value in bits a main item may describe, as in the kernel's
+/// `hid-core`.
+const MAX_REPORT_SIZE: usize = 256;
+/// Largest feature value in bits that can be read and written, wider ones
+/// are left out of [`ReportDescriptor::features`].
+const MAX_FIELD_SIZE: usize = 32;
+
+/// Kinds of report a field can be part of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ReportKind {
+    Input,
+    Output,
+    Feature,
+}
+
+/// A value in a feature report of a HID device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct HidField {
+    /// Usage page, e.g. [`HID_VESA_PAGE`].
+    pub usage_page: u16,
+    /// Usage on the page, e.g. [`HID_BRIGHTNESS`].
+    pub usage: u16,
+    /// ID of the report the field is in, `0` if the device numbers none.
+    pub report_id: u8,
+    /// Length of the report in bytes, including the leading report ID byte.
+    pub report_len: usize,
+    /// Offset of the field in bits, not counting the report ID byte.
+    pub bit_offset: usize,
+    /// Size of the field in bits.
+    pub bit_size: usize,
+    /// Smallest value of the field.
+    pub logical_min: i32,
+    /// Largest value of the field.
+    pub logical_max: i32,
+}
+
+impl HidField {
+    /// Extract the value of the field from `report`, the report ID in its
+    /// first byte as hidraw returns it, `0` if the device numbers none.
+    ///
+    /// Values of fields with a negative logical minimum are sign-extended,
+    /// so they read back correctly as `i32`.
+    pub fn extract(&self, report: &[u8]) -> Option<u32> {
+        let data = self.data(report)?;
+        let bits = self.bit_size.min(MAX_FIELD_SIZE);
+        let mut value = 0u32;
+        for bit in (0..bits).rev() {
+            let pos = self.bit_offset + bit;
+            value = (value << 1) | u32::from(data.get(pos / 8)? >> (pos % 8) & 1);
+        }
+        if self.logical_min < 0 && bits > 0 && bits < 32 && value >> (bits - 1) & 1 == 1 {
+            value |= !0 << bits;
+        }
+        Some(value)
+    }
+
+    /// Write `value` into the field in `report`.
+    pub fn insert(&self, report: &mut [u8], value: u32) -> Option<()> {
+        let data = report.get_mut(1..)?;
+        for bit in 0..self.bit_size.min(MAX_FIELD_SIZE) {
+            let pos = self.bit_offset + bit;
+            let byte = data.get_mut(pos / 8)?;
+            *byte = *byte & !(1 << (pos % 8)) | (((value >> bit) & 1) as u8) << (pos % 8);
+        }
+        Some(())
+    }
+
+    /// An empty report the field can be written into.
+    pub fn new_report(&self) -> Vec<u8> {
+        let mut report = vec![0; self.report_len.max(1)];
+        report[0] = self.report_id;
+        report
+    }
+
+    fn data<'a>(&self, report: &'a [u8]) -> Option<&'a [u8]> {
+        report.get(1..)
+    }
+}
+
+/// Parsed report descriptor of a HID device, listing the values of its
+/// feature reports.
+///
+/// Only what is needed to find and access feature values is kept; input
+/// and output reports are skipped.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::hid::{ReportDescriptor, HID_BRIGHTNESS, HID_VESA_PAGE};
+///
+/// let descriptor = ReportDescriptor::parse(&[
+///     0x05, 0x80, // Usage Page (Monitor)
+///     0x09, 0x01, // Usage (Monitor Control)
+///     0xA1, 0x01, // Collection (Application)
+///     0x85, 0x01, //   Report ID (1)
+///     0x05, 0x82, //   Usage Page (VESA Virtual Controls)
+///     0x09, 0x10, //   Usage (Brightness)
+///     0x15, 0x00, //   Logical Minimum (0)
+///     0x26, 0xFF, 0x00, // Logical Maximum (255)
+///     0x75, 0x08, //   Report Size (8)
+///     0x95, 0x01, //   Report Count (1)
+///     0xB1, 0x02, //   Feature (Data, Variable, Absolute)
+///     0xC0, // End Collection
+/// ])
+/// .unwrap();
+/// assert!(descriptor.is_monitor());
+/// let brightness = descriptor.find_feature(HID_VESA_PAGE, HID_BRIGHTNESS).unwrap();
+/// assert_eq!((brightness.report_id, brightness.logical_max), (1, 255));
+/// assert_eq!(brightness.extract(&[0x01, 0x80]), Some(128));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct ReportDescriptor {
+    /// Usages of the top level collections as `(page, usage)`.
+    pub applications: Vec<(u16, u16)>,
+    /// Values of the feature reports.
+    pub features: Vec<HidField>,
+}
+
+/// Global items, saved and restored by Push and Pop.
+#[derive(Debug, Clone, Copy, Default)]
+struct Globals {
+    usage_page: u16,
+    logical_min: i32,
+    logical_max: i32,
+    logical_max_raw: u32,
+    report_size: usize,
+    report_count: usize,
+    report_id: u8,
+}
+
+impl ReportDescriptor {
+    /// Parse a report descriptor, e.g. `/sys/class/hidraw/<device>/device/report_descriptor`.
+    ///
+    /// Fails with [`Error::ParseHidDescriptorError`] if an item runs past
+    /// the end of the descriptor, collections do not match or a report is
+    /// larger than the kernel accepts. Feature values wider than 32 bits
+    /// are skipped.
+    pub fn parse(bytes: &[u8]) -> Result<ReportDescriptor, Error> {
+        let invalid = |reason: String| Err(Error::ParseHidDescriptorError { reason });
+        let mut descriptor = ReportDescriptor::default();
+        let mut globals = Globals::default();
+        let mut stack: Vec<Globals> = Vec::new();
+        let mut usages: Vec<(u16, u16)> = Vec::new();
+        let mut usage_min: Option<u32> = None;
+        let mut depth = 0usize;
+        // bits used so far per report
+        let mut offsets: BTreeMap<(ReportKind, u8), usize> = BTreeMap::new();
+
+        let mut i = 0;
+        while i < bytes.len() {
+            let prefix = bytes[i];
+            if prefix == LONG_ITEM {
+                let size = usize::from(*bytes.get(i + 1).unwrap_or(&0));
+                i += 3 + size;
+                if i > bytes.len() {
+                    return invalid(String::from("long item runs past the end"));
+                }
+                continue;
+            }
+            let size = match prefix & 3 {
+                3 => 4,
+                n => usize::from(n),
+            };
+            let data = match bytes.get(i + 1..i + 1 + size) {
+                Some(data) => data,
+                None => return invalid(format!("item at byte {} runs past the end", i)),
+            };
+            i += 1 + size;
+            let unsigned = data
+                .iter()
+                .rev()
+                .fold(0u32, |value, &b| (value << 8) | u32::from(b));
+            let signed = match size {
+                1 => i32::from(unsigned as u8 as i8),
+                2 => i32::from(unsigned as u16 as i16),
+                _ => unsigned as i32,
+            };
+            let tag = prefix >> 4;
+
+            match (prefix >> 2 & 3, tag) {
+                (GLOBAL, 0x0) => globals.usage_page = unsigned as u16,
+                (GLOBAL, 0x1) => globals.logical_min = signed,
+                (GLOBAL, 0x2) => {
+                    globals.logical_max = signed;
+                    globals.logical_max_raw = unsigned;
+                }
+                (GLOBAL, 0x7) => {
+                    globals.report_size = unsigned as usize;
+                    if globals.report_size > MAX_REPORT_SIZE {
+                        return invalid(format!("report size {} is too large", unsigned));
+                    }
+                }
+                (GLOBAL, 0x8) => globals.report_id = unsigned as u8,
+                (GLOBAL, 0x9) => {
+                    globals.report_count = unsigned as usize;
+                    if globals.report_count > MAX_REPORT_COUNT {
+                        return invalid(format!("report count {} is too large", unsigned));
+                    }
+                }
+                (GLOBAL, 0xA) => stack.push(globals),
+                (GLOBAL, 0xB) => match stack.pop() {
+                    Some(saved) => globals = saved,
+                    None => return invalid(String::from("pop without push")),
+                },
+                (LOCAL, 0x0) => usages.push(usage(globals.usage_page, unsigned, size)),
+                (LOCAL, 0x1) => usage_min = Some(unsigned),
+                (LOCAL, 0x2) => {
+                    if let Some(min) = usage_min.take() {
+                        let (page, min) = usage(globals.usage_page, min, size);
+                        for u in u32::from(min)..=(unsigned & 0xFFFF) {
+                            usages.push((page, u as u16));
+                        }
+                    }
+                }
+                (MAIN, 0xA) => {
+                    if depth == 0 {
+                        descriptor
+                            .applications
+                            .push(usages.first().copied().unwrap_or((globals.usage_page, 0)));
+                    }
+                    depth += 1;
+                }
+                (MAIN, 0xC) => {
+                    if depth == 0 {
+                        return invalid(String::from("end of collection without a collection"));
+                    }
+                    depth -= 1;
+                }
+                (MAIN, tag @ (0x8 | 0x9 | 0xB)) => {
+                    let kind = match tag {
+                        0x8 => ReportKind::Input,
+                        0x9 => ReportKind::Output,
+                        _ => ReportKind::Feature,
+                    };
+                    let offset = offsets.entry((kind, globals.report_id)).or_insert(0);
+                    // bounded by the limits above, but checked all the same
+                    // as the descriptor comes from the device
+                    let end = match globals
+                        .report_count
+                        .checked_mul(globals.report_size)
+                        .and_then(|bits| offset.checked_add(bits))
+                    {
+                        Some(end) => end,
+                        None => return invalid(format!("report at byte {} is too large", i)),
+                    };
+                    let constant = unsigned & 1 == 1;
+                    if kind == ReportKind::Feature
+                        && !constant
+                        && globals.report_size <= MAX_FIELD_SIZE
+                    {
+                        // many devices encode an unsigned maximum without
+                        // room for the sign bit
+                        let logical_max = if globals.logical_min >= 0 && globals.logical_max < 0 {
+                            globals.logical_max_raw as i32
+                        } else {
+                            globals.logical_max
+                        };
+                        for n in 0..globals.report_count {
+                            let (usage_page, usage) = match usages.get(n).or(usages.last()) {
+                                Some(&usage) => usage,
+                                None => break,
+                            };
+                            descriptor.features.push(HidField {
+                                usage_page,
+                                usage,
+                                report_id: globals.report_id,
+                                report_len: 0,
+                                bit_offset: *offset + n * globals.report_size,
+                                bit_size: globals.report_size,
+                                logical_min: globals.logical_min,
+                                logical_max,
+                            });
+                        }
+                    }
+                    *offset = end;
+                }
+                _ => (),
+            }
+            if prefix >> 2 & 3 == MAIN {
+                usages.clear();
+                usage_min = None;
+            }
+        }
+        if depth != 0 {
+            return invalid(String::from("collection without an end"));
+        }
+
+        for field in descriptor.features.iter_mut() {
+            let bits = offsets[&(ReportKind::Feature, field.report_id)];
+            // hidraw puts the report ID in the first byte, 0 if the device
+            // numbers none
+            field.report_len = bits.div_ceil(8) + 1;
+        }
+        Ok(descriptor)
+    }
+
+    /// Whether the device is a monitor, i.e. has a Monitor Control
+    /// collection or VESA Virtual Controls.
+    pub fn is_monitor(&self) -> bool {
+        self.applications
+            .iter()
+            .any(|&(page, _)| page == HID_MONITOR_PAGE)
+            || self
+                .features
+                .iter()
+                .any(|field| field.usage_page == HID_VESA_PAGE)
+    }
+
+    /// Get the feature value with `usage` on `usage_page`.
+    pub fn find_feature(&self, usage_page: u16, usage: u16) -> Option<&HidField> {
+        self.features
+            .iter()
+            .find(|field| field.usage_page == usage_page && field.usage == usage)
+    }
+}
+
+/// Usage with its page, 4 byte usages carry their own page.
+fn usage(page: u16, data: u32, size: usize) -> (u16, u16) {
+    if size == 4 {
+        ((data >> 16) as u16, data as u16)
+    } else {
+        (page, data as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::FakeHidDisplay;
+
+    #[test]
+    fn monitor_descriptor() {
+        let descriptor = ReportDescriptor::parse(&FakeHidDisplay::descriptor()).unwrap();
+        assert_eq!(descriptor.applications, vec![(HID_MONITOR_PAGE, 0x01)]);
+        assert!(descriptor.is_monitor());
+        assert_eq!(descriptor.features.len(), 2);
+
+        let brightness = *descriptor
+            .find_feature(HID_VESA_PAGE, HID_BRIGHTNESS)
+            .unwrap();
+        assert_eq!(
+            brightness,
+            HidField {
+                usage_page: HID_VESA_PAGE,
+                usage: HID_BRIGHTNESS,
+                report_id: 1,
+                report_len: 5,
+                bit_offset: 0,
+                bit_size: 32,
+                logical_min: 400,
+                logical_max: 60000,
+            }
+        );
+
+        let contrast = *descriptor
+            .find_feature(HID_VESA_PAGE, HID_CONTRAST)
+            .unwrap();
+        assert_eq!(contrast.report_id, 2);
+        assert_eq!((contrast.bit_offset, contrast.bit_size), (4, 8));
+        assert_eq!(contrast.report_len, 3);
+        assert_eq!(descriptor.find_feature(HID_VESA_PAGE, 0xD6), None);
+
+        let mut report = contrast.new_report();
+        contrast.insert(&mut report, 0x5A).unwrap();
+        assert_eq!(report, [0x02, 0xA0, 0x05]);
+        assert_eq!(contrast.extract(&report), Some(0x5A));
+        assert_eq!(contrast.extract(&report[..2]), None);
+    }
+
+    #[test]
+    fn unnumbered() {
+        let descriptor = ReportDescriptor::parse(&[
+            0x05, 0x82, // Usage Page (VESA Virtual Controls)
+            0x09, 0x10, // Usage (Brightness)
+            0x15, 0x00, // Logical Minimum (0)
+            0x26, 0xFF, 0x00, // Logical Maximum (255)
+            0x75, 0x08, // Report Size (8)
+            0x95, 0x01, // Report Count (1)
+            0xB1, 0x02, // Feature (Data, Variable, Absolute)
+        ])
+        .unwrap();
+        let brightness = *descriptor
+            .find_feature(HID_VESA_PAGE, HID_BRIGHTNESS)
+            .unwrap();
+        assert_eq!((brightness.report_id, brightness.report_len), (0, 2));
+
+        let mut report = brightness.new_report();
+        assert_eq!(report, [0x00, 0x00]);
+        brightness.insert(&mut report, 0x80).unwrap();
+        assert_eq!(report, [0x00, 0x80]);
+        assert_eq!(brightness.extract(&report), Some(0x80));
+        assert_eq!(brightness.extract(&report[..1]), None);
+    }
+
+    #[test]
+    fn wide_vendor_field() {
+        let descriptor = ReportDescriptor::parse(&[
+            0x06, 0x00, 0xFF, // Usage Page (Vendor Defined)
+            0x09, 0x01, // Usage (1)
+            0x75, 0x40, // Report Size (64)
+            0x95, 0x01, // Report Count (1)
+            0xB1, 0x02, // Feature (Data, Variable, Absolute)
+            0x05, 0x82, // Usage Page (VESA Virtual Controls)
+            0x09, 0x10, // Usage (Brightness)
+            0x15, 0x00, // Logical Minimum (0)
+            0x26, 0xFF, 0x00, // Logical Maximum (255)
+            0x75, 0x08, // Report Size (8)
+            0xB1, 0x02, // Feature (Data, Variable, Absolute)
+        ])
+        .unwrap();
+        assert_eq!(descriptor.features.len(), 1);
+        let brightness = *descriptor
+            .find_feature(HID_VESA_PAGE, HID_BRIGHTNESS)
+            .unwrap();
+        assert_eq!((brightness.bit_offset, brightness.bit_size), (64, 8));
+        assert_eq!(brightness.report_len, 10);
+
+        let mut report = brightness.new_report();
+        brightness.insert(&mut report, 0x80).unwrap();
+        assert_eq!(report[9], 0x80);
+        assert_eq!(brightness.extract(&report), Some(0x80));
+    }
+
+    #[test]
+    fn signed() {
+        let descriptor = ReportDescriptor::parse(&[
+            0x05, 0x82, // Usage Page (VESA Virtual Controls)
+            0x09, 0x10, // Usage (Brightness)
+            0x15, 0x9C, // Logical Minimum (-100)
+            0x25, 0x64, // Logical Maximum (100)
+            0x75, 0x08, // Report Size (8)
+            0x95, 0x01, // Report Count (1)
+            0xB1, 0x02, // Feature (Data, Variable, Absolute)
+        ])
+        .unwrap();
+        let brightness = *descriptor
+            .find_feature(HID_VESA_PAGE, HID_BRIGHTNESS)
+            .unwrap();
+        assert_eq!(brightness.extract(&[0x00, 0xFB]), Some(-5i32 as u32));
+        assert_eq!(brightness.extract(&[0x00, 0x05]), Some(5));
+
+        let mut report = brightness.new_report();
+        brightness.insert(&mut report, -5i32 as u32).unwrap();
+        assert_eq!(report, [0x00, 0xFB]);
+    }
+
+    #[test]
+    fn invalid() {
+        let bad: [&[u8]; 8] = [
+            &[0x05],
+            &[0xA1, 0x01],
+            &[0xC0],
+            &[0xB4],
+            // Report Size (0xFFFFFFFF), Report Count (0xFFFFFFFF)
+            &[
+                0x77, 0xFF, 0xFF, 0xFF, 0xFF, 0x97, 0xFF, 0xFF, 0xFF, 0xFF, 0xB1, 0x01, 0xB1, 0x01,
+            ],
+            // Report Size (257)
+            &[0x76, 0x01, 0x01, 0x95, 0x01, 0xB1, 0x01],
+            // Report Count (12289) of a variable feature
+            &[0x09, 0x10, 0x75, 0x08, 0x96, 0x01, 0x30, 0xB1, 0x02],
+            // Report Count (0xFFFFFFFF) of a variable feature
+            &[
+                0x09, 0x10, 0x75, 0x01, 0x97, 0xFF, 0xFF, 0xFF, 0xFF, 0xB1, 0x02,
+            ],
+        ];
+        for bytes in bad.iter() {
+            assert!(
+                matches!(
+                    ReportDescriptor::parse(bytes),
+                    Err(Error::ParseHidDescriptorError { .. })
+                ),
+                "{:02X?}",
+                bytes
+            );
+        }
+        let keyboard =
+            ReportDescriptor::parse(&[0x05, 0x01, 0x09, 0x06, 0xA1, 0x01, 0xC0]).unwrap();
+        assert!(!keyboard.is_monitor());
+    }
+}
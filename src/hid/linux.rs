@@ -0,0 +1,509 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use super::{HidField, ReportDescriptor, HID_BRIGHTNESS, HID_VESA_PAGE};
+use crate::{
+    curve::Curve,
+    error::Error,
+    sysfs::{scan_dir, Scan, Sysfs},
+    utils::device_error,
+};
+
+/// `ioctl` number of `HIDIOCSFEATURE(len)` or `HIDIOCGFEATURE(len)` from
+/// `linux/hidraw.h`.
+fn feature_ioctl(nr: libc::c_ulong, len: usize) -> libc::c_ulong {
+    // _IOC(_IOC_WRITE | _IOC_READ, 'H', nr, len)
+    (3 << 30) | ((len as libc::c_ulong) << 16) | (libc::c_ulong::from(b'H') << 8) | nr
+}
+
+/// A device that can exchange HID feature reports, e.g. `/dev/hidraw3` or
+/// an in-memory display in tests.
+pub trait HidBus {
+    /// Fill `report` with the feature report whose ID is in `report[0]`,
+    /// returning its length.
+    fn get_feature(&mut self, report: &mut [u8]) -> io::Result<usize>;
+    /// Send feature report `report`, its ID in `report[0]`.
+    fn set_feature(&mut self, report: &[u8]) -> io::Result<()>;
+}
+
+impl<B: HidBus + ?Sized> HidBus for Box<B> {
+    fn get_feature(&mut self, report: &mut [u8]) -> io::Result<usize> {
+        (**self).get_feature(report)
+    }
+
+    fn set_feature(&mut self, report: &[u8]) -> io::Result<()> {
+        (**self).set_feature(report)
+    }
+}
+
+/// A raw HID device, `/dev/hidraw<N>`.
+#[derive(Debug)]
+pub struct HidrawDevice {
+    path: PathBuf,
+    file: File,
+}
+
+impl HidrawDevice {
+    /// Open the HID device at `path`, e.g. `/dev/hidraw3`.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<HidrawDevice, Error> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        Ok(HidrawDevice { path, file })
+    }
+
+    /// Get the path of the device.
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl HidBus for HidrawDevice {
+    fn get_feature(&mut self, report: &mut [u8]) -> io::Result<usize> {
+        // SAFETY: HIDIOCGFEATURE writes at most `report.len()` bytes, the
+        // length encoded in the request, into the buffer.
+        let res = unsafe {
+            libc::ioctl(
+                self.file.as_raw_fd(),
+                feature_ioctl(0x07, report.len()) as _,
+                report.as_mut_ptr(),
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(res as usize)
+    }
+
+    fn set_feature(&mut self, report: &[u8]) -> io::Result<()> {
+        // SAFETY: HIDIOCSFEATURE reads `report.len()` bytes, the length
+        // encoded in the request, from the buffer.
+        let res = unsafe {
+            libc::ioctl(
+                self.file.as_raw_fd(),
+                feature_ioctl(0x06, report.len()) as _,
+                report.as_ptr(),
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+type SharedHid = Arc<Mutex<Box<dyn HidBus + Send>>>;
+
+/// Monitor controlled through the USB Monitor Control class, like the
+/// Apple Studio Display or LG UltraFine.
+///
+/// Offers the same brightness methods as
+/// [`MonitorDevice`](crate::monitor::MonitorDevice). The brightness field
+/// of these displays rarely starts at zero, so brightness is counted from
+/// the field's logical minimum. Clones share the same device.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::{curve::Curve, hid::HidMonitor};
+///
+/// for monitor in HidMonitor::get_all_hid_monitors().unwrap() {
+///     println!("{}: {}%", monitor.get_device_name(), monitor.get_percent(Curve::Linear));
+///     monitor.set_percent(40.0, Curve::Linear).unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct HidMonitor {
+    /// Name of the raw HID device, e.g. `hidraw3`.
+    pub device: String,
+    /// Brightness when the monitor was read.
+    pub brightness: u32,
+    /// Maximum brightness.
+    pub max_brightness: u32,
+    /// Parsed report descriptor of the device.
+    pub descriptor: ReportDescriptor,
+    field: HidField,
+    hid: SharedHid,
+}
+
+impl fmt::Debug for HidMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HidMonitor")
+            .field("device", &self.device)
+            .field("brightness", &self.brightness)
+            .field("max_brightness", &self.max_brightness)
+            .finish()
+    }
+}
+
+impl HidMonitor {
+    /// Get the monitor on `/dev/<device>`, e.g. `hidraw3`.
+    pub fn get_hid_monitor(device: &str) -> Result<HidMonitor, Error> {
+        let descriptor = HidMonitor::read_descriptor_in(&Sysfs::default(), device)?;
+        let bus = HidrawDevice::open(Path::new("/dev").join(device))?;
+        HidMonitor::from_bus(device, Box::new(bus), descriptor)
+    }
+
+    /// Get the monitor behind `bus` whose report descriptor is
+    /// `descriptor`, reading its brightness.
+    ///
+    /// Fails with [`Error::Unsupported`] if the descriptor has no
+    /// brightness feature.
+    pub fn from_bus(
+        device: &str,
+        bus: Box<dyn HidBus + Send>,
+        descriptor: ReportDescriptor,
+    ) -> Result<HidMonitor, Error> {
+        let field = *descriptor
+            .find_feature(HID_VESA_PAGE, HID_BRIGHTNESS)
+            .ok_or(Error::Unsupported {
+                operation: "brightness over HID",
+            })?;
+        let mut monitor = HidMonitor {
+            device: device.to_string(),
+            brightness: 0,
+            max_brightness: field.logical_max.saturating_sub(field.logical_min).max(0) as u32,
+            descriptor,
+            field,
+            hid: Arc::new(Mutex::new(bus)),
+        };
+        monitor.refresh()?;
+        Ok(monitor)
+    }
+
+    /// Get every monitor offering brightness over USB HID.
+    ///
+    /// Devices that cannot be opened, usually for lack of permission on
+    /// `/dev/hidraw*`, are skipped, use
+    /// [`scan_hid_monitors`](HidMonitor::scan_hid_monitors) to find out why.
+    pub fn get_all_hid_monitors() -> Result<Vec<HidMonitor>, Error> {
+        Ok(HidMonitor::scan_hid_monitors()?.devices)
+    }
+
+    /// Get every monitor offering brightness over USB HID along with the
+    /// errors for those that could not be opened or read.
+    ///
+    /// Raw HID devices whose report descriptor cannot be parsed are
+    /// reported as errors too.
+    pub fn scan_hid_monitors() -> Result<Scan<HidMonitor>, Error> {
+        HidMonitor::scan_hid_monitors_in(&Sysfs::default(), Path::new("/dev"))
+    }
+
+    /// Like [`scan_hid_monitors`](HidMonitor::scan_hid_monitors), with the
+    /// devices listed from `sysfs` and opened in `dev`.
+    pub(crate) fn scan_hid_monitors_in(
+        sysfs: &Sysfs,
+        dev: &Path,
+    ) -> Result<Scan<HidMonitor>, Error> {
+        let devices = HidMonitor::scan_hid_devices_in(sysfs)?;
+        let mut scan = Scan {
+            devices: Vec::new(),
+            errors: devices.errors,
+        };
+        for (device, descriptor) in devices.devices {
+            let res = HidrawDevice::open(dev.join(&device))
+                .and_then(|bus| HidMonitor::from_bus(&device, Box::new(bus), descriptor));
+            match res {
+                Ok(monitor) => scan.devices.push(monitor),
+                Err(e) => scan.errors.push((device, e)),
+            }
+        }
+        Ok(scan)
+    }
+
+    /// Get the name and report descriptor of every raw HID device in the
+    /// given sysfs tree that has a brightness feature.
+    ///
+    /// Descriptors are read from sysfs, so no device has to be opened.
+    /// Devices whose descriptor cannot be read are skipped, use
+    /// [`scan_hid_devices_in`](HidMonitor::scan_hid_devices_in) to find out
+    /// why.
+    pub fn hid_devices_in(sysfs: &Sysfs) -> Result<Vec<(String, ReportDescriptor)>, Error> {
+        Ok(HidMonitor::scan_hid_devices_in(sysfs)?.devices)
+    }
+
+    /// Get the name and report descriptor of every raw HID device in the
+    /// given sysfs tree that has a brightness feature, along with the errors
+    /// for those whose descriptor could not be read or parsed.
+    pub fn scan_hid_devices_in(sysfs: &Sysfs) -> Result<Scan<(String, ReportDescriptor)>, Error> {
+        scan_dir(
+            &sysfs.hidraw_dir(),
+            |device| match HidMonitor::read_descriptor_in(sysfs, &device) {
+                Ok(descriptor)
+                    if descriptor
+                        .find_feature(HID_VESA_PAGE, HID_BRIGHTNESS)
+                        .is_some() =>
+                {
+                    Some(Ok((device, descriptor)))
+                }
+                Ok(_) | Err(Error::InvalidDeviceName { .. }) => None,
+                Err(e) => Some(Err(e)),
+            },
+        )
+    }
+
+    /// Read and parse the report descriptor of `device` from the given
+    /// sysfs tree.
+    pub fn read_descriptor_in(sysfs: &Sysfs, device: &str) -> Result<ReportDescriptor, Error> {
+        let path = sysfs
+            .hidraw_dir()
+            .join(device)
+            .join("device")
+            .join("report_descriptor");
+        match fs::read(&path) {
+            Ok(bytes) => ReportDescriptor::parse(&bytes),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Error::InvalidDeviceName {
+                device: device.to_string(),
+            }),
+            Err(e) => Err(device_error(&path, e)),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Box<dyn HidBus + Send>> {
+        self.hid.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get name of the raw HID device of monitor.
+    pub fn get_device_name(&self) -> &str {
+        &self.device
+    }
+
+    /// Get brightness of monitor when it was last read.
+    pub fn get_brightness(&self) -> u32 {
+        self.brightness
+    }
+
+    /// Get maximum brightness of monitor.
+    pub fn get_max_brightness(&self) -> u32 {
+        self.max_brightness
+    }
+
+    /// Get percentage of perceived brightness of monitor.
+    pub fn get_percent(&self, curve: Curve) -> f64 {
+        curve.to_percent(self.brightness, self.max_brightness)
+    }
+
+    /// Re-read brightness of monitor from the display.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        self.brightness = self.read_brightness()?;
+        Ok(())
+    }
+
+    /// Read the current brightness of monitor from the display, without
+    /// updating [`get_brightness`](HidMonitor::get_brightness).
+    pub fn read_brightness(&self) -> Result<u32, Error> {
+        let raw = self.get_feature(&self.field)?;
+        let raw = if self.field.logical_min < 0 {
+            i64::from(raw as i32)
+        } else {
+            i64::from(raw)
+        };
+        let value = raw - i64::from(self.field.logical_min);
+        Ok(value.clamp(0, i64::from(self.max_brightness)) as u32)
+    }
+
+    /// Set brightness of monitor.
+    pub fn set_brightness(&self, level: u32) -> Result<(), Error> {
+        if level > self.max_brightness {
+            return Err(Error::InvalidBrightnessLevel {
+                given: level,
+                max: self.max_brightness,
+            });
+        }
+        let value = i64::from(level) + i64::from(self.field.logical_min);
+        self.set_feature(&self.field, value as u32)
+    }
+
+    /// Set brightness of monitor to a percentage of perceived brightness.
+    pub fn set_percent(&self, percent: f64, curve: Curve) -> Result<(), Error> {
+        if (0.0..=100.0).contains(&percent) {
+            self.set_brightness(curve.to_raw(percent, self.max_brightness))
+        } else {
+            Err(Error::InvalidPercentage { given: percent })
+        }
+    }
+
+    /// Read the raw value of any feature field of monitor.
+    pub fn get_feature(&self, field: &HidField) -> Result<u32, Error> {
+        let mut report = field.new_report();
+        let len = self.lock().get_feature(&mut report)?;
+        report.truncate(len);
+        field.extract(&report).ok_or_else(|| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("feature report {} is too short", field.report_id),
+            ))
+        })
+    }
+
+    /// Write the raw value of any feature field of monitor, keeping the
+    /// other values in its report.
+    pub fn set_feature(&self, field: &HidField, value: u32) -> Result<(), Error> {
+        let mut hid = self.lock();
+        let mut report = field.new_report();
+        let len = hid.get_feature(&mut report)?;
+        report.resize(len.max(field.report_len), 0);
+        field.insert(&mut report, value).ok_or(Error::Unsupported {
+            operation: "writing feature reports shorter than their descriptor",
+        })?;
+        Ok(hid.set_feature(&report)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        hid::HID_CONTRAST,
+        testing::{FakeHidDisplay, FakeSysfs},
+    };
+
+    fn fake_monitor() -> (FakeHidDisplay, HidMonitor) {
+        let display = FakeHidDisplay::new();
+        let descriptor = ReportDescriptor::parse(&FakeHidDisplay::descriptor()).unwrap();
+        let monitor =
+            HidMonitor::from_bus("hidraw3", Box::new(display.clone()), descriptor).unwrap();
+        (display, monitor)
+    }
+
+    #[test]
+    fn brightness() {
+        let (display, mut monitor) = fake_monitor();
+        assert_eq!(monitor.get_max_brightness(), 59600);
+        assert_eq!(monitor.get_brightness(), 29600);
+
+        monitor.set_percent(100.0, Curve::Linear).unwrap();
+        assert_eq!(display.get(HID_VESA_PAGE, HID_BRIGHTNESS), Some(60000));
+        monitor.set_brightness(0).unwrap();
+        assert_eq!(display.get(HID_VESA_PAGE, HID_BRIGHTNESS), Some(400));
+        assert!(matches!(
+            monitor.set_brightness(59601),
+            Err(Error::InvalidBrightnessLevel { .. })
+        ));
+
+        display.set(HID_VESA_PAGE, HID_BRIGHTNESS, 10400);
+        assert_eq!(monitor.read_brightness().unwrap(), 10000);
+        assert_eq!(monitor.get_brightness(), 29600);
+        monitor.refresh().unwrap();
+        assert_eq!(monitor.get_brightness(), 10000);
+
+        // writing keeps the padding and other values of the report
+        let contrast = *monitor
+            .descriptor
+            .find_feature(HID_VESA_PAGE, HID_CONTRAST)
+            .unwrap();
+        assert_eq!(monitor.get_feature(&contrast).unwrap(), 50);
+        monitor.set_feature(&contrast, 75).unwrap();
+        assert_eq!(display.get(HID_VESA_PAGE, HID_CONTRAST), Some(75));
+    }
+
+    #[test]
+    fn unnumbered() {
+        let descriptor = [
+            0x05, 0x82, // Usage Page (VESA Virtual Controls)
+            0x09, 0x10, // Usage (Brightness)
+            0x15, 0x00, // Logical Minimum (0)
+            0x26, 0xFF, 0x00, // Logical Maximum (255)
+            0x75, 0x08, // Report Size (8)
+            0x95, 0x01, // Report Count (1)
+            0xB1, 0x02, // Feature (Data, Variable, Absolute)
+        ];
+        let display = FakeHidDisplay::with_descriptor(&descriptor).unwrap();
+        display.set(HID_VESA_PAGE, HID_BRIGHTNESS, 128);
+        let monitor = HidMonitor::from_bus(
+            "hidraw4",
+            Box::new(display.clone()),
+            ReportDescriptor::parse(&descriptor).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(monitor.get_brightness(), 128);
+        monitor.set_brightness(255).unwrap();
+        assert_eq!(display.get(HID_VESA_PAGE, HID_BRIGHTNESS), Some(255));
+    }
+
+    #[test]
+    fn signed() {
+        let descriptor = [
+            0x05, 0x82, // Usage Page (VESA Virtual Controls)
+            0x09, 0x10, // Usage (Brightness)
+            0x15, 0x9C, // Logical Minimum (-100)
+            0x25, 0x64, // Logical Maximum (100)
+            0x75, 0x08, // Report Size (8)
+            0x95, 0x01, // Report Count (1)
+            0xB1, 0x02, // Feature (Data, Variable, Absolute)
+        ];
+        let display = FakeHidDisplay::with_descriptor(&descriptor).unwrap();
+        display.set(HID_VESA_PAGE, HID_BRIGHTNESS, -50i32 as u32);
+        let monitor = HidMonitor::from_bus(
+            "hidraw4",
+            Box::new(display.clone()),
+            ReportDescriptor::parse(&descriptor).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(monitor.get_max_brightness(), 200);
+        assert_eq!(monitor.get_brightness(), 50);
+        monitor.set_brightness(0).unwrap();
+        assert_eq!(
+            display.get(HID_VESA_PAGE, HID_BRIGHTNESS),
+            Some(-100i32 as u32)
+        );
+        assert_eq!(monitor.read_brightness().unwrap(), 0);
+    }
+
+    #[test]
+    fn discovery() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_hidraw(
+            "hidraw3",
+            "pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.2/0003:05AC:1114.0005",
+            &FakeHidDisplay::descriptor(),
+        )
+        .unwrap();
+        fake.add_hidraw(
+            "hidraw0",
+            "pci0000:00/0000:00:14.0/usb1/1-1/1-1:1.0/0003:046D:C31C.0001",
+            &[0x05, 0x01, 0x09, 0x06, 0xA1, 0x01, 0xC0],
+        )
+        .unwrap();
+        fake.add_hidraw("hidraw1", "platform/broken", &[0xA1, 0x01])
+            .unwrap();
+
+        let devices = HidMonitor::hid_devices_in(&fake.sysfs()).unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].0, "hidraw3");
+        assert!(devices[0].1.is_monitor());
+        let scan = HidMonitor::scan_hid_devices_in(&fake.sysfs()).unwrap();
+        assert_eq!(scan.devices.len(), 1);
+        assert_eq!(scan.errors.len(), 1);
+        assert_eq!(scan.errors[0].0, "hidraw1");
+        assert!(matches!(
+            scan.errors[0].1,
+            Error::ParseHidDescriptorError { .. }
+        ));
+
+        assert!(matches!(
+            HidMonitor::read_descriptor_in(&fake.sysfs(), "hidraw9"),
+            Err(Error::InvalidDeviceName { .. })
+        ));
+        let keyboard = HidMonitor::read_descriptor_in(&fake.sysfs(), "hidraw0").unwrap();
+        assert!(matches!(
+            HidMonitor::from_bus("hidraw0", Box::new(FakeHidDisplay::new()), keyboard),
+            Err(Error::Unsupported { .. })
+        ));
+    }
+}
@@ -0,0 +1,499 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    collections::HashMap,
+    fmt, io, mem,
+    os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    str,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{error::Error, sysfs::DeviceClass};
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Multicast group the kernel sends uevents to.
+const KERNEL_GROUP: u32 = 1;
+/// Kernel uevents are at most `UEVENT_BUFFER_SIZE` (2048) bytes plus the header.
+const BUFFER_SIZE: usize = 8192;
+
+/// Kernel uevent, as broadcast over `NETLINK_KOBJECT_UEVENT`.
+///
+/// A uevent is a `<action>@<devpath>` header followed by `KEY=value`
+/// pairs, all NUL terminated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct Uevent {
+    /// `add`, `remove`, `change`, `move`, `bind`, ...
+    pub action: String,
+    /// Path of the device below `/sys`.
+    pub devpath: String,
+    /// Every `KEY=value` pair, including `ACTION` and `DEVPATH`.
+    pub env: HashMap<String, String>,
+}
+
+impl Uevent {
+    /// Trys to parse a netlink payload into Uevent.
+    ///
+    /// Messages rebroadcast by udev (starting with `libudev`) are not
+    /// kernel uevents and are rejected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::hotplug::Uevent;
+    ///
+    /// let uevent = Uevent::parse(
+    ///     b"change@/devices/platform/thinkpad_acpi/leds/tpacpi::kbd_backlight\0\
+    ///       ACTION=change\0\
+    ///       DEVPATH=/devices/platform/thinkpad_acpi/leds/tpacpi::kbd_backlight\0\
+    ///       SUBSYSTEM=leds\0\
+    ///       SEQNUM=3141\0",
+    /// )
+    /// .unwrap();
+    /// assert_eq!(uevent.action, "change");
+    /// assert_eq!(uevent.subsystem(), Some("leds"));
+    /// assert_eq!(uevent.seqnum(), Some(3141));
+    /// ```
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        let mut fields = buf
+            .split(|&b| b == 0)
+            .filter(|field| !field.is_empty())
+            .map(str::from_utf8);
+
+        let header = fields.next()?.ok()?;
+        let (header_action, header_devpath) = header.split_once('@')?;
+
+        let mut env = HashMap::new();
+        for field in fields {
+            if let Some((key, value)) = field.ok()?.split_once('=') {
+                env.insert(key.to_string(), value.to_string());
+            }
+        }
+        let action = env
+            .get("ACTION")
+            .map_or(header_action, String::as_str)
+            .to_string();
+        let devpath = env
+            .get("DEVPATH")
+            .map_or(header_devpath, String::as_str)
+            .to_string();
+        if action.is_empty() || !devpath.starts_with('/') {
+            return None;
+        }
+
+        Some(Uevent {
+            action,
+            devpath,
+            env,
+        })
+    }
+
+    /// Value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.env.get(key).map(String::as_str)
+    }
+
+    /// Subsystem of the device, e.g. `backlight` or `leds`.
+    pub fn subsystem(&self) -> Option<&str> {
+        self.get("SUBSYSTEM")
+    }
+
+    /// Sequence number of the uevent.
+    pub fn seqnum(&self) -> Option<u64> {
+        self.get("SEQNUM")?.parse().ok()
+    }
+
+    /// Name of the device, the last component of the devpath.
+    pub fn device_name(&self) -> &str {
+        self.devpath.rsplit('/').next().unwrap_or(&self.devpath)
+    }
+}
+
+/// What happened to a light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum HotplugAction {
+    /// Light appeared.
+    Add,
+    /// Light disappeared.
+    Remove,
+    /// Light changed, e.g. its brightness was changed by a hotkey.
+    Change,
+}
+
+impl HotplugAction {
+    /// Trys to parse str into HotplugAction.
+    pub fn from_id(s: &str) -> Option<Self> {
+        match s {
+            "add" => Some(HotplugAction::Add),
+            "remove" => Some(HotplugAction::Remove),
+            "change" => Some(HotplugAction::Change),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for HotplugAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            HotplugAction::Add => write!(f, "add"),
+            HotplugAction::Remove => write!(f, "remove"),
+            HotplugAction::Change => write!(f, "change"),
+        }
+    }
+}
+
+/// A backlight or LED was added, removed or changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct HotplugEvent {
+    /// What happened.
+    pub action: HotplugAction,
+    /// Class of the light.
+    pub class: DeviceClass,
+    /// Name of the light in `/sys/class/<class>/`.
+    pub device: String,
+    /// The uevent the event was made from.
+    pub uevent: Uevent,
+}
+
+impl HotplugEvent {
+    /// Trys to turn a uevent into HotplugEvent.
+    ///
+    /// Uevents for subsystems other than `backlight` and `leds`, and actions
+    /// other than `add`, `remove` and `change` give `None`.
+    pub fn from_uevent(uevent: Uevent) -> Option<Self> {
+        let action = HotplugAction::from_id(&uevent.action)?;
+        let class = DeviceClass::from_id(uevent.subsystem()?)?;
+        Some(HotplugEvent {
+            action,
+            class,
+            device: uevent.device_name().to_string(),
+            uevent,
+        })
+    }
+
+    /// Trys to parse a netlink payload into HotplugEvent.
+    pub fn parse(buf: &[u8]) -> Option<Self> {
+        HotplugEvent::from_uevent(Uevent::parse(buf)?)
+    }
+}
+
+/// Listens for kernel uevents about backlights and LEDs.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::hotplug::HotplugMonitor;
+///
+/// let monitor = HotplugMonitor::new().unwrap();
+/// for event in monitor.events() {
+///     let event = event.unwrap();
+///     println!("{} {} {}", event.action, event.class, event.device);
+/// }
+/// ```
+pub struct HotplugMonitor {
+    socket: OwnedFd,
+    buf: Mutex<Vec<u8>>,
+}
+
+impl fmt::Debug for HotplugMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HotplugMonitor")
+            .field("socket", &self.socket)
+            .finish()
+    }
+}
+
+impl HotplugMonitor {
+    /// Open a `NETLINK_KOBJECT_UEVENT` socket subscribed to kernel uevents.
+    pub fn new() -> Result<HotplugMonitor, Error> {
+        // SAFETY: plain socket(2) call, the returned descriptor is checked
+        // before being owned.
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                libc::NETLINK_KOBJECT_UEVENT,
+            )
+        };
+        if fd < 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+        // SAFETY: `fd` is a freshly opened socket nobody else owns.
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        // SAFETY: sockaddr_nl is plain data, all zeroes is a valid value.
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = KERNEL_GROUP;
+        // SAFETY: `addr` is a valid sockaddr_nl of the given length.
+        let res = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if res < 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+
+        Ok(HotplugMonitor {
+            socket,
+            buf: Mutex::new(vec![0u8; BUFFER_SIZE]),
+        })
+    }
+
+    /// Block until the next backlight or LED event.
+    pub fn recv(&self) -> Result<HotplugEvent, Error> {
+        loop {
+            if let Some(event) = self.recv_timeout(None)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Block until the next backlight or LED event or until `timeout` passes.
+    ///
+    /// `None` waits forever, returns `Ok(None)` after a timeout.
+    pub fn recv_timeout(&self, timeout: Option<Duration>) -> Result<Option<HotplugEvent>, Error> {
+        let deadline = deadline(timeout);
+        let mut buf = self.buf.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            if !self.wait_readable(remaining)? {
+                if remaining == Some(Duration::ZERO) {
+                    return Ok(None);
+                }
+                continue;
+            }
+            if let Some(len) = self.recv_from_kernel(&mut buf)? {
+                if let Some(event) = HotplugEvent::parse(&buf[..len]) {
+                    return Ok(Some(event));
+                }
+            }
+        }
+    }
+
+    /// Iterate over backlight and LED events, blocking until each one happens.
+    pub fn events(&self) -> HotplugEvents<'_> {
+        HotplugEvents { monitor: self }
+    }
+
+    fn wait_readable(&self, timeout: Option<Duration>) -> Result<bool, Error> {
+        let mut fd = libc::pollfd {
+            fd: self.socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: `fd` is a single valid pollfd.
+        let ready = unsafe { libc::poll(&mut fd, 1, poll_timeout(timeout)) };
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(false);
+            }
+            return Err(Error::Io(err));
+        }
+        Ok(ready > 0)
+    }
+
+    /// Receive one datagram, `None` if it was not sent by the kernel.
+    fn recv_from_kernel(&self, buf: &mut [u8]) -> Result<Option<usize>, Error> {
+        // SAFETY: sockaddr_nl is plain data, all zeroes is a valid value.
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+        // SAFETY: `buf` and `addr` are valid for writes of the given lengths.
+        let len = unsafe {
+            libc::recvfrom(
+                self.socket.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+                0,
+                &mut addr as *mut libc::sockaddr_nl as *mut libc::sockaddr,
+                &mut addr_len,
+            )
+        };
+        if len < 0 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => Ok(None),
+                _ => Err(Error::Io(err)),
+            };
+        }
+        // only the kernel (port id 0) sends uevents, ignore anyone else
+        if addr.nl_pid != 0 {
+            return Ok(None);
+        }
+        Ok(Some(len as usize))
+    }
+}
+
+/// Instant `timeout` from now, `None` to wait forever, also when the
+/// instant cannot be represented.
+fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.and_then(|t| Instant::now().checked_add(t))
+}
+
+/// `timeout` in milliseconds for poll(2), `-1` to wait forever.
+///
+/// Rounded up, so less than a millisecond left does not poll with `0` and
+/// spin until the deadline.
+fn poll_timeout(timeout: Option<Duration>) -> libc::c_int {
+    timeout.map_or(-1, |t| {
+        let millis = t.as_nanos().div_ceil(1_000_000);
+        millis.min(i32::MAX as u128) as libc::c_int
+    })
+}
+
+impl AsRawFd for HotplugMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+/// Blocking iterator over hotplug events, see [`HotplugMonitor::events`].
+#[derive(Debug)]
+pub struct HotplugEvents<'a> {
+    monitor: &'a HotplugMonitor,
+}
+
+impl Iterator for HotplugEvents<'_> {
+    type Item = Result<HotplugEvent, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.monitor.recv())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKLIGHT_ADD: &[u8] =
+        b"add@/devices/pci0000:00/0000:00:02.0/drm/card0/card0-eDP-1/intel_backlight\0\
+        ACTION=add\0\
+        DEVPATH=/devices/pci0000:00/0000:00:02.0/drm/card0/card0-eDP-1/intel_backlight\0\
+        SUBSYSTEM=backlight\0\
+        SEQNUM=4567\0";
+
+    const BACKLIGHT_HOTKEY: &[u8] =
+        b"change@/devices/pci0000:00/0000:00:02.0/drm/card0/card0-eDP-1/intel_backlight\0\
+        ACTION=change\0\
+        DEVPATH=/devices/pci0000:00/0000:00:02.0/drm/card0/card0-eDP-1/intel_backlight\0\
+        SUBSYSTEM=backlight\0\
+        SOURCE=hotkey\0\
+        SEQNUM=4590\0";
+
+    const KEYBOARD_REMOVE: &[u8] = b"remove@/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C31C.0004/input/input25/input25::capslock\0\
+        ACTION=remove\0\
+        DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C31C.0004/input/input25/input25::capslock\0\
+        SUBSYSTEM=leds\0\
+        SEQNUM=5123";
+
+    const INPUT_ADD: &[u8] = b"add@/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C31C.0004/input/input25\0\
+        ACTION=add\0\
+        DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C31C.0004/input/input25\0\
+        SUBSYSTEM=input\0\
+        PRODUCT=3/46d/c31c/110\0\
+        NAME=\"Logitech USB Keyboard\"\0\
+        SEQNUM=5101\0";
+
+    const LED_BIND: &[u8] = b"bind@/devices/platform/thinkpad_acpi/leds/tpacpi::power\0\
+        ACTION=bind\0\
+        DEVPATH=/devices/platform/thinkpad_acpi/leds/tpacpi::power\0\
+        SUBSYSTEM=leds\0\
+        SEQNUM=77\0";
+
+    #[test]
+    fn huge_timeout_waits_forever() {
+        assert_eq!(deadline(None), None);
+        assert_eq!(deadline(Some(Duration::MAX)), None);
+        assert!(deadline(Some(Duration::from_secs(1))).is_some());
+    }
+
+    #[test]
+    fn timeouts_round_up() {
+        assert_eq!(poll_timeout(None), -1);
+        assert_eq!(poll_timeout(Some(Duration::ZERO)), 0);
+        assert_eq!(poll_timeout(Some(Duration::from_nanos(1))), 1);
+        assert_eq!(poll_timeout(Some(Duration::from_micros(1500))), 2);
+        assert_eq!(poll_timeout(Some(Duration::from_millis(3))), 3);
+        assert_eq!(poll_timeout(Some(Duration::MAX)), i32::MAX);
+    }
+
+    #[test]
+    fn parse_uevent() {
+        let uevent = Uevent::parse(BACKLIGHT_ADD).unwrap();
+        assert_eq!(uevent.action, "add");
+        assert_eq!(
+            uevent.devpath,
+            "/devices/pci0000:00/0000:00:02.0/drm/card0/card0-eDP-1/intel_backlight"
+        );
+        assert_eq!(uevent.subsystem(), Some("backlight"));
+        assert_eq!(uevent.seqnum(), Some(4567));
+        assert_eq!(uevent.device_name(), "intel_backlight");
+
+        let uevent = Uevent::parse(INPUT_ADD).unwrap();
+        assert_eq!(uevent.get("NAME"), Some("\"Logitech USB Keyboard\""));
+        assert_eq!(uevent.get("PRODUCT"), Some("3/46d/c31c/110"));
+    }
+
+    #[test]
+    fn parse_hotplug_events() {
+        let event = HotplugEvent::parse(BACKLIGHT_ADD).unwrap();
+        assert_eq!(event.action, HotplugAction::Add);
+        assert_eq!(event.class, DeviceClass::Backlight);
+        assert_eq!(event.device, "intel_backlight");
+
+        let event = HotplugEvent::parse(BACKLIGHT_HOTKEY).unwrap();
+        assert_eq!(event.action, HotplugAction::Change);
+        assert_eq!(event.uevent.get("SOURCE"), Some("hotkey"));
+
+        // no trailing NUL
+        let event = HotplugEvent::parse(KEYBOARD_REMOVE).unwrap();
+        assert_eq!(event.action, HotplugAction::Remove);
+        assert_eq!(event.class, DeviceClass::Leds);
+        assert_eq!(event.device, "input25::capslock");
+        assert_eq!(event.uevent.seqnum(), Some(5123));
+
+        assert_eq!(HotplugEvent::parse(INPUT_ADD), None);
+        assert_eq!(HotplugEvent::parse(LED_BIND), None);
+    }
+
+    #[test]
+    fn reject_malformed() {
+        assert_eq!(Uevent::parse(b""), None);
+        assert_eq!(Uevent::parse(b"\0\0"), None);
+        assert_eq!(Uevent::parse(b"add\0ACTION=add\0"), None);
+        assert_eq!(Uevent::parse(b"add@\0SUBSYSTEM=leds\0"), None);
+        assert_eq!(
+            Uevent::parse(b"add@/devices/virtual/leds/x\0SUBSYSTEM=\xff\0"),
+            None
+        );
+        // udev rebroadcast: "libudev" magic and a binary header
+        assert_eq!(
+            Uevent::parse(b"libudev\0\xfe\xed\xca\xfe\x28\0\0\0ACTION=add\0"),
+            None
+        );
+    }
+
+    #[test]
+    fn header_fallback() {
+        let uevent = Uevent::parse(b"remove@/devices/virtual/leds/test::status\0").unwrap();
+        assert_eq!(uevent.action, "remove");
+        assert_eq!(uevent.device_name(), "test::status");
+        assert_eq!(uevent.subsystem(), None);
+    }
+}
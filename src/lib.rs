@@ -50,9 +50,40 @@ except according to those terms.
 
 mod utils;
 
+/// Ambient light sensors and automatic brightness.
+pub mod als;
+/// Ways of changing brightness (sysfs, logind, in-memory).
+pub mod backend;
+/// Brightness levels bound to a device's maximum.
+pub mod brightness;
+/// Map percentages to raw brightness levels.
+pub mod curve;
+/// Control external monitors over DDC/CI.
+pub mod ddc;
+/// One interface over every kind of light.
+pub mod device;
+/// Display connectors backlights belong to.
+pub mod drm;
+/// Parse display identification data.
+pub mod edid;
 /// Possible errors for this crate.
 pub mod error;
+/// Animated brightness transitions.
+pub mod fade;
+/// Control monitors over USB HID.
+pub mod hid;
+/// Notifications for lights being added or removed.
+pub mod hotplug;
 /// Get lighting of led(s)
 pub mod misc;
 /// Get backlighting of monitor(s)
 pub mod monitor;
+/// Save and restore brightness across reboots.
+pub mod state;
+/// Location of the sysfs tree devices are read from.
+pub mod sysfs;
+/// Fake sysfs trees for tests.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+/// Wait for brightness changes made outside of the program.
+pub mod watch;
@@ -7,22 +7,29 @@ https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
 option. This file may not be copied, modified, or distributed
 except according to those terms.
 */
-use std::{fmt, fs, path::Path};
+use std::{fmt, sync::Arc, time::Duration};
 
-use super::LEDS_DIR;
+use super::{LedQuery, LedTrigger};
 use crate::{
+    backend::{default_backend, BrightnessBackend},
+    brightness::{Brightness, StepOptions},
+    curve::Curve,
     error::Error,
+    fade::{fade_blocking, Easing, Fade},
+    sysfs::{scan_dir, DeviceClass, DeviceId, Scan, Sysfs},
     utils::{read_sys_led, SysBacklightInterface},
 };
 
-#[cfg(not(feature = "dbus"))]
-use std::{fs::OpenOptions, io::prelude::*};
-
 #[cfg(feature = "dbus")]
 use serde::{Deserialize, Serialize};
-#[cfg(feature = "dbus")]
-use zbus::Connection;
 
+/// Filter on LED names.
+///
+/// Criteria are ORed together and compared with the parsed [`LedInfo`] like
+/// the matching [`LedQuery`] predicates, a filter without criteria matches
+/// nothing. Only kept for the deprecated `get_led_devices_with_*filter`
+/// functions, build a [`LedQuery`] instead.
+#[deprecated(note = "use LedQuery")]
 #[derive(Clone, Copy, Debug)]
 pub struct LedFilterable<'a> {
     device_name: Option<&'a str>,
@@ -30,65 +37,61 @@ pub struct LedFilterable<'a> {
     function: Option<LedFunction>,
 }
 
+#[allow(deprecated)]
 impl<'a> LedFilterable<'a> {
-    fn new() -> LedFilterable<'a> {
+    /// Create an empty filter.
+    #[deprecated(note = "use LedQuery")]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> LedFilterable<'a> {
         LedFilterable {
             device_name: None,
             color: None,
             function: None,
         }
     }
-    fn with_device_name(&'a mut self, device_name: &'a str) -> &'a mut LedFilterable {
+    /// Match LEDs whose `devicename` is `device_name`.
+    #[deprecated(note = "use LedQuery::device_name")]
+    pub fn with_device_name(&'a mut self, device_name: &'a str) -> &'a mut LedFilterable<'a> {
         self.device_name = Some(device_name);
         self
     }
-    fn with_color(&'a mut self, color: LedColor) -> &'a mut LedFilterable {
+    /// Match LEDs whose `color` is `color`.
+    #[deprecated(note = "use LedQuery::color")]
+    pub fn with_color(&'a mut self, color: LedColor) -> &'a mut LedFilterable<'a> {
         self.color = Some(color);
         self
     }
-    fn with_function(&'a mut self, function: LedFunction) -> &'a mut LedFilterable {
+    /// Match LEDs whose `function` is `function`.
+    #[deprecated(note = "use LedQuery::function")]
+    pub fn with_function(&'a mut self, function: LedFunction) -> &'a mut LedFilterable<'a> {
         self.function = Some(function);
         self
     }
-    fn finish(&'a mut self) -> LedFilterable {
+    /// Finish building the filter.
+    #[deprecated(note = "use LedQuery")]
+    pub fn finish(&'a mut self) -> LedFilterable<'a> {
         *(self)
     }
-    fn filter_by_device_name(&'a self, to_be_filtered: &str) -> bool {
-        if let Some(device_name) = &self.device_name {
-            to_be_filtered.contains(device_name)
-        } else {
-            false
-        }
-    }
-    fn filter_by_color(&'a self, to_be_filtered: &str) -> bool {
-        if let Some(color) = &self.color {
-            to_be_filtered.contains(color.to_string().as_str())
-        } else {
-            false
-        }
-    }
-    fn filter_by_function(&'a self, pre_filter: &str) -> bool {
-        if let Some(function) = &self.function {
-            pre_filter.contains(function.to_string().as_str())
-        } else {
-            false
-        }
-    }
-    fn filter(&'a self, to_be_filtered: &str) -> bool {
-        self.filter_by_device_name(to_be_filtered)
-            || self.filter_by_color(to_be_filtered)
-            || self.filter_by_function(to_be_filtered)
+    fn to_query(self) -> Option<LedQuery> {
+        let criteria = [
+            self.device_name.map(LedQuery::device_name),
+            self.color.map(LedQuery::color),
+            self.function.map(LedQuery::function),
+        ];
+        IntoIterator::into_iter(criteria)
+            .flatten()
+            .reduce(LedQuery::or)
     }
 }
 
-fn multi_filter_led(filters: &[LedFilterable], to_be_filtered: &str) -> bool {
-    let mut status = false;
-    for f in filters {
-        if f.filter(to_be_filtered) {
-            status = true;
-        }
-    }
-    status
+/// Query matching any of `filters`.
+#[allow(deprecated)]
+fn filters_to_query(filters: &[LedFilterable]) -> LedQuery {
+    filters
+        .iter()
+        .filter_map(|f| f.to_query())
+        .reduce(LedQuery::or)
+        .unwrap_or_else(|| !LedQuery::Any)
 }
 
 #[derive(Debug, Clone)]
@@ -129,6 +132,18 @@ pub struct LedDevice {
     If the LED does not support different brightness levels, this
     should be 1. */
     pub max_brightness: u32,
+    /** Kernel trigger controlling the LED, if the LED has a `trigger` file.
+
+    [`LedTrigger::None`] means brightness is only changed by userspace. */
+    pub trigger: Option<LedTrigger>,
+    /// Stable identity of the LED, resolved from its `device` symlink.
+    pub id: DeviceId,
+    /// Sysfs tree the LED was read from.
+    #[cfg_attr(feature = "dbus", serde(skip))]
+    sysfs: Sysfs,
+    /// Backend used to change brightness.
+    #[cfg_attr(feature = "dbus", serde(skip, default = "default_backend"))]
+    backend: Arc<dyn BrightnessBackend>,
 }
 
 #[derive(Debug, Clone)]
@@ -157,48 +172,41 @@ pub struct LedInfo {
 }
 
 impl LedDevice {
+    #[deprecated(note = "use LedDevice::query")]
+    #[allow(deprecated)]
     pub fn get_led_devices_with_filter(f: LedFilterable) -> Result<Vec<LedDevice>, Error> {
-        if Path::new(LEDS_DIR).is_dir() {
-            fs::read_dir(LEDS_DIR)
-                .unwrap()
-                .into_iter()
-                .filter(|r| r.is_ok()) // Get rid of Err variants for Result<DirEntry>
-                .map(|r| r.unwrap().file_name().into_string()) // This is safe, since we only have the Ok variants
-                .filter(|r| r.is_ok())
-                .map(|r| r.unwrap()) // This is safe, since we only have the Ok variants
-                // Get rid of Err variants for Result<DirEntry>
-                .filter(|e| {
-                    f.filter_by_device_name(e) || f.filter_by_color(e) || f.filter_by_function(e)
-                })
-                .map(LedDevice::get_led_device)
-                .collect::<Result<Vec<LedDevice>, Error>>()
-        } else {
-            Ok(Vec::new())
-        }
+        LedDevice::query(&filters_to_query(&[f]))
+    }
+
+    #[deprecated(note = "use LedDevice::query_in")]
+    #[allow(deprecated)]
+    pub fn get_led_devices_with_filter_in(
+        sysfs: &Sysfs,
+        f: LedFilterable,
+    ) -> Result<Vec<LedDevice>, Error> {
+        LedDevice::query_in(sysfs, &filters_to_query(&[f]))
     }
 
+    #[deprecated(note = "use LedDevice::query")]
+    #[allow(deprecated)]
     pub fn get_led_devices_with_multi_filter(f: &[LedFilterable]) -> Result<Vec<LedDevice>, Error> {
-        if Path::new(LEDS_DIR).is_dir() {
-            fs::read_dir(LEDS_DIR)
-                .unwrap()
-                .into_iter()
-                .filter(|r| r.is_ok()) // Get rid of Err variants for Result<DirEntry>
-                .map(|r| r.unwrap().file_name().into_string()) // This is safe, since we only have the Ok variants
-                .filter(|r| r.is_ok())
-                .map(|r| r.unwrap()) // This is safe, since we only have the Ok variants
-                // Get rid of Err variants for Result<DirEntry>
-                .filter(|e| multi_filter_led(f, e))
-                .map(LedDevice::get_led_device)
-                .collect::<Result<Vec<LedDevice>, Error>>()
-        } else {
-            Ok(Vec::new())
-        }
+        LedDevice::query(&filters_to_query(f))
+    }
+
+    #[deprecated(note = "use LedDevice::query_in")]
+    #[allow(deprecated)]
+    pub fn get_led_devices_with_multi_filter_in(
+        sysfs: &Sysfs,
+        f: &[LedFilterable],
+    ) -> Result<Vec<LedDevice>, Error> {
+        LedDevice::query_in(sysfs, &filters_to_query(f))
     }
+
     /// Get LED by device name.
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
     /// use bulbb::misc::LedDevice;
     ///
     /// let device_name = format!("asus::kbd_backlight");
@@ -206,25 +214,86 @@ impl LedDevice {
     /// assert_eq!(led_device.get_device_name(), device_name);
     /// ```
     pub fn get_led_device(device: String) -> Result<LedDevice, Error> {
-        if Path::new(format!("{}/{}", LEDS_DIR, &device).as_str()).is_dir() {
+        LedDevice::get_led_device_in(&Sysfs::default(), device)
+    }
+
+    /// Get LED by device name from the given sysfs tree.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::{misc::LedDevice, sysfs::Sysfs};
+    ///
+    /// let sysfs = Sysfs::new("/mnt/chroot/sys");
+    /// let led_device = LedDevice::get_led_device_in(&sysfs, format!("asus::kbd_backlight")).unwrap();
+    /// assert_eq!(led_device.get_sysfs(), &sysfs);
+    /// ```
+    pub fn get_led_device_in(sysfs: &Sysfs, device: String) -> Result<LedDevice, Error> {
+        if sysfs.led_device(&device).is_dir() {
             let brightness =
-                read_sys_led(&device, SysBacklightInterface::Brightness)?.parse::<u32>()?;
+                read_sys_led(sysfs, &device, SysBacklightInterface::Brightness)?.parse::<u32>()?;
             let max_brightness =
-                read_sys_led(&device, SysBacklightInterface::MaxBrightness)?.parse::<u32>()?;
+                read_sys_led(sysfs, &device, SysBacklightInterface::MaxBrightness)?
+                    .parse::<u32>()?;
+            let trigger = if sysfs.led_device(&device).join("trigger").exists() {
+                let triggers = read_sys_led(sysfs, &device, SysBacklightInterface::Trigger)?;
+                LedTrigger::parse_list(&triggers).1
+            } else {
+                None
+            };
+            let id = DeviceId::resolve(sysfs, DeviceClass::Leds, &device);
             let info = LedInfo::from_string(device);
 
             Ok(LedDevice {
                 info,
                 brightness,
                 max_brightness,
+                trigger,
+                id,
+                sysfs: sysfs.clone(),
+                backend: default_backend(),
             })
         } else {
             Err(Error::InvalidDeviceName { device })
         }
     }
 
+    /// Get LED by its stable [`DeviceId`] key, e.g. one saved in a config file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::misc::LedDevice;
+    ///
+    /// let led_device =
+    ///     LedDevice::get_led_device_by_id("leds/platform-asus-nb-wmi/asus::kbd_backlight").unwrap();
+    /// assert_eq!(led_device.get_device_name(), "asus::kbd_backlight");
+    /// ```
+    pub fn get_led_device_by_id(id: &str) -> Result<LedDevice, Error> {
+        LedDevice::get_led_device_by_id_in(&Sysfs::default(), id)
+    }
+
+    /// Get LED by its stable [`DeviceId`] key from the given sysfs tree.
+    ///
+    /// Fails with [`Error::AmbiguousDeviceId`] if several LEDs have the key.
+    pub fn get_led_device_by_id_in(sysfs: &Sysfs, id: &str) -> Result<LedDevice, Error> {
+        let mut matches = LedDevice::get_all_led_devices_in(sysfs)?
+            .into_iter()
+            .filter(|led| led.id.matches_key(id));
+        match (matches.next(), matches.next()) {
+            (Some(led), None) => Ok(led),
+            (Some(_), Some(_)) => Err(Error::AmbiguousDeviceId { id: id.to_string() }),
+            (None, _) => Err(Error::InvalidDeviceName {
+                device: id.to_string(),
+            }),
+        }
+    }
+
     /// Get all LED devices.
     ///
+    /// Devices that cannot be read are skipped, use
+    /// [`scan_led_devices`](LedDevice::scan_led_devices) to find out why.
+    ///
     /// # Examples
     ///
     /// ```
@@ -236,21 +305,65 @@ impl LedDevice {
     /// }
     /// ```
     pub fn get_all_led_devices() -> Result<Vec<LedDevice>, Error> {
-        let mut leds = Vec::with_capacity(1);
+        LedDevice::get_all_led_devices_in(&Sysfs::default())
+    }
 
-        if Path::new(LEDS_DIR).is_dir() {
-            for device in fs::read_dir(LEDS_DIR)? {
-                let device = device?;
-                let device_name = device.file_name().into_string().unwrap();
+    /// Get all LED devices from the given sysfs tree.
+    ///
+    /// Devices that cannot be read are skipped, use
+    /// [`scan_led_devices_in`](LedDevice::scan_led_devices_in) to find out why.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{misc::LedDevice, sysfs::Sysfs};
+    ///
+    /// let led_devices = LedDevice::get_all_led_devices_in(&Sysfs::default()).unwrap();
+    /// for ld in led_devices {
+    ///     println!("LED Device: {:?}", ld);
+    /// }
+    /// ```
+    pub fn get_all_led_devices_in(sysfs: &Sysfs) -> Result<Vec<LedDevice>, Error> {
+        Ok(LedDevice::scan_led_devices_in(sysfs)?.devices)
+    }
 
-                match LedDevice::get_led_device(device_name) {
-                    Ok(dev) => leds.push(dev),
-                    Err(e) => return Err(e),
-                }
-            }
-        }
+    /// Get all LED devices along with the errors for those that could not
+    /// be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::misc::LedDevice;
+    ///
+    /// let scan = LedDevice::scan_led_devices().unwrap();
+    /// for ld in scan.devices {
+    ///     println!("LED Device: {:?}", ld);
+    /// }
+    /// for (device, e) in scan.errors {
+    ///     eprintln!("Skipped {}: {}", device, e);
+    /// }
+    /// ```
+    pub fn scan_led_devices() -> Result<Scan<LedDevice>, Error> {
+        LedDevice::scan_led_devices_in(&Sysfs::default())
+    }
 
-        Ok(leds)
+    /// Get all LED devices from the given sysfs tree along with the errors
+    /// for those that could not be read.
+    ///
+    /// Only fails if `class/leds` exists but cannot be listed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{misc::LedDevice, sysfs::Sysfs};
+    ///
+    /// let scan = LedDevice::scan_led_devices_in(&Sysfs::default()).unwrap();
+    /// assert!(scan.devices.len() + scan.errors.len() < 1000);
+    /// ```
+    pub fn scan_led_devices_in(sysfs: &Sysfs) -> Result<Scan<LedDevice>, Error> {
+        scan_dir(&sysfs.leds_dir(), |device| {
+            Some(LedDevice::get_led_device_in(sysfs, device))
+        })
     }
 
     /// Get all keyboards devices.
@@ -266,11 +379,65 @@ impl LedDevice {
     /// }
     /// ```
     pub fn get_all_keyboard_devices() -> Result<Vec<LedDevice>, Error> {
-        LedDevice::get_led_devices_with_filter(LedFilterable {
-            device_name: None,
-            color: None,
-            function: Some(LedFunction::KbdBacklight),
-        })
+        LedDevice::get_all_keyboard_devices_in(&Sysfs::default())
+    }
+
+    /// Get all keyboards devices from the given sysfs tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{misc::LedDevice, sysfs::Sysfs};
+    ///
+    /// let keyboards = LedDevice::get_all_keyboard_devices_in(&Sysfs::default()).unwrap();
+    /// for keyboard in keyboards {
+    ///     println!("Keyboard: {:?}", keyboard);
+    /// }
+    /// ```
+    pub fn get_all_keyboard_devices_in(sysfs: &Sysfs) -> Result<Vec<LedDevice>, Error> {
+        LedDevice::query_in(sysfs, &LedQuery::function(LedFunction::KbdBacklight))
+    }
+
+    /// Get all LED devices matching `query`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::misc::{LedDevice, LedFunction, LedQuery};
+    ///
+    /// let query = LedQuery::function(LedFunction::CapsLock) | LedQuery::function(LedFunction::NumLock);
+    /// for led in LedDevice::query(&query).unwrap() {
+    ///     println!("LED Device: {:?}", led);
+    /// }
+    /// ```
+    pub fn query(query: &LedQuery) -> Result<Vec<LedDevice>, Error> {
+        LedDevice::query_in(&Sysfs::default(), query)
+    }
+
+    /// Get all LED devices from the given sysfs tree matching `query`.
+    ///
+    /// Devices that cannot be read are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{misc::{LedColor, LedDevice, LedQuery}, sysfs::Sysfs};
+    ///
+    /// let leds = LedDevice::query_in(&Sysfs::default(), &LedQuery::color(LedColor::Red)).unwrap();
+    /// for led in leds {
+    ///     println!("LED Device: {:?}", led);
+    /// }
+    /// ```
+    pub fn query_in(sysfs: &Sysfs, query: &LedQuery) -> Result<Vec<LedDevice>, Error> {
+        let scan = scan_dir(
+            &sysfs.leds_dir(),
+            |device| match LedDevice::get_led_device_in(sysfs, device) {
+                Ok(led) if query.matches(&led) => Some(Ok(led)),
+                Ok(_) => None,
+                Err(e) => Some(Err(e)),
+            },
+        )?;
+        Ok(scan.devices)
     }
 
     /// Get name of LED device.
@@ -292,6 +459,22 @@ impl LedDevice {
         &self.info.device
     }
 
+    /// Get stable identity of LED.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::misc::LedDevice;
+    ///
+    /// let led_devices = LedDevice::get_all_led_devices().unwrap();
+    /// for led_device in led_devices {
+    ///     println!("{}: {}", led_device.get_device_name(), led_device.get_id());
+    /// }
+    /// ```
+    pub fn get_id(&self) -> &DeviceId {
+        &self.id
+    }
+
     /// Get brightness of LED.
     ///
     /// # Examples
@@ -329,72 +512,231 @@ impl LedDevice {
         self.max_brightness
     }
 
-    /// Set brightness of LED.
+    /// Get the sysfs tree LED was read from.
     ///
-    /// ### Examples
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{misc::LedDevice, sysfs::Sysfs};
     ///
+    /// let led_devices = LedDevice::get_all_led_devices().unwrap();
+    /// for led_device in led_devices {
+    ///     assert_eq!(led_device.get_sysfs(), &Sysfs::default());
+    /// }
     /// ```
+    pub fn get_sysfs(&self) -> &Sysfs {
+        &self.sysfs
+    }
+
+    /// Re-read every value of LED from sysfs, e.g. after
+    /// [`set_brightness`](LedDevice::set_brightness).
+    ///
+    /// Fails with [`Error::DeviceGone`] if the LED has been removed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
     /// use bulbb::misc::LedDevice;
     ///
-    /// let keyboard = LedDevice::get_all_led_devices().unwrap();
-    /// keyboard[0].set_brightness(20);
+    /// let mut leds = LedDevice::get_all_led_devices().unwrap();
+    /// leds[0].set_brightness(1).unwrap();
+    /// leds[0].refresh().unwrap();
+    /// assert_eq!(leds[0].get_brightness(), 1);
     /// ```
-    #[cfg(feature = "dbus")]
-    pub fn set_brightness(&self, level: u32) -> Result<(), Error> {
-        if level <= self.max_brightness {
-            let sd_bus = Connection::new_system().unwrap();
-            match sd_bus.call_method(
-                Some("org.freedesktop.login1"),
-                "/org/freedesktop/login1/session/auto",
-                Some("org.freedesktop.login1.Session"),
-                "SetBrightness",
-                &("leds", &self.info.device, level),
-            ) {
-                Ok(_) => Ok(()),
-                Err(e) => Err(Error::SetBrightnessDBusError(e)),
-            }
-        } else {
-            Err(Error::InvalidBrightnessLevel {
-                given: level,
-                max: self.max_brightness,
-            })
-        }
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        let fresh = match LedDevice::get_led_device_in(&self.sysfs, self.info.device.clone()) {
+            Err(Error::InvalidDeviceName { device }) => return Err(Error::DeviceGone { device }),
+            res => res?,
+        };
+        self.brightness = fresh.brightness;
+        self.max_brightness = fresh.max_brightness;
+        self.trigger = fresh.trigger;
+        self.id = fresh.id;
+        Ok(())
     }
 
-    /// Set brightness of led device.
+    /// Whether LED still exists in sysfs.
+    pub fn is_present(&self) -> bool {
+        self.sysfs.led_device(&self.info.device).is_dir()
+    }
+
+    /// Read the current brightness of LED from sysfs, without updating
+    /// [`get_brightness`](LedDevice::get_brightness).
     ///
-    /// ### NOTE
+    /// # Examples
     ///
-    /// This method writes to `/sys/class/leds/<led>/brightness`
-    /// and will fail if user is not root (even when executed with sudo).
-    /// It is recommended to create a udev rule to allow user of a certain
-    /// group to write to the file. The example below will allow all users in
-    /// the `input` group to change the brightness of all devices in `/sys/class/leds/`.
+    /// ```no_run
+    /// use bulbb::misc::LedDevice;
     ///
-    /// ```ignore
-    /// ACTION=="add", SUBSYSTEM=="leds", RUN+="/bin/chgrp input /sys/class/leds/%k/brightness"
-    /// ACTION=="add", SUBSYSTEM=="leds", RUN+="/bin/chmod g+w /sys/class/leds/%k/brightness"
+    /// let leds = LedDevice::get_all_led_devices().unwrap();
+    /// println!("Brightness: {}", leds[0].read_brightness().unwrap());
     /// ```
+    pub fn read_brightness(&self) -> Result<u32, Error> {
+        Ok(read_sys_led(
+            &self.sysfs,
+            &self.info.device,
+            SysBacklightInterface::Brightness,
+        )?
+        .parse::<u32>()?)
+    }
+
+    /// Read the current trigger of LED from sysfs, without updating
+    /// [`get_trigger`](LedDevice::get_trigger).
+    pub fn read_trigger(&self) -> Result<Option<LedTrigger>, Error> {
+        let triggers = read_sys_led(
+            &self.sysfs,
+            &self.info.device,
+            SysBacklightInterface::Trigger,
+        )?;
+        Ok(LedTrigger::parse_list(&triggers).1)
+    }
+
+    /// Get the active trigger of LED.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::misc::LedDevice;
+    ///
+    /// let led_devices = LedDevice::get_all_led_devices().unwrap();
+    /// for led_device in led_devices {
+    ///     if let Some(trigger) = led_device.get_trigger() {
+    ///         println!("Trigger: {}", trigger);
+    ///     }
+    /// }
+    /// ```
+    pub fn get_trigger(&self) -> Option<&LedTrigger> {
+        self.trigger.as_ref()
+    }
+
+    /// Read the triggers that can be set on LED.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::misc::LedDevice;
+    ///
+    /// let led_devices = LedDevice::get_all_led_devices().unwrap();
+    /// for led_device in led_devices {
+    ///     if let Ok(triggers) = led_device.get_available_triggers() {
+    ///         println!("Triggers: {:?}", triggers);
+    ///     }
+    /// }
+    /// ```
+    pub fn get_available_triggers(&self) -> Result<Vec<LedTrigger>, Error> {
+        let triggers = read_sys_led(
+            &self.sysfs,
+            &self.info.device,
+            SysBacklightInterface::Trigger,
+        )?;
+        Ok(LedTrigger::parse_list(&triggers).0)
+    }
+
+    /// Set trigger of LED.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::misc::{LedDevice, LedTrigger};
+    ///
+    /// let leds = LedDevice::get_all_led_devices().unwrap();
+    /// leds[0].set_trigger(&LedTrigger::Heartbeat).unwrap();
+    /// ```
+    pub fn set_trigger(&self, trigger: &LedTrigger) -> Result<(), Error> {
+        self.set_trigger_with(self.backend.as_ref(), trigger)
+    }
+
+    /// Set trigger of LED using `backend` for this call only.
     ///
-    /// ### Examples
+    /// # Examples
     ///
+    /// ```no_run
+    /// use bulbb::{
+    ///     backend::SysfsBackend,
+    ///     misc::{LedDevice, LedTrigger},
+    /// };
+    ///
+    /// let leds = LedDevice::get_all_led_devices().unwrap();
+    /// leds[0].set_trigger_with(&SysfsBackend, &LedTrigger::DiskActivity).unwrap();
     /// ```
+    pub fn set_trigger_with(
+        &self,
+        backend: &dyn BrightnessBackend,
+        trigger: &LedTrigger,
+    ) -> Result<(), Error> {
+        backend.set_trigger(&self.sysfs, &self.info.device, trigger)
+    }
+
+    /// Remove the trigger of LED, leaving brightness to userspace.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
     /// use bulbb::misc::LedDevice;
     ///
-    /// let keyboard = LedDevice::get_all_led_devices().unwrap();
-    /// keyboard[0].set_brightness(20);
+    /// let leds = LedDevice::get_all_led_devices().unwrap();
+    /// leds[0].clear_trigger().unwrap();
+    /// ```
+    pub fn clear_trigger(&self) -> Result<(), Error> {
+        self.set_trigger(&LedTrigger::None)
+    }
+
+    /// Get the backend used to change brightness of LED.
+    pub fn get_backend(&self) -> &Arc<dyn BrightnessBackend> {
+        &self.backend
+    }
+
+    /// Change the backend used to change brightness of LED.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use bulbb::{backend::SysfsBackend, misc::LedDevice};
+    ///
+    /// for led_device in LedDevice::get_all_led_devices().unwrap() {
+    ///     let led_device = led_device.with_backend(Arc::new(SysfsBackend));
+    /// }
+    /// ```
+    pub fn with_backend(mut self, backend: Arc<dyn BrightnessBackend>) -> LedDevice {
+        self.backend = backend;
+        self
+    }
+
+    /// Set brightness of LED.
+    ///
+    /// The device's backend is used, which is [`default_backend`] unless
+    /// changed with [`with_backend`](LedDevice::with_backend).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::misc::LedDevice;
+    ///
+    /// let leds = LedDevice::get_all_led_devices().unwrap();
+    /// leds[0].set_brightness(20).unwrap();
     /// ```
-    #[cfg(not(feature = "dbus"))]
     pub fn set_brightness(&self, level: u32) -> Result<(), Error> {
+        self.set_brightness_with(self.backend.as_ref(), level)
+    }
+
+    /// Set brightness of LED using `backend` for this call only.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::{backend::SysfsBackend, misc::LedDevice};
+    ///
+    /// let leds = LedDevice::get_all_led_devices().unwrap();
+    /// leds[0].set_brightness_with(&SysfsBackend, 20).unwrap();
+    /// ```
+    pub fn set_brightness_with(
+        &self,
+        backend: &dyn BrightnessBackend,
+        level: u32,
+    ) -> Result<(), Error> {
         if level <= self.max_brightness {
-            // write to /sys/class/leds/<led>/brightness
-            let mut brightness = OpenOptions::new()
-                .write(true)
-                .open(format!("{}/{}/brightness", LEDS_DIR, &self.info.device))?;
-            match brightness.write_all(level.to_string().as_bytes()) {
-                Ok(_) => Ok(()),
-                Err(e) => Err(Error::Io(e)),
-            }
+            backend.set_brightness(&self.sysfs, DeviceClass::Leds, &self.info.device, level)
         } else {
             Err(Error::InvalidBrightnessLevel {
                 given: level,
@@ -402,6 +744,149 @@ impl LedDevice {
             })
         }
     }
+
+    /// Get brightness of LED as a percentage of perceived brightness.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{curve::Curve, misc::LedDevice};
+    ///
+    /// for led_device in LedDevice::get_all_led_devices().unwrap() {
+    ///     let percent = led_device.get_percent(Curve::Cie1931);
+    ///     assert!(percent >= 0.0 && percent <= 100.0);
+    /// }
+    /// ```
+    pub fn get_percent(&self, curve: Curve) -> f64 {
+        curve.to_percent(self.brightness, self.max_brightness)
+    }
+
+    /// Set brightness of LED to a percentage of perceived brightness.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::{curve::Curve, misc::LedDevice};
+    ///
+    /// let leds = LedDevice::get_all_led_devices().unwrap();
+    /// leds[0].set_percent(50.0, Curve::Exponential(4.0)).unwrap();
+    /// ```
+    pub fn set_percent(&self, percent: f64, curve: Curve) -> Result<(), Error> {
+        if (0.0..=100.0).contains(&percent) {
+            self.set_brightness(curve.to_raw(percent, self.max_brightness))
+        } else {
+            Err(Error::InvalidPercentage { given: percent })
+        }
+    }
+
+    /// Raise brightness of LED by one step, returning the level written.
+    ///
+    /// The step starts from the brightness read fresh from sysfs, so
+    /// repeated calls keep stepping even though [`get_brightness`](Self::get_brightness)
+    /// is not updated.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::{
+    ///     brightness::{StepMode, StepOptions},
+    ///     misc::LedDevice,
+    /// };
+    ///
+    /// let options = StepOptions::new().with_mode(StepMode::Exponential);
+    /// let leds = LedDevice::get_all_led_devices().unwrap();
+    /// leds[0].step_up(&options).unwrap();
+    /// ```
+    pub fn step_up(&self, options: &StepOptions) -> Result<Brightness, Error> {
+        let level = options.step_up(self.read_level()?);
+        self.set_brightness(level.get_raw())?;
+        Ok(level)
+    }
+
+    /// Lower brightness of LED by one step, returning the level written.
+    ///
+    /// Stops at the floor of `options`, which is never 0 unless allowed.
+    pub fn step_down(&self, options: &StepOptions) -> Result<Brightness, Error> {
+        let level = options.step_down(self.read_level()?);
+        self.set_brightness(level.get_raw())?;
+        Ok(level)
+    }
+
+    fn read_level(&self) -> Result<Brightness, Error> {
+        Ok(Brightness::saturating(
+            self.read_brightness()?,
+            self.max_brightness,
+        ))
+    }
+
+    /// Gradually change brightness of LED to `level` over `duration`,
+    /// blocking until the fade ends.
+    ///
+    /// The fade starts from the level read from sysfs and stops any fade of
+    /// LED already running.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use bulbb::{fade::Easing, misc::LedDevice};
+    ///
+    /// let leds = LedDevice::get_all_led_devices().unwrap();
+    /// leds[0]
+    ///     .fade_to(0, Duration::from_millis(500), Easing::EaseOut)
+    ///     .unwrap();
+    /// ```
+    pub fn fade_to(&self, level: u32, duration: Duration, easing: Easing) -> Result<(), Error> {
+        fade_blocking(
+            &self.sysfs.led_device(&self.info.device),
+            || self.read_brightness(),
+            level,
+            self.max_brightness,
+            duration,
+            easing,
+            |l| self.set_brightness(l),
+        )
+    }
+
+    /// Gradually change brightness of LED to `level` over `duration` in a
+    /// background thread.
+    ///
+    /// The fade starts from the level read from sysfs and stops any fade of
+    /// LED already running. The returned [`Fade`] can be used to cancel
+    /// or retarget it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use bulbb::{fade::Easing, misc::LedDevice};
+    ///
+    /// let leds = LedDevice::get_all_led_devices().unwrap();
+    /// let fade = leds[0]
+    ///     .fade_to_in_background(0, Duration::from_secs(2), Easing::EaseInOut)
+    ///     .unwrap();
+    /// fade.retarget(leds[0].get_max_brightness(), Duration::from_millis(300), Easing::EaseOut)
+    ///     .unwrap();
+    /// fade.join().unwrap();
+    /// ```
+    pub fn fade_to_in_background(
+        &self,
+        level: u32,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<Fade, Error> {
+        let reader = self.clone();
+        let led = self.clone();
+        Fade::spawn(
+            &self.sysfs.led_device(&self.info.device),
+            move || reader.read_brightness(),
+            level,
+            self.max_brightness,
+            duration,
+            easing,
+            move |l| led.set_brightness(l),
+        )
+    }
 }
 
 impl LedInfo {
@@ -416,7 +901,7 @@ impl LedInfo {
                 device,
                 device_name: Some(led_info[0].to_string()),
                 color: LedColor::from_id(led_info[1]),
-                function: LedFunction::from_id(led_info[2]),
+                function: function_from_name(led_info[2]),
             }
         } else {
             let mut device_name: Option<String> = None;
@@ -427,8 +912,8 @@ impl LedInfo {
             while idx <= led_info.len() && !led_info.is_empty() {
                 if LedColor::from_id(led_info[idx]).is_some() {
                     color = LedColor::from_id(led_info.remove(idx))
-                } else if LedFunction::from_id(led_info[idx]).is_some() {
-                    function = LedFunction::from_id(led_info.remove(idx))
+                } else if function_from_name(led_info[idx]).is_some() {
+                    function = function_from_name(led_info.remove(idx))
                 } else if !led_info.is_empty() {
                     device_name = Some(led_info.remove(idx).to_string())
                 } else {
@@ -446,7 +931,7 @@ impl LedInfo {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
 /// Color of LED.
 pub enum LedColor {
@@ -483,7 +968,7 @@ impl LedColor {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
 /// Function of the LED.
 pub enum LedFunction {
@@ -623,6 +1108,19 @@ impl fmt::Display for LedColor {
     }
 }
 
+/// Parse the function part of an LED name, ignoring the `_<n>` suffix the
+/// kernel adds to tell apart LEDs that would otherwise share a name.
+fn function_from_name(s: &str) -> Option<LedFunction> {
+    LedFunction::from_id(s).or_else(|| {
+        let (base, n) = s.rsplit_once('_')?;
+        if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) {
+            LedFunction::from_id(base)
+        } else {
+            None
+        }
+    })
+}
+
 impl LedFunction {
     /// Trys to parse str into LedFunction.
     pub fn from_id(s: &str) -> Option<Self> {
@@ -935,7 +1433,26 @@ impl fmt::Display for LedFunction {
 mod tests {
     use super::*;
 
-    use crate::utils::format_led_device;
+    use crate::{
+        brightness::StepMode,
+        testing::{FakeLed, FakeSysfs},
+        utils::format_led_device,
+    };
+
+    fn fake_leds() -> FakeSysfs {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_led(
+            &FakeLed::new("asus::kbd_backlight")
+                .brightness(2)
+                .max_brightness(3),
+        )
+        .unwrap();
+        fake.add_led(&FakeLed::new("input2::capslock").max_brightness(1))
+            .unwrap();
+        fake.add_led(&FakeLed::new("phy0-led").brightness(255))
+            .unwrap();
+        fake
+    }
 
     #[test]
     fn parse_led_device_names() {
@@ -976,21 +1493,87 @@ mod tests {
 
     #[test]
     fn get_all_led_devices() {
-        let leds = LedDevice::get_all_led_devices().unwrap();
-        for led in leds {
+        let fake = fake_leds();
+        let leds = LedDevice::get_all_led_devices_in(&fake.sysfs()).unwrap();
+        assert_eq!(leds.len(), 3);
+        for led in leds.iter().cloned() {
             format_led_device(led)
         }
+
+        assert_eq!(leds[0].get_device_name(), "asus::kbd_backlight");
+        assert_eq!(leds[0].get_brightness(), 2);
+        assert_eq!(leds[0].get_max_brightness(), 3);
+        assert_eq!(leds[1].get_device_name(), "input2::capslock");
+        assert_eq!(leds[1].get_max_brightness(), 1);
+        assert_eq!(leds[2].get_device_name(), "phy0-led");
+        assert_eq!(leds[2].get_brightness(), 255);
     }
 
     #[test]
     fn get_all_keyboard_devices() {
-        let keyboards = LedDevice::get_all_keyboard_devices().unwrap();
+        let fake = fake_leds();
+        let keyboards = LedDevice::get_all_keyboard_devices_in(&fake.sysfs()).unwrap();
+        assert_eq!(keyboards.len(), 1);
         for kbd in keyboards {
+            assert_eq!(kbd.get_device_name(), "asus::kbd_backlight");
             format_led_device(kbd)
         }
     }
 
     #[test]
+    fn set_led() {
+        let fake = fake_leds();
+        let led = LedDevice::get_led_device_in(&fake.sysfs(), String::from("asus::kbd_backlight"))
+            .unwrap();
+
+        led.set_brightness(0).unwrap();
+        assert_eq!(
+            fake.read_led("asus::kbd_backlight", "brightness").unwrap(),
+            "0"
+        );
+        assert!(led.set_brightness(4).is_err());
+        assert_eq!(
+            fake.read_led("asus::kbd_backlight", "brightness").unwrap(),
+            "0"
+        );
+    }
+
+    #[test]
+    fn triggers() {
+        let fake = fake_leds();
+        fake.add_led(
+            &FakeLed::new("input3::numlock")
+                .max_brightness(1)
+                .trigger("none kbd-scrolllock [kbd-numlock] disk-activity heartbeat"),
+        )
+        .unwrap();
+        let sysfs = fake.sysfs();
+
+        let led = LedDevice::get_led_device_in(&sysfs, String::from("input3::numlock")).unwrap();
+        assert_eq!(
+            led.get_trigger(),
+            Some(&LedTrigger::Other(String::from("kbd-numlock")))
+        );
+        let available = led.get_available_triggers().unwrap();
+        assert_eq!(available.len(), 5);
+        assert_eq!(available[0], LedTrigger::None);
+        assert_eq!(available[4], LedTrigger::Heartbeat);
+
+        led.set_trigger(&LedTrigger::Heartbeat).unwrap();
+        assert_eq!(
+            fake.read_led("input3::numlock", "trigger").unwrap(),
+            "heartbeat"
+        );
+        led.clear_trigger().unwrap();
+        assert_eq!(fake.read_led("input3::numlock", "trigger").unwrap(), "none");
+
+        let led = LedDevice::get_led_device_in(&sysfs, String::from("phy0-led")).unwrap();
+        assert_eq!(led.get_trigger(), None);
+        assert!(led.get_available_triggers().is_err());
+    }
+
+    #[test]
+    #[allow(deprecated)]
     fn filter() {
         let filter1 = LedFilterable {
             device_name: Some("dev"),
@@ -1003,5 +1586,121 @@ mod tests {
         assert_eq!(filter1.device_name, filter2.device_name);
         assert_eq!(filter1.color.is_none(), filter2.color.is_none());
         assert_eq!(filter1.function.is_none(), filter2.function.is_none());
+
+        let fake = fake_leds();
+        let sysfs = fake.sysfs();
+        let mut input = LedFilterable::new();
+        let input = input.with_device_name("input").finish();
+        assert!(LedDevice::get_led_devices_with_filter_in(&sysfs, input)
+            .unwrap()
+            .is_empty());
+        let mut asus = LedFilterable::new();
+        let asus = asus.with_device_name("asus").finish();
+        let mut capslock = LedFilterable::new();
+        let capslock = capslock.with_function(LedFunction::CapsLock).finish();
+        let mut names: Vec<String> =
+            LedDevice::get_led_devices_with_multi_filter_in(&sysfs, &[asus, capslock])
+                .unwrap()
+                .iter()
+                .map(|led| led.get_device_name().to_string())
+                .collect();
+        names.sort();
+        assert_eq!(names, ["asus::kbd_backlight", "input2::capslock"]);
+        assert!(
+            LedDevice::get_led_devices_with_filter_in(&sysfs, LedFilterable::new())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn fade_led() {
+        let fake = fake_leds();
+        let led = LedDevice::get_led_device_in(&fake.sysfs(), String::from("phy0-led")).unwrap();
+
+        led.fade_to(0, Duration::from_millis(40), Easing::EaseIn)
+            .unwrap();
+        assert_eq!(fake.read_led("phy0-led", "brightness").unwrap(), "0");
+
+        let fade = led
+            .fade_to_in_background(128, Duration::from_millis(40), Easing::Linear)
+            .unwrap();
+        fade.join().unwrap();
+        assert_eq!(fake.read_led("phy0-led", "brightness").unwrap(), "128");
+        assert!(led
+            .fade_to_in_background(256, Duration::from_millis(40), Easing::Linear)
+            .is_err());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn scan_reports_broken_devices() {
+        let fake = fake_leds();
+        fake.add_led(&FakeLed::new("input3::capslock").without("brightness"))
+            .unwrap();
+
+        let scan = LedDevice::scan_led_devices_in(&fake.sysfs()).unwrap();
+        assert_eq!(scan.devices.len(), 3);
+        assert_eq!(scan.errors.len(), 1);
+        assert_eq!(scan.errors[0].0, "input3::capslock");
+
+        let filter = LedFilterable {
+            device_name: None,
+            color: None,
+            function: Some(LedFunction::CapsLock),
+        };
+        let capslocks = LedDevice::get_led_devices_with_filter_in(&fake.sysfs(), filter).unwrap();
+        assert_eq!(capslocks.len(), 1);
+        assert_eq!(capslocks[0].get_device_name(), "input2::capslock");
+    }
+
+    #[test]
+    fn step_led() {
+        let fake = fake_leds();
+        let led = LedDevice::get_led_device_in(&fake.sysfs(), String::from("asus::kbd_backlight"))
+            .unwrap();
+        let options = StepOptions::new()
+            .with_steps(3)
+            .with_mode(StepMode::Exponential);
+        assert_eq!(led.step_up(&options).unwrap().get_raw(), 3);
+        assert_eq!(led.step_down(&options).unwrap().get_raw(), 2);
+        assert_eq!(led.step_down(&options).unwrap().get_raw(), 1);
+        assert_eq!(led.step_down(&options).unwrap().get_raw(), 1);
+        let options = options.with_allow_off(true);
+        assert_eq!(led.step_down(&options).unwrap().get_raw(), 0);
+        assert_eq!(
+            fake.read_led("asus::kbd_backlight", "brightness").unwrap(),
+            "0"
+        );
+        assert_eq!(led.step_up(&options).unwrap().get_raw(), 1);
+    }
+
+    #[test]
+    fn refresh_led() {
+        let fake = fake_leds();
+        fake.add_led(&FakeLed::new("input3::numlock").trigger("[none] kbd-numlock"))
+            .unwrap();
+        let mut led =
+            LedDevice::get_led_device_in(&fake.sysfs(), String::from("input3::numlock")).unwrap();
+
+        led.set_brightness(200).unwrap();
+        led.set_trigger(&LedTrigger::from_id("kbd-numlock"))
+            .unwrap();
+        assert_eq!(led.get_brightness(), 0);
+        assert_eq!(led.read_brightness().unwrap(), 200);
+        led.refresh().unwrap();
+        assert_eq!(led.get_brightness(), 200);
+        assert_eq!(led.get_trigger(), led.read_trigger().unwrap().as_ref());
+
+        fake.remove_led("input3::numlock").unwrap();
+        assert!(!led.is_present());
+        match led.refresh() {
+            Err(Error::DeviceGone { device }) => assert_eq!(device, "input3::numlock"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(matches!(
+            led.set_trigger(&LedTrigger::None),
+            Err(Error::DeviceGone { .. })
+        ));
     }
 }
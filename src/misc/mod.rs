@@ -9,10 +9,23 @@ except according to those terms.
 */
 #[cfg(target_os = "linux")]
 mod linux;
+#[cfg(target_os = "linux")]
+mod multicolor;
+#[cfg(target_os = "linux")]
+mod query;
+mod trigger;
 
 #[cfg(target_os = "linux")]
 /// Directory containing all backlight devices.
 pub const LEDS_DIR: &str = "/sys/class/leds";
 
+#[cfg(target_os = "linux")]
+#[allow(deprecated)]
+pub use self::linux::LedFilterable;
 #[cfg(target_os = "linux")]
 pub use self::linux::{LedColor, LedDevice, LedFunction, LedInfo};
+#[cfg(target_os = "linux")]
+pub use self::multicolor::{MulticolorLed, Rgb};
+#[cfg(target_os = "linux")]
+pub use self::query::LedQuery;
+pub use self::trigger::LedTrigger;
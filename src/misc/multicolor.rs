@@ -0,0 +1,639 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::fmt;
+
+use super::{LedColor, LedDevice};
+use crate::{
+    backend::BrightnessBackend,
+    error::Error,
+    sysfs::{scan_dir, Scan, Sysfs},
+    utils::{read_sys_led, SysBacklightInterface},
+};
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// 24-bit RGB color.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::misc::Rgb;
+///
+/// assert_eq!(Rgb::from_hex("#ff8000"), Some(Rgb::new(255, 128, 0)));
+/// assert_eq!(Rgb::from_hsv(120.0, 1.0, 1.0), Rgb::new(0, 255, 0));
+/// assert_eq!(Rgb::new(255, 128, 0).to_string(), "#ff8000");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct Rgb {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Rgb {
+    /// Create a color from its components.
+    pub fn new(red: u8, green: u8, blue: u8) -> Rgb {
+        Rgb { red, green, blue }
+    }
+
+    /// Trys to parse `#rrggbb`, `#rgb` (the `#` is optional) into Rgb.
+    pub fn from_hex(s: &str) -> Option<Self> {
+        let s = s.strip_prefix('#').unwrap_or(s);
+        if !s.is_ascii() {
+            return None;
+        }
+        let component = |i: usize, len: usize| u8::from_str_radix(&s[i * len..(i + 1) * len], 16);
+        match s.len() {
+            6 => Some(Rgb::new(
+                component(0, 2).ok()?,
+                component(1, 2).ok()?,
+                component(2, 2).ok()?,
+            )),
+            3 => Some(Rgb::new(
+                component(0, 1).ok()? * 17,
+                component(1, 1).ok()? * 17,
+                component(2, 1).ok()? * 17,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Create a color from `hue` in degrees and `saturation` and `value` in `0.0..=1.0`.
+    pub fn from_hsv(hue: f64, saturation: f64, value: f64) -> Rgb {
+        let hue = if hue.is_finite() {
+            hue.rem_euclid(360.0)
+        } else {
+            0.0
+        };
+        let saturation = clamp_unit(saturation);
+        let value = clamp_unit(value);
+
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+        let (r, g, b) = match (hue / 60.0) as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let m = value - chroma;
+        let to_u8 = |c: f64| ((c + m) * 255.0).round() as u8;
+        Rgb::new(to_u8(r), to_u8(g), to_u8(b))
+    }
+
+    /// Hue in degrees and saturation and value in `0.0..=1.0`.
+    pub fn to_hsv(self) -> (f64, f64, f64) {
+        let r = f64::from(self.red) / 255.0;
+        let g = f64::from(self.green) / 255.0;
+        let b = f64::from(self.blue) / 255.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+        (hue, saturation, max)
+    }
+
+    /// Amount of this color each LED channel of `colors` should emit.
+    ///
+    /// White, yellow/amber and violet channels take the part shared by the
+    /// primaries they mix, which the primaries then leave out so it is not
+    /// emitted twice. Channels with no RGB equivalent (e.g. infrared) are off.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::misc::{LedColor, Rgb};
+    ///
+    /// let rgbw = [LedColor::Red, LedColor::Green, LedColor::Blue, LedColor::White];
+    /// assert_eq!(Rgb::new(200, 200, 100).channels(&rgbw), [100, 100, 0, 100]);
+    /// ```
+    pub fn channels(self, colors: &[LedColor]) -> Vec<u8> {
+        let has = |color: LedColor| colors.contains(&color);
+        let (mut red, mut green, mut blue) = (self.red, self.green, self.blue);
+        let white = if has(LedColor::White) {
+            red.min(green).min(blue)
+        } else {
+            0
+        };
+        red -= white;
+        green -= white;
+        blue -= white;
+        let yellow = if has(LedColor::Yellow) || has(LedColor::Amber) {
+            red.min(green)
+        } else {
+            0
+        };
+        red -= yellow;
+        green -= yellow;
+        let violet = if has(LedColor::Violet) {
+            red.min(blue)
+        } else {
+            0
+        };
+        red -= violet;
+        blue -= violet;
+        colors
+            .iter()
+            .map(|color| match color {
+                LedColor::Red => red,
+                LedColor::Green => green,
+                LedColor::Blue => blue,
+                LedColor::White => white,
+                LedColor::Yellow | LedColor::Amber => yellow,
+                LedColor::Violet => violet,
+                LedColor::Ir | LedColor::Multi | LedColor::Rgb | LedColor::Max => 0,
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
+}
+
+fn clamp_unit(value: f64) -> f64 {
+    if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, 1.0)
+    }
+}
+
+/// Multicolor LED.
+///
+/// A group of LEDs sharing one `brightness`, each channel scaled by its
+/// entry in `multi_intensity`:
+///
+/// > `led_brightness = brightness * multi_intensity/max_brightness`
+///
+/// See [Multicolor LED handling under Linux](https://www.kernel.org/doc/html/latest/leds/leds-class-multicolor.html).
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct MulticolorLed {
+    /// The LED itself.
+    pub led: LedDevice,
+    /// Color of each channel, read from `multi_index`.
+    pub channels: Vec<LedColor>,
+    /// Intensity of each channel, between 0 and
+    /// [max_brightness](struct.LedDevice.html#structfield.max_brightness).
+    pub intensities: Vec<u32>,
+}
+
+impl MulticolorLed {
+    /// Get multicolor LED by device name.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::misc::MulticolorLed;
+    ///
+    /// let led = MulticolorLed::get_multicolor_led(format!("rgb:kbd_backlight")).unwrap();
+    /// println!("Channels: {:?}", led.get_channels());
+    /// ```
+    pub fn get_multicolor_led(device: String) -> Result<MulticolorLed, Error> {
+        MulticolorLed::get_multicolor_led_in(&Sysfs::default(), device)
+    }
+
+    /// Get multicolor LED by device name from the given sysfs tree.
+    pub fn get_multicolor_led_in(sysfs: &Sysfs, device: String) -> Result<MulticolorLed, Error> {
+        MulticolorLed::from_led(LedDevice::get_led_device_in(sysfs, device)?)
+    }
+
+    /// Read the channels of `led`.
+    ///
+    /// Fails with [`Error::NotMulticolor`] if `led` has no `multi_index`.
+    pub fn from_led(led: LedDevice) -> Result<MulticolorLed, Error> {
+        let device = led.get_device_name();
+        if !led
+            .get_sysfs()
+            .led_device(device)
+            .join("multi_index")
+            .exists()
+        {
+            return Err(Error::NotMulticolor {
+                device: device.to_string(),
+            });
+        }
+        let channels = read_sys_led(led.get_sysfs(), device, SysBacklightInterface::MultiIndex)?
+            .split_whitespace()
+            .map(|color| {
+                LedColor::from_id(color).ok_or_else(|| Error::UnknownLedColor {
+                    device: device.to_string(),
+                    given: color.to_string(),
+                })
+            })
+            .collect::<Result<Vec<LedColor>, Error>>()?;
+        let intensities = read_sys_led(
+            led.get_sysfs(),
+            device,
+            SysBacklightInterface::MultiIntensity,
+        )?
+        .split_whitespace()
+        .map(|intensity| intensity.parse::<u32>())
+        .collect::<Result<Vec<u32>, _>>()?;
+        if intensities.len() != channels.len() {
+            return Err(Error::InvalidIntensities {
+                given: intensities.len(),
+                channels: channels.len(),
+            });
+        }
+
+        Ok(MulticolorLed {
+            led,
+            channels,
+            intensities,
+        })
+    }
+
+    /// Get all multicolor LEDs, LEDs without `multi_index` are left out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::misc::MulticolorLed;
+    ///
+    /// for led in MulticolorLed::get_all_multicolor_leds().unwrap() {
+    ///     println!("{}: {:?}", led.get_led().get_device_name(), led.get_channels());
+    /// }
+    /// ```
+    pub fn get_all_multicolor_leds() -> Result<Vec<MulticolorLed>, Error> {
+        MulticolorLed::get_all_multicolor_leds_in(&Sysfs::default())
+    }
+
+    /// Get all multicolor LEDs from the given sysfs tree.
+    pub fn get_all_multicolor_leds_in(sysfs: &Sysfs) -> Result<Vec<MulticolorLed>, Error> {
+        Ok(MulticolorLed::scan_multicolor_leds_in(sysfs)?.devices)
+    }
+
+    /// Get all multicolor LEDs, along with the ones that could not be read.
+    pub fn scan_multicolor_leds() -> Result<Scan<MulticolorLed>, Error> {
+        MulticolorLed::scan_multicolor_leds_in(&Sysfs::default())
+    }
+
+    /// Get all multicolor LEDs from the given sysfs tree, along with the
+    /// ones that could not be read.
+    pub fn scan_multicolor_leds_in(sysfs: &Sysfs) -> Result<Scan<MulticolorLed>, Error> {
+        scan_dir(&sysfs.leds_dir(), |device| {
+            if sysfs.led_device(&device).join("multi_index").exists() {
+                Some(MulticolorLed::get_multicolor_led_in(sysfs, device))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Get the LED.
+    pub fn get_led(&self) -> &LedDevice {
+        &self.led
+    }
+
+    /// Re-read the LED and its channel intensities from sysfs.
+    ///
+    /// Fails with [`Error::DeviceGone`] if the LED has been removed.
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        self.led.refresh()?;
+        let fresh = MulticolorLed::from_led(self.led.clone())?;
+        self.channels = fresh.channels;
+        self.intensities = fresh.intensities;
+        Ok(())
+    }
+
+    /// Get the color of each channel.
+    pub fn get_channels(&self) -> &[LedColor] {
+        &self.channels
+    }
+
+    /// Get the intensity of each channel.
+    pub fn get_intensities(&self) -> &[u32] {
+        &self.intensities
+    }
+
+    /// Set the intensity of each channel, in the order of
+    /// [`get_channels`](MulticolorLed::get_channels).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::misc::MulticolorLed;
+    ///
+    /// let leds = MulticolorLed::get_all_multicolor_leds().unwrap();
+    /// leds[0].set_intensities(&[255, 0, 0]).unwrap();
+    /// ```
+    pub fn set_intensities(&self, intensities: &[u32]) -> Result<(), Error> {
+        self.set_intensities_with(self.led.get_backend().as_ref(), intensities)
+    }
+
+    /// Set the intensity of each channel using `backend` for this call only.
+    pub fn set_intensities_with(
+        &self,
+        backend: &dyn BrightnessBackend,
+        intensities: &[u32],
+    ) -> Result<(), Error> {
+        if intensities.len() != self.channels.len() {
+            return Err(Error::InvalidIntensities {
+                given: intensities.len(),
+                channels: self.channels.len(),
+            });
+        }
+        let max = self.led.get_max_brightness();
+        if let Some(&given) = intensities.iter().find(|&&i| i > max) {
+            return Err(Error::InvalidBrightnessLevel { given, max });
+        }
+        backend.set_intensities(
+            self.led.get_sysfs(),
+            self.led.get_device_name(),
+            intensities,
+        )
+    }
+
+    /// Channel intensities and overall brightness showing `color`.
+    ///
+    /// The brightest channel sets the brightness and the intensities carry
+    /// the hue, so dimming the LED keeps its color. See
+    /// [`Rgb::channels`] for how the color is split across the channels.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::misc::{MulticolorLed, Rgb};
+    ///
+    /// let leds = MulticolorLed::get_all_multicolor_leds().unwrap();
+    /// let (intensities, brightness) = leds[0].color_to_levels(Rgb::new(128, 64, 0));
+    /// ```
+    pub fn color_to_levels(&self, color: Rgb) -> (Vec<u32>, u32) {
+        let max = u64::from(self.led.get_max_brightness());
+        let channels = color.channels(&self.channels);
+        let value = u64::from(channels.iter().copied().max().unwrap_or(0));
+        let scale = |num: u64, den: u64| {
+            (num * max + den / 2)
+                .checked_div(den)
+                .map_or(0, |level| level as u32)
+        };
+        let intensities = channels
+            .iter()
+            .map(|&channel| scale(u64::from(channel), value))
+            .collect();
+        (intensities, scale(value, 255))
+    }
+
+    /// Show `color`, setting the channel intensities and then brightness.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::misc::{MulticolorLed, Rgb};
+    ///
+    /// let leds = MulticolorLed::get_all_multicolor_leds().unwrap();
+    /// leds[0].set_color(Rgb::from_hex("#ff8000").unwrap()).unwrap();
+    /// leds[0].set_color(Rgb::from_hsv(200.0, 0.5, 0.8)).unwrap();
+    /// ```
+    pub fn set_color(&self, color: Rgb) -> Result<(), Error> {
+        let (intensities, brightness) = self.color_to_levels(color);
+        self.set_intensities(&intensities)?;
+        self.led.set_brightness(brightness)
+    }
+
+    /// Color shown when the LED was last read, combining channel
+    /// intensities and brightness.
+    ///
+    /// White, yellow/amber and violet channels add to the primaries they
+    /// mix, the reverse of [`Rgb::channels`].
+    pub fn get_color(&self) -> Rgb {
+        let max = u64::from(self.led.get_max_brightness());
+        if max == 0 {
+            return Rgb::default();
+        }
+        let brightness = u64::from(self.led.get_brightness());
+        let (mut red, mut green, mut blue) = (0u64, 0u64, 0u64);
+        for (&channel, &intensity) in self.channels.iter().zip(self.intensities.iter()) {
+            let level = u64::from(intensity) * brightness * 255;
+            match channel {
+                LedColor::Red => red += level,
+                LedColor::Green => green += level,
+                LedColor::Blue => blue += level,
+                LedColor::White => {
+                    red += level;
+                    green += level;
+                    blue += level;
+                }
+                LedColor::Yellow | LedColor::Amber => {
+                    red += level;
+                    green += level;
+                }
+                LedColor::Violet => {
+                    red += level;
+                    blue += level;
+                }
+                LedColor::Ir | LedColor::Multi | LedColor::Rgb | LedColor::Max => (),
+            }
+        }
+        let component = |level: u64| ((level + max * max / 2) / (max * max)).min(255) as u8;
+        Rgb::new(component(red), component(green), component(blue))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        backend::MockBackend,
+        testing::{FakeLed, FakeSysfs},
+    };
+    use std::sync::Arc;
+
+    fn fake_rgb() -> FakeSysfs {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_led(
+            &FakeLed::new("rgb:kbd_backlight")
+                .brightness(255)
+                .max_brightness(255)
+                .multi_index("red green blue")
+                .multi_intensity("255 128 0"),
+        )
+        .unwrap();
+        fake.add_led(&FakeLed::new("input2::capslock").max_brightness(1))
+            .unwrap();
+        fake
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(Rgb::from_hex("00ff7f"), Some(Rgb::new(0, 255, 127)));
+        assert_eq!(Rgb::from_hex("#f80"), Some(Rgb::new(255, 136, 0)));
+        assert_eq!(Rgb::from_hex("#ff80"), None);
+        assert_eq!(Rgb::from_hex("#gg0000"), None);
+        assert_eq!(Rgb::from_hex("#ffé00"), None);
+        assert_eq!(Rgb::new(0, 255, 127).to_string(), "#00ff7f");
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for color in [
+            Rgb::new(255, 0, 0),
+            Rgb::new(255, 128, 0),
+            Rgb::new(12, 200, 90),
+            Rgb::new(40, 40, 200),
+            Rgb::new(128, 128, 128),
+            Rgb::new(0, 0, 0),
+        ]
+        .iter()
+        {
+            let (h, s, v) = color.to_hsv();
+            assert_eq!(Rgb::from_hsv(h, s, v), *color);
+        }
+        assert_eq!(Rgb::from_hsv(-120.0, 1.0, 1.0), Rgb::new(0, 0, 255));
+        assert_eq!(Rgb::from_hsv(60.0, 2.0, 0.5), Rgb::new(128, 128, 0));
+    }
+
+    #[test]
+    fn read_channels() {
+        let fake = fake_rgb();
+        let leds = MulticolorLed::get_all_multicolor_leds_in(&fake.sysfs()).unwrap();
+        assert_eq!(leds.len(), 1);
+        assert_eq!(
+            leds[0].get_channels(),
+            &[LedColor::Red, LedColor::Green, LedColor::Blue]
+        );
+        assert_eq!(leds[0].get_intensities(), &[255, 128, 0]);
+        assert_eq!(leds[0].get_color(), Rgb::new(255, 128, 0));
+
+        match MulticolorLed::get_multicolor_led_in(&fake.sysfs(), String::from("input2::capslock"))
+        {
+            Err(Error::NotMulticolor { device }) => assert_eq!(device, "input2::capslock"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn set_color() {
+        let fake = fake_rgb();
+        let mut led =
+            MulticolorLed::get_multicolor_led_in(&fake.sysfs(), String::from("rgb:kbd_backlight"))
+                .unwrap();
+
+        led.set_color(Rgb::new(0, 64, 128)).unwrap();
+        assert_eq!(
+            fake.read_led("rgb:kbd_backlight", "multi_intensity")
+                .unwrap(),
+            "0 128 255"
+        );
+        assert_eq!(
+            fake.read_led("rgb:kbd_backlight", "brightness").unwrap(),
+            "128"
+        );
+
+        assert_eq!(led.get_color(), Rgb::new(255, 128, 0));
+        led.refresh().unwrap();
+        assert_eq!(led.get_color(), Rgb::new(0, 64, 128));
+
+        assert!(led.set_intensities(&[1, 2]).is_err());
+        assert!(led.set_intensities(&[1, 2, 256]).is_err());
+    }
+
+    #[test]
+    fn levels_for_other_channels() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_led(
+            &FakeLed::new("rgbw:status")
+                .max_brightness(100)
+                .multi_index("red green blue white")
+                .multi_intensity("0 0 0 0"),
+        )
+        .unwrap();
+        let mock = Arc::new(MockBackend::new());
+        let led = LedDevice::get_led_device_in(&fake.sysfs(), String::from("rgbw:status"))
+            .unwrap()
+            .with_backend(mock.clone());
+        let led = MulticolorLed::from_led(led).unwrap();
+
+        // white takes the part shared by red, green and blue
+        assert_eq!(
+            led.color_to_levels(Rgb::new(200, 200, 100)),
+            (vec![100, 100, 0, 100], 39)
+        );
+        assert_eq!(led.color_to_levels(Rgb::default()), (vec![0, 0, 0, 0], 0));
+
+        led.set_color(Rgb::new(255, 255, 255)).unwrap();
+        assert_eq!(
+            mock.get_intensities("rgbw:status"),
+            Some(vec![0, 0, 0, 100])
+        );
+        assert_eq!(
+            mock.get_brightness(crate::sysfs::DeviceClass::Leds, "rgbw:status"),
+            Some(100)
+        );
+    }
+
+    #[test]
+    fn rgbw_round_trip() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_led(
+            &FakeLed::new("rgbw:status")
+                .max_brightness(255)
+                .multi_index("red green blue white")
+                .multi_intensity("0 0 0 0"),
+        )
+        .unwrap();
+        let mut led =
+            MulticolorLed::get_multicolor_led_in(&fake.sysfs(), String::from("rgbw:status"))
+                .unwrap();
+
+        for color in [
+            Rgb::new(200, 200, 100),
+            Rgb::new(255, 255, 255),
+            Rgb::new(40, 0, 255),
+        ]
+        .iter()
+        {
+            led.set_color(*color).unwrap();
+            led.refresh().unwrap();
+            assert_eq!(led.get_color(), *color);
+        }
+
+        let amber = [LedColor::Red, LedColor::Green, LedColor::Amber];
+        assert_eq!(Rgb::new(255, 128, 0).channels(&amber), [127, 0, 128]);
+        let violet = [LedColor::Violet, LedColor::Ir];
+        assert_eq!(Rgb::new(30, 0, 60).channels(&violet), [30, 0]);
+    }
+
+    #[test]
+    fn unknown_channel() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_led(
+            &FakeLed::new("multi:status")
+                .multi_index("red lime")
+                .multi_intensity("0 0"),
+        )
+        .unwrap();
+        let scan = MulticolorLed::scan_multicolor_leds_in(&fake.sysfs()).unwrap();
+        assert!(scan.devices.is_empty());
+        match &scan.errors[0].1 {
+            Error::UnknownLedColor { given, .. } => assert_eq!(given, "lime"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
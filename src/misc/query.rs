@@ -0,0 +1,374 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::ops;
+
+use super::{LedColor, LedDevice, LedFunction, LedTrigger};
+
+#[cfg(feature = "regex")]
+use regex::Regex;
+
+/// Query selecting LED devices.
+///
+/// Predicates match the parsed [`LedInfo`](super::LedInfo) fields exactly and
+/// can be combined with [`and`](LedQuery::and) and [`or`](LedQuery::or) (or
+/// `&` and `|`) and negated with `!`.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::misc::{LedColor, LedDevice, LedFunction, LedQuery};
+///
+/// // red status LEDs that are not driven by a trigger
+/// let query = LedQuery::color(LedColor::Red)
+///     .and(LedQuery::function(LedFunction::Status))
+///     .and(LedQuery::trigger("none").or(!LedQuery::has_trigger()));
+/// let leds = LedDevice::query(&query).unwrap();
+/// for led in leds {
+///     println!("LED Device: {:?}", led);
+/// }
+/// ```
+///
+/// Non-exhaustive, as the `regex` feature adds a variant.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub enum LedQuery {
+    /// Matches every LED.
+    #[default]
+    Any,
+    /// `devicename` part of the LED name equals this.
+    DeviceName(String),
+    /// `color` part of the LED name equals this.
+    Color(LedColor),
+    /// `function` part of the LED name equals this.
+    Function(LedFunction),
+    /// Full LED name matches this glob (`*`, `?` and `[...]`).
+    NameGlob(String),
+    /// Full LED name matches this regular expression.
+    #[cfg(feature = "regex")]
+    NameRegex(Regex),
+    /// `max_brightness` is within `min..=max`.
+    MaxBrightness { min: u32, max: u32 },
+    /// The active trigger is this one.
+    Trigger(LedTrigger),
+    /// The LED has an active trigger other than `none`.
+    HasTrigger,
+    /// Both queries match.
+    And(Box<LedQuery>, Box<LedQuery>),
+    /// Either query matches.
+    Or(Box<LedQuery>, Box<LedQuery>),
+    /// The query does not match.
+    Not(Box<LedQuery>),
+}
+
+impl LedQuery {
+    /// Match LEDs whose `devicename` is `device_name`.
+    pub fn device_name(device_name: &str) -> LedQuery {
+        LedQuery::DeviceName(device_name.to_string())
+    }
+
+    /// Match LEDs whose `color` is `color`.
+    pub fn color(color: LedColor) -> LedQuery {
+        LedQuery::Color(color)
+    }
+
+    /// Match LEDs whose `function` is `function`.
+    pub fn function(function: LedFunction) -> LedQuery {
+        LedQuery::Function(function)
+    }
+
+    /// Match LEDs whose full name matches `pattern`, e.g. `"input*::capslock"`.
+    pub fn name_glob(pattern: &str) -> LedQuery {
+        LedQuery::NameGlob(pattern.to_string())
+    }
+
+    /// Match LEDs whose full name matches the regular expression `pattern`.
+    #[cfg(feature = "regex")]
+    pub fn name_regex(pattern: &str) -> Result<LedQuery, regex::Error> {
+        Ok(LedQuery::NameRegex(Regex::new(pattern)?))
+    }
+
+    /// Match LEDs whose `max_brightness` is within `min..=max`.
+    pub fn max_brightness(min: u32, max: u32) -> LedQuery {
+        LedQuery::MaxBrightness { min, max }
+    }
+
+    /// Match LEDs whose active trigger is `trigger`.
+    pub fn trigger<T: Into<LedTrigger>>(trigger: T) -> LedQuery {
+        LedQuery::Trigger(trigger.into())
+    }
+
+    /// Match LEDs with an active trigger other than `none`.
+    pub fn has_trigger() -> LedQuery {
+        LedQuery::HasTrigger
+    }
+
+    /// Match LEDs matching both `self` and `other`.
+    pub fn and(self, other: LedQuery) -> LedQuery {
+        LedQuery::And(Box::new(self), Box::new(other))
+    }
+
+    /// Match LEDs matching either `self` or `other`.
+    pub fn or(self, other: LedQuery) -> LedQuery {
+        LedQuery::Or(Box::new(self), Box::new(other))
+    }
+
+    /// Whether `led` matches the query.
+    pub fn matches(&self, led: &LedDevice) -> bool {
+        match self {
+            LedQuery::Any => true,
+            LedQuery::DeviceName(name) => led.info.device_name.as_deref() == Some(name.as_str()),
+            LedQuery::Color(color) => led.info.color == Some(*color),
+            LedQuery::Function(function) => led.info.function == Some(*function),
+            LedQuery::NameGlob(pattern) => glob_match(pattern, &led.info.device),
+            #[cfg(feature = "regex")]
+            LedQuery::NameRegex(regex) => regex.is_match(&led.info.device),
+            LedQuery::MaxBrightness { min, max } => (*min..=*max).contains(&led.max_brightness),
+            LedQuery::Trigger(trigger) => led.trigger.as_ref() == Some(trigger),
+            LedQuery::HasTrigger => match &led.trigger {
+                Some(trigger) => *trigger != LedTrigger::None,
+                None => false,
+            },
+            LedQuery::And(a, b) => a.matches(led) && b.matches(led),
+            LedQuery::Or(a, b) => a.matches(led) || b.matches(led),
+            LedQuery::Not(q) => !q.matches(led),
+        }
+    }
+}
+
+impl ops::BitAnd for LedQuery {
+    type Output = LedQuery;
+
+    fn bitand(self, other: LedQuery) -> LedQuery {
+        self.and(other)
+    }
+}
+
+impl ops::BitOr for LedQuery {
+    type Output = LedQuery;
+
+    fn bitor(self, other: LedQuery) -> LedQuery {
+        self.or(other)
+    }
+}
+
+impl ops::Not for LedQuery {
+    type Output = LedQuery;
+
+    /// Match LEDs not matching `self`.
+    fn not(self) -> LedQuery {
+        LedQuery::Not(Box::new(self))
+    }
+}
+
+/// Shell-style glob matching supporting `*`, `?`, `[abc]`, `[a-z]` and `[!abc]`.
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    glob_match_from(&pattern, &name)
+}
+
+fn glob_match_from(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // pattern after the last `*` and where in name it was resumed, so a
+    // mismatch only retries that `*` with one more character
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        // pattern characters consumed by matching name[n]
+        let matched = match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                star = Some((p, n));
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match class_end(&pattern[p..]) {
+                Some(end) if class_matches(&pattern[p + 1..p + end], name[n]) => Some(end + 1),
+                Some(_) => None,
+                None if name[n] == '[' => Some(1),
+                None => None,
+            },
+            Some(c) if *c == name[n] => Some(1),
+            _ => None,
+        };
+        match (matched, star) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            (None, Some((star_p, star_n))) => {
+                p = star_p;
+                n = star_n + 1;
+                star = Some((star_p, n));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Index of the `]` closing the class opened at `pattern[0]`.
+fn class_end(pattern: &[char]) -> Option<usize> {
+    let mut i = 1;
+    if pattern.get(i) == Some(&'!') {
+        i += 1;
+    }
+    // a leading `]` is part of the class
+    if pattern.get(i) == Some(&']') {
+        i += 1;
+    }
+    pattern[i..].iter().position(|c| *c == ']').map(|p| p + i)
+}
+
+fn class_matches(class: &[char], c: char) -> bool {
+    let (negate, class) = match class.first() {
+        Some('!') => (true, &class[1..]),
+        _ => (false, class),
+    };
+    let mut i = 0;
+    let mut found = false;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            found |= class[i] <= c && c <= class[i + 2];
+            i += 3;
+        } else {
+            found |= class[i] == c;
+            i += 1;
+        }
+    }
+    found != negate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{FakeLed, FakeSysfs};
+
+    fn fake_leds() -> FakeSysfs {
+        let fake = FakeSysfs::new().unwrap();
+        for led in [
+            FakeLed::new("red:status").trigger("[none] heartbeat"),
+            FakeLed::new("green:status").trigger("none [heartbeat]"),
+            FakeLed::new("red:power").max_brightness(1),
+            FakeLed::new("input2::capslock")
+                .max_brightness(1)
+                .trigger("none [input2-capslock]"),
+            FakeLed::new("input13::capslock").max_brightness(1),
+            FakeLed::new("asus::kbd_backlight").max_brightness(3),
+            FakeLed::new("asus::kbd_backlight_1").max_brightness(3),
+            // substring filters used to consider this one red
+            FakeLed::new("shredder::disk"),
+        ]
+        .iter()
+        {
+            fake.add_led(led).unwrap();
+        }
+        fake
+    }
+
+    fn names(fake: &FakeSysfs, query: &LedQuery) -> Vec<String> {
+        LedDevice::query_in(&fake.sysfs(), query)
+            .unwrap()
+            .into_iter()
+            .map(|led| led.info.device)
+            .collect()
+    }
+
+    #[test]
+    fn exact_fields() {
+        let fake = fake_leds();
+        assert_eq!(
+            names(&fake, &LedQuery::color(LedColor::Red)),
+            vec!["red:power", "red:status"]
+        );
+        assert_eq!(
+            names(&fake, &LedQuery::device_name("asus")),
+            vec!["asus::kbd_backlight", "asus::kbd_backlight_1"]
+        );
+        assert_eq!(
+            names(&fake, &LedQuery::function(LedFunction::KbdBacklight)),
+            vec!["asus::kbd_backlight", "asus::kbd_backlight_1"]
+        );
+    }
+
+    #[test]
+    fn combinators() {
+        let fake = fake_leds();
+        let red_status = LedQuery::color(LedColor::Red) & LedQuery::function(LedFunction::Status);
+        assert_eq!(names(&fake, &red_status), vec!["red:status"]);
+
+        let status_or_power =
+            LedQuery::function(LedFunction::Status).or(LedQuery::function(LedFunction::Power));
+        assert_eq!(
+            names(&fake, &status_or_power.and(!LedQuery::color(LedColor::Red))),
+            vec!["green:status"]
+        );
+        assert_eq!(names(&fake, &!LedQuery::Any), Vec::<String>::new());
+    }
+
+    #[test]
+    fn name_glob_and_brightness() {
+        let fake = fake_leds();
+        assert_eq!(
+            names(&fake, &LedQuery::name_glob("input*::capslock")),
+            vec!["input13::capslock", "input2::capslock"]
+        );
+        assert_eq!(
+            names(&fake, &LedQuery::name_glob("input[0-9]::*")),
+            vec!["input2::capslock"]
+        );
+        assert_eq!(
+            names(&fake, &LedQuery::max_brightness(2, 3)),
+            vec!["asus::kbd_backlight", "asus::kbd_backlight_1"]
+        );
+    }
+
+    #[test]
+    fn triggers() {
+        let fake = fake_leds();
+        assert_eq!(
+            names(&fake, &LedQuery::trigger("heartbeat")),
+            vec!["green:status"]
+        );
+        assert_eq!(
+            names(&fake, &LedQuery::has_trigger()),
+            vec!["green:status", "input2::capslock"]
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn name_regex() {
+        let fake = fake_leds();
+        let query = LedQuery::name_regex(r"^input\d+::").unwrap();
+        assert_eq!(
+            names(&fake, &query),
+            vec!["input13::capslock", "input2::capslock"]
+        );
+    }
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a?c", "abc"));
+        assert!(!glob_match("a?c", "ac"));
+        assert!(glob_match("[!x]*", "abc"));
+        assert!(!glob_match("[!a]*", "abc"));
+        assert!(glob_match("[]]", "]"));
+        assert!(glob_match("a[", "a["));
+        assert!(glob_match("*::kbd_backlight*", "tpacpi::kbd_backlight"));
+        assert!(glob_match("*a*b", "xaxxbab"));
+        assert!(!glob_match("*a*b", "xaxxba"));
+        assert!(glob_match("[a-c]*[!x]", "by"));
+        // many stars do not backtrack exponentially
+        let name = "a".repeat(64);
+        assert!(!glob_match(&format!("{}b", "a*".repeat(32)), &name));
+    }
+}
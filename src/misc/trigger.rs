@@ -0,0 +1,239 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::fmt;
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Kernel event that drives an LED.
+///
+/// Read from and written to
+/// [`/sys/class/leds/<led>/trigger`](https://www.kernel.org/doc/html/latest/admin-guide/abi-stable.html#abi-sys-class-leds-led-trigger).
+/// Triggers specific to a driver (e.g. `phy0rx` or `kbd-capslock`) are kept
+/// as [`Other`](LedTrigger::Other).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum LedTrigger {
+    /// No trigger, brightness is controlled by userspace.
+    None,
+    /// Blink with configurable on and off times.
+    Timer,
+    /// Blink once per `shot`.
+    OneShot,
+    /// Blink like a heart beat, faster with higher load.
+    Heartbeat,
+    /// Follow the framebuffer blanking state.
+    Backlight,
+    /// Follow a GPIO.
+    Gpio,
+    /// Blink on activity of any CPU.
+    Cpu,
+    /// Stay on.
+    DefaultOn,
+    /// Stay on for a while, then off.
+    Transient,
+    /// Camera flash.
+    Flash,
+    /// Camera torch.
+    Torch,
+    /// Blink on kernel panic.
+    Panic,
+    /// Blink on disk activity.
+    DiskActivity,
+    /// Blink on disk reads.
+    DiskRead,
+    /// Blink on disk writes.
+    DiskWrite,
+    /// Blink on IDE disk activity.
+    IdeDisk,
+    /// Blink on MTD activity.
+    Mtd,
+    /// Blink on NAND activity.
+    NandDisk,
+    /// Follow the state of a network device.
+    Netdev,
+    /// Play a brightness pattern.
+    Pattern,
+    /// Follow the audio mute state.
+    AudioMute,
+    /// Follow the microphone mute state.
+    AudioMicMute,
+    /// On while any rfkill switch is unblocked.
+    RfkillAny,
+    /// On while no rfkill switch is unblocked.
+    RfkillNone,
+    /// Any other trigger.
+    Other(String),
+}
+
+impl LedTrigger {
+    /// Parse a trigger name, keeping unknown triggers as [`Other`](LedTrigger::Other).
+    pub fn from_id(s: &str) -> LedTrigger {
+        match s {
+            "none" => LedTrigger::None,
+            "timer" => LedTrigger::Timer,
+            "oneshot" => LedTrigger::OneShot,
+            "heartbeat" => LedTrigger::Heartbeat,
+            "backlight" => LedTrigger::Backlight,
+            "gpio" => LedTrigger::Gpio,
+            "cpu" => LedTrigger::Cpu,
+            "default-on" => LedTrigger::DefaultOn,
+            "transient" => LedTrigger::Transient,
+            "flash" => LedTrigger::Flash,
+            "torch" => LedTrigger::Torch,
+            "panic" => LedTrigger::Panic,
+            "disk-activity" => LedTrigger::DiskActivity,
+            "disk-read" => LedTrigger::DiskRead,
+            "disk-write" => LedTrigger::DiskWrite,
+            "ide-disk" => LedTrigger::IdeDisk,
+            "mtd" => LedTrigger::Mtd,
+            "nand-disk" => LedTrigger::NandDisk,
+            "netdev" => LedTrigger::Netdev,
+            "pattern" => LedTrigger::Pattern,
+            "audio-mute" => LedTrigger::AudioMute,
+            "audio-micmute" => LedTrigger::AudioMicMute,
+            "rfkill-any" => LedTrigger::RfkillAny,
+            "rfkill-none" => LedTrigger::RfkillNone,
+            other => LedTrigger::Other(other.to_string()),
+        }
+    }
+
+    /// Name to write to the `trigger` file.
+    pub fn id(&self) -> &str {
+        match self {
+            LedTrigger::None => "none",
+            LedTrigger::Timer => "timer",
+            LedTrigger::OneShot => "oneshot",
+            LedTrigger::Heartbeat => "heartbeat",
+            LedTrigger::Backlight => "backlight",
+            LedTrigger::Gpio => "gpio",
+            LedTrigger::Cpu => "cpu",
+            LedTrigger::DefaultOn => "default-on",
+            LedTrigger::Transient => "transient",
+            LedTrigger::Flash => "flash",
+            LedTrigger::Torch => "torch",
+            LedTrigger::Panic => "panic",
+            LedTrigger::DiskActivity => "disk-activity",
+            LedTrigger::DiskRead => "disk-read",
+            LedTrigger::DiskWrite => "disk-write",
+            LedTrigger::IdeDisk => "ide-disk",
+            LedTrigger::Mtd => "mtd",
+            LedTrigger::NandDisk => "nand-disk",
+            LedTrigger::Netdev => "netdev",
+            LedTrigger::Pattern => "pattern",
+            LedTrigger::AudioMute => "audio-mute",
+            LedTrigger::AudioMicMute => "audio-micmute",
+            LedTrigger::RfkillAny => "rfkill-any",
+            LedTrigger::RfkillNone => "rfkill-none",
+            LedTrigger::Other(other) => other,
+        }
+    }
+
+    /// Parse the contents of a `trigger` file into the available triggers
+    /// and the active (bracketed) one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::misc::LedTrigger;
+    ///
+    /// let (available, active) = LedTrigger::parse_list("none [timer] phy0rx");
+    /// assert_eq!(available.len(), 3);
+    /// assert_eq!(active, Some(LedTrigger::Timer));
+    /// ```
+    pub fn parse_list(s: &str) -> (Vec<LedTrigger>, Option<LedTrigger>) {
+        let mut active = None;
+        let available = s
+            .split_whitespace()
+            .map(|t| {
+                if t.len() > 2 && t.starts_with('[') && t.ends_with(']') {
+                    let trigger = LedTrigger::from_id(&t[1..t.len() - 1]);
+                    active = Some(trigger.clone());
+                    trigger
+                } else {
+                    LedTrigger::from_id(t)
+                }
+            })
+            .collect();
+        (available, active)
+    }
+}
+
+impl From<&str> for LedTrigger {
+    fn from(val: &str) -> LedTrigger {
+        LedTrigger::from_id(val)
+    }
+}
+
+impl fmt::Display for LedTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.id())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for id in [
+            "none",
+            "timer",
+            "oneshot",
+            "heartbeat",
+            "backlight",
+            "gpio",
+            "cpu",
+            "default-on",
+            "transient",
+            "flash",
+            "torch",
+            "panic",
+            "disk-activity",
+            "disk-read",
+            "disk-write",
+            "ide-disk",
+            "mtd",
+            "nand-disk",
+            "netdev",
+            "pattern",
+            "audio-mute",
+            "audio-micmute",
+            "rfkill-any",
+            "rfkill-none",
+            "kbd-capslock",
+        ]
+        .iter()
+        {
+            assert_eq!(LedTrigger::from_id(id).id(), *id);
+        }
+        assert_eq!(
+            LedTrigger::from_id("phy0tpt"),
+            LedTrigger::Other(String::from("phy0tpt"))
+        );
+    }
+
+    #[test]
+    fn parse_list() {
+        let (available, active) =
+            LedTrigger::parse_list("[none] kbd-scrolllock kbd-numlock disk-activity\n");
+        assert_eq!(active, Some(LedTrigger::None));
+        assert_eq!(available[3], LedTrigger::DiskActivity);
+        assert_eq!(
+            available[1],
+            LedTrigger::Other(String::from("kbd-scrolllock"))
+        );
+
+        let (available, active) = LedTrigger::parse_list("");
+        assert!(available.is_empty());
+        assert_eq!(active, None);
+    }
+}
@@ -8,21 +8,22 @@ option. This file may not be copied, modified, or distributed
 except according to those terms.
 */
 
-use std::{fmt, fs, path::Path};
+use std::{cmp::Ordering, fmt, fs, sync::Arc, time::Duration};
 
-#[cfg(not(feature = "dbus"))]
-use std::{fs::OpenOptions, io::prelude::*};
-
-use super::BACKLIGHT_DIR;
 use crate::{
+    backend::{default_backend, BrightnessBackend},
+    brightness::{Brightness, StepOptions},
+    curve::Curve,
+    drm::{ConnectorStatus, DrmConnector},
+    edid::Edid,
     error::Error,
+    fade::{fade_blocking, Easing, Fade},
+    sysfs::{scan_dir, DeviceClass, DeviceId, Scan, Sysfs},
     utils::{read_sys_backlight, SysBacklightInterface},
 };
 
 #[cfg(feature = "dbus")]
 use serde::{Deserialize, Serialize};
-#[cfg(feature = "dbus")]
-use zbus::Connection;
 
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
@@ -38,7 +39,7 @@ pub struct MonitorDevice {
     /// [`/sys/class/backlight/<backlight>/bl_power`](https://www.kernel.org/doc/html/latest/admin-guide/abi-stable.html#abi-sys-class-backlight-backlight-bl-power).
     ///
     /// Controls the power of `<backlight>`.
-    pub bl_power: u32,
+    pub bl_power: BacklightPower,
     /// Value taken from
     /// [`/sys/class/backlight/<backlight>/brightness`](https://www.kernel.org/doc/html/latest/admin-guide/abi-stable.html#abi-sys-class-backlight-backlight-brightness).
     ///
@@ -62,10 +63,100 @@ pub struct MonitorDevice {
     ///
     /// The type of interface controlled by `<backlight>`.
     pub bl_type: BackLightType,
+    /// Resolved from `/sys/class/backlight/<backlight>/device`.
+    ///
+    /// Stable identity of `<backlight>`, see [`DeviceId`].
+    pub id: DeviceId,
+    /// Sysfs tree `<backlight>` was read from.
+    #[cfg_attr(feature = "dbus", serde(skip))]
+    sysfs: Sysfs,
+    /// Backend used to change brightness.
+    #[cfg_attr(feature = "dbus", serde(skip, default = "default_backend"))]
+    backend: Arc<dyn BrightnessBackend>,
+}
+
+/// Power state of [`<backlight>`](struct.MonitorDevice.html).
+///
+/// Mirrors the kernel's `FB_BLANK_*` values from
+/// [include/uapi/linux/fb.h](https://github.com/torvalds/linux/blob/master/include/uapi/linux/fb.h).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum BacklightPower {
+    /// Screen and backlight are on (`FB_BLANK_UNBLANK`, 0).
+    Unblank,
+    /// Screen blanked, backlight on (`FB_BLANK_NORMAL`, 1).
+    Normal,
+    /// Vertical sync suspended (`FB_BLANK_VSYNC_SUSPEND`, 2).
+    VsyncSuspend,
+    /// Horizontal sync suspended (`FB_BLANK_HSYNC_SUSPEND`, 3).
+    HsyncSuspend,
+    /// Screen and backlight are off (`FB_BLANK_POWERDOWN`, 4).
+    Powerdown,
+    /// A value the kernel does not define.
+    Unknown(u32),
+}
+
+impl BacklightPower {
+    /// Parse a `bl_power` value, keeping unknown values.
+    pub fn from_id(id: u32) -> BacklightPower {
+        match id {
+            0 => BacklightPower::Unblank,
+            1 => BacklightPower::Normal,
+            2 => BacklightPower::VsyncSuspend,
+            3 => BacklightPower::HsyncSuspend,
+            4 => BacklightPower::Powerdown,
+            n => BacklightPower::Unknown(n),
+        }
+    }
+
+    /// Value to write to `bl_power`.
+    pub fn id(self) -> u32 {
+        match self {
+            BacklightPower::Unblank => 0,
+            BacklightPower::Normal => 1,
+            BacklightPower::VsyncSuspend => 2,
+            BacklightPower::HsyncSuspend => 3,
+            BacklightPower::Powerdown => 4,
+            BacklightPower::Unknown(n) => n,
+        }
+    }
+
+    /// Whether the backlight is lit.
+    pub fn is_on(self) -> bool {
+        self == BacklightPower::Unblank
+    }
+}
+
+impl From<u32> for BacklightPower {
+    fn from(val: u32) -> BacklightPower {
+        BacklightPower::from_id(val)
+    }
+}
+
+impl From<BacklightPower> for u32 {
+    fn from(val: BacklightPower) -> u32 {
+        val.id()
+    }
+}
+
+impl fmt::Display for BacklightPower {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            BacklightPower::Unblank => write!(f, "Unblank"),
+            BacklightPower::Normal => write!(f, "Normal"),
+            BacklightPower::VsyncSuspend => write!(f, "VSync Suspend"),
+            BacklightPower::HsyncSuspend => write!(f, "HSync Suspend"),
+            BacklightPower::Powerdown => write!(f, "Powerdown"),
+            BacklightPower::Unknown(n) => write!(f, "Unknown ({})", n),
+        }
+    }
 }
 
 /// The type of interface controlled by [`<backlight>`](struct.MonitorDevice.html).
-#[derive(Debug, Clone, Copy)]
+///
+/// Ordered from most to least preferred, the way the kernel picks a
+/// backlight for ACPI brightness hotkeys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
 pub enum BackLightType {
     /// The driver uses a standard firmware interface
@@ -76,6 +167,18 @@ pub enum BackLightType {
     Raw,
 }
 
+impl BackLightType {
+    /// Trys to parse str into BackLightType.
+    pub fn from_id(s: &str) -> Option<Self> {
+        match s {
+            "firmware" => Some(BackLightType::FirmWare),
+            "platform" => Some(BackLightType::PlatForm),
+            "raw" => Some(BackLightType::Raw),
+            _ => None,
+        }
+    }
+}
+
 impl From<&BackLightType> for &str {
     fn from(val: &BackLightType) -> &'static str {
         match val {
@@ -126,12 +229,85 @@ impl fmt::Display for BackLightType {
     }
 }
 
+/// State of the panel behind [`<backlight>`](struct.MonitorDevice.html).
+///
+/// Ordered from most to least preferred. Platform and firmware backlights
+/// often have no connector, so [`Unknown`](PanelState::Unknown) is as good
+/// as [`Lit`](PanelState::Lit) when ranking, see [`BacklightRank`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum PanelState {
+    /// The panel's connector is connected and enabled.
+    Lit,
+    /// The backlight has no connector or its status is unknown.
+    Unknown,
+    /// Nothing is attached to the panel's connector.
+    Disconnected,
+    /// The backlight's parent device or connector is disabled, so changing
+    /// it has no visible effect.
+    Disabled,
+}
+
+impl fmt::Display for PanelState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            PanelState::Lit => write!(f, "Lit"),
+            PanelState::Unknown => write!(f, "Unknown"),
+            PanelState::Disconnected => write!(f, "Disconnected"),
+            PanelState::Disabled => write!(f, "Disabled"),
+        }
+    }
+}
+
+/// Where [`<backlight>`](struct.MonitorDevice.html) ranks when picking the
+/// screen backlight, see [`MonitorDevice::preferred`].
+///
+/// Lower ranks are preferred: firmware over platform over raw interfaces,
+/// with backlights of disconnected and then disabled panels last. Between
+/// backlights of the same interface, lit panels come first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct BacklightRank {
+    /// State of the panel behind the backlight.
+    pub panel: PanelState,
+    /// Interface of the backlight.
+    pub bl_type: BackLightType,
+}
+
+impl BacklightRank {
+    /// Whether the backlight may be picked at all.
+    pub fn is_usable(&self) -> bool {
+        self.panel != PanelState::Disabled
+    }
+
+    fn key(&self) -> (u8, BackLightType, PanelState) {
+        let demoted = match self.panel {
+            PanelState::Lit | PanelState::Unknown => 0,
+            PanelState::Disconnected => 1,
+            PanelState::Disabled => 2,
+        };
+        (demoted, self.bl_type, self.panel)
+    }
+}
+
+impl PartialOrd for BacklightRank {
+    fn partial_cmp(&self, other: &BacklightRank) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BacklightRank {
+    fn cmp(&self, other: &BacklightRank) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
 impl MonitorDevice {
     /// Get monitor by device name.
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
     /// use bulbb::monitor::MonitorDevice;
     ///
     /// let device_name = format!("amdgpu_bl0");
@@ -139,23 +315,46 @@ impl MonitorDevice {
     /// assert_eq!(monitor.get_device_name(), device_name);
     /// ```
     pub fn get_monitor_device(device: String) -> Result<MonitorDevice, Error> {
-        if Path::new(format!("{}/{}", BACKLIGHT_DIR, &device).as_str()).is_dir() {
-            let bl_power =
-                read_sys_backlight(&device, SysBacklightInterface::Power)?.parse::<u32>()?;
-            let brightness =
-                read_sys_backlight(&device, SysBacklightInterface::Brightness)?.parse::<u32>()?;
+        MonitorDevice::get_monitor_device_in(&Sysfs::default(), device)
+    }
+
+    /// Get monitor by device name from the given sysfs tree.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::{monitor::MonitorDevice, sysfs::Sysfs};
+    ///
+    /// let sysfs = Sysfs::new("/mnt/chroot/sys");
+    /// let monitor = MonitorDevice::get_monitor_device_in(&sysfs, format!("amdgpu_bl0")).unwrap();
+    /// assert_eq!(monitor.get_sysfs(), &sysfs);
+    /// ```
+    pub fn get_monitor_device_in(sysfs: &Sysfs, device: String) -> Result<MonitorDevice, Error> {
+        if sysfs.backlight_device(&device).is_dir() {
+            let bl_power = BacklightPower::from_id(
+                read_sys_backlight(sysfs, &device, SysBacklightInterface::Power)?.parse::<u32>()?,
+            );
+            let brightness = read_sys_backlight(sysfs, &device, SysBacklightInterface::Brightness)?
+                .parse::<u32>()?;
             let actual_brightness =
-                read_sys_backlight(&device, SysBacklightInterface::ActualBrightness)?
+                read_sys_backlight(sysfs, &device, SysBacklightInterface::ActualBrightness)?
                     .parse::<u32>()?;
-            let max_brightness = read_sys_backlight(&device, SysBacklightInterface::MaxBrightness)?
-                .parse::<u32>()?;
-            let bl_type = match read_sys_backlight(&device, SysBacklightInterface::Type)?.as_str() {
-                "firmware" => BackLightType::FirmWare,
-                "platform" => BackLightType::PlatForm,
-                "raw" => BackLightType::Raw,
-                _ => unreachable!(),
+            let max_brightness =
+                read_sys_backlight(sysfs, &device, SysBacklightInterface::MaxBrightness)?
+                    .parse::<u32>()?;
+            let bl_type = read_sys_backlight(sysfs, &device, SysBacklightInterface::Type)?;
+            let bl_type = match BackLightType::from_id(&bl_type) {
+                Some(bl_type) => bl_type,
+                None => {
+                    return Err(Error::UnknownBacklightType {
+                        device,
+                        given: bl_type,
+                    })
+                }
             };
 
+            let id = DeviceId::resolve(sysfs, DeviceClass::Backlight, &device);
+
             Ok(MonitorDevice {
                 device,
                 bl_power,
@@ -163,14 +362,149 @@ impl MonitorDevice {
                 actual_brightness,
                 max_brightness,
                 bl_type,
+                id,
+                sysfs: sysfs.clone(),
+                backend: default_backend(),
             })
         } else {
             Err(Error::InvalidDeviceName { device })
         }
     }
 
+    /// Get monitor by its stable [`DeviceId`] key, e.g. one saved in a config
+    /// file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::monitor::MonitorDevice;
+    ///
+    /// let id = "backlight/pci-0000:00:02.0-drm-eDP-1/intel_backlight";
+    /// let monitor = MonitorDevice::get_monitor_device_by_id(id).unwrap();
+    /// assert_eq!(monitor.get_device_name(), "intel_backlight");
+    /// ```
+    pub fn get_monitor_device_by_id(id: &str) -> Result<MonitorDevice, Error> {
+        MonitorDevice::get_monitor_device_by_id_in(&Sysfs::default(), id)
+    }
+
+    /// Get monitor by its stable [`DeviceId`] key from the given sysfs tree.
+    ///
+    /// Fails with [`Error::AmbiguousDeviceId`] if several monitors have the key.
+    pub fn get_monitor_device_by_id_in(sysfs: &Sysfs, id: &str) -> Result<MonitorDevice, Error> {
+        let mut matches = MonitorDevice::get_all_monitor_devices_in(sysfs)?
+            .into_iter()
+            .filter(|monitor| monitor.id.matches_key(id));
+        match (matches.next(), matches.next()) {
+            (Some(monitor), None) => Ok(monitor),
+            (Some(_), Some(_)) => Err(Error::AmbiguousDeviceId { id: id.to_string() }),
+            (None, _) => Err(Error::InvalidDeviceName {
+                device: id.to_string(),
+            }),
+        }
+    }
+
+    /// Get monitor whose panel has the EDID serial number `serial`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::monitor::MonitorDevice;
+    ///
+    /// let monitor = MonitorDevice::get_monitor_device_by_serial("JKNM0C3").unwrap();
+    /// ```
+    pub fn get_monitor_device_by_serial(serial: &str) -> Result<MonitorDevice, Error> {
+        MonitorDevice::get_monitor_device_by_serial_in(&Sysfs::default(), serial)
+    }
+
+    /// Get monitor whose panel has the EDID serial number `serial` from the
+    /// given sysfs tree.
+    ///
+    /// Monitors whose EDID cannot be read are skipped. Fails with
+    /// [`Error::SerialNotFound`] if no monitor has the serial number.
+    pub fn get_monitor_device_by_serial_in(
+        sysfs: &Sysfs,
+        serial: &str,
+    ) -> Result<MonitorDevice, Error> {
+        MonitorDevice::get_all_monitor_devices_in(sysfs)?
+            .into_iter()
+            .find(|monitor| {
+                let edid = monitor.get_edid().ok().flatten();
+                edid.and_then(|edid| edid.get_serial()).as_deref() == Some(serial)
+            })
+            .ok_or_else(|| Error::SerialNotFound {
+                serial: serial.to_string(),
+            })
+    }
+
+    /// Get the backlight of the screen, the best ranked usable monitor.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::monitor::MonitorDevice;
+    ///
+    /// if let Some(monitor) = MonitorDevice::preferred().unwrap() {
+    ///     monitor.set_brightness(monitor.get_max_brightness() / 2).unwrap();
+    /// }
+    /// ```
+    pub fn preferred() -> Result<Option<MonitorDevice>, Error> {
+        MonitorDevice::preferred_in(&Sysfs::default())
+    }
+
+    /// Get the backlight of the screen from the given sysfs tree.
+    pub fn preferred_in(sysfs: &Sysfs) -> Result<Option<MonitorDevice>, Error> {
+        Ok(MonitorDevice::rank_monitor_devices_in(sysfs)?
+            .into_iter()
+            .find(|(_, rank)| rank.is_usable())
+            .map(|(monitor, _)| monitor))
+    }
+
+    /// Get all monitor devices with their rank, best first.
+    ///
+    /// Monitors with the same rank stay sorted by name. Unusable monitors
+    /// are kept at the end.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::monitor::MonitorDevice;
+    ///
+    /// for (monitor, rank) in MonitorDevice::rank_monitor_devices().unwrap() {
+    ///     println!("{}: {} {}", monitor.get_device_name(), rank.panel, rank.bl_type);
+    /// }
+    /// ```
+    pub fn rank_monitor_devices() -> Result<Vec<(MonitorDevice, BacklightRank)>, Error> {
+        MonitorDevice::rank_monitor_devices_in(&Sysfs::default())
+    }
+
+    /// Get all monitor devices from the given sysfs tree with their rank,
+    /// best first.
+    ///
+    /// Monitors whose panel state cannot be read are ranked as
+    /// [`PanelState::Unknown`].
+    pub fn rank_monitor_devices_in(
+        sysfs: &Sysfs,
+    ) -> Result<Vec<(MonitorDevice, BacklightRank)>, Error> {
+        let mut ranked: Vec<(MonitorDevice, BacklightRank)> =
+            MonitorDevice::get_all_monitor_devices_in(sysfs)?
+                .into_iter()
+                .map(|monitor| {
+                    let rank = monitor.get_rank().unwrap_or(BacklightRank {
+                        panel: PanelState::Unknown,
+                        bl_type: monitor.bl_type,
+                    });
+                    (monitor, rank)
+                })
+                .collect();
+        ranked.sort_by_key(|(_, rank)| *rank);
+        Ok(ranked)
+    }
+
     /// Get all monitor devices.
     ///
+    /// Devices that cannot be read are skipped, use
+    /// [`scan_monitor_devices`](MonitorDevice::scan_monitor_devices) to find out why.
+    ///
     /// # Examples
     ///
     /// ```
@@ -182,21 +516,65 @@ impl MonitorDevice {
     /// }
     /// ```
     pub fn get_all_monitor_devices() -> Result<Vec<MonitorDevice>, Error> {
-        let mut monitors = Vec::with_capacity(1);
+        MonitorDevice::get_all_monitor_devices_in(&Sysfs::default())
+    }
 
-        if Path::new(BACKLIGHT_DIR).is_dir() {
-            for device in fs::read_dir(BACKLIGHT_DIR)? {
-                let device = device?;
-                let device_name = device.file_name().into_string().unwrap();
+    /// Get all monitor devices from the given sysfs tree.
+    ///
+    /// Devices that cannot be read are skipped, use
+    /// [`scan_monitor_devices_in`](MonitorDevice::scan_monitor_devices_in) to find out why.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{monitor::MonitorDevice, sysfs::Sysfs};
+    ///
+    /// let monitors = MonitorDevice::get_all_monitor_devices_in(&Sysfs::default()).unwrap();
+    /// for monitor in monitors {
+    ///     println!("Monitor: {:?}", monitor);
+    /// }
+    /// ```
+    pub fn get_all_monitor_devices_in(sysfs: &Sysfs) -> Result<Vec<MonitorDevice>, Error> {
+        Ok(MonitorDevice::scan_monitor_devices_in(sysfs)?.devices)
+    }
 
-                match MonitorDevice::get_monitor_device(device_name) {
-                    Ok(dev) => monitors.push(dev),
-                    Err(e) => return Err(e),
-                }
-            }
-        }
+    /// Get all monitor devices along with the errors for those that could
+    /// not be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::monitor::MonitorDevice;
+    ///
+    /// let scan = MonitorDevice::scan_monitor_devices().unwrap();
+    /// for monitor in scan.devices {
+    ///     println!("Monitor: {:?}", monitor);
+    /// }
+    /// for (device, e) in scan.errors {
+    ///     eprintln!("Skipped {}: {}", device, e);
+    /// }
+    /// ```
+    pub fn scan_monitor_devices() -> Result<Scan<MonitorDevice>, Error> {
+        MonitorDevice::scan_monitor_devices_in(&Sysfs::default())
+    }
 
-        Ok(monitors)
+    /// Get all monitor devices from the given sysfs tree along with the
+    /// errors for those that could not be read.
+    ///
+    /// Only fails if `class/backlight` exists but cannot be listed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{monitor::MonitorDevice, sysfs::Sysfs};
+    ///
+    /// let scan = MonitorDevice::scan_monitor_devices_in(&Sysfs::default()).unwrap();
+    /// assert!(scan.devices.len() + scan.errors.len() < 1000);
+    /// ```
+    pub fn scan_monitor_devices_in(sysfs: &Sysfs) -> Result<Scan<MonitorDevice>, Error> {
+        scan_dir(&sysfs.backlight_dir(), |device| {
+            Some(MonitorDevice::get_monitor_device_in(sysfs, device))
+        })
     }
 
     /// Get device name of monitor.
@@ -218,6 +596,109 @@ impl MonitorDevice {
         &self.device
     }
 
+    /// Get stable identity of monitor.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::monitor::MonitorDevice;
+    ///
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// for monitor in monitors {
+    ///     println!("{}: {}", monitor.get_device_name(), monitor.get_id());
+    /// }
+    /// ```
+    pub fn get_id(&self) -> &DeviceId {
+        &self.id
+    }
+
+    /// Get the DRM connector of the panel lit by monitor, read fresh from
+    /// sysfs.
+    ///
+    /// On hybrid graphics laptops this tells which of several backlights
+    /// drives the panel that is actually on. `None` for backlights that do
+    /// not belong to a graphics card, e.g. DDC/CI or USB monitors.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::monitor::MonitorDevice;
+    ///
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// let lit = monitors.iter().find(|monitor| {
+    ///     monitor
+    ///         .get_connector()
+    ///         .unwrap()
+    ///         .is_some_and(|connector| connector.is_lit())
+    /// });
+    /// ```
+    pub fn get_connector(&self) -> Result<Option<DrmConnector>, Error> {
+        match &self.id.parent {
+            Some(parent) => DrmConnector::find_for_parent(&self.sysfs, parent),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the EDID of the panel lit by monitor, e.g. to show its name.
+    ///
+    /// `None` if monitor has no connector or the connector has no EDID.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::monitor::MonitorDevice;
+    ///
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// for monitor in monitors {
+    ///     if let Some(edid) = monitor.get_edid().unwrap() {
+    ///         println!("{}: {}", monitor.get_device_name(), edid);
+    ///     }
+    /// }
+    /// ```
+    pub fn get_edid(&self) -> Result<Option<Edid>, Error> {
+        match self.get_connector()? {
+            Some(connector) => connector.read_edid(),
+            None => Ok(None),
+        }
+    }
+
+    /// Get rank of monitor when picking the screen backlight, reading the
+    /// state of its panel fresh from sysfs.
+    pub fn get_rank(&self) -> Result<BacklightRank, Error> {
+        Ok(BacklightRank {
+            panel: self.get_panel_state()?,
+            bl_type: self.bl_type,
+        })
+    }
+
+    fn get_panel_state(&self) -> Result<PanelState, Error> {
+        let connector = self.get_connector()?;
+        // disconnected connectors are always disabled too
+        if let Some(connector) = &connector {
+            if connector.get_status() == ConnectorStatus::Disconnected {
+                return Ok(PanelState::Disconnected);
+            }
+        }
+        if let Some(parent) = &self.id.parent {
+            let attribute = |name: &str| {
+                fs::read_to_string(parent.join(name))
+                    .map(|s| s.trim().to_string())
+                    .ok()
+            };
+            // `enabled` of platform devices and connectors, `enable` of PCI devices
+            if attribute("enabled").as_deref() == Some("disabled")
+                || attribute("enable").as_deref() == Some("0")
+            {
+                return Ok(PanelState::Disabled);
+            }
+        }
+        Ok(match connector {
+            Some(connector) if !connector.is_enabled() => PanelState::Disabled,
+            Some(connector) if connector.is_connected() => PanelState::Lit,
+            _ => PanelState::Unknown,
+        })
+    }
+
     /// Get power of monitor.
     ///
     /// # Examples
@@ -231,7 +712,7 @@ impl MonitorDevice {
     ///     println!("Power: {}", power);
     /// }
     /// ```
-    pub fn get_power(&self) -> u32 {
+    pub fn get_power(&self) -> BacklightPower {
         self.bl_power
     }
 
@@ -308,30 +789,148 @@ impl MonitorDevice {
         self.bl_type
     }
 
-    /// Set brightness of monitor.
+    /// Get the sysfs tree monitor was read from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{monitor::MonitorDevice, sysfs::Sysfs};
+    ///
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// for monitor in monitors {
+    ///     assert_eq!(monitor.get_sysfs(), &Sysfs::default());
+    /// }
+    /// ```
+    pub fn get_sysfs(&self) -> &Sysfs {
+        &self.sysfs
+    }
+
+    /// Re-read every value of monitor from sysfs, e.g. after
+    /// [`set_brightness`](MonitorDevice::set_brightness).
+    ///
+    /// Fails with [`Error::DeviceGone`] if the monitor has been removed.
     ///
     /// # Examples
     ///
+    /// ```no_run
+    /// use bulbb::monitor::MonitorDevice;
+    ///
+    /// let mut monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// monitors[0].set_brightness(20).unwrap();
+    /// monitors[0].refresh().unwrap();
+    /// assert_eq!(monitors[0].get_brightness(), 20);
     /// ```
+    pub fn refresh(&mut self) -> Result<(), Error> {
+        let fresh = match MonitorDevice::get_monitor_device_in(&self.sysfs, self.device.clone()) {
+            Err(Error::InvalidDeviceName { device }) => return Err(Error::DeviceGone { device }),
+            res => res?,
+        };
+        self.bl_power = fresh.bl_power;
+        self.brightness = fresh.brightness;
+        self.actual_brightness = fresh.actual_brightness;
+        self.max_brightness = fresh.max_brightness;
+        self.bl_type = fresh.bl_type;
+        self.id = fresh.id;
+        Ok(())
+    }
+
+    /// Whether monitor still exists in sysfs.
+    pub fn is_present(&self) -> bool {
+        self.sysfs.backlight_device(&self.device).is_dir()
+    }
+
+    /// Read the current brightness of monitor from sysfs, without updating
+    /// [`get_brightness`](MonitorDevice::get_brightness).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
     /// use bulbb::monitor::MonitorDevice;
     ///
     /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
-    /// monitors[0].set_brightness(20);
+    /// println!("Brightness: {}", monitors[0].read_brightness().unwrap());
+    /// ```
+    pub fn read_brightness(&self) -> Result<u32, Error> {
+        Ok(
+            read_sys_backlight(&self.sysfs, &self.device, SysBacklightInterface::Brightness)?
+                .parse::<u32>()?,
+        )
+    }
+
+    /// Read the current actual brightness of monitor from sysfs.
+    pub fn read_actual_brightness(&self) -> Result<u32, Error> {
+        Ok(read_sys_backlight(
+            &self.sysfs,
+            &self.device,
+            SysBacklightInterface::ActualBrightness,
+        )?
+        .parse::<u32>()?)
+    }
+
+    /// Read the current power of monitor from sysfs.
+    pub fn read_power(&self) -> Result<BacklightPower, Error> {
+        Ok(BacklightPower::from_id(
+            read_sys_backlight(&self.sysfs, &self.device, SysBacklightInterface::Power)?
+                .parse::<u32>()?,
+        ))
+    }
+
+    /// Get the backend used to change brightness of monitor.
+    pub fn get_backend(&self) -> &Arc<dyn BrightnessBackend> {
+        &self.backend
+    }
+
+    /// Change the backend used to change brightness of monitor.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use bulbb::{backend::SysfsBackend, monitor::MonitorDevice};
+    ///
+    /// for monitor in MonitorDevice::get_all_monitor_devices().unwrap() {
+    ///     let monitor = monitor.with_backend(Arc::new(SysfsBackend));
+    /// }
+    /// ```
+    pub fn with_backend(mut self, backend: Arc<dyn BrightnessBackend>) -> MonitorDevice {
+        self.backend = backend;
+        self
+    }
+
+    /// Set brightness of monitor.
+    ///
+    /// The device's backend is used, which is [`default_backend`] unless
+    /// changed with [`with_backend`](MonitorDevice::with_backend).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::monitor::MonitorDevice;
+    ///
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// monitors[0].set_brightness(20).unwrap();
     /// ```
-    #[cfg(feature = "dbus")]
     pub fn set_brightness(&self, level: u32) -> Result<(), Error> {
+        self.set_brightness_with(self.backend.as_ref(), level)
+    }
+
+    /// Set brightness of monitor using `backend` for this call only.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::{backend::SysfsBackend, monitor::MonitorDevice};
+    ///
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// monitors[0].set_brightness_with(&SysfsBackend, 20).unwrap();
+    /// ```
+    pub fn set_brightness_with(
+        &self,
+        backend: &dyn BrightnessBackend,
+        level: u32,
+    ) -> Result<(), Error> {
         if level <= self.max_brightness {
-            let sd_bus = Connection::new_system().unwrap();
-            match sd_bus.call_method(
-                Some("org.freedesktop.login1"),
-                "/org/freedesktop/login1/session/auto",
-                Some("org.freedesktop.login1.Session"),
-                "SetBrightness",
-                &("backlight", &self.device, level),
-            ) {
-                Ok(_) => Ok(()),
-                Err(e) => Err(Error::SetBrightnessDBusError(e)),
-            }
+            backend.set_brightness(&self.sysfs, DeviceClass::Backlight, &self.device, level)
         } else {
             Err(Error::InvalidBrightnessLevel {
                 given: level,
@@ -340,96 +939,625 @@ impl MonitorDevice {
         }
     }
 
-    /// Set brightness of monitor.
+    /// Set power of monitor, e.g. to turn the panel off without changing
+    /// its brightness.
     ///
-    /// ### NOTE
+    /// Power can only be changed through sysfs, backends without it such
+    /// as `LogindBackend` fail with [`Error::Unsupported`].
     ///
-    /// This method writes to `/sys/class/backlight/<backlight>/brightness`
-    /// and will fail if user is not root (even when executed with sudo).
-    /// It is recommended to create a udev rule to allow user of a certain
-    /// group to write to the file. The example below will allow all users in
-    /// the `video` group to change the brightness of all devices in `/sys/class/backlight/`.
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::monitor::{BacklightPower, MonitorDevice};
     ///
-    /// ```ignore
-    /// ACTION=="add", SUBSYSTEM=="backlight", RUN+="/bin/chgrp video /sys/class/backlight/%k/brightness"
-    /// ACTION=="add", SUBSYSTEM=="backlight", RUN+="/bin/chmod g+w /sys/class/backlight/%k/brightness"
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// monitors[0].set_power(BacklightPower::Powerdown).unwrap();
     /// ```
+    pub fn set_power(&self, power: BacklightPower) -> Result<(), Error> {
+        self.set_power_with(self.backend.as_ref(), power)
+    }
+
+    /// Set power of monitor using `backend` for this call only.
     ///
     /// # Examples
     ///
+    /// ```no_run
+    /// use bulbb::{
+    ///     backend::SysfsBackend,
+    ///     monitor::{BacklightPower, MonitorDevice},
+    /// };
+    ///
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// monitors[0].set_power_with(&SysfsBackend, BacklightPower::Unblank).unwrap();
     /// ```
-    /// use bulbb::monitor::MonitorDevice;
+    pub fn set_power_with(
+        &self,
+        backend: &dyn BrightnessBackend,
+        power: BacklightPower,
+    ) -> Result<(), Error> {
+        backend.set_power(&self.sysfs, &self.device, power)
+    }
+
+    /// Get brightness of monitor as a percentage of perceived brightness.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bulbb::{curve::Curve, monitor::MonitorDevice};
+    ///
+    /// for monitor in MonitorDevice::get_all_monitor_devices().unwrap() {
+    ///     let percent = monitor.get_percent(Curve::Cie1931);
+    ///     assert!(percent >= 0.0 && percent <= 100.0);
+    /// }
+    /// ```
+    pub fn get_percent(&self, curve: Curve) -> f64 {
+        curve.to_percent(self.brightness, self.max_brightness)
+    }
+
+    /// Set brightness of monitor to a percentage of perceived brightness.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::{curve::Curve, monitor::MonitorDevice};
     ///
     /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
-    /// monitors[0].set_brightness(20);
+    /// monitors[0].set_percent(50.0, Curve::Exponential(4.0)).unwrap();
     /// ```
-    #[cfg(not(feature = "dbus"))]
-    pub fn set_brightness(&self, level: u32) -> Result<(), Error> {
-        if level <= self.max_brightness {
-            // write to /sys/class/backlight/<backlight>/brightness
-            let mut brightness = OpenOptions::new()
-                .write(true)
-                .open(format!("{}/{}/brightness", BACKLIGHT_DIR, &self.device))?;
-            match brightness.write_all(level.to_string().as_bytes()) {
-                Ok(_) => Ok(()),
-                Err(e) => Err(Error::Io(e)),
-            }
+    pub fn set_percent(&self, percent: f64, curve: Curve) -> Result<(), Error> {
+        if (0.0..=100.0).contains(&percent) {
+            self.set_brightness(curve.to_raw(percent, self.max_brightness))
         } else {
-            Err(Error::InvalidBrightnessLevel {
-                given: level,
-                max: self.max_brightness,
-            })
+            Err(Error::InvalidPercentage { given: percent })
         }
     }
+
+    /// Raise brightness of monitor by one step, returning the level written.
+    ///
+    /// The step starts from the brightness read fresh from sysfs, so
+    /// repeated calls keep stepping even though [`get_brightness`](Self::get_brightness)
+    /// is not updated.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use bulbb::{
+    ///     brightness::{StepMode, StepOptions},
+    ///     monitor::MonitorDevice,
+    /// };
+    ///
+    /// let options = StepOptions::new().with_mode(StepMode::Exponential);
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// monitors[0].step_up(&options).unwrap();
+    /// ```
+    pub fn step_up(&self, options: &StepOptions) -> Result<Brightness, Error> {
+        let level = options.step_up(self.read_level()?);
+        self.set_brightness(level.get_raw())?;
+        Ok(level)
+    }
+
+    /// Lower brightness of monitor by one step, returning the level written.
+    ///
+    /// Stops at the floor of `options`, which is never 0 unless allowed.
+    pub fn step_down(&self, options: &StepOptions) -> Result<Brightness, Error> {
+        let level = options.step_down(self.read_level()?);
+        self.set_brightness(level.get_raw())?;
+        Ok(level)
+    }
+
+    fn read_level(&self) -> Result<Brightness, Error> {
+        Ok(Brightness::saturating(
+            self.read_brightness()?,
+            self.max_brightness,
+        ))
+    }
+
+    /// Gradually change brightness of monitor to `level` over `duration`,
+    /// blocking until the fade ends.
+    ///
+    /// The fade starts from the level read from sysfs and stops any fade of
+    /// monitor already running.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use bulbb::{fade::Easing, monitor::MonitorDevice};
+    ///
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// monitors[0]
+    ///     .fade_to(0, Duration::from_millis(500), Easing::EaseOut)
+    ///     .unwrap();
+    /// ```
+    pub fn fade_to(&self, level: u32, duration: Duration, easing: Easing) -> Result<(), Error> {
+        fade_blocking(
+            &self.sysfs.backlight_device(&self.device),
+            || self.read_brightness(),
+            level,
+            self.max_brightness,
+            duration,
+            easing,
+            |l| self.set_brightness(l),
+        )
+    }
+
+    /// Gradually change brightness of monitor to `level` over `duration` in a
+    /// background thread.
+    ///
+    /// The fade starts from the level read from sysfs and stops any fade of
+    /// monitor already running. The returned [`Fade`] can be used to cancel
+    /// or retarget it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use bulbb::{fade::Easing, monitor::MonitorDevice};
+    ///
+    /// let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
+    /// let fade = monitors[0]
+    ///     .fade_to_in_background(0, Duration::from_secs(2), Easing::EaseInOut)
+    ///     .unwrap();
+    /// fade.retarget(monitors[0].get_max_brightness(), Duration::from_millis(300), Easing::EaseOut)
+    ///     .unwrap();
+    /// fade.join().unwrap();
+    /// ```
+    pub fn fade_to_in_background(
+        &self,
+        level: u32,
+        duration: Duration,
+        easing: Easing,
+    ) -> Result<Fade, Error> {
+        let reader = self.clone();
+        let monitor = self.clone();
+        Fade::spawn(
+            &self.sysfs.backlight_device(&self.device),
+            move || reader.read_brightness(),
+            level,
+            self.max_brightness,
+            duration,
+            easing,
+            move |l| monitor.set_brightness(l),
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use crate::utils::format_monitor_device;
+    use crate::{
+        backend::MockBackend,
+        testing::{FakeBacklight, FakeConnector, FakeDevice, FakeSysfs},
+        utils::format_monitor_device,
+    };
+
+    fn fake_monitors() -> FakeSysfs {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_backlight(
+            &FakeBacklight::new("amdgpu_bl0")
+                .brightness(40)
+                .max_brightness(255)
+                .bl_type("raw"),
+        )
+        .unwrap();
+        fake.add_backlight(
+            &FakeBacklight::new("acpi_video0")
+                .brightness(7)
+                .actual_brightness(6)
+                .max_brightness(15)
+                .bl_power(4)
+                .bl_type("firmware"),
+        )
+        .unwrap();
+        fake
+    }
 
     #[test]
     fn get_all_monitor_devices() {
-        let monitors = MonitorDevice::get_all_monitor_devices().unwrap();
-        for monitor in monitors {
+        let fake = fake_monitors();
+        let monitors = MonitorDevice::get_all_monitor_devices_in(&fake.sysfs()).unwrap();
+        assert_eq!(monitors.len(), 2);
+        for monitor in monitors.iter().cloned() {
             format_monitor_device(monitor)
         }
+
+        let acpi = &monitors[0];
+        assert_eq!(acpi.get_device_name(), "acpi_video0");
+        assert_eq!(acpi.get_power(), BacklightPower::Powerdown);
+        assert_eq!(acpi.get_brightness(), 7);
+        assert_eq!(acpi.get_actual_brightness(), 6);
+        assert_eq!(acpi.get_max_brightness(), 15);
+        assert_eq!(acpi.get_type().to_string(), "Firmware");
+        assert_eq!(acpi.get_sysfs(), &fake.sysfs());
+    }
+
+    #[test]
+    fn get_monitor_device_missing() {
+        let fake = fake_monitors();
+        match MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("nvidia_0")) {
+            Err(Error::InvalidDeviceName { device }) => assert_eq!(device, "nvidia_0"),
+            other => panic!("unexpected result: {:?}", other),
+        }
     }
 
     #[test]
     fn set_monitor() {
-        let mut monitors = MonitorDevice::get_all_monitor_devices().unwrap();
-        if !monitors.is_empty() {
-            let first_device = monitors.remove(0);
-            let device_name = first_device.get_device_name();
-            let starting_brightness = first_device.get_actual_brightness();
-            let max_brightness = first_device.get_max_brightness();
-            let new_brightness: u32 = if starting_brightness > (max_brightness / 2) {
-                0
-            } else {
-                max_brightness
-            };
+        let fake = fake_monitors();
+        let sysfs = fake.sysfs();
+        let device =
+            MonitorDevice::get_monitor_device_in(&sysfs, String::from("amdgpu_bl0")).unwrap();
+        let starting_brightness = device.get_brightness();
+        let new_brightness = device.get_max_brightness();
 
-            let _set_brightness_1 =
-                MonitorDevice::set_brightness(&first_device.clone(), new_brightness);
+        device.set_brightness(new_brightness).unwrap();
+        assert_eq!(device.read_brightness().unwrap(), new_brightness);
+        let mut updated_device = device.clone();
+        updated_device.refresh().unwrap();
+        assert_eq!(updated_device.get_brightness(), new_brightness);
+        assert_eq!(device.get_brightness(), starting_brightness);
 
-            let udpated_device =
-                MonitorDevice::get_monitor_device(String::from(device_name)).unwrap();
-            let updated_brightness = udpated_device.get_actual_brightness();
+        updated_device.set_brightness(starting_brightness).unwrap();
+        assert_eq!(
+            fake.read_backlight("amdgpu_bl0", "brightness").unwrap(),
+            starting_brightness.to_string()
+        );
 
-            assert_ne!(starting_brightness, updated_brightness);
-            assert_eq!(updated_brightness, new_brightness);
+        match updated_device.set_brightness(new_brightness + 1) {
+            Err(Error::InvalidBrightnessLevel { given, max }) => {
+                assert_eq!(given, new_brightness + 1);
+                assert_eq!(max, new_brightness);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 
-            let _set_brightness_2 =
-                MonitorDevice::set_brightness(&udpated_device, starting_brightness);
+    #[test]
+    fn set_monitor_with_backend() {
+        let fake = fake_monitors();
+        let mock = Arc::new(MockBackend::new());
+        let device =
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("acpi_video0"))
+                .unwrap()
+                .with_backend(mock.clone());
 
-            let final_device =
-                MonitorDevice::get_monitor_device(String::from(device_name)).unwrap();
-            let final_brightness = final_device.get_actual_brightness();
+        device.set_brightness(3).unwrap();
+        assert_eq!(
+            mock.get_brightness(DeviceClass::Backlight, "acpi_video0"),
+            Some(3)
+        );
+        assert_eq!(
+            fake.read_backlight("acpi_video0", "brightness").unwrap(),
+            "7"
+        );
 
-            assert_ne!(final_brightness, updated_brightness);
-            assert_eq!(final_brightness, starting_brightness);
+        let other = MockBackend::new();
+        device.set_brightness_with(&other, 15).unwrap();
+        assert_eq!(
+            other.get_brightness(DeviceClass::Backlight, "acpi_video0"),
+            Some(15)
+        );
+        assert_eq!(
+            mock.get_brightness(DeviceClass::Backlight, "acpi_video0"),
+            Some(3)
+        );
+        assert!(device.set_brightness_with(&other, 16).is_err());
+    }
+
+    #[test]
+    fn percent() {
+        let fake = fake_monitors();
+        let device =
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("acpi_video0"))
+                .unwrap();
+        assert!((device.get_percent(Curve::Linear) - 46.666).abs() < 0.01);
+
+        device.set_percent(100.0, Curve::Cie1931).unwrap();
+        assert_eq!(
+            fake.read_backlight("acpi_video0", "brightness").unwrap(),
+            "15"
+        );
+        device.set_percent(50.0, Curve::Exponential(4.0)).unwrap();
+        assert_eq!(
+            fake.read_backlight("acpi_video0", "brightness").unwrap(),
+            "1"
+        );
+        match device.set_percent(100.5, Curve::Linear) {
+            Err(Error::InvalidPercentage { given }) => assert_eq!(given, 100.5),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn step() {
+        let fake = fake_monitors();
+        let device =
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("acpi_video0"))
+                .unwrap();
+        let options = StepOptions::new().with_steps(5);
+        assert_eq!(device.step_up(&options).unwrap().get_raw(), 10);
+        assert_eq!(device.step_up(&options).unwrap().get_raw(), 13);
+        assert_eq!(device.step_up(&options).unwrap().get_raw(), 15);
+        assert_eq!(device.step_up(&options).unwrap().get_raw(), 15);
+
+        fake.write_backlight("acpi_video0", "brightness", "2")
+            .unwrap();
+        assert_eq!(device.step_down(&options).unwrap().get_raw(), 1);
+        assert_eq!(device.step_down(&options).unwrap().get_raw(), 1);
+        assert_eq!(
+            fake.read_backlight("acpi_video0", "brightness").unwrap(),
+            "1"
+        );
+        let options = options.with_allow_off(true);
+        assert_eq!(device.step_down(&options).unwrap().get_raw(), 0);
+    }
+
+    #[test]
+    fn backlight_power() {
+        for id in 0..=4 {
+            assert_eq!(BacklightPower::from_id(id).id(), id);
+        }
+        assert_eq!(BacklightPower::from_id(17), BacklightPower::Unknown(17));
+        assert_eq!(u32::from(BacklightPower::Unknown(17)), 17);
+        assert!(BacklightPower::Unblank.is_on());
+        assert!(!BacklightPower::Powerdown.is_on());
+
+        let fake = fake_monitors();
+        fake.write_backlight("amdgpu_bl0", "bl_power", "9").unwrap();
+        let device =
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("amdgpu_bl0"))
+                .unwrap();
+        assert_eq!(device.get_power(), BacklightPower::Unknown(9));
+
+        device.set_power(BacklightPower::Powerdown).unwrap();
+        assert_eq!(fake.read_backlight("amdgpu_bl0", "bl_power").unwrap(), "4");
+        assert_eq!(
+            fake.read_backlight("amdgpu_bl0", "brightness").unwrap(),
+            "40"
+        );
+
+        let mock = MockBackend::new();
+        device
+            .set_power_with(&mock, BacklightPower::Unblank)
+            .unwrap();
+        assert_eq!(mock.get_power("amdgpu_bl0"), Some(BacklightPower::Unblank));
+    }
+
+    #[test]
+    fn scan_reports_broken_devices() {
+        use std::{ffi::OsStr, fs, os::unix::ffi::OsStrExt};
+
+        let fake = fake_monitors();
+        fake.add_backlight(&FakeBacklight::new("broken").without("max_brightness"))
+            .unwrap();
+        fake.add_backlight(&FakeBacklight::new("weird").bl_type("hologram"))
+            .unwrap();
+        fake.add_backlight(&FakeBacklight::new("garbage").attribute("brightness", "bright"))
+            .unwrap();
+        fs::create_dir(
+            fake.sysfs()
+                .backlight_dir()
+                .join(OsStr::from_bytes(b"bad\xffname")),
+        )
+        .unwrap();
+
+        let scan = MonitorDevice::scan_monitor_devices_in(&fake.sysfs()).unwrap();
+        assert!(!scan.is_complete());
+        let names: Vec<&str> = scan.devices.iter().map(|m| m.get_device_name()).collect();
+        assert_eq!(names, vec!["acpi_video0", "amdgpu_bl0"]);
+
+        let mut errors: Vec<&str> = scan.errors.iter().map(|(d, _)| d.as_str()).collect();
+        errors.sort_unstable();
+        assert_eq!(
+            errors,
+            vec!["bad\u{fffd}name", "broken", "garbage", "weird"]
+        );
+        for (device, e) in &scan.errors {
+            match (device.as_str(), e) {
+                ("broken", Error::Io(_)) => {}
+                ("garbage", Error::ParseBrightnessError(_)) => {}
+                ("weird", Error::UnknownBacklightType { given, .. }) => {
+                    assert_eq!(given, "hologram")
+                }
+                (_, Error::InvalidDeviceName { .. }) => {}
+                other => panic!("unexpected error: {:?}", other),
+            }
         }
+
+        let monitors = MonitorDevice::get_all_monitor_devices_in(&fake.sysfs()).unwrap();
+        assert_eq!(monitors.len(), 2);
+    }
+
+    #[test]
+    fn scan_missing_class() {
+        let sysfs = Sysfs::new("/does/not/exist");
+        let scan = MonitorDevice::scan_monitor_devices_in(&sysfs).unwrap();
+        assert!(scan.devices.is_empty());
+        assert!(scan.is_complete());
+    }
+
+    #[test]
+    fn device_gone() {
+        let fake = fake_monitors();
+        let mut device =
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("acpi_video0"))
+                .unwrap();
+        fake.write_backlight("acpi_video0", "actual_brightness", "3")
+            .unwrap();
+        assert_eq!(device.read_actual_brightness().unwrap(), 3);
+        assert_eq!(device.read_power().unwrap(), BacklightPower::Powerdown);
+        assert!(device.is_present());
+
+        fake.remove_backlight("acpi_video0").unwrap();
+        assert!(!device.is_present());
+        for res in [
+            device.read_brightness().map(|_| ()),
+            device.set_brightness(1),
+            device.refresh(),
+        ] {
+            match res {
+                Err(Error::DeviceGone { device }) => assert_eq!(device, "acpi_video0"),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        assert_eq!(device.get_actual_brightness(), 6);
+    }
+
+    #[test]
+    fn preferred() {
+        let fake = FakeSysfs::new().unwrap();
+        let rank = |panel, bl_type| BacklightRank { panel, bl_type };
+        assert!(MonitorDevice::preferred_in(&fake.sysfs())
+            .unwrap()
+            .is_none());
+
+        // hybrid laptop with the panel on the Intel card
+        let edp = FakeConnector::new("card0-eDP-1");
+        fake.add_connector(&edp).unwrap();
+        fake.add_connector(
+            &FakeConnector::new("card1-eDP-2")
+                .parent("pci0000:00/0000:00:01.0/0000:01:00.0")
+                .status("disconnected")
+                .enabled(false),
+        )
+        .unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight").device(&edp.devpath()))
+            .unwrap();
+        fake.add_backlight(
+            &FakeBacklight::new("nvidia_0").device("pci0000:00/0000:00:01.0/0000:01:00.0"),
+        )
+        .unwrap();
+        fake.add_backlight(&FakeBacklight::new("ddcci5")).unwrap();
+        let names = |ranked: Vec<(MonitorDevice, BacklightRank)>| {
+            ranked
+                .into_iter()
+                .map(|(monitor, rank)| (monitor.device, rank))
+                .collect::<Vec<(String, BacklightRank)>>()
+        };
+        assert_eq!(
+            names(MonitorDevice::rank_monitor_devices_in(&fake.sysfs()).unwrap()),
+            vec![
+                (
+                    String::from("intel_backlight"),
+                    rank(PanelState::Lit, BackLightType::Raw)
+                ),
+                (
+                    String::from("ddcci5"),
+                    rank(PanelState::Unknown, BackLightType::Raw)
+                ),
+                (
+                    String::from("nvidia_0"),
+                    rank(PanelState::Disconnected, BackLightType::Raw)
+                ),
+            ]
+        );
+
+        // firmware interfaces win over raw ones for the same panel
+        fake.add_backlight(
+            &FakeBacklight::new("acpi_video0")
+                .bl_type("firmware")
+                .device("pci0000:00/0000:00:02.0"),
+        )
+        .unwrap();
+        fake.add_backlight(
+            &FakeBacklight::new("thinkpad_screen")
+                .bl_type("platform")
+                .device("platform/thinkpad_acpi"),
+        )
+        .unwrap();
+        fake.add_device(&FakeDevice::new("platform/samsung").attribute("enabled", "disabled"))
+            .unwrap();
+        fake.add_backlight(
+            &FakeBacklight::new("samsung")
+                .bl_type("firmware")
+                .device("platform/samsung"),
+        )
+        .unwrap();
+        let ranked = names(MonitorDevice::rank_monitor_devices_in(&fake.sysfs()).unwrap());
+        let order: Vec<&str> = ranked.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            order,
+            [
+                "acpi_video0",
+                "thinkpad_screen",
+                "intel_backlight",
+                "ddcci5",
+                "nvidia_0",
+                "samsung"
+            ]
+        );
+        assert_eq!(
+            MonitorDevice::preferred_in(&fake.sysfs())
+                .unwrap()
+                .unwrap()
+                .device,
+            "acpi_video0"
+        );
+
+        // panel switched off, e.g. lid closed with an external monitor
+        fake.write_connector("card0-eDP-1", "enabled", "disabled")
+            .unwrap();
+        assert_eq!(
+            MonitorDevice::preferred_in(&fake.sysfs())
+                .unwrap()
+                .unwrap()
+                .device,
+            "thinkpad_screen"
+        );
+    }
+
+    #[test]
+    fn platform_over_raw() {
+        let fake = FakeSysfs::new().unwrap();
+        let edp = FakeConnector::new("card0-eDP-1");
+        fake.add_connector(&edp).unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight").device(&edp.devpath()))
+            .unwrap();
+        fake.add_backlight(
+            &FakeBacklight::new("dell_backlight")
+                .bl_type("platform")
+                .device("platform/dell-laptop"),
+        )
+        .unwrap();
+        let ranked = MonitorDevice::rank_monitor_devices_in(&fake.sysfs()).unwrap();
+        assert_eq!(ranked[0].0.device, "dell_backlight");
+        assert_eq!(ranked[0].1.panel, PanelState::Unknown);
+        assert_eq!(ranked[1].0.device, "intel_backlight");
+        assert_eq!(ranked[1].1.panel, PanelState::Lit);
+
+        // sysfs reports disconnected connectors as disabled as well
+        fake.write_connector("card0-eDP-1", "status", "disconnected")
+            .unwrap();
+        fake.write_connector("card0-eDP-1", "enabled", "disabled")
+            .unwrap();
+        let monitor =
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("intel_backlight"))
+                .unwrap();
+        assert_eq!(monitor.get_rank().unwrap().panel, PanelState::Disconnected);
+    }
+
+    #[test]
+    fn edid() {
+        let fake = FakeSysfs::new().unwrap();
+        let edp = FakeConnector::new("card0-eDP-1").edid(&crate::edid::sample_edid());
+        fake.add_connector(&edp).unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight").device(&edp.devpath()))
+            .unwrap();
+        fake.add_backlight(&FakeBacklight::new("ddcci5")).unwrap();
+
+        let monitor =
+            MonitorDevice::get_monitor_device_by_serial_in(&fake.sysfs(), "JKNM0C3").unwrap();
+        assert_eq!(monitor.get_device_name(), "intel_backlight");
+        assert_eq!(
+            monitor.get_edid().unwrap().unwrap().to_string(),
+            "Dell U2720Q (27\")"
+        );
+        let ddcci =
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("ddcci5")).unwrap();
+        assert_eq!(ddcci.get_edid().unwrap(), None);
+        assert!(matches!(
+            MonitorDevice::get_monitor_device_by_serial_in(&fake.sysfs(), "nope"),
+            Err(Error::SerialNotFound { serial }) if serial == "nope"
+        ));
     }
 }
@@ -15,4 +15,4 @@ mod linux;
 pub const BACKLIGHT_DIR: &str = "/sys/class/backlight";
 
 #[cfg(target_os = "linux")]
-pub use self::linux::{BackLightType, MonitorDevice};
+pub use self::linux::{BackLightType, BacklightPower, BacklightRank, MonitorDevice, PanelState};
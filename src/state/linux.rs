@@ -0,0 +1,784 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    fmt,
+    fs::{self, File},
+    io::Write,
+    path::Path,
+    sync::Arc,
+};
+
+use super::STATE_VERSION;
+use crate::{
+    backend::BrightnessBackend,
+    brightness,
+    error::Error,
+    misc::{LedDevice, LedTrigger, MulticolorLed},
+    monitor::{BacklightPower, MonitorDevice},
+    sysfs::{DeviceClass, DeviceId, Sysfs},
+};
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Saved state of one light.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct DeviceState {
+    /// Class of the light.
+    pub class: DeviceClass,
+    /// Name of the light in `/sys/class/<class>/`.
+    pub device: String,
+    /// Key of the light's [`DeviceId`], missing in states saved before it
+    /// was recorded.
+    pub id: Option<String>,
+    /// Brightness level.
+    pub brightness: u32,
+    /// Maximum brightness when the state was saved, used to rescale
+    /// `brightness` if the driver changed.
+    pub max_brightness: u32,
+    /// `bl_power` of backlights.
+    pub bl_power: Option<BacklightPower>,
+    /// Active trigger of LEDs.
+    pub trigger: Option<LedTrigger>,
+    /// Channel intensities of multicolor LEDs.
+    pub intensities: Option<Vec<u32>>,
+}
+
+impl DeviceState {
+    /// Current state of monitor.
+    pub fn from_monitor(monitor: &MonitorDevice) -> DeviceState {
+        DeviceState {
+            class: DeviceClass::Backlight,
+            device: monitor.get_device_name().to_string(),
+            id: Some(monitor.get_id().to_string()),
+            brightness: monitor.get_brightness(),
+            max_brightness: monitor.get_max_brightness(),
+            bl_power: Some(monitor.get_power()),
+            trigger: None,
+            intensities: None,
+        }
+    }
+
+    /// Current state of LED.
+    pub fn from_led(led: &LedDevice) -> DeviceState {
+        DeviceState {
+            class: DeviceClass::Leds,
+            device: led.get_device_name().to_string(),
+            id: Some(led.get_id().to_string()),
+            brightness: led.get_brightness(),
+            max_brightness: led.get_max_brightness(),
+            bl_power: None,
+            trigger: led.get_trigger().cloned(),
+            intensities: None,
+        }
+    }
+
+    /// Current state of multicolor LED.
+    pub fn from_multicolor(led: &MulticolorLed) -> DeviceState {
+        DeviceState {
+            intensities: Some(led.get_intensities().to_vec()),
+            ..DeviceState::from_led(led.get_led())
+        }
+    }
+
+    fn parse(line: &str, line_number: usize) -> Result<DeviceState, Error> {
+        let invalid = |reason: String| Error::ParseStateError {
+            line: line_number,
+            reason,
+        };
+        let parse_u32 = |key: &str, value: &str| {
+            value
+                .parse::<u32>()
+                .map_err(|_| invalid(format!("{} is not a number: {:?}", key, value)))
+        };
+
+        let mut fields = line.split('\t');
+        let class = fields.next().unwrap_or_default();
+        let class = DeviceClass::from_id(class)
+            .ok_or_else(|| invalid(format!("unknown class {:?}", class)))?;
+        let device = match fields.next() {
+            Some(device) if !device.is_empty() => device.to_string(),
+            _ => return Err(invalid(String::from("missing device name"))),
+        };
+
+        let mut id = None;
+        let mut brightness = None;
+        let mut max_brightness = None;
+        let mut bl_power = None;
+        let mut trigger = None;
+        let mut intensities = None;
+        for field in fields {
+            let i = field
+                .find('=')
+                .ok_or_else(|| invalid(format!("expected key=value, found {:?}", field)))?;
+            let (key, value) = (&field[..i], &field[i + 1..]);
+            match key {
+                "id" => id = Some(value.to_string()),
+                "brightness" => brightness = Some(parse_u32(key, value)?),
+                "max_brightness" => max_brightness = Some(parse_u32(key, value)?),
+                "bl_power" => bl_power = Some(BacklightPower::from_id(parse_u32(key, value)?)),
+                "trigger" => trigger = Some(LedTrigger::from_id(value)),
+                "multi_intensity" => {
+                    intensities = Some(
+                        value
+                            .split_whitespace()
+                            .map(|intensity| parse_u32(key, intensity))
+                            .collect::<Result<Vec<u32>, Error>>()?,
+                    )
+                }
+                // written by a newer version of this crate
+                _ => (),
+            }
+        }
+
+        Ok(DeviceState {
+            class,
+            device,
+            id,
+            brightness: brightness.ok_or_else(|| invalid(String::from("missing brightness")))?,
+            max_brightness: max_brightness
+                .ok_or_else(|| invalid(String::from("missing max_brightness")))?,
+            bl_power,
+            trigger,
+            intensities,
+        })
+    }
+}
+
+impl fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\tbrightness={}\tmax_brightness={}",
+            self.class, self.device, self.brightness, self.max_brightness
+        )?;
+        if let Some(power) = self.bl_power {
+            write!(f, "\tbl_power={}", power.id())?;
+        }
+        if let Some(trigger) = &self.trigger {
+            write!(f, "\ttrigger={}", trigger)?;
+        }
+        if let Some(intensities) = &self.intensities {
+            let intensities: Vec<String> = intensities.iter().map(u32::to_string).collect();
+            write!(f, "\tmulti_intensity={}", intensities.join(" "))?;
+        }
+        if let Some(id) = &self.id {
+            write!(f, "\tid={}", id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Saved state of every monitor and LED.
+///
+/// Stored as a text file starting with a `version` line followed by one
+/// line per light, its fields separated by tabs (shown as spaces here):
+///
+/// ```text
+/// # bulbb brightness state
+/// version 1
+/// backlight intel_backlight brightness=4800 max_brightness=120000 bl_power=0 id=backlight/pci-0000:00:02.0-drm-eDP-1/intel_backlight
+/// leds rgb:kbd_backlight brightness=255 max_brightness=255 trigger=none multi_intensity=255 128 0 id=leds/rgb:kbd_backlight
+/// ```
+///
+/// Lights are restored to the device with the same [`DeviceId`], so a
+/// saved state follows a light whose name changed between boots. The name
+/// is only used if no device has the saved identity.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::state::{RestoreOptions, State};
+///
+/// // on shutdown
+/// State::snapshot().unwrap().save("/var/lib/bulbb/state").unwrap();
+///
+/// // on boot
+/// let state = State::load("/var/lib/bulbb/state").unwrap();
+/// let restored = state.restore(&RestoreOptions::new()).unwrap();
+/// for (device, err) in restored.errors {
+///     eprintln!("{}: {}", device, err);
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct State {
+    /// Saved lights, backlights first.
+    pub devices: Vec<DeviceState>,
+}
+
+impl State {
+    /// Save the state of every monitor and LED.
+    pub fn snapshot() -> Result<State, Error> {
+        State::snapshot_in(&Sysfs::default())
+    }
+
+    /// Save the state of every monitor and LED in the given sysfs tree.
+    ///
+    /// Devices that cannot be read are left out.
+    pub fn snapshot_in(sysfs: &Sysfs) -> Result<State, Error> {
+        let mut devices: Vec<DeviceState> = MonitorDevice::get_all_monitor_devices_in(sysfs)?
+            .iter()
+            .map(DeviceState::from_monitor)
+            .collect();
+        for led in LedDevice::get_all_led_devices_in(sysfs)? {
+            devices.push(match MulticolorLed::from_led(led.clone()) {
+                Ok(multicolor) => DeviceState::from_multicolor(&multicolor),
+                Err(_) => DeviceState::from_led(&led),
+            });
+        }
+        Ok(State { devices })
+    }
+
+    /// Saved state of `device` in `class`.
+    pub fn get(&self, class: DeviceClass, device: &str) -> Option<&DeviceState> {
+        self.devices
+            .iter()
+            .find(|state| state.class == class && state.device == device)
+    }
+
+    /// Saved state of the light with identity `id`, or `None` if no or
+    /// several saved states have it.
+    pub fn get_by_id(&self, id: &DeviceId) -> Option<&DeviceState> {
+        let mut matches = self
+            .devices
+            .iter()
+            .filter(|state| state.id.as_deref().is_some_and(|key| id.matches_key(key)));
+        match (matches.next(), matches.next()) {
+            (Some(state), None) => Some(state),
+            _ => None,
+        }
+    }
+
+    /// Parse the contents of a state file.
+    ///
+    /// Files written by a newer, incompatible version are rejected.
+    pub fn parse(s: &str) -> Result<State, Error> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+
+        let (line, version) = lines.next().ok_or(Error::ParseStateError {
+            line: 1,
+            reason: String::from("missing version"),
+        })?;
+        let version = version
+            .strip_prefix("version ")
+            .and_then(|v| v.trim().parse::<u32>().ok())
+            .ok_or_else(|| Error::ParseStateError {
+                line,
+                reason: format!("expected version, found {:?}", version),
+            })?;
+        if version == 0 || version > STATE_VERSION {
+            return Err(Error::ParseStateError {
+                line,
+                reason: format!("unsupported version {}", version),
+            });
+        }
+
+        let devices = lines
+            .map(|(line, s)| DeviceState::parse(s, line))
+            .collect::<Result<Vec<DeviceState>, Error>>()?;
+        Ok(State { devices })
+    }
+
+    /// Read state from `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<State, Error> {
+        State::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Write state to `path`, creating its parent directories.
+    ///
+    /// The file is replaced atomically, so a crash never leaves half a state behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(self.to_string().as_bytes())?;
+        // the data must be on disk before the rename is, or a power loss
+        // can leave an empty state behind
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Restore every saved light that still exists.
+    pub fn restore(&self, options: &RestoreOptions) -> Result<Restored, Error> {
+        let mut restored = Restored::default();
+        for state in &self.devices {
+            let res = match state.class {
+                DeviceClass::Backlight => options.restore_monitor(state),
+                DeviceClass::Leds => options.restore_led(state),
+            };
+            match res {
+                Ok(true) => restored.restored.push(state.device.clone()),
+                Ok(false) => restored.missing.push(state.device.clone()),
+                Err(e) => restored.errors.push((state.device.clone(), e)),
+            }
+        }
+        Ok(restored)
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# bulbb brightness state")?;
+        writeln!(f, "version {}", STATE_VERSION)?;
+        for device in &self.devices {
+            writeln!(f, "{}", device)?;
+        }
+        Ok(())
+    }
+}
+
+/// How to restore a [`State`].
+///
+/// Backlights are never restored to 0, below `min_percent` of their
+/// maximum, or to a powered down `bl_power`, so a bad save cannot leave
+/// the panel black.
+#[derive(Debug, Clone)]
+pub struct RestoreOptions {
+    sysfs: Sysfs,
+    min_percent: f64,
+    backend: Option<Arc<dyn BrightnessBackend>>,
+}
+
+impl Default for RestoreOptions {
+    fn default() -> RestoreOptions {
+        RestoreOptions {
+            sysfs: Sysfs::default(),
+            min_percent: 5.0,
+            backend: None,
+        }
+    }
+}
+
+impl RestoreOptions {
+    /// Restore to `/sys` with a floor of 5%, like systemd-backlight.
+    pub fn new() -> RestoreOptions {
+        RestoreOptions::default()
+    }
+
+    /// Restore to the given sysfs tree.
+    pub fn with_sysfs(mut self, sysfs: Sysfs) -> RestoreOptions {
+        self.sysfs = sysfs;
+        self
+    }
+
+    /// Never restore a backlight below `min_percent` of its maximum brightness.
+    ///
+    /// Backlights are always restored to at least 1.
+    pub fn with_min_percent(mut self, min_percent: f64) -> RestoreOptions {
+        self.min_percent = min_percent;
+        self
+    }
+
+    /// Restore using `backend` instead of each device's default backend.
+    pub fn with_backend(mut self, backend: Arc<dyn BrightnessBackend>) -> RestoreOptions {
+        self.backend = Some(backend);
+        self
+    }
+
+    fn backend_for(&self, default: &Arc<dyn BrightnessBackend>) -> Arc<dyn BrightnessBackend> {
+        self.backend.clone().unwrap_or_else(|| default.clone())
+    }
+
+    /// Lowest level a backlight with `max_brightness` is restored to.
+    fn floor(&self, max_brightness: u32) -> u32 {
+        brightness::floor(self.min_percent, max_brightness, false)
+    }
+
+    /// Monitor with the saved identity, or with the saved name if no identity
+    /// was saved.
+    ///
+    /// A device that has since taken the name of one with a saved identity
+    /// is not restored in its place.
+    fn find_monitor(&self, state: &DeviceState) -> Result<Option<MonitorDevice>, Error> {
+        let res = match &state.id {
+            Some(id) => MonitorDevice::get_monitor_device_by_id_in(&self.sysfs, id),
+            None => MonitorDevice::get_monitor_device_in(&self.sysfs, state.device.clone()),
+        };
+        match res {
+            Err(Error::InvalidDeviceName { .. }) => Ok(None),
+            res => res.map(Some),
+        }
+    }
+
+    /// LED with the saved identity, or with the saved name if no identity
+    /// was saved.
+    ///
+    /// A device that has since taken the name of one with a saved identity
+    /// is not restored in its place.
+    fn find_led(&self, state: &DeviceState) -> Result<Option<LedDevice>, Error> {
+        let res = match &state.id {
+            Some(id) => LedDevice::get_led_device_by_id_in(&self.sysfs, id),
+            None => LedDevice::get_led_device_in(&self.sysfs, state.device.clone()),
+        };
+        match res {
+            Err(Error::InvalidDeviceName { .. }) => Ok(None),
+            res => res.map(Some),
+        }
+    }
+
+    fn restore_monitor(&self, state: &DeviceState) -> Result<bool, Error> {
+        let monitor = match self.find_monitor(state)? {
+            Some(monitor) => monitor,
+            None => return Ok(false),
+        };
+        let backend = self.backend_for(monitor.get_backend());
+        let max = monitor.get_max_brightness();
+
+        let level = rescale(state.brightness, state.max_brightness, max).max(self.floor(max));
+        monitor.set_brightness_with(backend.as_ref(), level)?;
+        if let Some(power) = state.bl_power.filter(|power| power.is_on()) {
+            if monitor.get_power() != power {
+                monitor.set_power_with(backend.as_ref(), power)?;
+            }
+        }
+        Ok(true)
+    }
+
+    fn restore_led(&self, state: &DeviceState) -> Result<bool, Error> {
+        let led = match self.find_led(state)? {
+            Some(led) => led,
+            None => return Ok(false),
+        };
+        let backend = self.backend_for(led.get_backend());
+        let max = led.get_max_brightness();
+
+        if let Some(intensities) = &state.intensities {
+            let multicolor = MulticolorLed::from_led(led.clone())?;
+            let intensities: Vec<u32> = intensities
+                .iter()
+                .map(|&i| rescale(i, state.max_brightness, max))
+                .collect();
+            multicolor.set_intensities_with(backend.as_ref(), &intensities)?;
+        }
+
+        // writing brightness 0 clears the trigger and writing a trigger may
+        // change brightness, so the order depends on the saved trigger
+        let level = rescale(state.brightness, state.max_brightness, max);
+        match &state.trigger {
+            Some(LedTrigger::None) => {
+                led.set_trigger_with(backend.as_ref(), &LedTrigger::None)?;
+                led.set_brightness_with(backend.as_ref(), level)?;
+            }
+            Some(trigger) => {
+                led.set_brightness_with(backend.as_ref(), level)?;
+                led.set_trigger_with(backend.as_ref(), trigger)?;
+            }
+            None => led.set_brightness_with(backend.as_ref(), level)?,
+        }
+        Ok(true)
+    }
+}
+
+/// Result of [`State::restore`].
+#[derive(Debug, Default)]
+pub struct Restored {
+    /// Lights that were restored.
+    pub restored: Vec<String>,
+    /// Saved lights that no longer exist.
+    pub missing: Vec<String>,
+    /// Lights that could not be restored and why.
+    pub errors: Vec<(String, Error)>,
+}
+
+/// `level` out of `from_max` as a level out of `to_max`.
+fn rescale(level: u32, from_max: u32, to_max: u32) -> u32 {
+    if from_max == to_max || from_max == 0 {
+        return level.min(to_max);
+    }
+    let level =
+        (u64::from(level) * u64::from(to_max) + u64::from(from_max) / 2) / u64::from(from_max);
+    (level as u32).min(to_max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{FakeBacklight, FakeDevice, FakeLed, FakeSysfs};
+
+    fn fake_tree() -> FakeSysfs {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_backlight(
+            &FakeBacklight::new("intel_backlight")
+                .brightness(4800)
+                .max_brightness(120000),
+        )
+        .unwrap();
+        fake.add_led(
+            &FakeLed::new("input2::capslock")
+                .max_brightness(1)
+                .trigger("none [kbd-capslock]"),
+        )
+        .unwrap();
+        fake.add_led(
+            &FakeLed::new("rgb:kbd_backlight")
+                .brightness(255)
+                .multi_index("red green blue")
+                .multi_intensity("255 128 0")
+                .trigger("[none] heartbeat"),
+        )
+        .unwrap();
+        fake
+    }
+
+    #[test]
+    fn round_trip() {
+        let fake = fake_tree();
+        let state = State::snapshot_in(&fake.sysfs()).unwrap();
+        assert_eq!(state.devices.len(), 3);
+        assert_eq!(
+            state.to_string(),
+            "# bulbb brightness state\n\
+             version 1\n\
+             backlight\tintel_backlight\tbrightness=4800\tmax_brightness=120000\tbl_power=0\tid=backlight/intel_backlight\n\
+             leds\tinput2::capslock\tbrightness=0\tmax_brightness=1\ttrigger=kbd-capslock\tid=leds/input::capslock\n\
+             leds\trgb:kbd_backlight\tbrightness=255\tmax_brightness=255\ttrigger=none\tmulti_intensity=255 128 0\tid=leds/rgb:kbd_backlight\n"
+        );
+        assert_eq!(State::parse(&state.to_string()).unwrap(), state);
+
+        let path = fake.root().join("var/lib/bulbb/state");
+        state.save(&path).unwrap();
+        assert_eq!(State::load(&path).unwrap(), state);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let bad = [
+            ("", 1),
+            ("version one\n", 1),
+            ("# comment\nversion 2\n", 2),
+            ("version 1\nbacklight\n", 2),
+            (
+                "version 1\nsomething\tx\tbrightness=1\tmax_brightness=1\n",
+                2,
+            ),
+            ("version 1\n\nleds\tx\tbrightness=1\n", 3),
+            ("version 1\nleds\tx\tbrightness=-1\tmax_brightness=1\n", 2),
+            ("version 1\nleds\tx\tbrightness\tmax_brightness=1\n", 2),
+        ];
+        for (s, expected) in bad.iter() {
+            match State::parse(s) {
+                Err(Error::ParseStateError { line, .. }) => assert_eq!(line, *expected, "{:?}", s),
+                other => panic!("unexpected result for {:?}: {:?}", s, other),
+            }
+        }
+
+        let state = State::parse("version 1\nleds\tx\tbrightness=1\tmax_brightness=1\tcolor=red\n")
+            .unwrap();
+        assert_eq!(state.get(DeviceClass::Leds, "x").unwrap().brightness, 1);
+    }
+
+    #[test]
+    fn restore() {
+        let fake = fake_tree();
+        let state = State::snapshot_in(&fake.sysfs()).unwrap();
+
+        fake.write_backlight("intel_backlight", "brightness", "100")
+            .unwrap();
+        fake.write_backlight("intel_backlight", "bl_power", "4")
+            .unwrap();
+        fake.write_led("input2::capslock", "trigger", "[none] kbd-capslock")
+            .unwrap();
+        fake.write_led("rgb:kbd_backlight", "multi_intensity", "0 0 255")
+            .unwrap();
+        fake.remove_led("rgb:kbd_backlight").unwrap();
+        fake.add_led(
+            &FakeLed::new("rgb:kbd_backlight")
+                .max_brightness(100)
+                .multi_index("red green blue")
+                .multi_intensity("0 0 100")
+                .trigger("none [heartbeat]"),
+        )
+        .unwrap();
+
+        let restored = state
+            .restore(&RestoreOptions::new().with_sysfs(fake.sysfs()))
+            .unwrap();
+        assert_eq!(restored.restored.len(), 3);
+        assert!(restored.errors.is_empty());
+
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "brightness")
+                .unwrap(),
+            "6000"
+        );
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "bl_power").unwrap(),
+            "0"
+        );
+        assert_eq!(
+            fake.read_led("input2::capslock", "trigger").unwrap(),
+            "kbd-capslock"
+        );
+        assert_eq!(
+            fake.read_led("rgb:kbd_backlight", "multi_intensity")
+                .unwrap(),
+            "100 50 0"
+        );
+        assert_eq!(
+            fake.read_led("rgb:kbd_backlight", "brightness").unwrap(),
+            "100"
+        );
+        assert_eq!(
+            fake.read_led("rgb:kbd_backlight", "trigger").unwrap(),
+            "none"
+        );
+    }
+
+    #[test]
+    fn restore_never_black() {
+        let fake = fake_tree();
+        let state = State::parse(
+            "version 1\n\
+             backlight\tintel_backlight\tbrightness=0\tmax_brightness=120000\tbl_power=4\n\
+             backlight\tacpi_video0\tbrightness=3\tmax_brightness=15\n",
+        )
+        .unwrap();
+
+        let restored = state
+            .restore(
+                &RestoreOptions::new()
+                    .with_sysfs(fake.sysfs())
+                    .with_min_percent(1.0),
+            )
+            .unwrap();
+        assert_eq!(restored.restored, vec![String::from("intel_backlight")]);
+        assert_eq!(restored.missing, vec![String::from("acpi_video0")]);
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "brightness")
+                .unwrap(),
+            "1200"
+        );
+        assert_eq!(
+            fake.read_backlight("intel_backlight", "bl_power").unwrap(),
+            "0"
+        );
+    }
+
+    #[test]
+    fn restore_by_id() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_led(
+            &FakeLed::new("input2::capslock")
+                .max_brightness(1)
+                .device("pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/input/input2"),
+        )
+        .unwrap();
+        fake.add_led(
+            &FakeLed::new("input3::capslock")
+                .max_brightness(1)
+                .device("pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/input/input3"),
+        )
+        .unwrap();
+        // the keyboards were probed in the other order on the previous boot
+        let state = State::parse(
+            "version 1\n\
+             leds\tinput3::capslock\tbrightness=1\tmax_brightness=1\tid=leds/pci-0000:00:14.0-usb-1-2:1.0/input::capslock\n\
+             leds\tinput9::numlock\tbrightness=1\tmax_brightness=1\tid=leds/pci-0000:00:14.0-usb-1-2:1.0/input::numlock\n\
+             leds\tinput2::capslock\tbrightness=0\tmax_brightness=1\tid=leds/pci-0000:00:14.0-usb-1-4:1.0/input::capslock\n",
+        )
+        .unwrap();
+        let led =
+            LedDevice::get_led_device_in(&fake.sysfs(), String::from("input2::capslock")).unwrap();
+        assert_eq!(
+            state.get_by_id(led.get_id()).unwrap().device,
+            "input3::capslock"
+        );
+
+        let restored = state
+            .restore(&RestoreOptions::new().with_sysfs(fake.sysfs()))
+            .unwrap();
+        assert_eq!(restored.restored, vec![String::from("input3::capslock")]);
+        // a keyboard that now has the name of an unplugged one is not it
+        assert_eq!(
+            restored.missing,
+            vec![
+                String::from("input9::numlock"),
+                String::from("input2::capslock")
+            ]
+        );
+        assert_eq!(
+            fake.read_led("input2::capslock", "brightness").unwrap(),
+            "1"
+        );
+        assert_eq!(
+            fake.read_led("input3::capslock", "brightness").unwrap(),
+            "0"
+        );
+    }
+
+    #[test]
+    fn restore_twins() {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_device(&FakeDevice::new("pci0000:00/0000:00:02.0").subsystem("pci"))
+            .unwrap();
+        for (name, brightness) in [("acpi_video0", 3), ("acpi_video1", 12)] {
+            fake.add_backlight(
+                &FakeBacklight::new(name)
+                    .brightness(brightness)
+                    .max_brightness(15)
+                    .device("pci0000:00/0000:00:02.0"),
+            )
+            .unwrap();
+        }
+        let state = State::snapshot_in(&fake.sysfs()).unwrap();
+        fake.write_backlight("acpi_video0", "brightness", "15")
+            .unwrap();
+        fake.write_backlight("acpi_video1", "brightness", "15")
+            .unwrap();
+
+        let restored = state
+            .restore(&RestoreOptions::new().with_sysfs(fake.sysfs()))
+            .unwrap();
+        assert_eq!(restored.restored.len(), 2);
+        assert_eq!(
+            fake.read_backlight("acpi_video0", "brightness").unwrap(),
+            "3"
+        );
+        assert_eq!(
+            fake.read_backlight("acpi_video1", "brightness").unwrap(),
+            "12"
+        );
+
+        let state = State::parse(
+            "version 1\n\
+             backlight\tacpi_video0\tbrightness=3\tmax_brightness=15\tid=backlight/pci-0000:00:02.0/acpi_video0\n\
+             backlight\tacpi_video9\tbrightness=9\tmax_brightness=15\tid=backlight/pci-0000:00:02.0/acpi_video0\n",
+        )
+        .unwrap();
+        let monitor =
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("acpi_video0"))
+                .unwrap();
+        assert_eq!(state.get_by_id(monitor.get_id()), None);
+    }
+
+    #[test]
+    fn floor() {
+        let options = RestoreOptions::new().with_min_percent(0.0);
+        assert_eq!(options.floor(15), 1);
+        assert_eq!(options.floor(0), 0);
+        let options = RestoreOptions::new().with_min_percent(10.0);
+        assert_eq!(options.floor(15), 2);
+        assert_eq!(options.floor(1), 1);
+        let options = RestoreOptions::new().with_min_percent(f64::NAN);
+        assert_eq!(options.floor(255), 1);
+    }
+}
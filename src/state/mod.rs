@@ -0,0 +1,17 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+#[cfg(target_os = "linux")]
+mod linux;
+
+/// Version of the state file format written by this crate.
+pub const STATE_VERSION: u32 = 1;
+
+#[cfg(target_os = "linux")]
+pub use self::linux::{DeviceState, RestoreOptions, Restored, State};
@@ -0,0 +1,474 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use super::{DeviceClass, Sysfs};
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// Identity of a light that survives reboots and kernel upgrades.
+///
+/// Sysfs names like `acpi_video0` or `input13::capslock` depend on probe
+/// order, so the identity is built from where the light's parent sits in
+/// the device tree (PCI address, USB port, HID device, DRM connector)
+/// instead, much like udev's `ID_PATH`. Lights that would still share a key
+/// keep their sysfs name, so the key of such a light depends on whether its
+/// twin is present; [`matches_key`](DeviceId::matches_key) accepts both forms.
+///
+/// Its [`Display`](fmt::Display) form is the key to persist, e.g.
+/// `leds/pci-0000:00:14.0-usb-1-2:1.0-hid-0003:046D:C31C/input::capslock`.
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::monitor::MonitorDevice;
+///
+/// for monitor in MonitorDevice::get_all_monitor_devices().unwrap() {
+///     let id = monitor.get_id();
+///     println!("{}: {} (driver {:?})", monitor.get_device_name(), id, id.driver);
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct DeviceId {
+    /// Class of the light.
+    pub class: DeviceClass,
+    /// Stable path of the parent device, e.g. `pci-0000:00:02.0-drm-eDP-1`,
+    /// empty for lights without a parent.
+    ///
+    /// HID devices are named by bus, vendor and product, e.g.
+    /// `usb-1-2:1.2-hid-0003:046D:4024`, so keyboards behind one receiver
+    /// are told apart.
+    pub path: String,
+    /// Name of the light with probe order numbers removed, e.g.
+    /// `input::capslock` for `input13::capslock`, or the full name if
+    /// another light with the same path would get the same one.
+    pub function: String,
+    /// Sysfs name of the light, e.g. `acpi_video0`.
+    pub name: String,
+    /// Parent device the `device` symlink points to.
+    pub parent: Option<PathBuf>,
+    /// Subsystem of the parent device, e.g. `pci`, `drm`, `input` or `platform`.
+    pub subsystem: Option<String>,
+    /// Driver bound to the parent device or its closest ancestor with one.
+    pub driver: Option<String>,
+    /// Address of the closest PCI device, e.g. `0000:00:02.0`.
+    pub pci_address: Option<String>,
+    /// Port path of the closest USB device or interface, e.g. `1-2:1.0`.
+    pub usb_path: Option<String>,
+    /// DRM connector, e.g. `card0-eDP-1`.
+    pub drm_connector: Option<String>,
+}
+
+impl DeviceId {
+    /// Resolve the identity of `device` in `class`.
+    ///
+    /// Lights without a readable `device` symlink get an identity made of
+    /// their class and name only.
+    pub fn resolve(sysfs: &Sysfs, class: DeviceClass, device: &str) -> DeviceId {
+        let mut id = DeviceId::resolve_stripped(sysfs, class, device);
+        if id.function != device && has_twin(sysfs, &id, device) {
+            id.function = device.to_string();
+        }
+        id
+    }
+
+    /// Identity of `device` with the probe order number always removed.
+    fn resolve_stripped(sysfs: &Sysfs, class: DeviceClass, device: &str) -> DeviceId {
+        let mut id = DeviceId {
+            class,
+            path: String::new(),
+            function: stable_name(class, device),
+            name: device.to_string(),
+            parent: None,
+            subsystem: None,
+            driver: None,
+            pci_address: None,
+            usb_path: None,
+            drm_connector: None,
+        };
+
+        let parent = match fs::canonicalize(sysfs.device_dir(class, device).join("device")) {
+            Ok(parent) => parent,
+            Err(_) => return id,
+        };
+        let devices = fs::canonicalize(sysfs.devices_dir()).unwrap_or_else(|_| sysfs.devices_dir());
+
+        if let Ok(devpath) = parent.strip_prefix(&devices) {
+            let mut path: Vec<String> = Vec::new();
+            let mut previous = "";
+            for component in devpath.iter().filter_map(|c| c.to_str()) {
+                if is_pci_address(component) {
+                    id.pci_address = Some(component.to_string());
+                    path.push(format!("pci-{}", component));
+                } else if is_usb_port(component) {
+                    id.usb_path = Some(component.to_string());
+                    // only the deepest port or interface of a USB chain is kept
+                    if path.last().is_some_and(|p| p.starts_with("usb-")) {
+                        path.pop();
+                    }
+                    path.push(format!("usb-{}", component));
+                } else if let Some(hid) = hid_device(component) {
+                    path.push(format!("hid-{}", hid));
+                } else if let Some(connector) = drm_connector(component) {
+                    id.drm_connector = Some(component.to_string());
+                    path.push(format!("drm-{}", connector));
+                } else if previous == "platform" {
+                    path.push(format!("platform-{}", component));
+                }
+                previous = component;
+            }
+            id.path = path.join("-");
+        }
+
+        id.subsystem = link_name(&parent.join("subsystem"));
+        id.driver = parent
+            .ancestors()
+            .take_while(|dir| dir.starts_with(&devices) && *dir != devices)
+            .find_map(|dir| link_name(&dir.join("driver")));
+        id.parent = Some(parent);
+        id
+    }
+
+    /// Whether `key` is the [`Display`](fmt::Display) form of this identity,
+    /// with or without the probe order number.
+    ///
+    /// A key saved while the light had a twin keeps matching once the twin
+    /// is gone and the other way around, so lookups by a key without the
+    /// number are ambiguous while both twins are present.
+    pub fn matches_key(&self, key: &str) -> bool {
+        self.key_with(&self.function) == key
+            || self.key_with(&stable_name(self.class, &self.name)) == key
+            || self.key_with(&self.name) == key
+    }
+
+    fn key_with(&self, function: &str) -> String {
+        if self.path.is_empty() {
+            format!("{}/{}", self.class, function)
+        } else {
+            format!("{}/{}/{}", self.class, self.path, function)
+        }
+    }
+}
+
+impl fmt::Display for DeviceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.key_with(&self.function))
+    }
+}
+
+/// Whether another light of the class gets the same key as `id` of `device`.
+fn has_twin(sysfs: &Sysfs, id: &DeviceId, device: &str) -> bool {
+    let entries = match fs::read_dir(sysfs.class_dir(id.class)) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name != device && stable_name(id.class, name) == id.function)
+        .any(|name| DeviceId::resolve_stripped(sysfs, id.class, &name).path == id.path)
+}
+
+/// Name of the file a symlink points to.
+fn link_name(link: &Path) -> Option<String> {
+    fs::read_link(link)
+        .ok()?
+        .file_name()?
+        .to_str()
+        .map(str::to_string)
+}
+
+/// Name with the probe order number removed, from an LED's device part
+/// (`input13::capslock` becomes `input::capslock`) or from the end of a
+/// backlight's (`acpi_video1` becomes `acpi_video`).
+///
+/// Only a number following a letter is removed, so `nvidia_0` is kept.
+fn stable_name(class: DeviceClass, name: &str) -> String {
+    match (class, name.find(':')) {
+        (DeviceClass::Backlight, _) => strip_number(name).to_string(),
+        (DeviceClass::Leds, Some(i)) => {
+            let (device, rest) = name.split_at(i);
+            format!("{}{}", strip_number(device), rest)
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// `s` without the number at its end if that follows a letter.
+fn strip_number(s: &str) -> &str {
+    let stripped = s.trim_end_matches(|c: char| c.is_ascii_digit());
+    if stripped.len() < s.len() && stripped.ends_with(|c: char| c.is_ascii_alphabetic()) {
+        stripped
+    } else {
+        s
+    }
+}
+
+/// `dddd:bb:dd.f`
+fn is_pci_address(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() == 12
+        && b[4] == b':'
+        && b[7] == b':'
+        && b[10] == b'.'
+        && b.iter()
+            .enumerate()
+            .filter(|(i, _)| ![4, 7, 10].contains(i))
+            .all(|(_, c)| c.is_ascii_hexdigit())
+}
+
+/// `<bus>-<port>[.<port>...][:<config>.<interface>]`, e.g. `1-2`, `3-1.4:1.0`
+fn is_usb_port(s: &str) -> bool {
+    let (port, interface) = match s.find(':') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|c| c.is_ascii_digit());
+    let port_ok = match port.find('-') {
+        Some(i) => digits(&port[..i]) && port[i + 1..].split('.').all(digits),
+        None => false,
+    };
+    let interface_ok = interface.map_or(true, |interface| {
+        let mut parts = interface.split('.');
+        parts.next().is_some_and(digits)
+            && parts.next().is_some_and(digits)
+            && parts.next().is_none()
+    });
+    port_ok && interface_ok
+}
+
+/// `<bus>:<vendor>:<product>` part of a HID device `bbbb:vvvv:pppp.iiii`,
+/// whose instance number depends on probe order.
+fn hid_device(s: &str) -> Option<&str> {
+    let b = s.as_bytes();
+    let ok = b.len() == 19
+        && b[4] == b':'
+        && b[9] == b':'
+        && b[14] == b'.'
+        && b.iter()
+            .enumerate()
+            .filter(|(i, _)| ![4, 9, 14].contains(i))
+            .all(|(_, c)| c.is_ascii_hexdigit());
+    if ok {
+        Some(&s[..14])
+    } else {
+        None
+    }
+}
+
+/// Connector part of `card<N>-<connector>`, e.g. `eDP-1`.
+pub(crate) fn drm_connector(s: &str) -> Option<&str> {
+    let rest = s.strip_prefix("card")?;
+    let i = rest.find('-')?;
+    if i > 0 && rest[..i].bytes().all(|c| c.is_ascii_digit()) {
+        Some(&rest[i + 1..])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{FakeBacklight, FakeDevice, FakeLed, FakeSysfs};
+
+    #[test]
+    fn path_components() {
+        assert!(is_pci_address("0000:00:02.0"));
+        assert!(is_pci_address("0000:3b:00.1"));
+        assert!(!is_pci_address("pci0000:00"));
+        assert!(!is_pci_address("0000:00:02"));
+        assert!(is_usb_port("1-2"));
+        assert!(is_usb_port("3-1.4.2"));
+        assert!(is_usb_port("1-2:1.0"));
+        assert_eq!(hid_device("0003:046D:C31C.0004"), Some("0003:046D:C31C"));
+        assert_eq!(hid_device("0003:046D:C31C"), None);
+        assert!(!is_usb_port("usb1"));
+        assert!(!is_usb_port("1-2:1"));
+        assert!(!is_usb_port("card0-eDP-1"));
+        assert_eq!(drm_connector("card0-eDP-1"), Some("eDP-1"));
+        assert_eq!(drm_connector("card12-HDMI-A-1"), Some("HDMI-A-1"));
+        assert_eq!(drm_connector("card0"), None);
+        assert_eq!(drm_connector("cardreader-1"), None);
+    }
+
+    #[test]
+    fn stable_names() {
+        assert_eq!(
+            stable_name(DeviceClass::Leds, "input13::capslock"),
+            "input::capslock"
+        );
+        assert_eq!(stable_name(DeviceClass::Leds, "mmc0::"), "mmc::");
+        assert_eq!(
+            stable_name(DeviceClass::Leds, "asus::kbd_backlight"),
+            "asus::kbd_backlight"
+        );
+        assert_eq!(stable_name(DeviceClass::Leds, "phy0-led"), "phy0-led");
+        assert_eq!(
+            stable_name(DeviceClass::Backlight, "acpi_video0"),
+            "acpi_video"
+        );
+        assert_eq!(
+            stable_name(DeviceClass::Backlight, "acpi_video1"),
+            "acpi_video"
+        );
+        assert_eq!(
+            stable_name(DeviceClass::Backlight, "intel_backlight"),
+            "intel_backlight"
+        );
+        assert_eq!(stable_name(DeviceClass::Backlight, "nvidia_0"), "nvidia_0");
+        assert_eq!(stable_name(DeviceClass::Leds, "led_1::"), "led_1::");
+    }
+
+    #[test]
+    fn twins() {
+        let fake = FakeSysfs::new().unwrap();
+        let sysfs = fake.sysfs();
+        fake.add_device(&FakeDevice::new("pci0000:00/0000:00:02.0").subsystem("pci"))
+            .unwrap();
+        for name in ["acpi_video0", "acpi_video1"] {
+            fake.add_backlight(&FakeBacklight::new(name).device("pci0000:00/0000:00:02.0"))
+                .unwrap();
+        }
+        let receiver = "pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.2";
+        let leds = [
+            ("input5::capslock", "0003:046D:4024.0005/input/input5"),
+            ("input6::capslock", "0003:046D:4023.0006/input/input6"),
+            ("input7::capslock", "0003:046D:4023.0007/input/input7"),
+        ];
+        for (name, device) in leds {
+            let device = format!("{}/{}", receiver, device);
+            fake.add_device(&FakeDevice::new(&device).subsystem("input"))
+                .unwrap();
+            fake.add_led(&FakeLed::new(name).device(&device)).unwrap();
+        }
+
+        let key = |class, device| DeviceId::resolve(&sysfs, class, device).to_string();
+        assert_eq!(
+            key(DeviceClass::Backlight, "acpi_video0"),
+            "backlight/pci-0000:00:02.0/acpi_video0"
+        );
+        assert_eq!(
+            key(DeviceClass::Backlight, "acpi_video1"),
+            "backlight/pci-0000:00:02.0/acpi_video1"
+        );
+        assert_eq!(
+            key(DeviceClass::Leds, "input5::capslock"),
+            "leds/pci-0000:00:14.0-usb-1-2:1.2-hid-0003:046D:4024/input::capslock"
+        );
+        // two keyboards of the same model behind one receiver
+        assert_eq!(
+            key(DeviceClass::Leds, "input6::capslock"),
+            "leds/pci-0000:00:14.0-usb-1-2:1.2-hid-0003:046D:4023/input6::capslock"
+        );
+        assert_eq!(
+            key(DeviceClass::Leds, "input7::capslock"),
+            "leds/pci-0000:00:14.0-usb-1-2:1.2-hid-0003:046D:4023/input7::capslock"
+        );
+
+        // keys saved with or without a twin keep matching
+        let id = DeviceId::resolve(&sysfs, DeviceClass::Backlight, "acpi_video0");
+        assert!(id.matches_key("backlight/pci-0000:00:02.0/acpi_video"));
+        fake.remove_backlight("acpi_video1").unwrap();
+        let id = DeviceId::resolve(&sysfs, DeviceClass::Backlight, "acpi_video0");
+        assert_eq!(id.to_string(), "backlight/pci-0000:00:02.0/acpi_video");
+        assert!(id.matches_key("backlight/pci-0000:00:02.0/acpi_video0"));
+        assert!(!id.matches_key("backlight/pci-0000:00:02.0/acpi_video1"));
+    }
+
+    #[test]
+    fn resolve() {
+        let fake = FakeSysfs::new().unwrap();
+        let sysfs = fake.sysfs();
+        fake.add_device(
+            &FakeDevice::new("pci0000:00/0000:00:02.0")
+                .subsystem("pci")
+                .driver("i915"),
+        )
+        .unwrap();
+        fake.add_device(
+            &FakeDevice::new("pci0000:00/0000:00:02.0/drm/card0/card0-eDP-1").subsystem("drm"),
+        )
+        .unwrap();
+        fake.add_backlight(
+            &FakeBacklight::new("intel_backlight")
+                .device("pci0000:00/0000:00:02.0/drm/card0/card0-eDP-1"),
+        )
+        .unwrap();
+        fake.add_device(
+            &FakeDevice::new(
+                "pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C31C.0004/input/input13",
+            )
+            .subsystem("input"),
+        )
+        .unwrap();
+        fake.add_device(
+            &FakeDevice::new("pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0")
+                .subsystem("usb")
+                .driver("usbhid"),
+        )
+        .unwrap();
+        fake.add_led(
+            &FakeLed::new("input13::capslock").device(
+                "pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C31C.0004/input/input13",
+            ),
+        )
+        .unwrap();
+        fake.add_device(
+            &FakeDevice::new("platform/asus-nb-wmi")
+                .subsystem("platform")
+                .driver("asus-nb-wmi"),
+        )
+        .unwrap();
+        fake.add_led(&FakeLed::new("asus::kbd_backlight").device("platform/asus-nb-wmi"))
+            .unwrap();
+        fake.add_led(&FakeLed::new("phy0-led")).unwrap();
+
+        let id = DeviceId::resolve(&sysfs, DeviceClass::Backlight, "intel_backlight");
+        assert_eq!(
+            id.to_string(),
+            "backlight/pci-0000:00:02.0-drm-eDP-1/intel_backlight"
+        );
+        assert_eq!(id.subsystem.as_deref(), Some("drm"));
+        assert_eq!(id.driver.as_deref(), Some("i915"));
+        assert_eq!(id.pci_address.as_deref(), Some("0000:00:02.0"));
+        assert_eq!(id.drm_connector.as_deref(), Some("card0-eDP-1"));
+        assert!(id.parent.unwrap().ends_with("card0-eDP-1"));
+
+        let id = DeviceId::resolve(&sysfs, DeviceClass::Leds, "input13::capslock");
+        assert_eq!(
+            id.to_string(),
+            "leds/pci-0000:00:14.0-usb-1-2:1.0-hid-0003:046D:C31C/input::capslock"
+        );
+        assert_eq!(id.subsystem.as_deref(), Some("input"));
+        assert_eq!(id.driver.as_deref(), Some("usbhid"));
+        assert_eq!(id.usb_path.as_deref(), Some("1-2:1.0"));
+        assert!(
+            id.matches_key("leds/pci-0000:00:14.0-usb-1-2:1.0-hid-0003:046D:C31C/input::capslock")
+        );
+
+        let id = DeviceId::resolve(&sysfs, DeviceClass::Leds, "asus::kbd_backlight");
+        assert_eq!(
+            id.to_string(),
+            "leds/platform-asus-nb-wmi/asus::kbd_backlight"
+        );
+        assert_eq!(id.driver.as_deref(), Some("asus-nb-wmi"));
+
+        let id = DeviceId::resolve(&sysfs, DeviceClass::Leds, "phy0-led");
+        assert_eq!(id.to_string(), "leds/phy0-led");
+        assert_eq!(id.parent, None);
+        assert_eq!(id.driver, None);
+    }
+}
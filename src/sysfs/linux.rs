@@ -0,0 +1,255 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use super::SYSFS_DIR;
+use crate::error::Error;
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+/// Location of the sysfs tree devices are read from and written to.
+///
+/// Defaults to `/sys`, but can point anywhere that mirrors its layout
+/// (a chroot, a container bind-mount or a test fixture).
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::{monitor::MonitorDevice, sysfs::Sysfs};
+///
+/// let sysfs = Sysfs::new("/does/not/exist");
+/// let monitors = MonitorDevice::get_all_monitor_devices_in(&sysfs).unwrap();
+/// assert!(monitors.is_empty());
+/// ```
+pub struct Sysfs {
+    root: PathBuf,
+}
+
+impl Sysfs {
+    /// Use the sysfs tree mounted at `root`.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Sysfs {
+        Sysfs { root: root.into() }
+    }
+
+    /// Root of the sysfs tree.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Directory containing all backlight devices, `<root>/class/backlight`.
+    pub fn backlight_dir(&self) -> PathBuf {
+        self.root.join("class").join("backlight")
+    }
+
+    /// Directory containing all LED devices, `<root>/class/leds`.
+    pub fn leds_dir(&self) -> PathBuf {
+        self.root.join("class").join("leds")
+    }
+
+    /// Directory of the backlight `device`.
+    pub fn backlight_device(&self, device: &str) -> PathBuf {
+        self.backlight_dir().join(device)
+    }
+
+    /// Directory of the LED `device`.
+    pub fn led_device(&self, device: &str) -> PathBuf {
+        self.leds_dir().join(device)
+    }
+
+    /// Directory containing all devices of `class`.
+    pub fn class_dir(&self, class: DeviceClass) -> PathBuf {
+        match class {
+            DeviceClass::Backlight => self.backlight_dir(),
+            DeviceClass::Leds => self.leds_dir(),
+        }
+    }
+
+    /// Directory of `device` in `class`.
+    pub fn device_dir(&self, class: DeviceClass, device: &str) -> PathBuf {
+        self.class_dir(class).join(device)
+    }
+
+    /// Directory containing all DRM cards and connectors, `<root>/class/drm`.
+    pub fn drm_dir(&self) -> PathBuf {
+        self.root.join("class").join("drm")
+    }
+
+    /// Directory containing all raw HID devices, `<root>/class/hidraw`.
+    pub fn hidraw_dir(&self) -> PathBuf {
+        self.root.join("class").join("hidraw")
+    }
+
+    /// Directory containing all Industrial I/O devices, `<root>/bus/iio/devices`.
+    pub fn iio_dir(&self) -> PathBuf {
+        self.root.join("bus").join("iio").join("devices")
+    }
+
+    /// Root of the device tree the class directories link into, `<root>/devices`.
+    pub fn devices_dir(&self) -> PathBuf {
+        self.root.join("devices")
+    }
+}
+
+/// Result of enumerating a device class.
+///
+/// Devices that could be read are in `devices`; every device that could not
+/// be read is in `errors` together with its name, so one broken driver does
+/// not hide the rest.
+#[derive(Debug)]
+pub struct Scan<T> {
+    /// Devices that were read successfully, sorted by name.
+    pub devices: Vec<T>,
+    /// Name of each device that could not be read and why.
+    pub errors: Vec<(String, Error)>,
+}
+
+impl<T> Scan<T> {
+    /// Whether every device was read successfully.
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl<T> Default for Scan<T> {
+    fn default() -> Scan<T> {
+        Scan {
+            devices: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+/// Read every device in `dir` with `read`, which returns `None` for devices
+/// to skip.
+///
+/// A missing `dir` yields an empty scan; only failing to list an existing
+/// `dir` is an error.
+pub(crate) fn scan_dir<T, F>(dir: &Path, mut read: F) -> Result<Scan<T>, Error>
+where
+    F: FnMut(String) -> Option<Result<T, Error>>,
+{
+    let mut scan = Scan::default();
+    if !dir.is_dir() {
+        return Ok(scan);
+    }
+
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        match entry {
+            Ok(entry) => match entry.file_name().into_string() {
+                Ok(name) => names.push(name),
+                Err(name) => {
+                    let device = name.to_string_lossy().into_owned();
+                    scan.errors
+                        .push((device.clone(), Error::InvalidDeviceName { device }));
+                }
+            },
+            Err(e) => scan.errors.push((dir.display().to_string(), Error::Io(e))),
+        }
+    }
+    names.sort();
+
+    for name in names {
+        match read(name.clone()) {
+            Some(Ok(device)) => scan.devices.push(device),
+            Some(Err(e)) => scan.errors.push((name, e)),
+            None => {}
+        }
+    }
+    Ok(scan)
+}
+
+/// Device class a light belongs to, `/sys/class/<class>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum DeviceClass {
+    /// `/sys/class/backlight`
+    Backlight,
+    /// `/sys/class/leds`
+    Leds,
+}
+
+impl DeviceClass {
+    /// Trys to parse str into DeviceClass.
+    pub fn from_id(s: &str) -> Option<Self> {
+        match s {
+            "backlight" => Some(DeviceClass::Backlight),
+            "leds" => Some(DeviceClass::Leds),
+            _ => None,
+        }
+    }
+}
+
+impl From<DeviceClass> for &str {
+    fn from(val: DeviceClass) -> &'static str {
+        match val {
+            DeviceClass::Backlight => "backlight",
+            DeviceClass::Leds => "leds",
+        }
+    }
+}
+
+impl fmt::Display for DeviceClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", <&str>::from(*self))
+    }
+}
+
+impl Default for Sysfs {
+    fn default() -> Sysfs {
+        Sysfs::new(SYSFS_DIR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{misc::LEDS_DIR, monitor::BACKLIGHT_DIR};
+
+    #[test]
+    fn default_matches_constants() {
+        let sysfs = Sysfs::default();
+        assert_eq!(sysfs.backlight_dir(), Path::new(BACKLIGHT_DIR));
+        assert_eq!(sysfs.leds_dir(), Path::new(LEDS_DIR));
+    }
+
+    #[test]
+    fn custom_root() {
+        let sysfs = Sysfs::new("/tmp/fake");
+        assert_eq!(
+            sysfs.backlight_device("acpi_video0"),
+            Path::new("/tmp/fake/class/backlight/acpi_video0")
+        );
+        assert_eq!(
+            sysfs.led_device("input2::capslock"),
+            Path::new("/tmp/fake/class/leds/input2::capslock")
+        );
+        assert_eq!(
+            sysfs.device_dir(DeviceClass::Backlight, "acpi_video0"),
+            sysfs.backlight_device("acpi_video0")
+        );
+        assert_eq!(sysfs.class_dir(DeviceClass::Leds), sysfs.leds_dir());
+    }
+
+    #[test]
+    fn device_class_round_trip() {
+        for class in [DeviceClass::Backlight, DeviceClass::Leds].iter() {
+            assert_eq!(DeviceClass::from_id(&class.to_string()), Some(*class));
+        }
+        assert_eq!(DeviceClass::from_id("drm"), None);
+    }
+}
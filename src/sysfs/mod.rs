@@ -0,0 +1,27 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+#[cfg(target_os = "linux")]
+mod id;
+#[cfg(target_os = "linux")]
+mod linux;
+
+#[cfg(target_os = "linux")]
+/// Default mount point of sysfs.
+pub const SYSFS_DIR: &str = "/sys";
+
+#[cfg(target_os = "linux")]
+pub use self::id::DeviceId;
+#[cfg(target_os = "linux")]
+pub use self::linux::{DeviceClass, Scan, Sysfs};
+
+#[cfg(target_os = "linux")]
+pub(crate) use self::id::drm_connector;
+#[cfg(target_os = "linux")]
+pub(crate) use self::linux::scan_dir;
@@ -0,0 +1,198 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use crate::ddc::{I2cBus, DDC_ADDRESS, VCP_LUMINANCE, VCP_POWER_MODE};
+
+/// Request a read answers.
+#[derive(Debug, Clone, Copy)]
+enum Pending {
+    Vcp(u8),
+    Capabilities(u16),
+}
+
+#[derive(Debug, Default)]
+struct Display {
+    features: BTreeMap<u8, (u16, u16)>,
+    capabilities: Vec<u8>,
+    pending: Option<Pending>,
+    busy: u32,
+    corrupt: u32,
+    requests: usize,
+}
+
+/// An in-memory display answering DDC/CI, for testing [`Ddc`](crate::ddc::Ddc)
+/// without hardware.
+///
+/// Clones share the same display, so a test can keep one to inspect what
+/// was written through another.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::{
+///     ddc::{Ddc, DdcTiming, VCP_LUMINANCE},
+///     testing::FakeDdcDisplay,
+/// };
+///
+/// let display = FakeDdcDisplay::new();
+/// let mut ddc = Ddc::new(display.clone()).with_timing(DdcTiming::immediate());
+/// ddc.set_vcp(VCP_LUMINANCE, 20).unwrap();
+/// assert_eq!(display.get(VCP_LUMINANCE), Some(20));
+/// ```
+#[derive(Debug, Clone)]
+pub struct FakeDdcDisplay {
+    display: Arc<Mutex<Display>>,
+}
+
+impl FakeDdcDisplay {
+    /// Create a display that is on with a luminance of 50 out of 100.
+    pub fn new() -> FakeDdcDisplay {
+        FakeDdcDisplay {
+            display: Arc::new(Mutex::new(Display::default())),
+        }
+        .feature(VCP_LUMINANCE, 50, 100)
+        .feature(VCP_POWER_MODE, 1, 5)
+    }
+
+    /// Support VCP feature `code` with a value of `current` out of `max`.
+    pub fn feature(self, code: u8, current: u16, max: u16) -> FakeDdcDisplay {
+        self.lock().features.insert(code, (max, current));
+        self
+    }
+
+    /// Stop supporting VCP feature `code`.
+    pub fn without(self, code: u8) -> FakeDdcDisplay {
+        self.lock().features.remove(&code);
+        self
+    }
+
+    /// Report `capabilities` as capabilities string.
+    pub fn capabilities(self, capabilities: &str) -> FakeDdcDisplay {
+        self.lock().capabilities = capabilities.as_bytes().to_vec();
+        self
+    }
+
+    /// Current value of VCP feature `code`.
+    pub fn get(&self, code: u8) -> Option<u16> {
+        self.lock().features.get(&code).map(|&(_, current)| current)
+    }
+
+    /// Change VCP feature `code`, as if by the display's own buttons.
+    pub fn set(&self, code: u8, current: u16) {
+        if let Some(feature) = self.lock().features.get_mut(&code) {
+            feature.1 = current;
+        }
+    }
+
+    /// Answer the next `n` reads with a null message, as a busy display does.
+    pub fn busy(&self, n: u32) {
+        self.lock().busy = n;
+    }
+
+    /// Garble the checksum of the next `n` replies.
+    pub fn corrupt(&self, n: u32) {
+        self.lock().corrupt = n;
+    }
+
+    /// Number of requests written so far.
+    pub fn requests(&self) -> usize {
+        self.lock().requests
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Display> {
+        self.display.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for FakeDdcDisplay {
+    fn default() -> FakeDdcDisplay {
+        FakeDdcDisplay::new()
+    }
+}
+
+fn checksum(seed: u8, bytes: &[u8]) -> u8 {
+    bytes.iter().fold(seed, |sum, b| sum ^ b)
+}
+
+impl I2cBus for FakeDdcDisplay {
+    fn write(&mut self, address: u16, data: &[u8]) -> io::Result<()> {
+        if address != DDC_ADDRESS {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no device at address",
+            ));
+        }
+        let mut display = self.lock();
+        display.requests += 1;
+        let len = data.get(1).map_or(0, |len| usize::from(len & 0x7F));
+        if data.len() != len + 3 || checksum(0x6E, &data[..len + 2]) != data[len + 2] {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad request"));
+        }
+        match &data[2..len + 2] {
+            [0x01, code] => display.pending = Some(Pending::Vcp(*code)),
+            [0xF3, high, low] => {
+                display.pending = Some(Pending::Capabilities(u16::from_be_bytes([*high, *low])))
+            }
+            [0x03, code, high, low] => {
+                if let Some(feature) = display.features.get_mut(code) {
+                    feature.1 = u16::from_be_bytes([*high, *low]);
+                }
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "bad request")),
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, address: u16, buf: &mut [u8]) -> io::Result<()> {
+        if address != DDC_ADDRESS {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no device at address",
+            ));
+        }
+        let mut display = self.lock();
+        let mut reply = vec![0x6E, 0x80];
+        match display.pending.take() {
+            Some(Pending::Vcp(code)) if display.busy == 0 => {
+                let (result, (max, current)) = match display.features.get(&code) {
+                    Some(&feature) => (0, feature),
+                    None => (1, (0, 0)),
+                };
+                reply[1] |= 8;
+                reply.extend_from_slice(&[0x02, result, code, 0x00]);
+                reply.extend_from_slice(&max.to_be_bytes());
+                reply.extend_from_slice(&current.to_be_bytes());
+            }
+            Some(Pending::Capabilities(offset)) if display.busy == 0 => {
+                let start = usize::from(offset).min(display.capabilities.len());
+                let end = (start + 32).min(display.capabilities.len());
+                reply[1] |= (3 + end - start) as u8;
+                reply.push(0xE3);
+                reply.extend_from_slice(&offset.to_be_bytes());
+                reply.extend_from_slice(&display.capabilities[start..end]);
+            }
+            _ => display.busy = display.busy.saturating_sub(1),
+        }
+        let mut sum = checksum(0x50, &reply);
+        if display.corrupt > 0 {
+            display.corrupt -= 1;
+            sum ^= 0xFF;
+        }
+        reply.push(sum);
+        reply.resize(buf.len().max(reply.len()), 0);
+        buf.copy_from_slice(&reply[..buf.len()]);
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    collections::BTreeMap,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    error::Error,
+    hid::{HidBus, HidField, ReportDescriptor, HID_BRIGHTNESS, HID_CONTRAST, HID_VESA_PAGE},
+};
+
+/// Descriptor of a display with brightness and contrast controls, laid out
+/// like those of Apple and LG monitors.
+const MONITOR_DESCRIPTOR: [u8; 50] = [
+    0x05, 0x80, // Usage Page (Monitor)
+    0x09, 0x01, // Usage (Monitor Control)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x01, //   Report ID (1)
+    0x05, 0x82, //   Usage Page (VESA Virtual Controls)
+    0x09, 0x10, //   Usage (Brightness)
+    0x16, 0x90, 0x01, //   Logical Minimum (400)
+    0x26, 0x60, 0xEA, //   Logical Maximum (60000), sign bit not accounted for
+    0x75, 0x20, //   Report Size (32)
+    0x95, 0x01, //   Report Count (1)
+    0xB1, 0x02, //   Feature (Data, Variable, Absolute)
+    0x85, 0x02, //   Report ID (2)
+    0x75, 0x04, //   Report Size (4)
+    0xB1, 0x03, //   Feature (Constant) padding
+    0x09, 0x12, //   Usage (Contrast)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x64, //   Logical Maximum (100)
+    0x75, 0x08, //   Report Size (8)
+    0xB1, 0x02, //   Feature (Data, Variable, Absolute)
+    0x09, 0xD6, //   Usage (Power Mode)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0xC0, // End Collection
+    0x06, 0x00, 0xFF, // Usage Page (Vendor)
+    0x09, 0x01, // Usage (1)
+];
+
+#[derive(Debug, Default)]
+struct Display {
+    descriptor: ReportDescriptor,
+    reports: BTreeMap<u8, Vec<u8>>,
+    requests: usize,
+}
+
+/// An in-memory display offering brightness and contrast over USB HID, for
+/// testing [`HidMonitor`](crate::hid::HidMonitor) without hardware.
+///
+/// Brightness ranges from 400 to 60000 in report 1, contrast from 0 to 100
+/// in report 2, as described by its report descriptor. Reports are framed
+/// like hidraw does, the report ID in their first byte, `0` for displays
+/// that number none. Clones share the same display.
+#[derive(Debug, Clone)]
+pub struct FakeHidDisplay {
+    display: Arc<Mutex<Display>>,
+}
+
+impl FakeHidDisplay {
+    /// Create a display with a brightness of 30000 and a contrast of 50.
+    pub fn new() -> FakeHidDisplay {
+        let display = FakeHidDisplay::with_descriptor(&FakeHidDisplay::descriptor())
+            .expect("fake HID descriptor is valid");
+        display.set(HID_VESA_PAGE, HID_BRIGHTNESS, 30000);
+        display.set(HID_VESA_PAGE, HID_CONTRAST, 50);
+        display
+    }
+
+    /// Create a display described by `descriptor`, every value 0.
+    pub fn with_descriptor(descriptor: &[u8]) -> Result<FakeHidDisplay, Error> {
+        let descriptor = ReportDescriptor::parse(descriptor)?;
+        let mut reports = BTreeMap::new();
+        for field in descriptor.features.iter() {
+            reports
+                .entry(field.report_id)
+                .or_insert_with(|| field.new_report());
+        }
+        Ok(FakeHidDisplay {
+            display: Arc::new(Mutex::new(Display {
+                descriptor,
+                reports,
+                requests: 0,
+            })),
+        })
+    }
+
+    /// Report descriptor of the display.
+    pub fn descriptor() -> Vec<u8> {
+        MONITOR_DESCRIPTOR.to_vec()
+    }
+
+    /// Current raw value of the feature with `usage` on `usage_page`.
+    pub fn get(&self, usage_page: u16, usage: u16) -> Option<u32> {
+        let display = self.lock();
+        let field = display.descriptor.find_feature(usage_page, usage)?;
+        field.extract(display.reports.get(&field.report_id)?)
+    }
+
+    /// Change the feature with `usage` on `usage_page`, as if by the
+    /// display's own buttons.
+    pub fn set(&self, usage_page: u16, usage: u16, value: u32) {
+        let mut display = self.lock();
+        let field: HidField = match display.descriptor.find_feature(usage_page, usage) {
+            Some(field) => *field,
+            None => return,
+        };
+        if let Some(report) = display.reports.get_mut(&field.report_id) {
+            field.insert(report, value);
+        }
+    }
+
+    /// Number of feature reports exchanged so far.
+    pub fn requests(&self) -> usize {
+        self.lock().requests
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Display> {
+        self.display.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for FakeHidDisplay {
+    fn default() -> FakeHidDisplay {
+        FakeHidDisplay::new()
+    }
+}
+
+impl HidBus for FakeHidDisplay {
+    fn get_feature(&mut self, report: &mut [u8]) -> io::Result<usize> {
+        let mut display = self.lock();
+        display.requests += 1;
+        let stored = report
+            .first()
+            .and_then(|id| display.reports.get(id))
+            .ok_or_else(|| io::Error::from_raw_os_error(libc::EPIPE))?;
+        let len = stored.len().min(report.len());
+        report[..len].copy_from_slice(&stored[..len]);
+        Ok(len)
+    }
+
+    fn set_feature(&mut self, report: &[u8]) -> io::Result<()> {
+        let mut display = self.lock();
+        display.requests += 1;
+        let id = report.first().copied().unwrap_or(0);
+        match display.reports.get_mut(&id) {
+            Some(stored) if stored.len() == report.len() => {
+                stored.copy_from_slice(report);
+                Ok(())
+            }
+            _ => Err(io::Error::from_raw_os_error(libc::EPIPE)),
+        }
+    }
+}
@@ -0,0 +1,584 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    env, fs, io,
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::sysfs::Sysfs;
+
+static FIXTURE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A temporary sysfs tree for tests.
+///
+/// The tree is created under the system temporary directory and removed
+/// when the `FakeSysfs` is dropped.
+///
+/// # Examples
+///
+/// ```
+/// use bulbb::{
+///     monitor::MonitorDevice,
+///     testing::{FakeBacklight, FakeSysfs},
+/// };
+///
+/// let fake = FakeSysfs::new().unwrap();
+/// fake.add_backlight(&FakeBacklight::new("intel_backlight").brightness(5).max_brightness(10))
+///     .unwrap();
+///
+/// let monitor =
+///     MonitorDevice::get_monitor_device_in(&fake.sysfs(), format!("intel_backlight")).unwrap();
+/// assert_eq!(monitor.get_brightness(), 5);
+/// assert_eq!(monitor.get_max_brightness(), 10);
+/// ```
+#[derive(Debug)]
+pub struct FakeSysfs {
+    root: PathBuf,
+}
+
+impl FakeSysfs {
+    /// Create an empty sysfs tree with `class/backlight` and `class/leds`.
+    pub fn new() -> io::Result<FakeSysfs> {
+        let root = env::temp_dir().join(format!(
+            "bulbb-{}-{}",
+            process::id(),
+            FIXTURE_COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let fake = FakeSysfs { root };
+        let sysfs = fake.sysfs();
+        fs::create_dir_all(sysfs.backlight_dir())?;
+        fs::create_dir_all(sysfs.leds_dir())?;
+        Ok(fake)
+    }
+
+    /// Root of the tree.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// [`Sysfs`] pointing at this tree.
+    pub fn sysfs(&self) -> Sysfs {
+        Sysfs::new(&self.root)
+    }
+
+    /// Add a backlight device, returning its directory.
+    pub fn add_backlight(&self, backlight: &FakeBacklight) -> io::Result<PathBuf> {
+        let dir = self.sysfs().backlight_device(&backlight.name);
+        write_attributes(&dir, &backlight.attributes)?;
+        self.link_parent(&dir, backlight.device.as_deref())?;
+        Ok(dir)
+    }
+
+    /// Add an LED device, returning its directory.
+    pub fn add_led(&self, led: &FakeLed) -> io::Result<PathBuf> {
+        let dir = self.sysfs().led_device(&led.name);
+        write_attributes(&dir, &led.attributes)?;
+        self.link_parent(&dir, led.device.as_deref())?;
+        Ok(dir)
+    }
+
+    /// Add a device under `<root>/devices`, returning its directory.
+    ///
+    /// Devices may be added before or after the lights that link to them.
+    pub fn add_device(&self, device: &FakeDevice) -> io::Result<PathBuf> {
+        let dir = self.sysfs().devices_dir().join(&device.devpath);
+        write_attributes(&dir, &device.attributes)?;
+        if let Some(subsystem) = &device.subsystem {
+            let bus = self.root.join("bus").join(subsystem);
+            fs::create_dir_all(&bus)?;
+            replace_symlink(&bus, &dir.join("subsystem"))?;
+            if let Some(driver) = &device.driver {
+                let driver = bus.join("drivers").join(driver);
+                fs::create_dir_all(&driver)?;
+                replace_symlink(&driver, &dir.join("driver"))?;
+            }
+        }
+        Ok(dir)
+    }
+
+    /// Add a DRM connector under its card's device and link it into
+    /// `class/drm`, returning its directory.
+    pub fn add_connector(&self, connector: &FakeConnector) -> io::Result<PathBuf> {
+        let dir = self.sysfs().devices_dir().join(connector.devpath());
+        write_attributes(&dir, &connector.attributes)?;
+        if let Some(edid) = &connector.edid {
+            fs::write(dir.join("edid"), edid)?;
+        }
+        if let Some(bus) = &connector.ddc {
+            let bus = self.sysfs().devices_dir().join(&connector.parent).join(bus);
+            fs::create_dir_all(&bus)?;
+            replace_symlink(&bus, &dir.join("ddc"))?;
+        }
+        let drm = self.sysfs().drm_dir();
+        fs::create_dir_all(&drm)?;
+        replace_symlink(&dir, &drm.join(&connector.name))?;
+        Ok(dir)
+    }
+
+    /// Overwrite an attribute of a DRM connector.
+    pub fn write_connector(&self, name: &str, attribute: &str, value: &str) -> io::Result<()> {
+        fs::write(self.sysfs().drm_dir().join(name).join(attribute), value)
+    }
+
+    /// Remove a DRM connector, as if its card was unbound.
+    pub fn remove_connector(&self, name: &str) -> io::Result<()> {
+        let link = self.sysfs().drm_dir().join(name);
+        fs::remove_dir_all(fs::canonicalize(&link)?)?;
+        fs::remove_file(link)
+    }
+
+    /// Add an ambient light sensor under `bus/iio/devices`, returning its
+    /// directory.
+    pub fn add_light_sensor(&self, sensor: &FakeLightSensor) -> io::Result<PathBuf> {
+        let dir = self.sysfs().iio_dir().join(&sensor.name);
+        write_attributes(&dir, &sensor.attributes)?;
+        Ok(dir)
+    }
+
+    /// Overwrite an attribute of an ambient light sensor.
+    pub fn write_light_sensor(&self, device: &str, attribute: &str, value: &str) -> io::Result<()> {
+        fs::write(self.sysfs().iio_dir().join(device).join(attribute), value)
+    }
+
+    /// Add a raw HID device whose HID device sits at `<root>/devices/<devpath>`
+    /// with report descriptor `descriptor`, returning its directory.
+    pub fn add_hidraw(&self, name: &str, devpath: &str, descriptor: &[u8]) -> io::Result<PathBuf> {
+        let parent = self.sysfs().devices_dir().join(devpath);
+        let dir = parent.join("hidraw").join(name);
+        fs::create_dir_all(&dir)?;
+        fs::write(parent.join("report_descriptor"), descriptor)?;
+        replace_symlink(&parent, &dir.join("device"))?;
+        let hidraw = self.sysfs().hidraw_dir();
+        fs::create_dir_all(&hidraw)?;
+        replace_symlink(&dir, &hidraw.join(name))?;
+        Ok(dir)
+    }
+
+    /// Point the `device` symlink of a light at `<root>/devices/<devpath>`.
+    fn link_parent(&self, dir: &Path, devpath: Option<&str>) -> io::Result<()> {
+        if let Some(devpath) = devpath {
+            let parent = self.sysfs().devices_dir().join(devpath);
+            fs::create_dir_all(&parent)?;
+            replace_symlink(&parent, &dir.join("device"))?;
+        }
+        Ok(())
+    }
+
+    /// Remove a backlight device, as if it was unplugged.
+    pub fn remove_backlight(&self, device: &str) -> io::Result<()> {
+        fs::remove_dir_all(self.sysfs().backlight_device(device))
+    }
+
+    /// Remove an LED device, as if it was unplugged.
+    pub fn remove_led(&self, device: &str) -> io::Result<()> {
+        fs::remove_dir_all(self.sysfs().led_device(device))
+    }
+
+    /// Read an attribute of a backlight device.
+    pub fn read_backlight(&self, device: &str, attribute: &str) -> io::Result<String> {
+        fs::read_to_string(self.sysfs().backlight_device(device).join(attribute))
+            .map(|s| s.trim().to_string())
+    }
+
+    /// Read an attribute of an LED device.
+    pub fn read_led(&self, device: &str, attribute: &str) -> io::Result<String> {
+        fs::read_to_string(self.sysfs().led_device(device).join(attribute))
+            .map(|s| s.trim().to_string())
+    }
+
+    /// Overwrite an attribute of a backlight device.
+    pub fn write_backlight(&self, device: &str, attribute: &str, value: &str) -> io::Result<()> {
+        fs::write(self.sysfs().backlight_device(device).join(attribute), value)
+    }
+
+    /// Overwrite an attribute of an LED device.
+    pub fn write_led(&self, device: &str, attribute: &str, value: &str) -> io::Result<()> {
+        fs::write(self.sysfs().led_device(device).join(attribute), value)
+    }
+}
+
+impl Drop for FakeSysfs {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+fn write_attributes(dir: &Path, attributes: &[(String, Option<String>)]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (attribute, value) in attributes {
+        if let Some(value) = value {
+            fs::write(dir.join(attribute), format!("{}\n", value))?;
+        }
+    }
+    Ok(())
+}
+
+fn replace_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    match fs::remove_file(link) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+    symlink(target, link)
+}
+
+fn set_attribute(
+    attributes: &mut Vec<(String, Option<String>)>,
+    name: &str,
+    value: Option<String>,
+) {
+    match attributes.iter_mut().find(|(n, _)| n == name) {
+        Some(attribute) => attribute.1 = value,
+        None => attributes.push((name.to_string(), value)),
+    }
+}
+
+/// Backlight device to add to a [`FakeSysfs`].
+///
+/// Defaults to a `raw` device at full brightness with a `max_brightness` of 255.
+#[derive(Debug, Clone)]
+pub struct FakeBacklight {
+    name: String,
+    device: Option<String>,
+    attributes: Vec<(String, Option<String>)>,
+}
+
+impl FakeBacklight {
+    /// Create a backlight device called `name`.
+    pub fn new(name: &str) -> FakeBacklight {
+        FakeBacklight {
+            name: name.to_string(),
+            device: None,
+            attributes: vec![
+                (String::from("bl_power"), Some(String::from("0"))),
+                (String::from("brightness"), Some(String::from("255"))),
+                (String::from("actual_brightness"), Some(String::from("255"))),
+                (String::from("max_brightness"), Some(String::from("255"))),
+                (String::from("type"), Some(String::from("raw"))),
+            ],
+        }
+    }
+
+    /// Set `brightness` and `actual_brightness`.
+    pub fn brightness(self, brightness: u32) -> FakeBacklight {
+        self.attribute("brightness", &brightness.to_string())
+            .attribute("actual_brightness", &brightness.to_string())
+    }
+
+    /// Set `actual_brightness` only.
+    pub fn actual_brightness(self, actual_brightness: u32) -> FakeBacklight {
+        self.attribute("actual_brightness", &actual_brightness.to_string())
+    }
+
+    /// Set `max_brightness`.
+    pub fn max_brightness(self, max_brightness: u32) -> FakeBacklight {
+        self.attribute("max_brightness", &max_brightness.to_string())
+    }
+
+    /// Set `bl_power`.
+    pub fn bl_power(self, bl_power: u32) -> FakeBacklight {
+        self.attribute("bl_power", &bl_power.to_string())
+    }
+
+    /// Set `type` (`firmware`, `platform` or `raw`).
+    pub fn bl_type(self, bl_type: &str) -> FakeBacklight {
+        self.attribute("type", bl_type)
+    }
+
+    /// Link `device` to the parent `<root>/devices/<devpath>`, e.g.
+    /// `pci0000:00/0000:00:02.0`. The parent directory is created if it was
+    /// not added with [`FakeSysfs::add_device`].
+    pub fn device(mut self, devpath: &str) -> FakeBacklight {
+        self.device = Some(devpath.to_string());
+        self
+    }
+
+    /// Set an arbitrary attribute file.
+    pub fn attribute(mut self, name: &str, value: &str) -> FakeBacklight {
+        set_attribute(&mut self.attributes, name, Some(value.to_string()));
+        self
+    }
+
+    /// Do not create the attribute file `name`.
+    pub fn without(mut self, name: &str) -> FakeBacklight {
+        set_attribute(&mut self.attributes, name, None);
+        self
+    }
+}
+
+/// LED device to add to a [`FakeSysfs`].
+///
+/// Defaults to an LED that is off with a `max_brightness` of 255.
+#[derive(Debug, Clone)]
+pub struct FakeLed {
+    name: String,
+    device: Option<String>,
+    attributes: Vec<(String, Option<String>)>,
+}
+
+impl FakeLed {
+    /// Create an LED device called `name`.
+    pub fn new(name: &str) -> FakeLed {
+        FakeLed {
+            name: name.to_string(),
+            device: None,
+            attributes: vec![
+                (String::from("brightness"), Some(String::from("0"))),
+                (String::from("max_brightness"), Some(String::from("255"))),
+            ],
+        }
+    }
+
+    /// Set `brightness`.
+    pub fn brightness(self, brightness: u32) -> FakeLed {
+        self.attribute("brightness", &brightness.to_string())
+    }
+
+    /// Set `max_brightness`.
+    pub fn max_brightness(self, max_brightness: u32) -> FakeLed {
+        self.attribute("max_brightness", &max_brightness.to_string())
+    }
+
+    /// Set `trigger`, e.g. `"none [kbd-capslock] heartbeat"`.
+    pub fn trigger(self, trigger: &str) -> FakeLed {
+        self.attribute("trigger", trigger)
+    }
+
+    /// Set `multi_index`, e.g. `"red green blue"`.
+    pub fn multi_index(self, multi_index: &str) -> FakeLed {
+        self.attribute("multi_index", multi_index)
+    }
+
+    /// Set `multi_intensity`, e.g. `"255 128 0"`.
+    pub fn multi_intensity(self, multi_intensity: &str) -> FakeLed {
+        self.attribute("multi_intensity", multi_intensity)
+    }
+
+    /// Link `device` to the parent `<root>/devices/<devpath>`, e.g.
+    /// `pci0000:00/0000:00:02.0`. The parent directory is created if it was
+    /// not added with [`FakeSysfs::add_device`].
+    pub fn device(mut self, devpath: &str) -> FakeLed {
+        self.device = Some(devpath.to_string());
+        self
+    }
+
+    /// Set an arbitrary attribute file.
+    pub fn attribute(mut self, name: &str, value: &str) -> FakeLed {
+        set_attribute(&mut self.attributes, name, Some(value.to_string()));
+        self
+    }
+
+    /// Do not create the attribute file `name`.
+    pub fn without(mut self, name: &str) -> FakeLed {
+        set_attribute(&mut self.attributes, name, None);
+        self
+    }
+}
+
+/// Parent device to add to a [`FakeSysfs`], see [`FakeBacklight::device`]
+/// and [`FakeLed::device`].
+#[derive(Debug, Clone)]
+pub struct FakeDevice {
+    devpath: String,
+    subsystem: Option<String>,
+    driver: Option<String>,
+    attributes: Vec<(String, Option<String>)>,
+}
+
+impl FakeDevice {
+    /// Create a device at `<root>/devices/<devpath>`, e.g. `pci0000:00/0000:00:02.0`.
+    pub fn new(devpath: &str) -> FakeDevice {
+        FakeDevice {
+            devpath: devpath.to_string(),
+            subsystem: None,
+            driver: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Link `subsystem` to `<root>/bus/<subsystem>`.
+    pub fn subsystem(mut self, subsystem: &str) -> FakeDevice {
+        self.subsystem = Some(subsystem.to_string());
+        self
+    }
+
+    /// Link `driver` to `<root>/bus/<subsystem>/drivers/<driver>`, only
+    /// created if the subsystem is set.
+    pub fn driver(mut self, driver: &str) -> FakeDevice {
+        self.driver = Some(driver.to_string());
+        self
+    }
+
+    /// Set an arbitrary attribute file.
+    pub fn attribute(mut self, name: &str, value: &str) -> FakeDevice {
+        set_attribute(&mut self.attributes, name, Some(value.to_string()));
+        self
+    }
+}
+
+/// DRM connector to add to a [`FakeSysfs`].
+///
+/// Defaults to a connected and enabled connector of the graphics card at
+/// `pci0000:00/0000:00:02.0`.
+#[derive(Debug, Clone)]
+pub struct FakeConnector {
+    name: String,
+    parent: String,
+    ddc: Option<String>,
+    edid: Option<Vec<u8>>,
+    attributes: Vec<(String, Option<String>)>,
+}
+
+impl FakeConnector {
+    /// Create a connector called `name`, e.g. `card0-eDP-1`.
+    pub fn new(name: &str) -> FakeConnector {
+        FakeConnector {
+            name: name.to_string(),
+            parent: String::from("pci0000:00/0000:00:02.0"),
+            ddc: None,
+            edid: None,
+            attributes: vec![
+                (String::from("status"), Some(String::from("connected"))),
+                (String::from("enabled"), Some(String::from("enabled"))),
+                (String::from("dpms"), Some(String::from("On"))),
+            ],
+        }
+    }
+
+    /// Set the graphics card the connector belongs to, e.g.
+    /// `pci0000:00/0000:00:01.0/0000:01:00.0`.
+    pub fn parent(mut self, devpath: &str) -> FakeConnector {
+        self.parent = devpath.to_string();
+        self
+    }
+
+    /// Link `ddc` to the I2C bus `bus`, e.g. `i2c-4`, of the card.
+    pub fn ddc(mut self, bus: &str) -> FakeConnector {
+        self.ddc = Some(bus.to_string());
+        self
+    }
+
+    /// Set the binary `edid` attribute.
+    pub fn edid(mut self, edid: &[u8]) -> FakeConnector {
+        self.edid = Some(edid.to_vec());
+        self
+    }
+
+    /// Set `status` (`connected`, `disconnected` or `unknown`).
+    pub fn status(self, status: &str) -> FakeConnector {
+        self.attribute("status", status)
+    }
+
+    /// Set `enabled`.
+    pub fn enabled(self, enabled: bool) -> FakeConnector {
+        self.attribute("enabled", if enabled { "enabled" } else { "disabled" })
+    }
+
+    /// Set an arbitrary attribute file.
+    pub fn attribute(mut self, name: &str, value: &str) -> FakeConnector {
+        set_attribute(&mut self.attributes, name, Some(value.to_string()));
+        self
+    }
+
+    /// Location of the connector below `<root>/devices`, to pass to
+    /// [`FakeBacklight::device`].
+    pub fn devpath(&self) -> String {
+        let card = self.name.split('-').next().unwrap_or_default();
+        format!("{}/drm/{}/{}", self.parent, card, self.name)
+    }
+}
+
+/// Ambient light sensor to add to a [`FakeSysfs`].
+///
+/// Defaults to a sensor called `als` reading a raw value of 0 without
+/// scale or offset.
+#[derive(Debug, Clone)]
+pub struct FakeLightSensor {
+    name: String,
+    attributes: Vec<(String, Option<String>)>,
+}
+
+impl FakeLightSensor {
+    /// Create a sensor device called `name`, e.g. `iio:device0`.
+    pub fn new(name: &str) -> FakeLightSensor {
+        FakeLightSensor {
+            name: name.to_string(),
+            attributes: vec![
+                (String::from("name"), Some(String::from("als"))),
+                (String::from("in_illuminance_raw"), Some(String::from("0"))),
+            ],
+        }
+    }
+
+    /// Set `in_illuminance_raw`.
+    pub fn raw(self, raw: u32) -> FakeLightSensor {
+        self.attribute("in_illuminance_raw", &raw.to_string())
+    }
+
+    /// Set `in_illuminance_input` (lux) instead of `in_illuminance_raw`.
+    pub fn input(self, lux: &str) -> FakeLightSensor {
+        self.without("in_illuminance_raw")
+            .attribute("in_illuminance_input", lux)
+    }
+
+    /// Set `in_illuminance_scale`.
+    pub fn scale(self, scale: &str) -> FakeLightSensor {
+        self.attribute("in_illuminance_scale", scale)
+    }
+
+    /// Set `in_illuminance_offset`.
+    pub fn offset(self, offset: &str) -> FakeLightSensor {
+        self.attribute("in_illuminance_offset", offset)
+    }
+
+    /// Set an arbitrary attribute file.
+    pub fn attribute(mut self, name: &str, value: &str) -> FakeLightSensor {
+        set_attribute(&mut self.attributes, name, Some(value.to_string()));
+        self
+    }
+
+    /// Do not create the attribute file `name`.
+    pub fn without(mut self, name: &str) -> FakeLightSensor {
+        set_attribute(&mut self.attributes, name, None);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_and_removes_tree() {
+        let fake = FakeSysfs::new().unwrap();
+        let root = fake.root().to_path_buf();
+        fake.add_backlight(&FakeBacklight::new("acpi_video0").bl_power(4))
+            .unwrap();
+        fake.add_led(
+            &FakeLed::new("rgb:kbd_backlight")
+                .multi_index("red green blue")
+                .without("brightness"),
+        )
+        .unwrap();
+
+        assert_eq!(fake.read_backlight("acpi_video0", "bl_power").unwrap(), "4");
+        assert_eq!(fake.read_backlight("acpi_video0", "type").unwrap(), "raw");
+        assert_eq!(
+            fake.read_led("rgb:kbd_backlight", "multi_index").unwrap(),
+            "red green blue"
+        );
+        assert!(fake.read_led("rgb:kbd_backlight", "brightness").is_err());
+
+        drop(fake);
+        assert!(!root.exists());
+    }
+}
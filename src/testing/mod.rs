@@ -0,0 +1,23 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+mod ddc;
+#[cfg(target_os = "linux")]
+mod hid;
+#[cfg(target_os = "linux")]
+mod linux;
+
+pub use self::ddc::FakeDdcDisplay;
+#[cfg(target_os = "linux")]
+pub use self::hid::FakeHidDisplay;
+
+#[cfg(target_os = "linux")]
+pub use self::linux::{
+    FakeBacklight, FakeConnector, FakeDevice, FakeLed, FakeLightSensor, FakeSysfs,
+};
@@ -8,15 +8,14 @@ option. This file may not be copied, modified, or distributed
 except according to those terms.
 */
 use std::{
-    fs::{self},
-    path::PathBuf,
+    fs::{self, OpenOptions},
+    io::{self, prelude::*},
+    path::Path,
 };
 
-use crate::{
-    error::Error,
-    misc::{LedDevice, LEDS_DIR},
-    monitor::{MonitorDevice, BACKLIGHT_DIR},
-};
+use crate::{error::Error, sysfs::Sysfs};
+#[cfg(test)]
+use crate::{misc::LedDevice, monitor::MonitorDevice};
 
 pub enum SysBacklightInterface {
     Power,
@@ -24,44 +23,87 @@ pub enum SysBacklightInterface {
     ActualBrightness,
     MaxBrightness,
     Type,
+    Trigger,
+    MultiIndex,
+    MultiIntensity,
 }
 
-pub fn read_sys_backlight(device: &str, info: SysBacklightInterface) -> Result<String, Error> {
-    let mut path = PathBuf::new();
-    path.push(BACKLIGHT_DIR);
-    path.push(device);
+pub fn read_sys_backlight(
+    sysfs: &Sysfs,
+    device: &str,
+    info: SysBacklightInterface,
+) -> Result<String, Error> {
+    let mut path = sysfs.backlight_device(device);
     match info {
         SysBacklightInterface::Power => path.push("bl_power"),
         SysBacklightInterface::Brightness => path.push("brightness"),
         SysBacklightInterface::ActualBrightness => path.push("actual_brightness"),
         SysBacklightInterface::MaxBrightness => path.push("max_brightness"),
         SysBacklightInterface::Type => path.push("type"),
+        SysBacklightInterface::Trigger => path.push("trigger"),
+        SysBacklightInterface::MultiIndex => path.push("multi_index"),
+        SysBacklightInterface::MultiIntensity => path.push("multi_intensity"),
     }
 
-    match fs::read_to_string(path) {
+    match fs::read_to_string(&path) {
         Ok(s) => Ok(s.trim().to_string()),
-        Err(e) => Err(Error::Io(e)),
+        Err(e) => Err(device_error(&path, e)),
     }
 }
 
-pub fn read_sys_led(device: &str, info: SysBacklightInterface) -> Result<String, Error> {
-    let mut path = PathBuf::new();
-    path.push(LEDS_DIR);
-    path.push(device);
+pub fn read_sys_led(
+    sysfs: &Sysfs,
+    device: &str,
+    info: SysBacklightInterface,
+) -> Result<String, Error> {
+    let mut path = sysfs.led_device(device);
     match info {
         SysBacklightInterface::Power => path.push("bl_power"),
         SysBacklightInterface::Brightness => path.push("brightness"),
         SysBacklightInterface::ActualBrightness => path.push("actual_brightness"),
         SysBacklightInterface::MaxBrightness => path.push("max_brightness"),
         SysBacklightInterface::Type => path.push("type"),
+        SysBacklightInterface::Trigger => path.push("trigger"),
+        SysBacklightInterface::MultiIndex => path.push("multi_index"),
+        SysBacklightInterface::MultiIntensity => path.push("multi_intensity"),
     }
 
-    match fs::read_to_string(path) {
+    match fs::read_to_string(&path) {
         Ok(s) => Ok(s.trim().to_string()),
-        Err(e) => Err(Error::Io(e)),
+        Err(e) => Err(device_error(&path, e)),
+    }
+}
+
+pub fn write_sys(path: &Path, value: &str) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .map_err(|e| device_error(path, e))?;
+    match file.write_all(value.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(device_error(path, e)),
+    }
+}
+
+/// Turn an error accessing the attribute at `path` into
+/// [`Error::DeviceGone`] if the device it belongs to has been removed.
+///
+/// Removed devices fail with `ENODEV` on files that were already open, or
+/// `ENOENT` once their directory is gone.
+pub fn device_error(path: &Path, e: io::Error) -> Error {
+    let dir = path.parent();
+    let gone = e.raw_os_error() == Some(libc::ENODEV)
+        || (e.kind() == io::ErrorKind::NotFound && dir.is_some_and(|dir| !dir.exists()));
+    match dir.and_then(Path::file_name) {
+        Some(device) if gone => Error::DeviceGone {
+            device: device.to_string_lossy().into_owned(),
+        },
+        _ => Error::Io(e),
     }
 }
 
+#[cfg(test)]
 pub fn format_monitor_device(bl: MonitorDevice) {
     println!(
         "
@@ -82,6 +124,7 @@ Brightness
     );
 }
 
+#[cfg(test)]
 pub fn format_led_device(led: LedDevice) {
     let led_c = if let Some(c) = led.info.color {
         c.to_string()
@@ -93,6 +136,11 @@ pub fn format_led_device(led: LedDevice) {
     } else {
         String::from("")
     };
+    let led_t = if let Some(t) = led.trigger {
+        t.to_string()
+    } else {
+        String::from("")
+    };
 
     println!(
         "
@@ -101,6 +149,7 @@ Info
 \tDevice Name: {}
 \tColor: {}
 \tFunction: {}
+\tTrigger: {}
 Brightness
 \tMax: {}
 \tCurrent: {}
@@ -109,6 +158,7 @@ Brightness
         led.info.device_name.unwrap_or_else(|| String::from("")),
         led_c,
         led_f,
+        led_t,
         led.max_brightness,
         led.brightness
     );
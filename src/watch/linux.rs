@@ -0,0 +1,512 @@
+/*
+Copyright 2021 David Karrick
+
+Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+<LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+option. This file may not be copied, modified, or distributed
+except according to those terms.
+*/
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::{self, prelude::*, SeekFrom},
+    os::unix::io::AsRawFd,
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{
+    error::Error,
+    misc::LedDevice,
+    monitor::MonitorDevice,
+    sysfs::{DeviceClass, Sysfs},
+    utils::device_error,
+};
+
+#[cfg(feature = "dbus")]
+use serde::{Deserialize, Serialize};
+
+/// What reported a brightness change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub enum ChangeSource {
+    /// The backlight core updated `actual_brightness`, e.g. after a
+    /// brightness hotkey handled by firmware or another program writing
+    /// `brightness`.
+    ActualBrightness,
+    /// The hardware changed the LED on its own and reported it through
+    /// `brightness_hw_changed`.
+    Hardware,
+}
+
+impl fmt::Display for ChangeSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self {
+            ChangeSource::ActualBrightness => write!(f, "actual_brightness"),
+            ChangeSource::Hardware => write!(f, "brightness_hw_changed"),
+        }
+    }
+}
+
+/// A brightness level that changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "dbus", derive(Serialize, Deserialize))]
+pub struct BrightnessChange {
+    /// Class of the device.
+    pub class: DeviceClass,
+    /// Name of the device, e.g. `intel_backlight` or `asus::kbd_backlight`.
+    pub device: String,
+    /// Level before the change.
+    pub old: u32,
+    /// Level after the change.
+    pub new: u32,
+    /// What reported the change.
+    pub source: ChangeSource,
+}
+
+#[derive(Debug)]
+struct Watched {
+    class: DeviceClass,
+    device: String,
+    source: ChangeSource,
+    path: PathBuf,
+    file: File,
+    level: u32,
+}
+
+impl Watched {
+    fn open(
+        class: DeviceClass,
+        device: &str,
+        source: ChangeSource,
+        path: PathBuf,
+        level: u32,
+    ) -> Result<Watched, Error> {
+        let mut watched = Watched {
+            class,
+            device: device.to_string(),
+            source,
+            file: File::open(&path).map_err(|e| device_error(&path, e))?,
+            path,
+            level,
+        };
+        // sysfs only notifies pollers that have read the attribute since the
+        // last notification.
+        if let Some(level) = watched.read()? {
+            watched.level = level;
+        }
+        Ok(watched)
+    }
+
+    /// Re-read the attribute, `None` if it has no value yet.
+    fn read(&mut self) -> Result<Option<u32>, Error> {
+        let mut contents = String::new();
+        self.file
+            .seek(SeekFrom::Start(0))
+            .map_err(|e| device_error(&self.path, e))?;
+        match self.file.read_to_string(&mut contents) {
+            // outside of sysfs open files stay readable after removal
+            Ok(_) if !self.path.exists() => Err(device_error(
+                &self.path,
+                io::Error::from(io::ErrorKind::NotFound),
+            )),
+            Ok(_) => Ok(Some(contents.trim().parse::<u32>()?)),
+            // brightness_hw_changed has no value until the first hardware change
+            Err(e) if e.raw_os_error() == Some(libc::ENODATA) => Ok(None),
+            Err(e) => Err(device_error(&self.path, e)),
+        }
+    }
+
+    fn update(&mut self) -> Result<Option<BrightnessChange>, Error> {
+        match self.read()? {
+            Some(level) if level != self.level => {
+                let old = self.level;
+                self.level = level;
+                Ok(Some(BrightnessChange {
+                    class: self.class,
+                    device: self.device.clone(),
+                    old,
+                    new: level,
+                    source: self.source,
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Waits for brightness changes made outside of the program.
+///
+/// The backlight core notifies `actual_brightness` whenever the backlight
+/// level changes and LEDs that change on their own notify
+/// `brightness_hw_changed`, the watcher blocks on these with
+/// `poll(POLLPRI)` instead of re-reading them on a timer.
+///
+/// Devices that are removed stop being watched, their removal reported
+/// once as [`Error::DeviceGone`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use bulbb::watch::BrightnessWatcher;
+///
+/// let mut watcher = BrightnessWatcher::all().unwrap();
+/// for change in watcher.events() {
+///     let change = change.unwrap();
+///     println!("{}: {} -> {}", change.device, change.old, change.new);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct BrightnessWatcher {
+    watched: Vec<Watched>,
+    // errors, e.g. removed devices, not reported yet
+    errors: VecDeque<Error>,
+}
+
+impl BrightnessWatcher {
+    /// Create a watcher that is not watching anything yet.
+    pub fn new() -> BrightnessWatcher {
+        BrightnessWatcher::default()
+    }
+
+    /// Watch every monitor and every LED that reports hardware changes.
+    pub fn all() -> Result<BrightnessWatcher, Error> {
+        BrightnessWatcher::all_in(&Sysfs::default())
+    }
+
+    /// Watch every monitor and every LED that reports hardware changes in
+    /// the given sysfs tree.
+    pub fn all_in(sysfs: &Sysfs) -> Result<BrightnessWatcher, Error> {
+        let mut watcher = BrightnessWatcher::new();
+        for monitor in MonitorDevice::get_all_monitor_devices_in(sysfs)? {
+            watcher.watch_monitor(&monitor)?;
+        }
+        for led in LedDevice::get_all_led_devices_in(sysfs)? {
+            match watcher.watch_led(&led) {
+                Err(Error::Unsupported { .. }) => (),
+                res => res?,
+            }
+        }
+        Ok(watcher)
+    }
+
+    /// Watch `actual_brightness` of monitor.
+    pub fn watch_monitor(&mut self, monitor: &MonitorDevice) -> Result<(), Error> {
+        let watched = Watched::open(
+            DeviceClass::Backlight,
+            monitor.get_device_name(),
+            ChangeSource::ActualBrightness,
+            monitor
+                .get_sysfs()
+                .backlight_device(monitor.get_device_name())
+                .join("actual_brightness"),
+            monitor.get_actual_brightness(),
+        )?;
+        self.watched.push(watched);
+        Ok(())
+    }
+
+    /// Watch `brightness_hw_changed` of LED.
+    ///
+    /// Fails with [`Error::Unsupported`] for LEDs that never change on their own.
+    pub fn watch_led(&mut self, led: &LedDevice) -> Result<(), Error> {
+        let path = led
+            .get_sysfs()
+            .led_device(led.get_device_name())
+            .join("brightness_hw_changed");
+        if !path.exists() {
+            return Err(Error::Unsupported {
+                operation: "brightness_hw_changed",
+            });
+        }
+        let watched = Watched::open(
+            DeviceClass::Leds,
+            led.get_device_name(),
+            ChangeSource::Hardware,
+            path,
+            led.get_brightness(),
+        )?;
+        self.watched.push(watched);
+        Ok(())
+    }
+
+    /// Stop watching device `device` of `class`.
+    ///
+    /// Returns whether it was being watched.
+    pub fn unwatch(&mut self, class: DeviceClass, device: &str) -> bool {
+        let len = self.watched.len();
+        self.watched
+            .retain(|w| !(w.class == class && w.device == device));
+        self.watched.len() != len
+    }
+
+    /// Whether nothing is being watched.
+    pub fn is_empty(&self) -> bool {
+        self.watched.is_empty()
+    }
+
+    /// Block until at least one watched level changes or `timeout` passes.
+    ///
+    /// Returns the changes, which is empty after a timeout. `None` waits
+    /// forever.
+    ///
+    /// Fails with [`Error::DeviceGone`] once for each watched device that
+    /// has been removed, and with the error of any watched level that
+    /// could not be read. The changes of the other devices are returned by
+    /// the same or the next call.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Vec<BrightnessChange>, Error> {
+        if let Some(e) = self.errors.pop_front() {
+            return Err(e);
+        }
+        if self.watched.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut fds: Vec<libc::pollfd> = self
+            .watched
+            .iter()
+            .map(|w| libc::pollfd {
+                fd: w.file.as_raw_fd(),
+                events: libc::POLLPRI | libc::POLLERR,
+                revents: 0,
+            })
+            .collect();
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as libc::c_int);
+
+        // SAFETY: `fds` is a valid array of `fds.len()` pollfds whose file
+        // descriptors stay open for the duration of the call.
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        if ready < 0 {
+            let err = io::Error::last_os_error();
+            return if err.kind() == io::ErrorKind::Interrupted {
+                Ok(Vec::new())
+            } else {
+                Err(Error::Io(err))
+            };
+        }
+
+        let ready: Vec<bool> = fds.iter().map(|fd| fd.revents != 0).collect();
+        self.update(|i| ready[i])
+    }
+
+    /// Re-read every watched level without waiting, for filesystems that
+    /// do not notify pollers.
+    ///
+    /// Fails with [`Error::DeviceGone`] like [`wait`](BrightnessWatcher::wait).
+    pub fn check(&mut self) -> Result<Vec<BrightnessChange>, Error> {
+        if let Some(e) = self.errors.pop_front() {
+            return Err(e);
+        }
+        self.update(|_| true)
+    }
+
+    /// Update the watches for which `ready` is true, dropping those of
+    /// removed devices.
+    ///
+    /// Errors are queued, so the changes read before one are not lost.
+    fn update<F: Fn(usize) -> bool>(&mut self, ready: F) -> Result<Vec<BrightnessChange>, Error> {
+        let mut changes = Vec::new();
+        let mut gone = Vec::new();
+        for (i, watched) in self.watched.iter_mut().enumerate() {
+            if !ready(i) {
+                continue;
+            }
+            match watched.update() {
+                Ok(change) => changes.extend(change),
+                Err(Error::DeviceGone { .. }) => {
+                    gone.push(i);
+                    self.errors.push_back(Error::DeviceGone {
+                        device: watched.device.clone(),
+                    });
+                }
+                Err(e) => self.errors.push_back(e),
+            }
+        }
+        for i in gone.into_iter().rev() {
+            self.watched.remove(i);
+        }
+        if changes.is_empty() {
+            if let Some(e) = self.errors.pop_front() {
+                return Err(e);
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Iterate over changes, blocking until each one happens.
+    ///
+    /// The iterator ends once nothing is being watched and every removal
+    /// has been reported.
+    pub fn events(&mut self) -> Events<'_> {
+        Events {
+            watcher: self,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+/// Blocking iterator over brightness changes, see
+/// [`BrightnessWatcher::events`].
+#[derive(Debug)]
+pub struct Events<'a> {
+    watcher: &'a mut BrightnessWatcher,
+    pending: VecDeque<BrightnessChange>,
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<BrightnessChange, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            if self.watcher.is_empty() && self.watcher.errors.is_empty() {
+                return None;
+            }
+            match self.watcher.wait(None) {
+                Ok(changes) => self.pending.extend(changes),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{FakeBacklight, FakeLed, FakeSysfs};
+
+    fn fake_tree() -> FakeSysfs {
+        let fake = FakeSysfs::new().unwrap();
+        fake.add_backlight(&FakeBacklight::new("intel_backlight").brightness(400))
+            .unwrap();
+        fake.add_led(
+            &FakeLed::new("asus::kbd_backlight")
+                .brightness(1)
+                .max_brightness(3)
+                .attribute("brightness_hw_changed", "1"),
+        )
+        .unwrap();
+        fake.add_led(&FakeLed::new("input2::capslock").max_brightness(1))
+            .unwrap();
+        fake
+    }
+
+    #[test]
+    fn watch_all() {
+        let fake = fake_tree();
+        let mut watcher = BrightnessWatcher::all_in(&fake.sysfs()).unwrap();
+        assert_eq!(watcher.watched.len(), 2);
+        assert!(watcher.check().unwrap().is_empty());
+
+        fake.write_backlight("intel_backlight", "actual_brightness", "300")
+            .unwrap();
+        fake.write_led("asus::kbd_backlight", "brightness_hw_changed", "3")
+            .unwrap();
+        assert_eq!(
+            watcher.check().unwrap(),
+            vec![
+                BrightnessChange {
+                    class: DeviceClass::Backlight,
+                    device: String::from("intel_backlight"),
+                    old: 400,
+                    new: 300,
+                    source: ChangeSource::ActualBrightness,
+                },
+                BrightnessChange {
+                    class: DeviceClass::Leds,
+                    device: String::from("asus::kbd_backlight"),
+                    old: 1,
+                    new: 3,
+                    source: ChangeSource::Hardware,
+                },
+            ]
+        );
+        assert!(watcher.check().unwrap().is_empty());
+    }
+
+    #[test]
+    fn device_gone() {
+        let fake = fake_tree();
+        let mut watcher = BrightnessWatcher::all_in(&fake.sysfs()).unwrap();
+        fake.remove_backlight("intel_backlight").unwrap();
+        fake.write_led("asus::kbd_backlight", "brightness_hw_changed", "2")
+            .unwrap();
+
+        // the other devices are still watched, the removal is reported once
+        let changes = watcher.check().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].device, "asus::kbd_backlight");
+        match watcher.check() {
+            Err(Error::DeviceGone { device }) => assert_eq!(device, "intel_backlight"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(watcher.check().unwrap().is_empty());
+        assert_eq!(watcher.watched.len(), 1);
+
+        assert!(watcher.unwatch(DeviceClass::Leds, "asus::kbd_backlight"));
+        assert!(!watcher.unwatch(DeviceClass::Leds, "asus::kbd_backlight"));
+        assert!(watcher.is_empty());
+    }
+
+    #[test]
+    fn read_error_keeps_changes() {
+        let fake = fake_tree();
+        let sysfs = fake.sysfs();
+        let led =
+            LedDevice::get_led_device_in(&sysfs, String::from("asus::kbd_backlight")).unwrap();
+        let monitor =
+            MonitorDevice::get_monitor_device_in(&sysfs, String::from("intel_backlight")).unwrap();
+        let mut watcher = BrightnessWatcher::new();
+        watcher.watch_led(&led).unwrap();
+        watcher.watch_monitor(&monitor).unwrap();
+
+        fake.write_led("asus::kbd_backlight", "brightness_hw_changed", "2")
+            .unwrap();
+        fake.write_backlight("intel_backlight", "actual_brightness", "garbage")
+            .unwrap();
+        let changes = watcher.check().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].new, 2);
+        match watcher.check() {
+            Err(Error::ParseBrightnessError(_)) => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        fake.write_backlight("intel_backlight", "actual_brightness", "300")
+            .unwrap();
+        let changes = watcher.check().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].new, 300);
+    }
+
+    #[test]
+    fn led_without_hw_changes() {
+        let fake = fake_tree();
+        let led =
+            LedDevice::get_led_device_in(&fake.sysfs(), String::from("input2::capslock")).unwrap();
+        let mut watcher = BrightnessWatcher::new();
+        assert!(matches!(
+            watcher.watch_led(&led),
+            Err(Error::Unsupported { .. })
+        ));
+        assert!(watcher.is_empty());
+        assert!(watcher.events().next().is_none());
+    }
+
+    #[test]
+    fn wait_times_out() {
+        let fake = fake_tree();
+        let monitor =
+            MonitorDevice::get_monitor_device_in(&fake.sysfs(), String::from("intel_backlight"))
+                .unwrap();
+        let mut watcher = BrightnessWatcher::new();
+        watcher.watch_monitor(&monitor).unwrap();
+        // regular files never report POLLPRI
+        assert!(watcher
+            .wait(Some(Duration::from_millis(10)))
+            .unwrap()
+            .is_empty());
+    }
+}